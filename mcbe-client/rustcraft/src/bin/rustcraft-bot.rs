@@ -0,0 +1,273 @@
+//! Runs a bot script against a server, for integration tests of server plugins.
+//!
+//! Exits with status 1 at the first failing step, so it can be used from CI.
+
+use bevy::math::{DVec3, IVec3};
+use std::time::Duration;
+
+use rustcraft::bot::{Bot, parse_duration};
+use rustcraft::console::tokenize;
+use rustcraft::net::Edition;
+
+const USAGE: &str = "\
+Usage: rustcraft-bot <script> [--server host[:port]] [--edition java|bedrock] [--username name]
+
+Script commands, one per line (# starts a comment, \"quotes\" group words):
+  connect <host[:port]> [edition] [username]
+  wait_spawn                   wait until the server places the bot in the world
+  chat <text>                  send a chat message
+  command <command>            run a command (leading / optional)
+  walk <x> <y> <z>             walk in a straight line; ~ makes a coordinate relative
+  look <x> <y> <z>             look at a point
+  break <x> <y> <z>            break a block
+  place <x> <y> <z> <face>     place the held item against a block face
+  sleep <duration>             keep the connection running, e.g. 500ms or 2s
+  timeout <duration>           timeout for the wait and expect commands (default 10s)
+  expect_chat <text>           wait for a chat message containing text
+  expect_packet <id>           wait for a play packet, e.g. 0x72
+  expect_block <x> <y> <z>     wait for a block update at a position
+  stats                        print the session's traffic metrics as JSON
+  disconnect";
+
+struct Runner {
+    bot: Option<Bot>,
+    server: Option<String>,
+    edition: Edition,
+    username: String,
+    timeout: Duration,
+}
+
+/// A coordinate, relative to `base` when prefixed with `~`.
+fn parse_coord(value: &str, base: f64) -> Result<f64, String> {
+    let parse = |s: &str| {
+        s.parse::<f64>()
+            .map_err(|_| format!("bad coordinate '{}'", value))
+    };
+    match value.strip_prefix('~') {
+        Some("") => Ok(base),
+        Some(offset) => Ok(base + parse(offset)?),
+        None => parse(value),
+    }
+}
+
+impl Runner {
+    fn bot(&mut self) -> Result<&mut Bot, String> {
+        self.bot
+            .as_mut()
+            .ok_or_else(|| "not connected (use 'connect' or --server)".to_string())
+    }
+
+    fn point(&mut self, args: &[String]) -> Result<DVec3, String> {
+        let [x, y, z] = args else {
+            return Err("expected <x> <y> <z>".into());
+        };
+        let base = self
+            .bot
+            .as_ref()
+            .and_then(|b| b.position)
+            .unwrap_or_default();
+        Ok(DVec3::new(
+            parse_coord(x, base.x)?,
+            parse_coord(y, base.y)?,
+            parse_coord(z, base.z)?,
+        ))
+    }
+
+    fn block(&mut self, args: &[String]) -> Result<IVec3, String> {
+        Ok(self.point(args)?.floor().as_ivec3())
+    }
+
+    fn connect(&mut self, address: &str) -> Result<(), String> {
+        let bot =
+            Bot::connect(address, self.edition, &self.username).map_err(|err| err.to_string())?;
+        self.bot = Some(bot);
+        Ok(())
+    }
+
+    fn run(&mut self, words: &[String]) -> Result<(), String> {
+        let (command, args) = (words[0].as_str(), &words[1..]);
+        let timeout = self.timeout;
+        match command {
+            "connect" => {
+                let address = args.first().cloned().or(self.server.clone());
+                let address = address.ok_or("usage: connect <host[:port]> [edition] [username]")?;
+                if let Some(edition) = args.get(1) {
+                    self.edition = edition.parse()?;
+                }
+                if let Some(username) = args.get(2) {
+                    self.username = username.clone();
+                }
+                self.connect(&address)
+            }
+            "wait_spawn" => {
+                let position = self
+                    .bot()?
+                    .wait_for_spawn(timeout)
+                    .map_err(|e| e.to_string())?;
+                println!("spawned at {:.2}", position);
+                Ok(())
+            }
+            "chat" => {
+                let message = args.join(" ");
+                self.bot()?.chat(&message);
+                Ok(())
+            }
+            "command" => {
+                let command = args.join(" ");
+                self.bot()?.command(&command);
+                Ok(())
+            }
+            "walk" => {
+                let target = self.point(args)?;
+                self.bot()?.walk_to(target).map_err(|e| e.to_string())
+            }
+            "look" => {
+                let target = self.point(args)?;
+                self.bot()?.look_at(target).map_err(|e| e.to_string())
+            }
+            "break" => {
+                let pos = self.block(args)?;
+                self.bot()?.break_block(pos).map_err(|e| e.to_string())
+            }
+            "place" => {
+                let (coords, face) = match args {
+                    [x, y, z, face] => ([x.clone(), y.clone(), z.clone()], face.parse()?),
+                    _ => return Err("usage: place <x> <y> <z> <face>".into()),
+                };
+                let pos = self.block(&coords)?;
+                self.bot()?
+                    .place_block(pos, face)
+                    .map_err(|e| e.to_string())
+            }
+            "sleep" => {
+                let duration = parse_duration(args.first().ok_or("usage: sleep <duration>")?)?;
+                self.bot()?.sleep(duration).map_err(|e| e.to_string())
+            }
+            "timeout" => {
+                self.timeout = parse_duration(args.first().ok_or("usage: timeout <duration>")?)?;
+                Ok(())
+            }
+            "expect_chat" => {
+                let text = args.join(" ");
+                let message = self
+                    .bot()?
+                    .expect_chat(&text, timeout)
+                    .map_err(|e| e.to_string())?;
+                println!("chat: {}", message);
+                Ok(())
+            }
+            "expect_packet" => {
+                let id = args.first().ok_or("usage: expect_packet <id>")?;
+                let id = match id.strip_prefix("0x") {
+                    Some(hex) => i32::from_str_radix(hex, 16),
+                    None => id.parse(),
+                }
+                .map_err(|_| format!("bad packet id '{}'", id))?;
+                let packet = self
+                    .bot()?
+                    .expect_packet(id, timeout)
+                    .map_err(|e| e.to_string())?;
+                println!("packet 0x{:02X} ({} bytes)", id, packet.payload.len());
+                Ok(())
+            }
+            "expect_block" => {
+                let pos = self.block(args)?;
+                let state = self
+                    .bot()?
+                    .expect_block_change(pos, timeout)
+                    .map_err(|e| e.to_string())?;
+                println!("block {} is now state {}", pos, state);
+                Ok(())
+            }
+            "stats" => {
+                let stats = self.bot()?.stats();
+                println!(
+                    "{}",
+                    serde_json::to_string(&stats).map_err(|e| e.to_string())?
+                );
+                Ok(())
+            }
+            "disconnect" => {
+                if let Some(bot) = self.bot.take() {
+                    bot.disconnect();
+                }
+                Ok(())
+            }
+            other => Err(format!("unknown command '{}'", other)),
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut script = None;
+    let mut runner = Runner {
+        bot: None,
+        server: None,
+        edition: Edition::Java,
+        username: "Bot".into(),
+        timeout: Duration::from_secs(10),
+    };
+
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "--server" => args.next().map(|v| runner.server = Some(v)).ok_or(()),
+            "--edition" => args
+                .next()
+                .and_then(|v| v.parse().ok())
+                .map(|v| runner.edition = v)
+                .ok_or(()),
+            "--username" => args.next().map(|v| runner.username = v).ok_or(()),
+            _ if script.is_none() && !arg.starts_with('-') => {
+                script = Some(arg.clone());
+                Ok(())
+            }
+            _ => Err(()),
+        };
+        if result.is_err() {
+            eprintln!("error: bad argument '{}'\n\n{}", arg, USAGE);
+            std::process::exit(2);
+        }
+    }
+
+    let Some(script) = script else {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+    let text = match std::fs::read_to_string(&script) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("error: could not read {}: {}", script, err);
+            std::process::exit(2);
+        }
+    };
+
+    // --server connects before the first line, so scripts can skip `connect`
+    if let Some(server) = runner.server.clone()
+        && let Err(err) = runner.connect(&server)
+    {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        for words in tokenize(line) {
+            if let Err(err) = runner.run(&words) {
+                eprintln!("{}:{}: {}: {}", script, number + 1, words[0], err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(bot) = runner.bot.take() {
+        bot.disconnect();
+    }
+}
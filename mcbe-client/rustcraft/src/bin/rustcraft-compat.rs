@@ -0,0 +1,143 @@
+//! Checks recorded Bedrock sessions against the packet tables and decoders of the protocol
+//! version each was recorded with, and prints the result as a matrix of supported versions.
+//!
+//! Fixtures are ordinary capture files with raw payloads: join a server of the version with
+//! `net.capture true` and `net.capture_mode full`, then copy the file into the fixtures folder.
+//! The fixtures in the crate hold one of each version-gated packet, written with the
+//! client's own encoders; the unit tests in `net::bedrock` decode them too.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use rustcraft::net::bedrock::summarize_packet;
+use rustcraft::net::bedrock::versions::{BedrockVersion, SUPPORTED};
+use rustcraft::net::capture::{Direction, read_capture};
+
+const USAGE: &str = "\
+Usage: rustcraft-compat [fixtures-dir]
+
+Checks every Bedrock capture in the folder (default: the fixtures/bedrock folder of the crate) and exits with an
+error if any packet failed to decode with its version's layouts.";
+
+/// What one fixture showed.
+#[derive(Default)]
+struct FixtureResult {
+    packets: usize,
+    /// Ids missing from the version's packet table.
+    unknown: BTreeMap<i32, usize>,
+    decoded: usize,
+    failures: Vec<String>,
+}
+
+fn check_fixture(path: &Path) -> Result<(i32, FixtureResult), String> {
+    let (header, records) = read_capture(path).map_err(|err| err.to_string())?;
+    if header.edition != "bedrock" {
+        return Err(format!("{} capture, not bedrock", header.edition));
+    }
+    let version = BedrockVersion::find(header.protocol).ok_or_else(|| {
+        format!(
+            "protocol {} is not supported ({})",
+            header.protocol,
+            BedrockVersion::supported_range()
+        )
+    })?;
+
+    let mut result = FixtureResult::default();
+    for record in &records {
+        let Some(payload) = record.payload_bytes().map_err(|err| err.to_string())? else {
+            return Err("recorded without raw payloads".into());
+        };
+        result.packets += 1;
+        if version.packet_name(record.id as u32).is_none() {
+            *result.unknown.entry(record.id).or_default() += 1;
+            continue;
+        }
+        match summarize_packet(version, record.id as u32, &payload) {
+            Ok(Some(_)) => result.decoded += 1,
+            Ok(None) => {}
+            Err(err) => {
+                let direction = match record.direction {
+                    Direction::Inbound => "in",
+                    Direction::Outbound => "out",
+                };
+                result.failures.push(format!(
+                    "{:.0} ms {} 0x{:02X}: {}",
+                    record.time_ms, direction, record.id, err
+                ));
+            }
+        }
+    }
+    Ok((version.protocol, result))
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let dir = match args.next().as_deref() {
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return;
+        }
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/bedrock")),
+    };
+
+    let mut fixtures: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+            .collect(),
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", dir.display(), err);
+            std::process::exit(2);
+        }
+    };
+    fixtures.sort();
+
+    let mut covered = Vec::new();
+    let mut failed = false;
+    println!(
+        "{:<40} {:>8} {:>8} {:>8} {:>8}",
+        "fixture", "protocol", "packets", "decoded", "unknown"
+    );
+    for path in &fixtures {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match check_fixture(path) {
+            Ok((protocol, result)) => {
+                covered.push(protocol);
+                let unknown: usize = result.unknown.values().sum();
+                println!(
+                    "{:<40} {:>8} {:>8} {:>8} {:>8}",
+                    name, protocol, result.packets, result.decoded, unknown
+                );
+                for (id, count) in &result.unknown {
+                    println!("    unknown id 0x{:02X} x{}", id, count);
+                }
+                for failure in &result.failures {
+                    println!("    FAILED {}", failure);
+                }
+                failed |= !result.failures.is_empty();
+            }
+            Err(err) => {
+                println!("{:<40} error: {}", name, err);
+                failed = true;
+            }
+        }
+    }
+
+    println!();
+    for version in SUPPORTED {
+        let status = if covered.contains(&version.protocol) {
+            "checked"
+        } else {
+            "no fixture"
+        };
+        println!(
+            "{:<8} {:>4}  {}",
+            version.game_version, version.protocol, status
+        );
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
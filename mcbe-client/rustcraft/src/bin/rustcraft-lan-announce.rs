@@ -0,0 +1,133 @@
+//! Pretends to be a game open to LAN, so the multiplayer screen's LAN section can be tried
+//! without one. With `--discover` it lists the games it can see instead.
+
+use std::time::Duration;
+
+use rustcraft::net::bedrock::{GAME_VERSION, PROTOCOL_VERSION};
+use rustcraft::net::lan::{BedrockMotd, LanAnnouncer, LanDiscovery};
+use rustcraft::net::{Edition, NetError};
+
+const USAGE: &str = "\
+Usage: rustcraft-lan-announce [options]
+
+Options:
+  --edition <edition>   java, bedrock or both (default both)
+  --motd <text>         name shown in the server list (default \"RustCraft LAN\")
+  --port <port>         game port to advertise (default: the edition's port)
+  --discover            list games found on the network instead of announcing one";
+
+struct AnnounceArgs {
+    editions: Vec<Edition>,
+    motd: String,
+    port: Option<u16>,
+    discover: bool,
+}
+
+fn parse_args() -> Result<Option<AnnounceArgs>, String> {
+    let mut editions = vec![Edition::Bedrock, Edition::Java];
+    let mut motd = "RustCraft LAN".to_string();
+    let mut port = None;
+    let mut discover = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--edition" => {
+                editions = match value("--edition")?.as_str() {
+                    "both" => vec![Edition::Bedrock, Edition::Java],
+                    other => vec![other.parse()?],
+                }
+            }
+            "--motd" => motd = value("--motd")?,
+            "--port" => {
+                let text = value("--port")?;
+                port = Some(text.parse().map_err(|_| format!("bad port '{}'", text))?);
+            }
+            "--discover" => discover = true,
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("unknown option '{}'", other)),
+        }
+    }
+    Ok(Some(AnnounceArgs {
+        editions,
+        motd,
+        port,
+        discover,
+    }))
+}
+
+fn announce(edition: Edition, motd: &str, port: Option<u16>) -> Result<LanAnnouncer, NetError> {
+    let port = port.unwrap_or(edition.default_port());
+    match edition {
+        Edition::Java => LanAnnouncer::java(motd, port),
+        Edition::Bedrock => LanAnnouncer::bedrock(
+            BedrockMotd {
+                edition: "MCPE".into(),
+                motd: motd.to_string(),
+                protocol: PROTOCOL_VERSION,
+                version: GAME_VERSION.into(),
+                players: 0,
+                max_players: 8,
+                server_guid: fastrand::u64(..),
+                sub_motd: "Bedrock level".into(),
+                game_mode: "Survival".into(),
+                port_v4: Some(port),
+                port_v6: None,
+            },
+            // pings only ever arrive on the default port
+            Edition::Bedrock.default_port(),
+        ),
+    }
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if args.discover {
+        let discovery = match LanDiscovery::start() {
+            Ok(discovery) => discovery,
+            Err(err) => {
+                eprintln!("error: cannot look for LAN games: {}", err);
+                std::process::exit(1);
+            }
+        };
+        loop {
+            for server in discovery.poll() {
+                let details = server.details.unwrap_or_default();
+                println!(
+                    "{} {} \"{}\" {}",
+                    server.edition, server.address, server.motd, details
+                );
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    let mut announcers = Vec::new();
+    for edition in args.editions {
+        match announce(edition, &args.motd, args.port) {
+            Ok(announcer) => {
+                println!("Announcing \"{}\" as a {} LAN game", args.motd, edition);
+                announcers.push(announcer);
+            }
+            Err(err) => eprintln!("error: cannot announce a {} game: {}", edition, err),
+        }
+    }
+    if announcers.is_empty() {
+        std::process::exit(1);
+    }
+    loop {
+        std::thread::sleep(Duration::from_secs(60));
+    }
+}
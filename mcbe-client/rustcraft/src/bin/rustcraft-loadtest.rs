@@ -0,0 +1,570 @@
+//! Opens many lightweight sessions against a server and reports how it coped, as JSON.
+//!
+//! Sessions use the networking stack directly: no ECS, no rendering, one thread per
+//! connection plus the driver loop below.
+
+use bevy::math::DVec3;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use rustcraft::bot::{WALK_SPEED, parse_duration};
+use rustcraft::config::validate_username;
+use rustcraft::net::session::{PlayerAction, Session, SessionEvent, SessionOptions};
+use rustcraft::net::stats::{NetStats, TrafficSample};
+use rustcraft::net::{Edition, ServerAddress};
+
+const USAGE: &str = "\
+Usage: rustcraft-loadtest --server host[:port] [options]
+
+Options:
+  --bots <n>               number of sessions (default 10)
+  --stagger <duration>     delay between logins (default 200ms)
+  --duration <duration>    how long to run after the first login (default 60s)
+  --behaviour <list>       comma separated, assigned round robin:
+                           idle, walk, chat, chunks (default idle)
+  --chat-interval <d>      delay between chat messages (default 2s)
+  --walk-radius <blocks>   how far random walks go from spawn (default 16)
+  --view-distance <n>      chunks requested from the server (default 8)
+  --ping-interval <d>      how often latency is measured (default 1s)
+  --prefix <name>          username prefix, numbered per bot (default Load)
+  --edition <edition>      java or bedrock (default java)
+  --output <file>          write the report here instead of stdout";
+
+/// The driver loop runs at the game's tick rate.
+const TICK: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Behaviour {
+    /// Stay connected and answer keepalives.
+    Idle,
+    /// Walk to random points around spawn.
+    RandomWalk,
+    /// Send chat messages and time their echo.
+    ChatSpam,
+    /// Walk away from spawn in a straight line, forcing new chunks to load.
+    ChunkLoading,
+}
+
+impl Behaviour {
+    fn name(self) -> &'static str {
+        match self {
+            Behaviour::Idle => "idle",
+            Behaviour::RandomWalk => "walk",
+            Behaviour::ChatSpam => "chat",
+            Behaviour::ChunkLoading => "chunks",
+        }
+    }
+}
+
+impl FromStr for Behaviour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "idle" => Ok(Behaviour::Idle),
+            "walk" => Ok(Behaviour::RandomWalk),
+            "chat" => Ok(Behaviour::ChatSpam),
+            "chunks" => Ok(Behaviour::ChunkLoading),
+            other => Err(format!(
+                "unknown behaviour '{}' (expected idle, walk, chat or chunks)",
+                other
+            )),
+        }
+    }
+}
+
+struct Options {
+    server: Option<String>,
+    edition: Edition,
+    bots: usize,
+    stagger: Duration,
+    duration: Duration,
+    behaviours: Vec<Behaviour>,
+    chat_interval: Duration,
+    walk_radius: f64,
+    view_distance: u8,
+    ping_interval: Duration,
+    prefix: String,
+    output: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            server: None,
+            edition: Edition::Java,
+            bots: 10,
+            stagger: Duration::from_millis(200),
+            duration: Duration::from_secs(60),
+            behaviours: vec![Behaviour::Idle],
+            chat_interval: Duration::from_secs(2),
+            walk_radius: 16.0,
+            view_distance: 8,
+            ping_interval: Duration::from_secs(1),
+            prefix: "Load".into(),
+            output: None,
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("bad value '{}' for {}", value, name))
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            if name == "-h" || name == "--help" {
+                return Ok(None);
+            }
+            let value = match inline.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(format!("missing value for {}", name)),
+            };
+            match name.as_str() {
+                "--server" => options.server = Some(value),
+                "--edition" => options.edition = value.parse()?,
+                "--bots" => options.bots = parse_number(&name, &value)?,
+                "--stagger" => options.stagger = parse_duration(&value)?,
+                "--duration" => options.duration = parse_duration(&value)?,
+                "--behaviour" | "--behavior" => {
+                    options.behaviours = value
+                        .split(',')
+                        .map(|b| b.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--chat-interval" => options.chat_interval = parse_duration(&value)?,
+                "--walk-radius" => options.walk_radius = parse_number(&name, &value)?,
+                "--view-distance" => options.view_distance = parse_number(&name, &value)?,
+                "--ping-interval" => options.ping_interval = parse_duration(&value)?,
+                "--prefix" => options.prefix = value,
+                "--output" => options.output = Some(PathBuf::from(value)),
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+        }
+
+        if options.server.is_none() {
+            return Err("--server is required".into());
+        }
+        if options.bots == 0 {
+            return Err("--bots must be at least 1".into());
+        }
+        if options.behaviours.is_empty() {
+            return Err("--behaviour needs at least one behaviour".into());
+        }
+        // the longest name has to fit the username rules too
+        validate_username(&username(&options.prefix, options.bots - 1, options.bots))?;
+        Ok(Some(options))
+    }
+}
+
+fn username(prefix: &str, index: usize, count: usize) -> String {
+    let width = count.to_string().len();
+    format!("{}{:0width$}", prefix, index, width = width)
+}
+
+/// One simulated player and what was measured for it.
+struct Client {
+    username: String,
+    behaviour: Behaviour,
+    session: Option<Session>,
+    /// Outlives the session, so the report covers dropped bots too.
+    stats: NetStats,
+    started_at: Instant,
+    login_time: Option<Duration>,
+    position: Option<DVec3>,
+    yaw: f32,
+    home: DVec3,
+    target: Option<DVec3>,
+    next_chat: Instant,
+    chat_sent: u32,
+    /// Messages sent and not echoed yet.
+    pending_chat: VecDeque<(String, Instant)>,
+    chat_latency: Vec<Duration>,
+    rtt: Vec<Duration>,
+    chunks: u32,
+    teleports: u32,
+    disconnect_reason: Option<String>,
+}
+
+impl Client {
+    fn start(
+        username: String,
+        behaviour: Behaviour,
+        address: ServerAddress,
+        options: &Options,
+    ) -> Self {
+        let session_options = SessionOptions {
+            username: username.clone(),
+            view_distance: options.view_distance,
+            ping_interval: Some(options.ping_interval),
+            ..Default::default()
+        };
+        let session = Session::connect(address, options.edition, session_options);
+        let now = Instant::now();
+        Self {
+            username,
+            behaviour,
+            stats: session.stats_handle().clone(),
+            session: Some(session),
+            started_at: now,
+            login_time: None,
+            position: None,
+            yaw: 0.0,
+            home: DVec3::ZERO,
+            target: None,
+            next_chat: now,
+            chat_sent: 0,
+            pending_chat: VecDeque::new(),
+            chat_latency: Vec::new(),
+            rtt: Vec::new(),
+            chunks: 0,
+            teleports: 0,
+            disconnect_reason: None,
+        }
+    }
+
+    fn connected(&self) -> bool {
+        self.session.is_some()
+    }
+
+    fn handle_events(&mut self) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        let events: Vec<_> = session.poll().collect();
+        for event in events {
+            match event {
+                SessionEvent::Spawned { position, yaw, .. } => {
+                    self.login_time = Some(self.started_at.elapsed());
+                    self.position = Some(position);
+                    self.home = position;
+                    self.yaw = yaw;
+                }
+                SessionEvent::Teleported { position, yaw, .. } => {
+                    self.teleports += 1;
+                    self.position = Some(position);
+                    self.yaw = yaw;
+                    self.target = None;
+                }
+                SessionEvent::Chat { message, .. } => {
+                    if let Some(index) = self
+                        .pending_chat
+                        .iter()
+                        .position(|(text, _)| message.contains(text.as_str()))
+                    {
+                        // anything older than the echoed message was lost
+                        let (_, sent) = self.pending_chat.drain(..=index).next_back().unwrap();
+                        self.chat_latency.push(sent.elapsed());
+                    }
+                }
+                SessionEvent::ChunkLoaded { .. } => self.chunks += 1,
+                SessionEvent::Latency { rtt } => self.rtt.push(rtt),
+                SessionEvent::Disconnected { reason } => {
+                    self.disconnect_reason = Some(reason);
+                    self.session = None;
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn step(&mut self, options: &Options, now: Instant) {
+        let (Some(session), Some(position)) = (&self.session, self.position) else {
+            return;
+        };
+        match self.behaviour {
+            Behaviour::Idle => {}
+            Behaviour::ChatSpam => {
+                if now >= self.next_chat {
+                    self.chat_sent += 1;
+                    let text = format!("{} #{} ping", self.username, self.chat_sent);
+                    session.send(PlayerAction::Chat(text.clone()));
+                    self.pending_chat.push_back((text, now));
+                    self.next_chat = now + options.chat_interval;
+                }
+            }
+            Behaviour::RandomWalk | Behaviour::ChunkLoading => {
+                let target = *self.target.get_or_insert_with(|| match self.behaviour {
+                    Behaviour::ChunkLoading => {
+                        // far enough that it is never reached during a run
+                        let angle = fastrand::f64() * std::f64::consts::TAU;
+                        position + DVec3::new(angle.cos(), 0.0, angle.sin()) * 1.0e6
+                    }
+                    _ => {
+                        let angle = fastrand::f64() * std::f64::consts::TAU;
+                        let distance = fastrand::f64().sqrt() * options.walk_radius;
+                        self.home + DVec3::new(angle.cos(), 0.0, angle.sin()) * distance
+                    }
+                });
+                let delta = DVec3::new(target.x - position.x, 0.0, target.z - position.z);
+                let step = WALK_SPEED * TICK.as_secs_f64();
+                let next = if delta.length() <= step {
+                    self.target = None;
+                    target.with_y(position.y)
+                } else {
+                    position + delta.normalize() * step
+                };
+                if delta.length_squared() > 1e-6 {
+                    self.yaw = (-delta.x).atan2(delta.z).to_degrees() as f32;
+                }
+                session.send(PlayerAction::Move {
+                    position: next,
+                    yaw: self.yaw,
+                    pitch: 0.0,
+                    on_ground: true,
+                });
+                self.position = Some(next);
+            }
+        }
+    }
+
+    fn report(&self) -> BotReport {
+        BotReport {
+            username: self.username.clone(),
+            behaviour: self.behaviour.name(),
+            joined: self.login_time.is_some(),
+            login_ms: self.login_time.map(millis),
+            rtt: Percentiles::of(&self.rtt),
+            chat_sent: self.chat_sent,
+            chat_received: self.chat_latency.len() as u32,
+            chunks: self.chunks,
+            teleports: self.teleports,
+            traffic: self.stats.snapshot().total,
+            disconnect_reason: self.disconnect_reason.clone(),
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Summary of a set of durations, in milliseconds.
+#[derive(Serialize)]
+struct Percentiles {
+    count: usize,
+    min: f64,
+    mean: f64,
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+impl Percentiles {
+    fn of(samples: &[Duration]) -> Option<Self> {
+        let mut values: Vec<f64> = samples.iter().copied().map(millis).collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        // nearest rank
+        let rank =
+            |p: f64| values[((p * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+        Some(Self {
+            count: values.len(),
+            min: values[0],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            p50: rank(0.50),
+            p90: rank(0.90),
+            p99: rank(0.99),
+            max: values[values.len() - 1],
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct BotReport {
+    username: String,
+    behaviour: &'static str,
+    joined: bool,
+    login_ms: Option<f64>,
+    rtt: Option<Percentiles>,
+    chat_sent: u32,
+    chat_received: u32,
+    chunks: u32,
+    teleports: u32,
+    traffic: TrafficSample,
+    disconnect_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Connections {
+    attempted: usize,
+    succeeded: usize,
+    failed: usize,
+    /// Joined, but dropped by the server before the end of the run.
+    dropped: usize,
+    success_rate: f64,
+}
+
+#[derive(Serialize)]
+struct Report {
+    server: String,
+    edition: String,
+    duration_secs: f64,
+    connections: Connections,
+    login_ms: Option<Percentiles>,
+    rtt_ms: Option<Percentiles>,
+    chat_echo_ms: Option<Percentiles>,
+    chunks_received: u64,
+    /// Bytes and packets of all sessions together.
+    traffic: TrafficSample,
+    /// Reasons for failed logins and dropped sessions, with how often each happened.
+    disconnect_reasons: BTreeMap<String, usize>,
+    bots: Vec<BotReport>,
+}
+
+fn build_report(
+    options: &Options,
+    address: &ServerAddress,
+    elapsed: Duration,
+    clients: &[Client],
+) -> Report {
+    let succeeded = clients.iter().filter(|c| c.login_time.is_some()).count();
+    let dropped = clients
+        .iter()
+        .filter(|c| c.login_time.is_some() && c.disconnect_reason.is_some())
+        .count();
+    let mut disconnect_reasons = BTreeMap::new();
+    for reason in clients.iter().filter_map(|c| c.disconnect_reason.clone()) {
+        *disconnect_reasons.entry(reason).or_default() += 1;
+    }
+    let all = |f: fn(&Client) -> &[Duration]| -> Vec<Duration> {
+        clients.iter().flat_map(|c| f(c).iter().copied()).collect()
+    };
+    let logins: Vec<_> = clients.iter().filter_map(|c| c.login_time).collect();
+    let bots: Vec<BotReport> = clients.iter().map(Client::report).collect();
+
+    Report {
+        server: address.to_string(),
+        edition: options.edition.to_string(),
+        duration_secs: elapsed.as_secs_f64(),
+        connections: Connections {
+            attempted: clients.len(),
+            succeeded,
+            failed: clients.len() - succeeded,
+            dropped,
+            success_rate: succeeded as f64 / clients.len().max(1) as f64,
+        },
+        login_ms: Percentiles::of(&logins),
+        rtt_ms: Percentiles::of(&all(|c| &c.rtt)),
+        chat_echo_ms: Percentiles::of(&all(|c| &c.chat_latency)),
+        chunks_received: clients.iter().map(|c| c.chunks as u64).sum(),
+        traffic: bots
+            .iter()
+            .fold(TrafficSample::default(), |sum, bot| TrafficSample {
+                bytes_in: sum.bytes_in + bot.traffic.bytes_in,
+                bytes_out: sum.bytes_out + bot.traffic.bytes_out,
+                packets_in: sum.packets_in + bot.traffic.packets_in,
+                packets_out: sum.packets_out + bot.traffic.packets_out,
+            }),
+        disconnect_reasons,
+        bots,
+    }
+}
+
+fn run(options: &Options) -> Result<Report, String> {
+    let server = options.server.as_deref().unwrap_or_default();
+    let address = ServerAddress::parse(server, options.edition)?;
+    eprintln!(
+        "starting {} sessions against {} ({:?} apart, running {:?})",
+        options.bots, address, options.stagger, options.duration
+    );
+
+    let begin = Instant::now();
+    let end = begin + options.duration;
+    let mut clients: Vec<Client> = Vec::with_capacity(options.bots);
+    let mut next_tick = begin;
+
+    while Instant::now() < end {
+        let now = Instant::now();
+        while clients.len() < options.bots && now >= begin + options.stagger * clients.len() as u32
+        {
+            let index = clients.len();
+            let behaviour = options.behaviours[index % options.behaviours.len()];
+            let name = username(&options.prefix, index, options.bots);
+            clients.push(Client::start(name, behaviour, address.clone(), options));
+        }
+
+        for client in &mut clients {
+            client.handle_events();
+            client.step(options, now);
+        }
+
+        let all_started = clients.len() == options.bots;
+        if all_started && clients.iter().all(|c| !c.connected()) {
+            eprintln!("every session has ended, stopping early");
+            break;
+        }
+
+        next_tick += TICK;
+        std::thread::sleep(next_tick.saturating_duration_since(Instant::now()));
+    }
+    let elapsed = begin.elapsed();
+
+    // let the last events (and pongs) arrive before closing
+    for client in &mut clients {
+        client.handle_events();
+    }
+    for client in &mut clients {
+        // sessions still logging in are just dropped, which stops them without waiting
+        if let Some(session) = client.session.take()
+            && client.login_time.is_some()
+        {
+            session.disconnect();
+        }
+    }
+
+    Ok(build_report(options, &address, elapsed, &clients))
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let report = match run(&options) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(2);
+        }
+    };
+    eprintln!(
+        "{}/{} sessions joined, {} dropped",
+        report.connections.succeeded, report.connections.attempted, report.connections.dropped
+    );
+
+    let json = serde_json::to_string_pretty(&report).expect("report is always serializable");
+    match &options.output {
+        Some(path) => {
+            if let Err(err) = std::fs::write(path, json + "\n") {
+                eprintln!("error: could not write {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+            eprintln!("report written to {}", path.display());
+        }
+        None => println!("{}", json),
+    }
+}
@@ -0,0 +1,502 @@
+//! Sits between a client and a server, showing every packet in an inspector window.
+//!
+//! Point any client at the listen address; traffic is forwarded to `--upstream` after the
+//! rules (from `--rules` or the Rules panel) had a chance to drop or rewrite it.
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, EguiPlugin, EguiPrimaryContextPass, egui};
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use rustcraft::net::capture::{Direction, to_hex};
+use rustcraft::net::proxy::{
+    Proxy, ProxyConfig, ProxyEvent, ProxyPacket, ProxyRule, Verdict, load_rules,
+};
+use rustcraft::net::{Edition, ServerAddress};
+
+const USAGE: &str = "\
+Usage: rustcraft-proxy --upstream host[:port] [options]
+
+Options:
+  --edition <edition>   java or bedrock (default bedrock)
+  --listen <addr:port>  where clients connect (default 0.0.0.0 on the edition's port + 1)
+  --rules <file>        drop/replace rules, one per line:
+                          drop <in|out|both> <id|name>
+                          replace <in|out|both> <id|name> <find-hex> <replace-hex>
+  --headless            print packets to stdout instead of opening the inspector";
+
+/// Packets kept by the inspector; the oldest are forgotten first.
+const MAX_PACKETS: usize = 100_000;
+
+struct ProxyArgs {
+    config: ProxyConfig,
+    rules: Option<PathBuf>,
+    headless: bool,
+}
+
+fn parse_args() -> Result<Option<ProxyArgs>, String> {
+    let mut upstream = None;
+    let mut edition = Edition::Bedrock;
+    let mut listen = None;
+    let mut rules = None;
+    let mut headless = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--upstream" => upstream = Some(value("--upstream")?),
+            "--edition" => edition = value("--edition")?.parse()?,
+            "--listen" => {
+                let addr = value("--listen")?;
+                listen = Some(
+                    addr.parse::<SocketAddr>()
+                        .map_err(|_| format!("bad listen address '{}'", addr))?,
+                );
+            }
+            "--rules" => rules = Some(PathBuf::from(value("--rules")?)),
+            "--headless" => headless = true,
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("unknown option '{}'", other)),
+        }
+    }
+
+    let upstream = ServerAddress::parse(&upstream.ok_or("--upstream is required")?, edition)?;
+    let listen = listen.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], upstream.port + 1)));
+    Ok(Some(ProxyArgs {
+        config: ProxyConfig {
+            edition,
+            listen,
+            upstream,
+        },
+        rules,
+        headless,
+    }))
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let rules = match &args.rules {
+        Some(path) => match load_rules(path) {
+            Ok(rules) => rules,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(2);
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let proxy = match Proxy::start(args.config.clone(), rules) {
+        Ok(proxy) => proxy,
+        Err(err) => {
+            eprintln!("error: cannot start proxy: {}", err);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Proxying {} ({}) on {}",
+        args.config.upstream, args.config.edition, args.config.listen
+    );
+
+    if args.headless {
+        for event in proxy.events.iter() {
+            println!("{}", event_line(&event));
+        }
+        return;
+    }
+
+    App::new()
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: format!("RustCraft Proxy - {}", args.config.upstream),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }))
+        .add_plugins(EguiPlugin::default())
+        .insert_resource(Inspector::new(proxy))
+        .add_systems(Startup, |mut commands: Commands| {
+            commands.spawn(Camera2d);
+        })
+        .add_systems(Update, proxy_event_system)
+        .add_systems(EguiPrimaryContextPass, inspector_ui_system)
+        .run();
+}
+
+fn direction_arrow(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Inbound => "S->C",
+        Direction::Outbound => "C->S",
+    }
+}
+
+fn packet_label(packet: &ProxyPacket) -> String {
+    match packet.name {
+        Some(name) => format!("0x{:02X} {}", packet.id, name),
+        None => format!("0x{:02X}", packet.id),
+    }
+}
+
+fn packet_line(packet: &ProxyPacket) -> String {
+    let mut line = format!(
+        "{:>10.3} #{:<3} {} {:<13} {:<40} {:>7}B",
+        packet.time.as_secs_f64(),
+        packet.connection,
+        direction_arrow(packet.direction),
+        packet.state,
+        packet_label(packet),
+        packet.payload.len()
+    );
+    if packet.verdict != Verdict::Forwarded {
+        line.push_str(&format!(" [{}]", packet.verdict.name()));
+    }
+    if let Some(summary) = &packet.summary {
+        line.push_str("  ");
+        line.push_str(summary);
+    }
+    line
+}
+
+fn event_line(event: &ProxyEvent) -> String {
+    match event {
+        ProxyEvent::Opened { connection, peer } => format!("#{} opened from {}", connection, peer),
+        ProxyEvent::Packet(packet) => packet_line(packet),
+        ProxyEvent::Note {
+            connection,
+            message,
+        } => format!("#{} {}", connection, message),
+        ProxyEvent::Closed { connection, reason } => format!("#{} closed: {}", connection, reason),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DetailView {
+    Structured,
+    Hex,
+}
+
+/// Everything the inspector shows, plus its filters.
+#[derive(Resource)]
+struct Inspector {
+    proxy: Proxy,
+    packets: VecDeque<ProxyPacket>,
+    /// Connection events and notes, newest last.
+    notes: VecDeque<String>,
+    /// Packet counts by (state, id, name), also the list of types to filter on.
+    types: BTreeMap<(&'static str, i32), (Option<&'static str>, u64)>,
+    hidden_types: std::collections::HashSet<(&'static str, i32)>,
+    paused: bool,
+    autoscroll: bool,
+    show_inbound: bool,
+    show_outbound: bool,
+    search: String,
+    selected: Option<usize>,
+    view: DetailView,
+    new_rule: String,
+    rule_error: Option<String>,
+}
+
+impl Inspector {
+    fn new(proxy: Proxy) -> Self {
+        Self {
+            proxy,
+            packets: VecDeque::new(),
+            notes: VecDeque::new(),
+            types: BTreeMap::new(),
+            hidden_types: Default::default(),
+            paused: false,
+            autoscroll: true,
+            show_inbound: true,
+            show_outbound: true,
+            search: String::new(),
+            selected: None,
+            view: DetailView::Structured,
+            new_rule: String::new(),
+            rule_error: None,
+        }
+    }
+
+    fn visible(&self, packet: &ProxyPacket) -> bool {
+        let direction = match packet.direction {
+            Direction::Inbound => self.show_inbound,
+            Direction::Outbound => self.show_outbound,
+        };
+        if !direction || self.hidden_types.contains(&(packet.state, packet.id)) {
+            return false;
+        }
+        if self.search.is_empty() {
+            return true;
+        }
+        let search = self.search.to_ascii_lowercase();
+        packet.name.is_some_and(|name| name.contains(&search))
+            || packet
+                .summary
+                .as_ref()
+                .is_some_and(|summary| summary.to_ascii_lowercase().contains(&search))
+            || format!("0x{:02x}", packet.id) == search
+    }
+}
+
+/// Move new events from the proxy threads into the inspector.
+fn proxy_event_system(mut inspector: ResMut<Inspector>) {
+    let inspector = &mut *inspector;
+    for event in inspector.proxy.events.try_iter() {
+        let ProxyEvent::Packet(packet) = event else {
+            inspector.notes.push_back(event_line(&event));
+            if inspector.notes.len() > 200 {
+                inspector.notes.pop_front();
+            }
+            continue;
+        };
+        let entry = inspector
+            .types
+            .entry((packet.state, packet.id))
+            .or_insert((packet.name, 0));
+        entry.1 += 1;
+        if inspector.paused {
+            continue;
+        }
+        inspector.packets.push_back(packet);
+        if inspector.packets.len() > MAX_PACKETS {
+            inspector.packets.pop_front();
+            inspector.selected = inspector.selected.and_then(|i| i.checked_sub(1));
+        }
+    }
+}
+
+fn inspector_ui_system(mut contexts: EguiContexts, mut inspector: ResMut<Inspector>) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    let inspector = &mut *inspector;
+
+    egui::TopBottomPanel::top("proxy_toolbar").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            let config = &inspector.proxy.config;
+            ui.label(format!(
+                "{} {} -> {}",
+                config.edition, config.listen, config.upstream
+            ));
+            ui.separator();
+            ui.toggle_value(&mut inspector.paused, "Pause");
+            ui.toggle_value(&mut inspector.autoscroll, "Autoscroll");
+            if ui.button("Clear").clicked() {
+                inspector.packets.clear();
+                inspector.types.clear();
+                inspector.selected = None;
+            }
+            ui.separator();
+            ui.label(format!("{} packets", inspector.packets.len()));
+        });
+    });
+
+    egui::SidePanel::left("proxy_filters")
+        .default_width(240.0)
+        .show(ctx, |ui| filters_ui(ui, inspector));
+    egui::SidePanel::right("proxy_rules")
+        .default_width(300.0)
+        .show(ctx, |ui| rules_ui(ui, inspector));
+    egui::TopBottomPanel::bottom("proxy_detail")
+        .resizable(true)
+        .default_height(220.0)
+        .show(ctx, |ui| detail_ui(ui, inspector));
+    egui::CentralPanel::default().show(ctx, |ui| packet_list_ui(ui, inspector));
+}
+
+fn filters_ui(ui: &mut egui::Ui, inspector: &mut Inspector) {
+    ui.heading("Filters");
+    ui.add(egui::TextEdit::singleline(&mut inspector.search).hint_text("search"));
+    ui.checkbox(&mut inspector.show_inbound, "Server to client");
+    ui.checkbox(&mut inspector.show_outbound, "Client to server");
+    ui.horizontal(|ui| {
+        if ui.button("All").clicked() {
+            inspector.hidden_types.clear();
+        }
+        if ui.button("None").clicked() {
+            inspector.hidden_types = inspector.types.keys().copied().collect();
+        }
+    });
+    ui.separator();
+    egui::ScrollArea::vertical()
+        .id_salt("proxy_types")
+        .show(ui, |ui| {
+            for (&key, &(name, count)) in &inspector.types {
+                let mut shown = !inspector.hidden_types.contains(&key);
+                let label = format!(
+                    "{} 0x{:02X} {} ({})",
+                    key.0,
+                    key.1,
+                    name.unwrap_or("?"),
+                    count
+                );
+                if ui.checkbox(&mut shown, label).changed() {
+                    if shown {
+                        inspector.hidden_types.remove(&key);
+                    } else {
+                        inspector.hidden_types.insert(key);
+                    }
+                }
+            }
+            if !inspector.notes.is_empty() {
+                ui.separator();
+                ui.label("Connections");
+                for note in &inspector.notes {
+                    ui.label(egui::RichText::new(note).small());
+                }
+            }
+        });
+}
+
+fn rules_ui(ui: &mut egui::Ui, inspector: &mut Inspector) {
+    ui.heading("Rules");
+    let Ok(mut rules) = inspector.proxy.rules.lock() else {
+        return;
+    };
+    let mut remove = None;
+    for (i, rule) in rules.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut rule.enabled, "");
+            ui.label(egui::RichText::new(rule.describe()).monospace());
+            ui.label(format!("{} hits", rule.hits));
+            if ui.small_button("x").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        rules.remove(i);
+    }
+    ui.separator();
+    let response = ui.add(
+        egui::TextEdit::singleline(&mut inspector.new_rule)
+            .hint_text("drop in 0x1C")
+            .font(egui::TextStyle::Monospace),
+    );
+    let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    if ui.button("Add rule").clicked() || submitted {
+        match ProxyRule::parse(&inspector.new_rule) {
+            Ok(rule) => {
+                rules.push(rule);
+                inspector.new_rule.clear();
+                inspector.rule_error = None;
+            }
+            Err(err) => inspector.rule_error = Some(err),
+        }
+    }
+    if let Some(err) = &inspector.rule_error {
+        ui.colored_label(egui::Color32::LIGHT_RED, err);
+    }
+}
+
+fn packet_list_ui(ui: &mut egui::Ui, inspector: &mut Inspector) {
+    let visible: Vec<usize> = (0..inspector.packets.len())
+        .filter(|&i| inspector.visible(&inspector.packets[i]))
+        .collect();
+    let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+    egui::ScrollArea::vertical()
+        .auto_shrink(false)
+        .stick_to_bottom(inspector.autoscroll)
+        .show_rows(ui, row_height, visible.len(), |ui, range| {
+            for &i in &visible[range] {
+                let packet = &inspector.packets[i];
+                let color = match packet.verdict {
+                    Verdict::Forwarded => match packet.direction {
+                        Direction::Inbound => egui::Color32::LIGHT_BLUE,
+                        Direction::Outbound => egui::Color32::LIGHT_GREEN,
+                    },
+                    Verdict::Dropped => egui::Color32::LIGHT_RED,
+                    Verdict::Modified => egui::Color32::YELLOW,
+                };
+                let text = egui::RichText::new(packet_line(packet))
+                    .monospace()
+                    .color(color);
+                if ui
+                    .selectable_label(inspector.selected == Some(i), text)
+                    .clicked()
+                {
+                    inspector.selected = Some(i);
+                }
+            }
+        });
+}
+
+fn detail_ui(ui: &mut egui::Ui, inspector: &mut Inspector) {
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut inspector.view, DetailView::Structured, "Structured");
+        ui.selectable_value(&mut inspector.view, DetailView::Hex, "Hex");
+    });
+    ui.separator();
+    let Some(packet) = inspector.selected.and_then(|i| inspector.packets.get(i)) else {
+        ui.label("Select a packet");
+        return;
+    };
+    egui::ScrollArea::vertical()
+        .id_salt("proxy_detail")
+        .auto_shrink(false)
+        .show(ui, |ui| match inspector.view {
+            DetailView::Structured => {
+                egui::Grid::new("proxy_fields")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let fields = [
+                            ("connection", format!("#{}", packet.connection)),
+                            ("time", format!("{:.3}s", packet.time.as_secs_f64())),
+                            ("direction", direction_arrow(packet.direction).to_string()),
+                            ("state", packet.state.to_string()),
+                            ("packet", packet_label(packet)),
+                            ("length", format!("{} bytes", packet.payload.len())),
+                            ("verdict", packet.verdict.name().to_string()),
+                            ("fields", packet.summary.clone().unwrap_or_default()),
+                        ];
+                        for (name, value) in fields {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+            }
+            DetailView::Hex => {
+                ui.label(egui::RichText::new(hex_dump(&packet.payload)).monospace());
+                if ui.button("Copy hex").clicked() {
+                    ui.ctx().copy_text(to_hex(&packet.payload));
+                }
+            }
+        });
+}
+
+/// Classic 16 bytes per line dump with offsets and printable characters.
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "{:08x}  {:<48} {}\n",
+            line * 16,
+            hex.join(" "),
+            text
+        ));
+    }
+    out
+}
@@ -0,0 +1,627 @@
+//! Names and properties behind block states. States are edition-local numbers, so physics and
+//! lighting first turn them into a `BlockDescription` here. Bedrock's runtime ids are hashes
+//! of the state, which are computed for the blocks the client cares about; Java's ids are
+//! only listed in the block report of the vanilla data generator, read from
+//! `paths::block_report()` when it is there. States that aren't described fall back to air or
+//! a plain solid block.
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::net::bedrock::chunk::{block_state, fnv1a_32};
+use crate::net::session::BlockFace;
+use crate::net::{ConnectionState, Edition};
+use crate::paths;
+use crate::world::BlockState;
+
+/// A block state as the server's data names it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockDescription {
+    /// Namespaced, e.g. `minecraft:oak_slab`.
+    pub name: String,
+    pub properties: Vec<(String, String)>,
+}
+
+impl BlockDescription {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            properties: Vec::new(),
+        }
+    }
+
+    /// The name without its `minecraft:` namespace.
+    pub fn id(&self) -> &str {
+        self.name.strip_prefix("minecraft:").unwrap_or(&self.name)
+    }
+
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the block sits in the upper half of its space: top slabs and upside-down
+    /// stairs.
+    pub fn upper_half(&self) -> bool {
+        self.property("half") == Some("top")
+            || self.property("type") == Some("top")
+            || self.property("upside_down_bit") == Some("1")
+            || self.property("minecraft:vertical_half") == Some("top")
+    }
+
+    /// Whether the block fills its whole space: double slabs.
+    pub fn double(&self) -> bool {
+        self.property("type") == Some("double") || self.id().ends_with("_double_slab")
+    }
+
+    /// The horizontal direction the block faces, from whichever property the edition uses.
+    pub fn facing(&self) -> Option<BlockFace> {
+        if let Some(facing) = self
+            .property("facing")
+            .or_else(|| self.property("minecraft:cardinal_direction"))
+        {
+            return facing.parse().ok();
+        }
+        if let Some(direction) = self.property("weirdo_direction") {
+            return match direction {
+                "0" => Some(BlockFace::East),
+                "1" => Some(BlockFace::West),
+                "2" => Some(BlockFace::South),
+                "3" => Some(BlockFace::North),
+                _ => None,
+            };
+        }
+        match self.property("facing_direction")? {
+            "2" => Some(BlockFace::North),
+            "3" => Some(BlockFace::South),
+            "4" => Some(BlockFace::West),
+            "5" => Some(BlockFace::East),
+            _ => None,
+        }
+    }
+}
+
+/// The descriptions of the current edition's block states.
+#[derive(Resource, Default)]
+pub struct BlockRegistry {
+    pub edition: Option<Edition>,
+    states: HashMap<BlockState, BlockDescription>,
+}
+
+impl BlockRegistry {
+    pub fn get(&self, state: BlockState) -> Option<&BlockDescription> {
+        self.states.get(&state)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockState, &BlockDescription)> {
+        self.states.iter().map(|(state, block)| (*state, block))
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// The registry for `edition`, from what this edition makes available.
+    pub fn load(edition: Edition) -> Self {
+        match edition {
+            Edition::Bedrock => Self::bedrock(),
+            Edition::Java => {
+                let path = paths::block_report();
+                let registry = match std::fs::read_to_string(&path) {
+                    Ok(report) => Self::java(&report).unwrap_or_else(|err| {
+                        warn!("Cannot read {}: {}", path.display(), err);
+                        Self::default()
+                    }),
+                    Err(_) => {
+                        info!(
+                            "No Java block report at {}; blocks will be plain cubes",
+                            path.display()
+                        );
+                        Self::default()
+                    }
+                };
+                Self {
+                    edition: Some(Edition::Java),
+                    ..registry
+                }
+            }
+        }
+    }
+
+    /// Hashed Bedrock runtime ids of the blocks in `BEDROCK_BLOCKS`.
+    pub fn bedrock() -> Self {
+        let mut states = HashMap::new();
+        for (names, properties) in BEDROCK_BLOCKS {
+            for name in *names {
+                let name = format!("minecraft:{}", name);
+                for values in combinations(properties) {
+                    let hash = bedrock_hash(&name, properties, &values);
+                    let properties = properties
+                        .iter()
+                        .zip(&values)
+                        .map(|((key, _), value)| (key.to_string(), value.to_string()))
+                        .collect();
+                    states.insert(
+                        block_state(hash),
+                        BlockDescription {
+                            name: name.clone(),
+                            properties,
+                        },
+                    );
+                }
+            }
+        }
+        Self {
+            edition: Some(Edition::Bedrock),
+            states,
+        }
+    }
+
+    /// Every state of a Java block report: `{"minecraft:stone": {"states": [{"id": 1,
+    /// "properties": {...}}]}}`.
+    pub fn java(report: &str) -> Result<Self, String> {
+        let report: serde_json::Value = serde_json::from_str(report).map_err(|e| e.to_string())?;
+        let blocks = report.as_object().ok_or("not an object of blocks")?;
+        let mut states = HashMap::new();
+        for (name, block) in blocks {
+            let list = block
+                .get("states")
+                .and_then(|s| s.as_array())
+                .ok_or_else(|| format!("{} has no states", name))?;
+            for state in list {
+                let id = state
+                    .get("id")
+                    .and_then(|id| id.as_u64())
+                    .ok_or_else(|| format!("{} has a state without an id", name))?;
+                let properties = state
+                    .get("properties")
+                    .and_then(|p| p.as_object())
+                    .map(|p| {
+                        p.iter()
+                            .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                states.insert(
+                    BlockState(id as u32),
+                    BlockDescription {
+                        name: name.clone(),
+                        properties,
+                    },
+                );
+            }
+        }
+        Ok(Self {
+            edition: Some(Edition::Java),
+            states,
+        })
+    }
+}
+
+/// The values one Bedrock block state property takes.
+#[derive(Clone, Copy, Debug)]
+enum Values {
+    Byte,
+    Int(i32),
+    Str(&'static [&'static str]),
+}
+
+impl Values {
+    fn count(self) -> usize {
+        match self {
+            Values::Byte => 2,
+            Values::Int(count) => count as usize,
+            Values::Str(values) => values.len(),
+        }
+    }
+}
+
+type BedrockBlock = (&'static [&'static str], &'static [(&'static str, Values)]);
+
+macro_rules! woods {
+    ($suffix:literal) => {
+        &[
+            concat!("oak", $suffix),
+            concat!("spruce", $suffix),
+            concat!("birch", $suffix),
+            concat!("jungle", $suffix),
+            concat!("acacia", $suffix),
+            concat!("dark_oak", $suffix),
+            concat!("mangrove", $suffix),
+            concat!("cherry", $suffix),
+            concat!("pale_oak", $suffix),
+            concat!("bamboo", $suffix),
+            concat!("crimson", $suffix),
+            concat!("warped", $suffix),
+        ]
+    };
+}
+
+const STONE_SLABS: &[&str] = &[
+    "normal_stone_slab",
+    "smooth_stone_slab",
+    "cobblestone_slab",
+    "mossy_cobblestone_slab",
+    "stone_brick_slab",
+    "mossy_stone_brick_slab",
+    "sandstone_slab",
+    "cut_sandstone_slab",
+    "smooth_sandstone_slab",
+    "red_sandstone_slab",
+    "brick_slab",
+    "nether_brick_slab",
+    "red_nether_brick_slab",
+    "quartz_slab",
+    "smooth_quartz_slab",
+    "granite_slab",
+    "polished_granite_slab",
+    "diorite_slab",
+    "polished_diorite_slab",
+    "andesite_slab",
+    "polished_andesite_slab",
+    "prismarine_slab",
+    "dark_prismarine_slab",
+    "prismarine_brick_slab",
+    "purpur_slab",
+    "end_stone_brick_slab",
+    "blackstone_slab",
+    "polished_blackstone_slab",
+    "polished_blackstone_brick_slab",
+    "cobbled_deepslate_slab",
+    "polished_deepslate_slab",
+    "deepslate_brick_slab",
+    "deepslate_tile_slab",
+    "mud_brick_slab",
+    "tuff_slab",
+    "polished_tuff_slab",
+    "tuff_brick_slab",
+];
+
+const STONE_STAIRS: &[&str] = &[
+    "stone_stairs",
+    "normal_stone_stairs",
+    "mossy_cobblestone_stairs",
+    "stone_brick_stairs",
+    "mossy_stone_brick_stairs",
+    "sandstone_stairs",
+    "smooth_sandstone_stairs",
+    "red_sandstone_stairs",
+    "brick_stairs",
+    "nether_brick_stairs",
+    "red_nether_brick_stairs",
+    "quartz_stairs",
+    "smooth_quartz_stairs",
+    "granite_stairs",
+    "polished_granite_stairs",
+    "diorite_stairs",
+    "polished_diorite_stairs",
+    "andesite_stairs",
+    "polished_andesite_stairs",
+    "prismarine_stairs",
+    "dark_prismarine_stairs",
+    "prismarine_bricks_stairs",
+    "purpur_stairs",
+    "end_brick_stairs",
+    "blackstone_stairs",
+    "polished_blackstone_stairs",
+    "polished_blackstone_brick_stairs",
+    "cobbled_deepslate_stairs",
+    "polished_deepslate_stairs",
+    "deepslate_brick_stairs",
+    "deepslate_tile_stairs",
+    "mud_brick_stairs",
+    "tuff_stairs",
+    "polished_tuff_stairs",
+    "tuff_brick_stairs",
+];
+
+const HALF: (&str, Values) = ("minecraft:vertical_half", Values::Str(&["bottom", "top"]));
+const STAIRS: &[(&str, Values)] = &[
+    ("upside_down_bit", Values::Byte),
+    ("weirdo_direction", Values::Int(4)),
+];
+const UPPER_BLOCK: &[(&str, Values)] = &[("upper_block_bit", Values::Byte)];
+
+/// Bedrock blocks by name with their state properties, as of 1.21. Blocks whose states
+/// changed in the supported versions are listed under both names.
+const BEDROCK_BLOCKS: &[BedrockBlock] = &[
+    (&["air"], &[]),
+    (
+        &[
+            "stone",
+            "granite",
+            "diorite",
+            "andesite",
+            "cobblestone",
+            "mossy_cobblestone",
+            "dirt",
+            "coarse_dirt",
+            "grass_block",
+            "sand",
+            "red_sand",
+            "gravel",
+            "clay",
+            "netherrack",
+            "end_stone",
+            "obsidian",
+            "crying_obsidian",
+            "iron_block",
+            "gold_block",
+            "diamond_block",
+            "glass",
+            "glass_pane",
+            "glowstone",
+            "sea_lantern",
+            "ice",
+            "packed_ice",
+            "blue_ice",
+            "barrier",
+        ],
+        &[],
+    ),
+    (woods!("_planks"), &[]),
+    (&["bedrock"], &[("infiniburn_bit", Values::Byte)]),
+    (
+        &[
+            "oak_leaves",
+            "spruce_leaves",
+            "birch_leaves",
+            "jungle_leaves",
+            "acacia_leaves",
+            "dark_oak_leaves",
+            "mangrove_leaves",
+            "cherry_leaves",
+            "pale_oak_leaves",
+            "azalea_leaves",
+            "azalea_leaves_flowered",
+        ],
+        &[
+            ("persistent_bit", Values::Byte),
+            ("update_bit", Values::Byte),
+        ],
+    ),
+    (
+        &[
+            "torch",
+            "soul_torch",
+            "redstone_torch",
+            "unlit_redstone_torch",
+        ],
+        &[(
+            "torch_facing_direction",
+            Values::Str(&["unknown", "west", "east", "north", "south", "top"]),
+        )],
+    ),
+    (&["lantern", "soul_lantern"], &[("hanging", Values::Byte)]),
+    (
+        &["water", "flowing_water", "lava", "flowing_lava"],
+        &[("liquid_depth", Values::Int(16))],
+    ),
+    (&["ladder"], &[("facing_direction", Values::Int(6))]),
+    (&["vine"], &[("vine_direction_bits", Values::Int(16))]),
+    (
+        &["weeping_vines"],
+        &[("weeping_vines_age", Values::Int(26))],
+    ),
+    (
+        &["twisting_vines"],
+        &[("twisting_vines_age", Values::Int(26))],
+    ),
+    (woods!("_slab"), &[HALF]),
+    (woods!("_double_slab"), &[HALF]),
+    (STONE_SLABS, &[HALF]),
+    (woods!("_stairs"), STAIRS),
+    (STONE_STAIRS, STAIRS),
+    (
+        &[
+            "short_grass",
+            "fern",
+            "dandelion",
+            "poppy",
+            "blue_orchid",
+            "allium",
+            "azure_bluet",
+            "red_tulip",
+            "orange_tulip",
+            "white_tulip",
+            "pink_tulip",
+            "oxeye_daisy",
+            "cornflower",
+            "lily_of_the_valley",
+            "wither_rose",
+            "torchflower",
+            "deadbush",
+            "brown_mushroom",
+            "red_mushroom",
+            "crimson_roots",
+            "warped_roots",
+            "nether_sprouts",
+        ],
+        &[],
+    ),
+    (
+        &[
+            "tall_grass",
+            "large_fern",
+            "sunflower",
+            "lilac",
+            "rose_bush",
+            "peony",
+        ],
+        UPPER_BLOCK,
+    ),
+    (
+        &[
+            "oak_sapling",
+            "spruce_sapling",
+            "birch_sapling",
+            "jungle_sapling",
+            "acacia_sapling",
+            "dark_oak_sapling",
+            "cherry_sapling",
+            "pale_oak_sapling",
+        ],
+        &[("age_bit", Values::Byte)],
+    ),
+    (&["reeds"], &[("age", Values::Int(16))]),
+    (
+        &["wheat", "carrots", "potatoes", "beetroot"],
+        &[("growth", Values::Int(8))],
+    ),
+    (
+        &["seagrass"],
+        &[(
+            "sea_grass_type",
+            Values::Str(&["default", "double_top", "double_bot"]),
+        )],
+    ),
+    (&["kelp"], &[("kelp_age", Values::Int(26))]),
+];
+
+/// Every combination of values of `properties`, as the strings they hash as.
+fn combinations(properties: &[(&str, Values)]) -> Vec<Vec<String>> {
+    let mut all = vec![Vec::new()];
+    for (_, values) in properties {
+        all = all
+            .into_iter()
+            .flat_map(|done: Vec<String>| {
+                (0..values.count()).map(move |i| {
+                    let mut next = done.clone();
+                    next.push(match values {
+                        Values::Byte | Values::Int(_) => i.to_string(),
+                        Values::Str(values) => values[i].to_string(),
+                    });
+                    next
+                })
+            })
+            .collect();
+    }
+    all
+}
+
+/// The network id Bedrock servers give a block state with `block-network-ids-are-hashes`:
+/// FNV-1a of `{name, states}` in little-endian NBT, states sorted by name.
+fn bedrock_hash(name: &str, properties: &[(&str, Values)], values: &[String]) -> u32 {
+    fn tag(out: &mut Vec<u8>, kind: u8, name: &str) {
+        out.push(kind);
+        out.extend((name.len() as u16).to_le_bytes());
+        out.extend(name.as_bytes());
+    }
+    let mut sorted: Vec<_> = properties.iter().zip(values).collect();
+    sorted.sort_by_key(|((key, _), _)| *key);
+
+    let mut nbt = Vec::new();
+    tag(&mut nbt, 0x0A, "");
+    tag(&mut nbt, 0x08, "name");
+    nbt.extend((name.len() as u16).to_le_bytes());
+    nbt.extend(name.as_bytes());
+    tag(&mut nbt, 0x0A, "states");
+    for ((key, kind), value) in sorted {
+        match kind {
+            Values::Byte => {
+                tag(&mut nbt, 0x01, key);
+                nbt.push(value.parse::<u8>().unwrap_or_default());
+            }
+            Values::Int(_) => {
+                tag(&mut nbt, 0x03, key);
+                nbt.extend(value.parse::<i32>().unwrap_or_default().to_le_bytes());
+            }
+            Values::Str(_) => {
+                tag(&mut nbt, 0x08, key);
+                nbt.extend((value.len() as u16).to_le_bytes());
+                nbt.extend(value.as_bytes());
+            }
+        }
+    }
+    nbt.push(0);
+    nbt.push(0);
+    fnv1a_32(&nbt)
+}
+
+/// Keeps `BlockRegistry` on the edition of the current server.
+pub struct BlocksPlugin;
+
+impl Plugin for BlocksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockRegistry>().add_systems(
+            PreUpdate,
+            block_registry_system.run_if(resource_changed::<ConnectionState>),
+        );
+    }
+}
+
+/// Load the block descriptions of an edition when connecting to a server of it.
+pub fn block_registry_system(state: Res<ConnectionState>, mut registry: ResMut<BlockRegistry>) {
+    let edition = match &*state {
+        ConnectionState::Disconnected => return,
+        ConnectionState::Connecting { edition, .. }
+        | ConnectionState::Connected { edition, .. } => *edition,
+    };
+    if registry.edition != Some(edition) {
+        *registry = BlockRegistry::load(edition);
+        info!("{} block states described for {}", registry.len(), edition);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::bedrock::chunk::AIR_RUNTIME_ID;
+
+    #[test]
+    fn air_hashes_like_the_network_id() {
+        assert_eq!(bedrock_hash("minecraft:air", &[], &[]), AIR_RUNTIME_ID);
+        let registry = BlockRegistry::bedrock();
+        assert_eq!(registry.get(BlockState::AIR).unwrap().id(), "air");
+    }
+
+    #[test]
+    fn bedrock_states_are_all_distinct() {
+        let expected: usize = BEDROCK_BLOCKS
+            .iter()
+            .map(|(names, properties)| names.len() * combinations(properties).len())
+            .sum();
+        assert_eq!(BlockRegistry::bedrock().len(), expected);
+    }
+
+    #[test]
+    fn java_report() {
+        let report = r#"{
+            "minecraft:air": {"states": [{"default": true, "id": 0}]},
+            "minecraft:oak_slab": {
+                "properties": {"type": ["top", "bottom", "double"]},
+                "states": [
+                    {"id": 11, "properties": {"type": "top"}},
+                    {"default": true, "id": 12, "properties": {"type": "bottom"}},
+                    {"id": 13, "properties": {"type": "double"}}
+                ]
+            }
+        }"#;
+        let registry = BlockRegistry::java(report).unwrap();
+        assert_eq!(registry.len(), 4);
+        assert!(registry.get(BlockState(11)).unwrap().upper_half());
+        assert!(!registry.get(BlockState(12)).unwrap().upper_half());
+        assert!(registry.get(BlockState(13)).unwrap().double());
+        assert!(BlockRegistry::java("[]").is_err());
+    }
+
+    #[test]
+    fn facing_from_either_edition() {
+        let java = BlockDescription {
+            name: "minecraft:ladder".into(),
+            properties: vec![("facing".into(), "west".into())],
+        };
+        let bedrock = BlockDescription {
+            name: "minecraft:ladder".into(),
+            properties: vec![("facing_direction".into(), "4".into())],
+        };
+        assert_eq!(java.facing(), Some(BlockFace::West));
+        assert_eq!(bedrock.facing(), Some(BlockFace::West));
+    }
+}
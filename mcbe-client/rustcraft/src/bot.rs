@@ -0,0 +1,477 @@
+use bevy::math::{DVec3, IVec3, Vec3};
+use crossbeam_channel::RecvTimeoutError;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::config::validate_username;
+use crate::net::session::{
+    BlockFace, PlayerAction, RawPacket, Session, SessionEvent, SessionOptions,
+};
+use crate::net::stats::NetStatsSnapshot;
+use crate::net::{Edition, NetError, ServerAddress};
+
+/// Vanilla walking speed in blocks per second.
+pub const WALK_SPEED: f64 = 4.317;
+/// Movement packets are sent once per game tick.
+const TICK: Duration = Duration::from_millis(50);
+/// Unconsumed events kept for later assertions.
+const MAX_BACKLOG: usize = 4096;
+/// Player eye height when standing.
+const EYE_HEIGHT: f64 = 1.62;
+
+/// Parse a duration such as `500ms`, `2s` or `1.5` (seconds).
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = value.strip_suffix('s') {
+        (s, 1.0)
+    } else {
+        (value, 1.0)
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .map(|n| Duration::from_secs_f64(n * scale))
+        .ok_or_else(|| format!("bad duration '{}'", value))
+}
+
+/// A scripted client for integration tests: every call blocks until it's done.
+/// Events that arrive while the bot is busy are kept, so assertions can
+/// match packets that were received earlier.
+pub struct Bot {
+    session: Session,
+    pub username: String,
+    pub entity_id: Option<i64>,
+    pub position: Option<DVec3>,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Number of times the server moved us after spawning.
+    pub teleports: u32,
+    backlog: VecDeque<SessionEvent>,
+}
+
+impl Bot {
+    /// Start connecting with an offline username; use `wait_for_spawn` to finish joining.
+    pub fn connect(address: &str, edition: Edition, username: &str) -> Result<Self, NetError> {
+        validate_username(username).map_err(NetError::Protocol)?;
+        let address = ServerAddress::parse(address, edition).map_err(NetError::Protocol)?;
+        let options = SessionOptions {
+            username: username.to_string(),
+            forward_packets: true,
+            ..Default::default()
+        };
+        Ok(Self::over(
+            Session::connect(address, edition, options),
+            username,
+        ))
+    }
+
+    /// Play back the server side of a capture instead of joining a server, `speed` times as
+    /// fast as it was recorded; what the bot sends goes nowhere.
+    pub fn replay(path: &Path, speed: f64) -> Result<Self, NetError> {
+        let options = SessionOptions {
+            forward_packets: true,
+            ..Default::default()
+        };
+        let username = options.username.clone();
+        Ok(Self::over(
+            Session::replay(path, speed, options)?,
+            &username,
+        ))
+    }
+
+    fn over(session: Session, username: &str) -> Self {
+        Self {
+            session,
+            username: username.to_string(),
+            entity_id: None,
+            position: None,
+            yaw: 0.0,
+            pitch: 0.0,
+            teleports: 0,
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// Track state from an event and keep it for later assertions.
+    fn record(&mut self, event: SessionEvent) -> Result<(), NetError> {
+        match &event {
+            SessionEvent::Joined { entity_id } => self.entity_id = Some(*entity_id),
+            SessionEvent::Spawned {
+                position,
+                yaw,
+                pitch,
+            }
+            | SessionEvent::Teleported {
+                position,
+                yaw,
+                pitch,
+            } => {
+                if matches!(event, SessionEvent::Teleported { .. }) {
+                    self.teleports += 1;
+                }
+                self.position = Some(*position);
+                self.yaw = *yaw;
+                self.pitch = *pitch;
+            }
+            SessionEvent::Disconnected { reason } => {
+                return Err(NetError::Disconnected(reason.clone()));
+            }
+            _ => {}
+        }
+        if self.backlog.len() >= MAX_BACKLOG {
+            self.backlog.pop_front();
+        }
+        self.backlog.push_back(event);
+        Ok(())
+    }
+
+    /// Take in everything received so far without waiting.
+    pub fn pump(&mut self) -> Result<(), NetError> {
+        while let Ok(event) = self.session.events().try_recv() {
+            self.record(event)?;
+        }
+        Ok(())
+    }
+
+    /// Wait until `matches` accepts an event (earlier ones included) and remove it from the backlog.
+    pub fn wait_for<T>(
+        &mut self,
+        timeout: Duration,
+        what: &str,
+        mut matches: impl FnMut(&SessionEvent) -> Option<T>,
+    ) -> Result<T, NetError> {
+        let deadline = Instant::now() + timeout;
+        let mut checked = 0;
+        loop {
+            while checked < self.backlog.len() {
+                if let Some(found) = matches(&self.backlog[checked]) {
+                    self.backlog.remove(checked);
+                    return Ok(found);
+                }
+                checked += 1;
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.session.events().recv_timeout(left) {
+                Ok(event) => self.record(event)?,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(NetError::Timeout(format!(
+                        "{} not received within {:?}",
+                        what, timeout
+                    )));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(NetError::Disconnected("session ended".into()));
+                }
+            }
+        }
+    }
+
+    /// Block until the server has placed us in the world.
+    pub fn wait_for_spawn(&mut self, timeout: Duration) -> Result<DVec3, NetError> {
+        self.wait_for(timeout, "spawn", |event| match event {
+            SessionEvent::Spawned { position, .. } => Some(*position),
+            _ => None,
+        })
+    }
+
+    /// Let the session run for `duration`, collecting events.
+    pub fn sleep(&mut self, duration: Duration) -> Result<(), NetError> {
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.session.events().recv_timeout(left) {
+                Ok(event) => self.record(event)?,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(NetError::Disconnected("session ended".into()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn send(&self, action: PlayerAction) {
+        self.session.send(action);
+    }
+
+    pub fn chat(&self, message: &str) {
+        self.send(PlayerAction::Chat(message.to_string()));
+    }
+
+    /// Run a command; the leading `/` is optional.
+    pub fn command(&self, command: &str) {
+        self.send(PlayerAction::Command(
+            command.trim_start_matches('/').to_string(),
+        ));
+    }
+
+    fn require_position(&self) -> Result<DVec3, NetError> {
+        self.position
+            .ok_or_else(|| NetError::Protocol("bot has not spawned yet".into()))
+    }
+
+    fn send_move(&self, position: DVec3) {
+        self.send(PlayerAction::Move {
+            position,
+            yaw: self.yaw,
+            pitch: self.pitch,
+            on_ground: true,
+        });
+    }
+
+    /// Turn to face `target` from eye height.
+    pub fn look_at(&mut self, target: DVec3) -> Result<(), NetError> {
+        let eye = self.require_position()? + DVec3::Y * EYE_HEIGHT;
+        let delta = target - eye;
+        let horizontal = (delta.x * delta.x + delta.z * delta.z).sqrt();
+        self.yaw = (-delta.x).atan2(delta.z).to_degrees() as f32;
+        self.pitch = (-delta.y).atan2(horizontal).to_degrees() as f32;
+        self.send_move(self.require_position()?);
+        Ok(())
+    }
+
+    /// Walk in a straight line at walking speed, one movement packet per tick.
+    /// There is no pathfinding; fails if the server corrects our position.
+    pub fn walk_to(&mut self, target: DVec3) -> Result<(), NetError> {
+        let start = self.require_position()?;
+        let delta = target - start;
+        let distance = delta.length();
+        let steps = (distance / (WALK_SPEED * TICK.as_secs_f64()))
+            .ceil()
+            .max(1.0) as u32;
+
+        let horizontal = DVec3::new(delta.x, 0.0, delta.z);
+        if horizontal.length_squared() > 1e-6 {
+            self.yaw = (-horizontal.x).atan2(horizontal.z).to_degrees() as f32;
+        }
+
+        let teleports = self.teleports;
+        for step in 1..=steps {
+            let position = start + delta * (step as f64 / steps as f64);
+            self.send_move(position);
+            self.position = Some(position);
+            self.sleep(TICK)?;
+
+            if self.teleports != teleports {
+                return Err(NetError::Protocol(format!(
+                    "server rejected movement towards {} at step {}/{}",
+                    target, step, steps
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Break the block at `pos`. Instant in creative mode; in survival the
+    /// server decides whether enough time has passed.
+    pub fn break_block(&mut self, pos: IVec3) -> Result<(), NetError> {
+        self.look_at(pos.as_dvec3() + DVec3::splat(0.5))?;
+        let face = self.facing_side(pos)?;
+        self.send(PlayerAction::StartBreaking { pos, face });
+        self.send(PlayerAction::SwingArm);
+        self.send(PlayerAction::FinishBreaking { pos, face });
+        Ok(())
+    }
+
+    /// Place the held item against `face` of the block at `against`.
+    pub fn place_block(&mut self, against: IVec3, face: BlockFace) -> Result<(), NetError> {
+        let offset = face.offset().as_dvec3() * 0.5;
+        self.look_at(against.as_dvec3() + DVec3::splat(0.5) + offset)?;
+        let cursor = Vec3::splat(0.5) + offset.as_vec3();
+        self.send(PlayerAction::Place {
+            pos: against,
+            face,
+            cursor,
+        });
+        self.send(PlayerAction::SwingArm);
+        Ok(())
+    }
+
+    /// The face of the block at `pos` that points most directly at the bot.
+    fn facing_side(&self, pos: IVec3) -> Result<BlockFace, NetError> {
+        let eye = self.require_position()? + DVec3::Y * EYE_HEIGHT;
+        let to_eye = eye - (pos.as_dvec3() + DVec3::splat(0.5));
+        Ok(BlockFace::ALL
+            .into_iter()
+            .max_by(|a, b| {
+                let da = a.offset().as_dvec3().dot(to_eye);
+                let db = b.offset().as_dvec3().dot(to_eye);
+                da.total_cmp(&db)
+            })
+            .unwrap_or(BlockFace::Up))
+    }
+
+    /// Wait for a chat or system message containing `text`.
+    pub fn expect_chat(&mut self, text: &str, timeout: Duration) -> Result<String, NetError> {
+        self.wait_for(
+            timeout,
+            &format!("chat containing '{}'", text),
+            |event| match event {
+                SessionEvent::Chat { message, .. } if message.contains(text) => {
+                    Some(message.clone())
+                }
+                _ => None,
+            },
+        )
+    }
+
+    /// Wait for a packet with the given id (in the play state) and return it.
+    pub fn expect_packet(&mut self, id: i32, timeout: Duration) -> Result<RawPacket, NetError> {
+        self.expect_packet_where(id, timeout, |_| true)
+    }
+
+    /// Wait for a play packet with the given id whose payload satisfies `check`.
+    pub fn expect_packet_where(
+        &mut self,
+        id: i32,
+        timeout: Duration,
+        mut check: impl FnMut(&[u8]) -> bool,
+    ) -> Result<RawPacket, NetError> {
+        self.wait_for(
+            timeout,
+            &format!("packet 0x{:02X}", id),
+            |event| match event {
+                SessionEvent::Packet(packet)
+                    if packet.state == "play" && packet.id == id && check(&packet.payload) =>
+                {
+                    Some(packet.clone())
+                }
+                _ => None,
+            },
+        )
+    }
+
+    /// Wait for the block at `pos` to change, returning its new state id.
+    pub fn expect_block_change(&mut self, pos: IVec3, timeout: Duration) -> Result<u32, NetError> {
+        self.wait_for(
+            timeout,
+            &format!("block change at {}", pos),
+            |event| match event {
+                SessionEvent::BlockChanged { pos: p, state } if *p == pos => Some(*state),
+                _ => None,
+            },
+        )
+    }
+
+    /// Traffic metrics of the bot's session.
+    pub fn stats(&self) -> NetStatsSnapshot {
+        self.session.stats()
+    }
+
+    pub fn disconnect(self) {
+        self.session.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::capture::{CaptureRecord, Direction, read_capture, to_hex};
+    use crate::net::codec::PacketWriter;
+    use crate::net::java::ids;
+    use std::path::PathBuf;
+
+    /// A copy of the Java fixture with a block update before its chat line and the server
+    /// only closing after a minute, removed again when dropped.
+    struct TestCapture(PathBuf);
+
+    impl TestCapture {
+        fn new(name: &str) -> Self {
+            let fixture =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/java/session.jsonl");
+            let (header, mut records) = read_capture(&fixture).unwrap();
+            let chat = records
+                .iter()
+                .position(|r| r.name.as_deref() == Some("system_chat"))
+                .unwrap();
+            let mut update = PacketWriter::new();
+            update.position(IVec3::new(3, 64, -7)).varint(42);
+            records.insert(
+                chat,
+                CaptureRecord {
+                    id: ids::play::BLOCK_UPDATE,
+                    name: Some("block_update".into()),
+                    length: update.buf.len(),
+                    payload: Some(to_hex(&update.buf)),
+                    decoded: None,
+                    ..records[chat].clone()
+                },
+            );
+            let closing = records.last_mut().unwrap();
+            assert_eq!(closing.direction, Direction::Inbound);
+            closing.time_ms = 60_000.0;
+
+            let path = std::env::temp_dir().join(format!(
+                "rustcraft-bot-{}-{}.jsonl",
+                name,
+                std::process::id()
+            ));
+            let mut text = serde_json::to_string(&header).unwrap();
+            for record in &records {
+                text.push('\n');
+                text.push_str(&serde_json::to_string(record).unwrap());
+            }
+            std::fs::write(&path, text + "\n").unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TestCapture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn expectations_match_replayed_events() {
+        let capture = TestCapture::new("expect");
+        let mut bot = Bot::replay(&capture.0, 1.0).unwrap();
+
+        let spawn = bot.wait_for_spawn(TIMEOUT).unwrap();
+        assert_eq!(spawn, DVec3::new(8.5, 64.0, -3.5));
+        assert_eq!(bot.position, Some(spawn));
+        assert_eq!(bot.entity_id, Some(42));
+
+        assert_eq!(
+            bot.expect_chat("fixture", TIMEOUT).unwrap(),
+            "Welcome to the fixture"
+        );
+        // the update came first and waited in the backlog
+        assert_eq!(
+            bot.expect_block_change(IVec3::new(3, 64, -7), Duration::ZERO)
+                .unwrap(),
+            42
+        );
+        // matched events are used up
+        assert!(matches!(
+            bot.expect_chat("fixture", Duration::from_millis(50)),
+            Err(NetError::Timeout(_))
+        ));
+        bot.disconnect();
+    }
+
+    #[test]
+    fn expectations_time_out() {
+        let capture = TestCapture::new("timeout");
+        let mut bot = Bot::replay(&capture.0, 1.0).unwrap();
+        bot.expect_chat("Welcome", TIMEOUT).unwrap();
+
+        let started = Instant::now();
+        let err = bot
+            .expect_block_change(IVec3::ZERO, Duration::from_millis(200))
+            .unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(
+            matches!(&err, NetError::Timeout(what) if what.contains("block change at")),
+            "{}",
+            err
+        );
+        bot.disconnect();
+    }
+}
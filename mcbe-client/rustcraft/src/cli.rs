@@ -0,0 +1,467 @@
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PresentMode, WindowMode};
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::config::{
+    ResolvedConfig, fps_cap_name, parse_fps_cap, parse_present_mode, present_mode_name,
+    validate_username, window_mode_name,
+};
+use crate::data::FpsMode;
+use crate::net::capture::CaptureSettings;
+use crate::net::{ConnectRequest, Edition, ReplayRequest, ServerAddress};
+
+pub const USAGE: &str = "\
+Usage: rustcraft [OPTIONS]
+
+Options:
+  --server <host[:port]>     Join a server on startup; IPv6 addresses go in brackets
+  --connect <host[:port]>    Same as --server
+  --edition <bedrock|java>   Protocol used by --server (default: bedrock)
+  --config <file>            Read settings from this file instead of the default one
+  --fps-cap <n|vsync|uncapped>
+                             Frame rate cap, 1-1000
+  --present-mode <mode>      auto_vsync, auto_no_vsync, fifo, fifo_relaxed, immediate or mailbox
+  --windowed                 Start in a window
+  --fullscreen               Start in borderless fullscreen
+  --username <name>          Offline mode username (3-16 letters, digits or _)
+  --headless                 Run without a window or renderer; console commands are read from stdin
+  --capture <file|dir>       Record the packets of every session
+  --replay <file>            Play back a capture instead of joining a server
+  -h, --help                 Print this help
+  -V, --version              Print the version
+
+Options only apply to this run; saved settings are left unchanged.
+Settings are also read from game.config, the user settings file and
+RUSTCRAFT_* environment variables (e.g. RUSTCRAFT_RENDER_DISTANCE=12).";
+
+/// Bad command line, printed together with a pointer to `--help`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\n\nRun 'rustcraft --help' for the list of options.",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// What the command line asks for besides running the game.
+#[derive(Debug)]
+pub enum CliAction {
+    Run(Box<CliArgs>),
+    Help,
+    Version,
+}
+
+/// Command line overrides for this run.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct CliArgs {
+    pub server: Option<ServerAddress>,
+    pub edition: Option<Edition>,
+    pub config: Option<PathBuf>,
+    pub fps_cap: Option<FpsMode>,
+    pub present_mode: Option<PresentMode>,
+    pub window_mode: Option<WindowMode>,
+    pub username: Option<String>,
+    pub headless: bool,
+    pub capture: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+}
+
+impl CliArgs {
+    /// Parse the process arguments.
+    pub fn from_env() -> Result<CliAction, CliError> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parse arguments (without the program name). Accepts `--opt value` and `--opt=value`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<CliAction, CliError> {
+        let mut cli = CliArgs::default();
+        let mut server = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => {
+                    (name.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| CliError(format!("{} needs a value", name)))
+            };
+
+            match name.as_str() {
+                "-h" | "--help" => return Ok(CliAction::Help),
+                "-V" | "--version" => return Ok(CliAction::Version),
+                // parsed once the edition is known, so the right default port is used
+                "--server" | "--connect" => server = Some((value()?, name.clone())),
+                "--edition" => {
+                    cli.edition = Some(
+                        value()?
+                            .parse()
+                            .map_err(|err| CliError(format!("--edition: {}", err)))?,
+                    )
+                }
+                // refused rather than ignored until local worlds can be opened
+                "--world" => {
+                    return Err(CliError(
+                        "--world: local worlds are not supported yet".into(),
+                    ));
+                }
+                "--config" => {
+                    let path = PathBuf::from(value()?);
+                    if !path.is_file() {
+                        return Err(CliError(format!(
+                            "--config: '{}' does not exist",
+                            path.display()
+                        )));
+                    }
+                    cli.config = Some(path);
+                }
+                "--fps-cap" => {
+                    cli.fps_cap = Some(
+                        parse_fps_cap(&value()?)
+                            .map_err(|err| CliError(format!("--fps-cap: {}", err)))?,
+                    )
+                }
+                "--present-mode" => {
+                    cli.present_mode = Some(
+                        parse_present_mode(&value()?)
+                            .map_err(|err| CliError(format!("--present-mode: {}", err)))?,
+                    )
+                }
+                "--windowed" | "--fullscreen" => {
+                    if cli.window_mode.is_some() {
+                        return Err(CliError(
+                            "--windowed and --fullscreen can't be used together".into(),
+                        ));
+                    }
+                    cli.window_mode = Some(if name == "--windowed" {
+                        WindowMode::Windowed
+                    } else {
+                        // borderless, so alt-tabbing doesn't change the display mode
+                        WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+                    });
+                }
+                "--username" => {
+                    let name = value()?;
+                    validate_username(&name)
+                        .map_err(|err| CliError(format!("--username: {}", err)))?;
+                    cli.username = Some(name);
+                }
+                "--headless" => cli.headless = true,
+                "--capture" => cli.capture = Some(PathBuf::from(value()?)),
+                "--replay" => {
+                    let path = PathBuf::from(value()?);
+                    if !path.is_file() {
+                        return Err(CliError(format!(
+                            "--replay: '{}' does not exist",
+                            path.display()
+                        )));
+                    }
+                    cli.replay = Some(path);
+                }
+                other => return Err(CliError(format!("unknown option '{}'", other))),
+            }
+        }
+
+        if cli.replay.is_some() && server.is_some() {
+            return Err(CliError(
+                "--server and --replay can't be used together".into(),
+            ));
+        }
+        if let Some((server, option)) = server {
+            let edition = cli.edition.unwrap_or_default();
+            cli.server = Some(
+                ServerAddress::parse(&server, edition)
+                    .map_err(|err| CliError(format!("{}: {}", option, err)))?,
+            );
+        }
+        Ok(CliAction::Run(Box::new(cli)))
+    }
+
+    /// Values for the command line config layer.
+    pub fn config_values(&self) -> Vec<(String, String)> {
+        let mut values = Vec::new();
+        let mut set = |name: &str, value: String| values.push((name.to_string(), value));
+        if let Some(server) = &self.server {
+            set("server", server.to_string());
+        }
+        if let Some(edition) = self.edition {
+            set("edition", edition.to_string());
+        }
+        if let Some(mode) = self.fps_cap {
+            set("fps_cap", fps_cap_name(mode));
+        }
+        if let Some(mode) = self.present_mode {
+            set("present_mode", present_mode_name(mode).to_string());
+        }
+        if let Some(mode) = self.window_mode {
+            set("window_mode", window_mode_name(mode).to_string());
+        }
+        if let Some(username) = &self.username {
+            set("username", username.clone());
+        }
+        values
+    }
+}
+
+/// Join the configured server, or start the `--replay`, on startup.
+pub fn cli_startup_system(
+    cli: Res<CliArgs>,
+    config: Res<ResolvedConfig>,
+    mut capture: ResMut<CaptureSettings>,
+    mut connects: MessageWriter<ConnectRequest>,
+    mut replays: MessageWriter<ReplayRequest>,
+) {
+    if let Some(path) = &cli.capture {
+        capture.enabled = true;
+        capture.path = Some(path.clone());
+    }
+    if let Some(path) = &cli.replay {
+        replays.write(ReplayRequest {
+            path: path.clone(),
+            speed: 1.0,
+        });
+    } else if let Some(address) = config.server() {
+        connects.write(ConnectRequest {
+            address,
+            edition: config.edition(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<CliAction, CliError> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn run(args: &[&str]) -> CliArgs {
+        match parse(args) {
+            Ok(CliAction::Run(cli)) => *cli,
+            other => panic!("{:?} gave {:?}", args, other),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Err(CliError(message)) => message,
+            other => panic!("{:?} gave {:?}", args, other),
+        }
+    }
+
+    fn server(args: &[&str]) -> (String, u16) {
+        let server = run(args).server.expect("no server");
+        (server.host, server.port)
+    }
+
+    /// A directory holding a file that exists, for the options that check their paths.
+    /// Removed again when dropped.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("rustcraft-cli-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("game.config"), "render_distance = 8\n").unwrap();
+            Self(dir)
+        }
+
+        fn file(&self) -> PathBuf {
+            self.0.join("game.config")
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn help_and_version() {
+        for args in [&["--help"][..], &["-h"], &["--fps-cap", "60", "--help"]] {
+            assert!(matches!(parse(args), Ok(CliAction::Help)), "{:?}", args);
+        }
+        for args in [["--version"], ["-V"]] {
+            assert!(matches!(parse(&args), Ok(CliAction::Version)));
+        }
+        let cli = run(&[]);
+        assert!(cli.server.is_none() && !cli.headless);
+        assert!(cli.config_values().is_empty());
+    }
+
+    #[test]
+    fn every_flag() {
+        let scratch = Scratch::new("flags");
+        let file = scratch.file();
+        let file = file.to_str().unwrap();
+        let cli = run(&[
+            "--server",
+            "play.example.com",
+            "--edition",
+            "java",
+            "--config",
+            file,
+            "--fps-cap",
+            "144",
+            "--present-mode",
+            "mailbox",
+            "--fullscreen",
+            "--username",
+            "Steve_2",
+            "--headless",
+            "--capture",
+            "captures",
+        ]);
+        assert_eq!(
+            cli.server,
+            Some(ServerAddress {
+                host: "play.example.com".into(),
+                port: 25565,
+            })
+        );
+        assert_eq!(cli.edition, Some(Edition::Java));
+        assert_eq!(cli.config.as_deref(), Some(Path::new(file)));
+        assert_eq!(cli.fps_cap, Some(FpsMode::Manual(144)));
+        assert_eq!(cli.present_mode, Some(PresentMode::Mailbox));
+        assert_eq!(
+            cli.window_mode,
+            Some(WindowMode::BorderlessFullscreen(MonitorSelection::Current))
+        );
+        assert_eq!(cli.username.as_deref(), Some("Steve_2"));
+        assert!(cli.headless);
+        assert_eq!(cli.capture.as_deref(), Some(Path::new("captures")));
+
+        let values = cli.config_values();
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(value("server"), Some("play.example.com:25565"));
+        assert_eq!(value("edition"), Some("java"));
+        assert_eq!(value("fps_cap"), Some("144"));
+        assert_eq!(value("present_mode"), Some("mailbox"));
+        assert_eq!(value("window_mode"), Some("borderless"));
+        assert_eq!(value("username"), Some("Steve_2"));
+
+        let cli = run(&["--windowed", "--fps-cap=vsync", "--replay", file]);
+        assert_eq!(cli.window_mode, Some(WindowMode::Windowed));
+        assert_eq!(cli.fps_cap, Some(FpsMode::VSync));
+        assert_eq!(cli.replay.as_deref(), Some(Path::new(file)));
+        assert_eq!(
+            run(&["--fps-cap", "uncapped"]).fps_cap,
+            Some(FpsMode::Uncapped)
+        );
+    }
+
+    #[test]
+    fn server_addresses() {
+        assert_eq!(
+            server(&["--connect", "localhost:19133"]),
+            ("localhost".into(), 19133)
+        );
+        assert_eq!(
+            server(&["--connect=10.0.0.2:25566"]),
+            ("10.0.0.2".into(), 25566)
+        );
+        assert_eq!(
+            server(&["--server", "10.0.0.2"]),
+            ("10.0.0.2".into(), 19132)
+        );
+
+        // a missing port is the edition's default, whichever order the options come in
+        assert_eq!(
+            server(&["--connect", "example.com", "--edition", "java"]),
+            ("example.com".into(), 25565)
+        );
+        assert_eq!(
+            server(&["--edition=bedrock", "--connect", "example.com"]),
+            ("example.com".into(), 19132)
+        );
+
+        assert_eq!(server(&["--connect", "[::1]:19134"]), ("::1".into(), 19134));
+        assert_eq!(server(&["--connect", "[::1]"]), ("::1".into(), 19132));
+        assert_eq!(
+            server(&["--connect", "fe80::1:2", "--edition", "java"]),
+            ("fe80::1:2".into(), 25565)
+        );
+        let ipv6 = run(&["--connect", "[2001:db8::7]:25570"]).server.unwrap();
+        assert_eq!(ipv6.to_string(), "[2001:db8::7]:25570");
+    }
+
+    #[test]
+    fn bad_command_lines() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["--connect"], "--connect needs a value"),
+            (&["--server="], "--server: empty server address"),
+            (&["--connect", "example.com:"], "--connect: invalid port ''"),
+            (&["--connect", "example.com:http"], "invalid port 'http'"),
+            (&["--connect", "example.com:70000"], "invalid port '70000'"),
+            (&["--connect", ":19132"], "missing host"),
+            (&["--connect", "[::1"], "unterminated IPv6 address"),
+            (&["--connect", "[::1]:x"], "invalid port 'x'"),
+            (
+                &["--edition", "pocket"],
+                "--edition: unknown edition 'pocket'",
+            ),
+            (
+                &["--fps-cap", "1001"],
+                "--fps-cap: expected a number from 1 to 1000",
+            ),
+            (&["--fps-cap", "fast"], "--fps-cap: expected a number"),
+            (
+                &["--present-mode", "tearing"],
+                "--present-mode: unknown mode 'tearing'",
+            ),
+            (&["--windowed", "--fullscreen"], "can't be used together"),
+            (&["--username", "ab"], "--username: 'ab' must be 3 to 16"),
+            (&["--username", "Steve!"], "contains '!'"),
+            (
+                &["--world", "saves/New World"],
+                "--world: local worlds are not supported yet",
+            ),
+            (
+                &["--config", "/nonexistent.config"],
+                "--config: '/nonexistent.config' does",
+            ),
+            (
+                &["--replay", "/nonexistent.jsonl"],
+                "--replay: '/nonexistent.jsonl' does",
+            ),
+            (&["--fly"], "unknown option '--fly'"),
+            (&["localhost"], "unknown option 'localhost'"),
+        ];
+        for (args, expected) in cases {
+            let message = error(args);
+            assert!(message.contains(expected), "{:?}: {}", args, message);
+        }
+
+        let scratch = Scratch::new("errors");
+        let file = scratch.file();
+        let file = file.to_str().unwrap();
+        assert!(error(&["--replay", file, "--connect", "localhost"]).contains("together"));
+        assert!(
+            CliError("bad".into())
+                .to_string()
+                .ends_with("Run 'rustcraft --help' for the list of options.")
+        );
+    }
+}
@@ -0,0 +1,829 @@
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PresentMode, VideoModeSelection, WindowMode};
+use bevy_egui::egui;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::cli::CliArgs;
+use crate::console::{PRESENT_MODES, WINDOW_MODES};
+use crate::data::{FpsCap, FpsMode, GlobalFlags, GlobalSettings};
+use crate::diagnostics::DiagnosticsAppExt;
+use crate::net::{Edition, ServerAddress};
+use crate::paths::data_dir;
+
+/// Project or launcher level config, looked up next to the executable and in the working directory.
+pub const GAME_CONFIG: &str = "game.config";
+/// Per-user settings file in the data directory.
+pub const USER_SETTINGS: &str = "settings.toml";
+/// Prefix of the environment variables that override settings, e.g. `RUSTCRAFT_RENDER_DISTANCE`.
+pub const ENV_PREFIX: &str = "RUSTCRAFT_";
+
+/// Where a setting came from, lowest priority first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigLayer {
+    Default,
+    GameConfig,
+    UserSettings,
+    Environment,
+    CommandLine,
+}
+
+impl fmt::Display for ConfigLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConfigLayer::Default => "default",
+            ConfigLayer::GameConfig => "game.config",
+            ConfigLayer::UserSettings => "user settings",
+            ConfigLayer::Environment => "environment",
+            ConfigLayer::CommandLine => "command line",
+        })
+    }
+}
+
+/// A setting that can be given in any config layer.
+pub struct ConfigKey {
+    pub name: &'static str,
+    /// The value used when no layer sets it, taken from the settings it configures so the
+    /// two can't disagree.
+    pub default: fn(&GlobalSettings) -> String,
+    pub validate: fn(&str) -> Result<(), String>,
+}
+
+pub const CONFIG_KEYS: &[ConfigKey] = &[
+    ConfigKey {
+        name: "render_distance",
+        default: |settings| settings.game_settings.render_distance.to_string(),
+        validate: |v| parse_render_distance(v).map(drop),
+    },
+    ConfigKey {
+        name: "fps_cap",
+        // a manual cap of 0 leaves the frame rate to the default mode
+        default: |settings| match settings.game_settings.fps_cap {
+            0 => fps_cap_name(FpsCap::default().mode),
+            n => n.to_string(),
+        },
+        validate: |v| parse_fps_cap(v).map(drop),
+    },
+    ConfigKey {
+        name: "present_mode",
+        default: |settings| present_mode_name(settings.game_settings.present_mode).into(),
+        validate: |v| parse_present_mode(v).map(drop),
+    },
+    ConfigKey {
+        name: "window_mode",
+        default: |_| window_mode_name(Window::default().mode).into(),
+        validate: |v| parse_window_mode(v).map(drop),
+    },
+    ConfigKey {
+        name: "username",
+        default: |settings| settings.game_settings.username.clone(),
+        validate: validate_username,
+    },
+    ConfigKey {
+        name: "debug_overlay",
+        default: |settings| {
+            settings
+                .flags
+                .contains(GlobalFlags::DEBUG_OVERLAY)
+                .to_string()
+        },
+        validate: |v| parse_bool(v).map(drop),
+    },
+    ConfigKey {
+        name: "edition",
+        default: |_| Edition::default().to_string(),
+        validate: |v| v.parse::<Edition>().map(drop),
+    },
+    ConfigKey {
+        name: "server",
+        default: |_| String::new(),
+        validate: |v| {
+            if v.is_empty() {
+                return Ok(());
+            }
+            ServerAddress::parse(v, Edition::default()).map(drop)
+        },
+    },
+];
+
+pub fn parse_render_distance(value: &str) -> Result<u8, String> {
+    match value.parse::<u8>() {
+        Ok(n @ 4..=96) => Ok(n),
+        _ => Err(format!(
+            "expected a number of chunks from 4 to 96, got '{}'",
+            value
+        )),
+    }
+}
+
+pub fn parse_fps_cap(value: &str) -> Result<FpsMode, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "vsync" => Ok(FpsMode::VSync),
+        "uncapped" | "0" => Ok(FpsMode::Uncapped),
+        n => match n.parse::<u32>() {
+            Ok(n @ 1..=1000) => Ok(FpsMode::Manual(n)),
+            _ => Err(format!(
+                "expected a number from 1 to 1000, 'vsync' or 'uncapped', got '{}'",
+                value
+            )),
+        },
+    }
+}
+
+pub fn fps_cap_name(mode: FpsMode) -> String {
+    match mode {
+        FpsMode::VSync => "vsync".into(),
+        FpsMode::Uncapped => "uncapped".into(),
+        FpsMode::Manual(n) => n.to_string(),
+    }
+}
+
+pub fn parse_present_mode(value: &str) -> Result<PresentMode, String> {
+    PRESENT_MODES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(value))
+        .map(|(_, mode)| *mode)
+        .ok_or_else(|| {
+            let names: Vec<&str> = PRESENT_MODES.iter().map(|(name, _)| *name).collect();
+            format!(
+                "unknown mode '{}' (expected one of {})",
+                value,
+                names.join(", ")
+            )
+        })
+}
+
+pub fn present_mode_name(mode: PresentMode) -> &'static str {
+    PRESENT_MODES
+        .iter()
+        .find(|(_, m)| *m == mode)
+        .map(|(name, _)| *name)
+        .unwrap_or("fifo")
+}
+
+pub fn parse_window_mode(value: &str) -> Result<WindowMode, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "windowed" => Ok(WindowMode::Windowed),
+        "borderless" => Ok(WindowMode::BorderlessFullscreen(MonitorSelection::Current)),
+        "fullscreen" => Ok(WindowMode::Fullscreen(
+            MonitorSelection::Current,
+            VideoModeSelection::Current,
+        )),
+        _ => Err(format!(
+            "unknown window mode '{}' (expected one of {})",
+            value,
+            WINDOW_MODES.join(", ")
+        )),
+    }
+}
+
+pub fn window_mode_name(mode: WindowMode) -> &'static str {
+    match mode {
+        WindowMode::Windowed => "windowed",
+        WindowMode::BorderlessFullscreen(_) => "borderless",
+        WindowMode::Fullscreen(..) => "fullscreen",
+    }
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Ok(true),
+        "0" | "false" | "off" | "no" => Ok(false),
+        _ => Err(format!("expected true or false, got '{}'", value)),
+    }
+}
+
+/// Offline usernames follow the Java rules, which Bedrock servers accept too.
+pub fn validate_username(name: &str) -> Result<(), String> {
+    if !(3..=16).contains(&name.len()) {
+        return Err(format!("'{}' must be 3 to 16 characters long", name));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+    {
+        return Err(format!(
+            "'{}' contains '{}'; only letters, digits and _ are allowed",
+            name, c
+        ));
+    }
+    Ok(())
+}
+
+/// The winning value of one setting.
+#[derive(Debug, Clone)]
+pub struct ConfigValue {
+    pub name: &'static str,
+    pub value: String,
+    pub source: ConfigLayer,
+}
+
+/// Settings merged from every layer, plus what was read and what was rejected.
+#[derive(Resource, Debug, Clone)]
+pub struct ResolvedConfig {
+    pub values: Vec<ConfigValue>,
+    pub files: Vec<(ConfigLayer, PathBuf)>,
+    pub warnings: Vec<String>,
+}
+
+impl ResolvedConfig {
+    /// Merge defaults, `game.config`, the user settings file, `RUSTCRAFT_*` and the command line.
+    pub fn resolve(cli: &CliArgs) -> Self {
+        Self::resolve_with(cli, find_game_config(), |var| std::env::var(var).ok())
+    }
+
+    /// `resolve` with the `game.config` found and the environment variables read by `env`.
+    fn resolve_with(
+        cli: &CliArgs,
+        game_config: Option<PathBuf>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let mut config = Self::defaults();
+
+        if let Some(path) = game_config {
+            match read_game_config(&path) {
+                Ok(entries) => config.merge(ConfigLayer::GameConfig, &path, entries),
+                Err(err) => config.warnings.push(format!("{}: {}", path.display(), err)),
+            }
+        }
+
+        // --config replaces the user settings file rather than adding a layer
+        let user = cli
+            .config
+            .clone()
+            .unwrap_or_else(|| data_dir().join(USER_SETTINGS));
+        match std::fs::read_to_string(&user) {
+            Ok(text) => match parse_settings(&text) {
+                Ok(entries) => config.merge(ConfigLayer::UserSettings, &user, entries),
+                Err(err) => config.warnings.push(format!("{}: {}", user.display(), err)),
+            },
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound || cli.config.is_some() => {
+                config
+                    .warnings
+                    .push(format!("could not read {}: {}", user.display(), err));
+            }
+            Err(_) => {}
+        }
+
+        let env: Vec<(String, String)> = CONFIG_KEYS
+            .iter()
+            .filter_map(|key| {
+                let var = format!("{}{}", ENV_PREFIX, key.name.to_ascii_uppercase());
+                env(&var).map(|v| (key.name.to_string(), v))
+            })
+            .collect();
+        config.merge(ConfigLayer::Environment, Path::new("RUSTCRAFT_*"), env);
+
+        config.merge(
+            ConfigLayer::CommandLine,
+            Path::new("command line"),
+            cli.config_values(),
+        );
+        config
+    }
+
+    /// Every setting at its default.
+    pub fn defaults() -> Self {
+        let settings = GlobalSettings::default();
+        Self {
+            values: CONFIG_KEYS
+                .iter()
+                .map(|key| ConfigValue {
+                    name: key.name,
+                    value: (key.default)(&settings),
+                    source: ConfigLayer::Default,
+                })
+                .collect(),
+            files: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Apply one layer; invalid or unknown entries are reported and skipped.
+    fn merge(&mut self, layer: ConfigLayer, origin: &Path, entries: Vec<(String, String)>) {
+        if entries.is_empty() {
+            return;
+        }
+        if matches!(layer, ConfigLayer::GameConfig | ConfigLayer::UserSettings) {
+            self.files.push((layer, origin.to_path_buf()));
+        }
+
+        for (name, value) in entries {
+            let Some(key) = CONFIG_KEYS.iter().find(|key| key.name == name) else {
+                self.warnings
+                    .push(format!("{}: unknown setting '{}'", origin.display(), name));
+                continue;
+            };
+            if let Err(err) = (key.validate)(&value) {
+                self.warnings
+                    .push(format!("{}: {}: {}", origin.display(), name, err));
+                continue;
+            }
+            if let Some(entry) = self.values.iter_mut().find(|v| v.name == key.name) {
+                entry.value = value;
+                entry.source = layer;
+            }
+        }
+    }
+
+    pub fn entry(&self, name: &str) -> &ConfigValue {
+        self.values
+            .iter()
+            .find(|v| v.name == name)
+            .expect("unknown config key")
+    }
+
+    pub fn get(&self, name: &str) -> &str {
+        &self.entry(name).value
+    }
+
+    /// True when `name` was set by anything other than the built-in defaults.
+    pub fn is_set(&self, name: &str) -> bool {
+        self.entry(name).source > ConfigLayer::Default
+    }
+
+    // values were validated while merging, so parsing can't fail here
+
+    pub fn render_distance(&self) -> u8 {
+        parse_render_distance(self.get("render_distance")).unwrap_or(16)
+    }
+
+    pub fn fps_cap(&self) -> FpsMode {
+        parse_fps_cap(self.get("fps_cap")).unwrap_or(FpsMode::VSync)
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        parse_present_mode(self.get("present_mode")).unwrap_or(PresentMode::Fifo)
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        parse_window_mode(self.get("window_mode")).unwrap_or(WindowMode::Windowed)
+    }
+
+    pub fn edition(&self) -> Edition {
+        self.get("edition").parse().unwrap_or_default()
+    }
+
+    /// Server to join on startup.
+    pub fn server(&self) -> Option<ServerAddress> {
+        let server = self.get("server");
+        if server.is_empty() {
+            return None;
+        }
+        ServerAddress::parse(server, self.edition()).ok()
+    }
+
+    /// Log the files that were read and any rejected entries.
+    pub fn report(&self) {
+        for (layer, path) in &self.files {
+            info!("Loaded {} from {}", layer, path.display());
+        }
+        for warning in &self.warnings {
+            warn!("Ignoring config entry: {}", warning);
+        }
+    }
+
+    pub fn apply_to_settings(&self, settings: &mut GlobalSettings, fps_cap: &mut FpsCap) {
+        let game = &mut settings.game_settings;
+        game.render_distance = self.render_distance();
+        game.username = self.get("username").to_string();
+        if self.is_set("present_mode") {
+            game.present_mode = self.present_mode();
+        }
+
+        fps_cap.mode = self.fps_cap();
+        if let FpsMode::Manual(n) = fps_cap.mode {
+            game.fps_cap = n.min(u8::MAX as u32) as u8;
+        }
+
+        settings.flags.set(
+            GlobalFlags::DEBUG_OVERLAY,
+            parse_bool(self.get("debug_overlay")).unwrap_or(false),
+        );
+    }
+
+    /// Apply window settings before the window is created.
+    pub fn apply_to_window(&self, window: &mut Window) {
+        // an explicit present mode wins over the one implied by the fps cap
+        if self.is_set("present_mode") {
+            window.present_mode = self.present_mode();
+        } else if self.is_set("fps_cap") {
+            window.present_mode = self.fps_cap().present_mode();
+        }
+        window.mode = self.window_mode();
+    }
+}
+
+fn find_game_config() -> Option<PathBuf> {
+    let beside_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(GAME_CONFIG)));
+    beside_exe
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(GAME_CONFIG)))
+        .find(|path| path.is_file())
+}
+
+/// Read `<add key=".." value=".."/>` entries from an `appSettings`-style XML file.
+fn read_game_config(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let doc = roxmltree::Document::parse(text.trim_start_matches('\u{feff}'))
+        .map_err(|err| err.to_string())?;
+    Ok(doc
+        .descendants()
+        .filter(|node| node.has_tag_name("add"))
+        .filter_map(|node| Some((node.attribute("key")?, node.attribute("value")?)))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect())
+}
+
+/// Parse the `key = value` lines of the user settings file (a flat TOML subset).
+fn parse_settings(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(format!(
+                "line {}: tables are not supported, settings go at the top level",
+                number + 1
+            ));
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected 'key = value'", number + 1))?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        entries.push((key.trim().to_string(), value.to_string()));
+    }
+    Ok(entries)
+}
+
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_diagnostics_panel("Config", config_panel)
+            .add_systems(Startup, config_report_system);
+    }
+}
+
+/// Log the files that were read and any rejected entries.
+pub fn config_report_system(config: Option<Res<ResolvedConfig>>) {
+    if let Some(config) = config {
+        config.report();
+    }
+}
+
+/// Debug overlay section listing every setting and the layer it came from.
+pub fn config_panel(InMut(ui): InMut<egui::Ui>, config: Option<Res<ResolvedConfig>>) {
+    let Some(config) = config else {
+        ui.label("No config resolved");
+        return;
+    };
+
+    egui::Grid::new("config_panel_grid")
+        .striped(true)
+        .show(ui, |ui| {
+            for entry in &config.values {
+                ui.label(entry.name);
+                ui.label(if entry.value.is_empty() {
+                    "-"
+                } else {
+                    entry.value.as_str()
+                });
+                ui.label(entry.source.to_string());
+                ui.end_row();
+            }
+        });
+    for (layer, path) in &config.files {
+        ui.label(format!("{}: {}", layer, path.display()));
+    }
+    if !config.warnings.is_empty() {
+        ui.colored_label(
+            egui::Color32::from_rgb(255, 200, 60),
+            format!("{} ignored entries (see log)", config.warnings.len()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        for value in ResolvedConfig::defaults().values {
+            let key = CONFIG_KEYS
+                .iter()
+                .find(|key| key.name == value.name)
+                .unwrap();
+            assert_eq!((key.validate)(&value.value), Ok(()), "{}", key.name);
+        }
+    }
+
+    #[test]
+    fn defaults_match_the_default_settings() {
+        let config = ResolvedConfig::defaults();
+        let expected = GlobalSettings::default();
+        assert_eq!(config.present_mode(), expected.game_settings.present_mode);
+        assert_eq!(
+            config.render_distance(),
+            expected.game_settings.render_distance
+        );
+        assert_eq!(config.fps_cap(), FpsCap::default().mode);
+        assert_eq!(config.window_mode(), Window::default().mode);
+        assert_eq!(config.edition(), Edition::default());
+        assert_eq!(config.server(), None);
+
+        // applying the defaults changes nothing
+        let mut settings = GlobalSettings::default();
+        let mut fps_cap = FpsCap::default();
+        config.apply_to_settings(&mut settings, &mut fps_cap);
+        assert_eq!(format!("{:?}", settings), format!("{:?}", expected));
+        assert_eq!(fps_cap.mode, FpsCap::default().mode);
+
+        let mut window = Window::default();
+        config.apply_to_window(&mut window);
+        assert_eq!(window.present_mode, Window::default().present_mode);
+        assert_eq!(window.mode, Window::default().mode);
+    }
+
+    /// A directory for config files, removed again when dropped.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "rustcraft-config-{}-{}",
+                name,
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, text: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, text).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn game_config(entries: &[(&str, &str)]) -> String {
+        let adds: String = entries
+            .iter()
+            .map(|(key, value)| format!("    <add key=\"{}\" value=\"{}\"/>\n", key, value))
+            .collect();
+        format!(
+            "<configuration>\n  <appSettings>\n{}  </appSettings>\n</configuration>\n",
+            adds
+        )
+    }
+
+    fn settings(entries: &[(&str, &str)]) -> String {
+        entries
+            .iter()
+            .map(|(key, value)| format!("{} = \"{}\"\n", key, value))
+            .collect()
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let scratch = Scratch::new("layers");
+        let game = scratch.write(
+            GAME_CONFIG,
+            &game_config(&[
+                ("render_distance", "8"),
+                ("fps_cap", "30"),
+                ("present_mode", "immediate"),
+                ("window_mode", "borderless"),
+            ]),
+        );
+        let user = scratch.write(
+            USER_SETTINGS,
+            &settings(&[
+                ("fps_cap", "60"),
+                ("present_mode", "mailbox"),
+                ("window_mode", "fullscreen"),
+            ]),
+        );
+        let env = |var: &str| match var {
+            "RUSTCRAFT_PRESENT_MODE" => Some("fifo_relaxed".to_string()),
+            "RUSTCRAFT_WINDOW_MODE" => Some("fullscreen".to_string()),
+            _ => None,
+        };
+        let cli = CliArgs {
+            config: Some(user.clone()),
+            window_mode: Some(WindowMode::Windowed),
+            ..default()
+        };
+
+        let config = ResolvedConfig::resolve_with(&cli, Some(game.clone()), env);
+        assert_eq!(config.warnings, Vec::<String>::new());
+        assert_eq!(
+            config.files,
+            [
+                (ConfigLayer::GameConfig, game),
+                (ConfigLayer::UserSettings, user)
+            ]
+        );
+        for (name, value, source) in [
+            ("debug_overlay", "false", ConfigLayer::Default),
+            ("render_distance", "8", ConfigLayer::GameConfig),
+            ("fps_cap", "60", ConfigLayer::UserSettings),
+            ("present_mode", "fifo_relaxed", ConfigLayer::Environment),
+            ("window_mode", "windowed", ConfigLayer::CommandLine),
+        ] {
+            let entry = config.entry(name);
+            assert_eq!(
+                (entry.value.as_str(), entry.source),
+                (value, source),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(config.render_distance(), 8);
+        assert_eq!(config.fps_cap(), FpsMode::Manual(60));
+        assert_eq!(config.present_mode(), PresentMode::FifoRelaxed);
+        assert_eq!(config.window_mode(), WindowMode::Windowed);
+    }
+
+    #[test]
+    fn each_layer_overrides_the_ones_below() {
+        let scratch = Scratch::new("override");
+        let layers = [
+            ConfigLayer::Default,
+            ConfigLayer::GameConfig,
+            ConfigLayer::UserSettings,
+            ConfigLayer::Environment,
+            ConfigLayer::CommandLine,
+        ];
+        // the username set by each layer, up to the highest one that sets it
+        for top in layers {
+            let sets = |layer: ConfigLayer| layer <= top && layer > ConfigLayer::Default;
+            let game = sets(ConfigLayer::GameConfig)
+                .then(|| scratch.write(GAME_CONFIG, &game_config(&[("username", "FromGame")])));
+            let user = scratch.write(
+                USER_SETTINGS,
+                &settings(if sets(ConfigLayer::UserSettings) {
+                    &[("username", "FromUser")]
+                } else {
+                    &[]
+                }),
+            );
+            let env = |var: &str| {
+                (sets(ConfigLayer::Environment) && var == "RUSTCRAFT_USERNAME")
+                    .then(|| "FromEnv".to_string())
+            };
+            let cli = CliArgs {
+                config: Some(user),
+                username: sets(ConfigLayer::CommandLine).then(|| "FromCli".to_string()),
+                ..default()
+            };
+
+            let config = ResolvedConfig::resolve_with(&cli, game, env);
+            let entry = config.entry("username");
+            assert_eq!(entry.source, top);
+            let expected = match top {
+                ConfigLayer::Default => GlobalSettings::default().game_settings.username,
+                ConfigLayer::GameConfig => "FromGame".into(),
+                ConfigLayer::UserSettings => "FromUser".into(),
+                ConfigLayer::Environment => "FromEnv".into(),
+                ConfigLayer::CommandLine => "FromCli".into(),
+            };
+            assert_eq!(entry.value, expected);
+        }
+    }
+
+    #[test]
+    fn bad_entries_leave_lower_layers_alone() {
+        let scratch = Scratch::new("bad");
+        let game = scratch.write(
+            GAME_CONFIG,
+            &game_config(&[
+                ("render_distance", "8"),
+                ("fps_cap", "fast"),
+                ("colour", "red"),
+            ]),
+        );
+        let user = scratch.write(USER_SETTINGS, &settings(&[("render_distance", "500")]));
+        let env = |var: &str| (var == "RUSTCRAFT_EDITION").then(|| "pocket".to_string());
+        let cli = CliArgs {
+            config: Some(user.clone()),
+            ..default()
+        };
+
+        let config = ResolvedConfig::resolve_with(&cli, Some(game), env);
+        assert_eq!(config.warnings.len(), 4, "{:?}", config.warnings);
+        assert_eq!(
+            config.entry("render_distance").source,
+            ConfigLayer::GameConfig
+        );
+        assert_eq!(config.render_distance(), 8);
+        assert_eq!(config.entry("fps_cap").source, ConfigLayer::Default);
+        assert_eq!(config.entry("edition").source, ConfigLayer::Default);
+        assert!(
+            config
+                .warnings
+                .iter()
+                .any(|w| w.contains("unknown setting 'colour'"))
+        );
+
+        // files that don't parse at all are skipped whole
+        let game = scratch.write(GAME_CONFIG, "<configuration><appSettings>");
+        scratch.write(USER_SETTINGS, "[video]\nrender_distance = 12\n");
+        let config = ResolvedConfig::resolve_with(&cli, Some(game), |_| None);
+        assert_eq!(config.warnings.len(), 2, "{:?}", config.warnings);
+        assert!(config.files.is_empty());
+        assert!(
+            config
+                .values
+                .iter()
+                .all(|v| v.source == ConfigLayer::Default)
+        );
+
+        // an explicit --config must exist; the default settings file may not
+        let missing = CliArgs {
+            config: Some(scratch.0.join("missing.toml")),
+            ..default()
+        };
+        let config = ResolvedConfig::resolve_with(&missing, None, |_| None);
+        assert_eq!(config.warnings.len(), 1);
+    }
+
+    #[test]
+    fn settings_files() {
+        let text = "\
+# comment
+render_distance = 12
+  username=\"Steve\"\t
+
+present_mode = fifo
+server = \"\"
+";
+        assert_eq!(
+            parse_settings(text).unwrap(),
+            [
+                ("render_distance".to_string(), "12".to_string()),
+                ("username".to_string(), "Steve".to_string()),
+                ("present_mode".to_string(), "fifo".to_string()),
+                ("server".to_string(), String::new()),
+            ]
+        );
+        assert_eq!(parse_settings("").unwrap(), []);
+        assert_eq!(
+            parse_settings("fps_cap = 60\nvsync\n"),
+            Err("line 2: expected 'key = value'".into())
+        );
+        assert_eq!(
+            parse_settings("fps_cap = 60\n[video]\nwindow_mode = \"fullscreen\"\n"),
+            Err("line 2: tables are not supported, settings go at the top level".into())
+        );
+    }
+
+    #[test]
+    fn game_config_files() {
+        let scratch = Scratch::new("xml");
+        let path = scratch.write(
+            GAME_CONFIG,
+            "\u{feff}<?xml version=\"1.0\"?>\n\
+             <configuration>\n\
+               <!-- launcher settings -->\n\
+               <appSettings>\n\
+                 <add key=\"username\" value=\"Alex\"/>\n\
+                 <add key=\"no_value\"/>\n\
+                 <add key=\"server\" value=\"\"/>\n\
+               </appSettings>\n\
+             </configuration>\n",
+        );
+        assert_eq!(
+            read_game_config(&path).unwrap(),
+            [
+                ("username".to_string(), "Alex".to_string()),
+                ("server".to_string(), String::new()),
+            ]
+        );
+
+        for bad in [
+            "",
+            "<configuration>",
+            "<add key=\"a\" value=\"b\"></configuration>",
+        ] {
+            let path = scratch.write(GAME_CONFIG, bad);
+            assert!(read_game_config(&path).is_err(), "{:?}", bad);
+        }
+        assert!(read_game_config(&scratch.0.join("missing.config")).is_err());
+    }
+}
@@ -0,0 +1,1087 @@
+use bevy::prelude::*;
+use bevy::render::view::screenshot::{Screenshot, save_to_disk};
+use bevy::window::{MonitorSelection, PresentMode, PrimaryWindow, VideoModeSelection, WindowMode};
+use chrono::Local;
+use crossbeam_channel::Receiver;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::cli::CliArgs;
+use crate::config::ResolvedConfig;
+use crate::data::{DebugFlags, FpsCap, FpsMode, GlobalFlags, GlobalSettings};
+use crate::net::session::PlayerAction;
+use crate::net::{ConnectRequest, DisconnectRequest, Edition, SendAction, ServerAddress};
+use crate::paths::{data_dir, screenshots_dir};
+
+/// Lines kept in the console scrollback.
+const MAX_OUTPUT_LINES: usize = 500;
+/// Nested `exec` limit, so a script that execs itself can't hang the game.
+const MAX_EXEC_DEPTH: usize = 8;
+/// Script executed on startup and on `reload`, relative to the data directory.
+pub const AUTOEXEC_CFG: &str = "autoexec.cfg";
+
+/// Names accepted for present modes by the console and the command line.
+pub const PRESENT_MODES: &[(&str, PresentMode)] = &[
+    ("auto_vsync", PresentMode::AutoVsync),
+    ("auto_no_vsync", PresentMode::AutoNoVsync),
+    ("fifo", PresentMode::Fifo),
+    ("fifo_relaxed", PresentMode::FifoRelaxed),
+    ("immediate", PresentMode::Immediate),
+    ("mailbox", PresentMode::Mailbox),
+];
+
+const PRESENT_MODE_NAMES: [&str; PRESENT_MODES.len()] = names(PRESENT_MODES);
+
+pub const WINDOW_MODES: &[&str] = &["windowed", "borderless", "fullscreen"];
+
+/// Keys the console can be bound to.
+pub const CONSOLE_KEYS: &[(&str, KeyCode)] = &[
+    ("backquote", KeyCode::Backquote),
+    ("backslash", KeyCode::Backslash),
+    ("insert", KeyCode::Insert),
+    ("home", KeyCode::Home),
+    ("f7", KeyCode::F7),
+    ("f8", KeyCode::F8),
+    ("f9", KeyCode::F9),
+    ("f12", KeyCode::F12),
+];
+
+const CONSOLE_KEY_NAMES: [&str; CONSOLE_KEYS.len()] = names(CONSOLE_KEYS);
+
+/// The names of a `(name, value)` table, for `CvarKind::Choice`.
+const fn names<T, const N: usize>(table: &[(&'static str, T)]) -> [&'static str; N] {
+    let mut names = [""; N];
+    let mut i = 0;
+    while i < N {
+        names[i] = table[i].0;
+        i += 1;
+    }
+    names
+}
+
+/// Ask every subsystem to reload its data (config, scripts, resources).
+#[derive(Message, Clone, Debug, Default)]
+pub struct ReloadRequest;
+
+/// Type of a console variable, used for parsing, validation and completion.
+#[derive(Clone, Copy, Debug)]
+pub enum CvarKind {
+    Bool,
+    Int { min: i64, max: i64 },
+    Choice(&'static [&'static str]),
+    Text,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CvarValue {
+    Bool(bool),
+    Int(i64),
+    Text(String),
+}
+
+impl fmt::Display for CvarValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CvarValue::Bool(v) => write!(f, "{}", v),
+            CvarValue::Int(v) => write!(f, "{}", v),
+            CvarValue::Text(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl CvarValue {
+    pub fn as_bool(&self) -> bool {
+        matches!(self, CvarValue::Bool(true))
+    }
+
+    pub fn as_int(&self) -> i64 {
+        match self {
+            CvarValue::Int(v) => *v,
+            CvarValue::Bool(v) => *v as i64,
+            CvarValue::Text(_) => 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            CvarValue::Text(v) => v,
+            _ => "",
+        }
+    }
+}
+
+impl CvarKind {
+    pub fn parse(&self, input: &str) -> Result<CvarValue, String> {
+        match self {
+            CvarKind::Bool => match input.to_ascii_lowercase().as_str() {
+                "1" | "true" | "on" | "yes" => Ok(CvarValue::Bool(true)),
+                "0" | "false" | "off" | "no" => Ok(CvarValue::Bool(false)),
+                _ => Err(format!("expected a boolean, got '{}'", input)),
+            },
+            CvarKind::Int { min, max } => {
+                let value = input
+                    .parse::<i64>()
+                    .map_err(|_| format!("expected a number, got '{}'", input))?;
+                if value < *min || value > *max {
+                    return Err(format!("{} is outside {}..={}", value, min, max));
+                }
+                Ok(CvarValue::Int(value))
+            }
+            CvarKind::Choice(options) => options
+                .iter()
+                .find(|o| o.eq_ignore_ascii_case(input))
+                .map(|o| CvarValue::Text(o.to_string()))
+                .ok_or_else(|| format!("expected one of {}", options.join("|"))),
+            CvarKind::Text => Ok(CvarValue::Text(input.to_string())),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            CvarKind::Bool => "<bool>".into(),
+            CvarKind::Int { min, max } => format!("<{}..={}>", min, max),
+            CvarKind::Choice(options) => format!("<{}>", options.join("|")),
+            CvarKind::Text => "<text>".into(),
+        }
+    }
+}
+
+pub type CvarGetter = Box<dyn Fn(&mut World) -> CvarValue + Send + Sync>;
+pub type CvarSetter = Box<dyn Fn(&mut World, CvarValue) + Send + Sync>;
+pub type CommandHandler =
+    Box<dyn Fn(&mut World, &[String]) -> Result<Option<String>, String> + Send + Sync>;
+
+/// A typed console variable backed by existing game state.
+pub struct Cvar {
+    pub help: &'static str,
+    pub kind: CvarKind,
+    pub get: CvarGetter,
+    pub set: CvarSetter,
+}
+
+pub struct ConsoleCommand {
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub handler: CommandHandler,
+}
+
+/// Every cvar and command known to the console.
+#[derive(Resource, Default)]
+pub struct ConsoleRegistry {
+    pub cvars: BTreeMap<String, Cvar>,
+    pub commands: BTreeMap<String, ConsoleCommand>,
+}
+
+/// Names handled by the executor itself rather than the registry.
+const BUILTINS: &[(&str, &str, &str)] = &[
+    (
+        "help",
+        "help [name]",
+        "List commands and cvars, or describe one",
+    ),
+    ("cvars", "cvars", "Print every cvar with its current value"),
+    (
+        "exec",
+        "exec <file>",
+        "Run a .cfg script from the data directory",
+    ),
+    ("echo", "echo <text>", "Print text"),
+    ("clear", "clear", "Clear the console"),
+];
+
+impl ConsoleRegistry {
+    /// Every command and cvar name, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = BUILTINS
+            .iter()
+            .map(|(name, ..)| *name)
+            .chain(self.commands.keys().map(String::as_str))
+            .chain(self.cvars.keys().map(String::as_str))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Complete the word under the cursor (end of `input`).
+    /// Returns the new input and, when ambiguous, the candidates.
+    pub fn complete(&self, input: &str) -> (String, Vec<String>) {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        let completing_first = words.is_empty() || (words.len() == 1 && !input.ends_with(' '));
+
+        let (prefix, partial, candidates): (String, &str, Vec<&str>) = if completing_first {
+            (
+                String::new(),
+                words.first().copied().unwrap_or(""),
+                self.names(),
+            )
+        } else {
+            let partial = if input.ends_with(' ') {
+                ""
+            } else {
+                words.last().copied().unwrap_or("")
+            };
+            let head = &input[..input.len() - partial.len()];
+            let options: Vec<&str> = match self.cvars.get(words[0]).map(|c| c.kind) {
+                Some(CvarKind::Bool) => vec!["true", "false"],
+                Some(CvarKind::Choice(options)) => options.to_vec(),
+                _ => Vec::new(),
+            };
+            (head.to_string(), partial, options)
+        };
+
+        let matches: Vec<&str> = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(partial))
+            .collect();
+        match matches.as_slice() {
+            [] => (input.to_string(), Vec::new()),
+            [only] => (format!("{}{} ", prefix, only), Vec::new()),
+            many => {
+                let mut common = many[0].to_string();
+                for m in &many[1..] {
+                    while !m.starts_with(common.as_str()) {
+                        common.pop();
+                    }
+                }
+                (
+                    format!("{}{}", prefix, common),
+                    many.iter().map(|m| m.to_string()).collect(),
+                )
+            }
+        }
+    }
+}
+
+/// Lets plugins add their own cvars and console commands.
+pub trait ConsoleAppExt {
+    fn add_cvar(
+        &mut self,
+        name: impl Into<String>,
+        help: &'static str,
+        kind: CvarKind,
+        get: impl Fn(&mut World) -> CvarValue + Send + Sync + 'static,
+        set: impl Fn(&mut World, CvarValue) + Send + Sync + 'static,
+    ) -> &mut Self;
+
+    fn add_console_command(
+        &mut self,
+        name: impl Into<String>,
+        usage: &'static str,
+        help: &'static str,
+        handler: impl Fn(&mut World, &[String]) -> Result<Option<String>, String>
+        + Send
+        + Sync
+        + 'static,
+    ) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_cvar(
+        &mut self,
+        name: impl Into<String>,
+        help: &'static str,
+        kind: CvarKind,
+        get: impl Fn(&mut World) -> CvarValue + Send + Sync + 'static,
+        set: impl Fn(&mut World, CvarValue) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<ConsoleRegistry>()
+            .cvars
+            .insert(
+                name.into(),
+                Cvar {
+                    help,
+                    kind,
+                    get: Box::new(get),
+                    set: Box::new(set),
+                },
+            );
+        self
+    }
+
+    fn add_console_command(
+        &mut self,
+        name: impl Into<String>,
+        usage: &'static str,
+        help: &'static str,
+        handler: impl Fn(&mut World, &[String]) -> Result<Option<String>, String>
+        + Send
+        + Sync
+        + 'static,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<ConsoleRegistry>()
+            .commands
+            .insert(
+                name.into(),
+                ConsoleCommand {
+                    usage,
+                    help,
+                    handler: Box::new(handler),
+                },
+            );
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleLineKind {
+    Input,
+    Output,
+    Error,
+}
+
+/// Drop-down console state.
+#[derive(Resource)]
+pub struct ConsoleState {
+    pub open: bool,
+    pub toggle_key: KeyCode,
+    pub input: String,
+    pub output: Vec<(ConsoleLineKind, String)>,
+    pub history: Vec<String>,
+    pub history_cursor: Option<usize>,
+    /// Lines waiting to be executed by `console_exec_system`.
+    pub pending: Vec<String>,
+    /// Set on the frame the console opens, so the toggle key isn't typed into it.
+    pub just_opened: bool,
+}
+
+impl Default for ConsoleState {
+    fn default() -> Self {
+        Self {
+            open: false,
+            toggle_key: KeyCode::Backquote,
+            input: String::new(),
+            output: Vec::new(),
+            history: Vec::new(),
+            history_cursor: None,
+            pending: Vec::new(),
+            just_opened: false,
+        }
+    }
+}
+
+impl ConsoleState {
+    pub fn print(&mut self, kind: ConsoleLineKind, text: impl Into<String>) {
+        for line in text.into().lines() {
+            self.output.push((kind, line.to_string()));
+        }
+        let excess = self.output.len().saturating_sub(MAX_OUTPUT_LINES);
+        self.output.drain(..excess);
+    }
+
+    /// Queue a line for execution and remember it in the history.
+    pub fn submit(&mut self, line: String) {
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        self.history_cursor = None;
+        self.pending.push(line);
+    }
+}
+
+/// Split a line into `;`-separated commands of whitespace-separated words.
+/// Double quotes group words; `//` starts a comment.
+pub fn tokenize(line: &str) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '/' if !in_quotes && chars.peek() == Some(&'/') => break,
+            ';' if !in_quotes => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    commands
+}
+
+/// Run one console line against the world.
+pub fn execute(world: &mut World, registry: &ConsoleRegistry, line: &str, depth: usize) {
+    for words in tokenize(line) {
+        let (name, args) = (words[0].as_str(), &words[1..]);
+        let result = run_command(world, registry, name, args, depth);
+
+        let mut console = world.resource_mut::<ConsoleState>();
+        match result {
+            Ok(Some(output)) => console.print(ConsoleLineKind::Output, output),
+            Ok(None) => {}
+            Err(err) => console.print(ConsoleLineKind::Error, format!("{}: {}", name, err)),
+        }
+    }
+}
+
+fn run_command(
+    world: &mut World,
+    registry: &ConsoleRegistry,
+    name: &str,
+    args: &[String],
+    depth: usize,
+) -> Result<Option<String>, String> {
+    match name {
+        "help" => Ok(Some(help(registry, args.first().map(String::as_str)))),
+        "cvars" => {
+            let mut out = String::new();
+            for (name, cvar) in &registry.cvars {
+                out.push_str(&format!("{} = {}\n", name, (cvar.get)(world)));
+            }
+            Ok(Some(out))
+        }
+        "exec" => {
+            let file = args.first().ok_or("usage: exec <file>")?;
+            exec_file(world, registry, file, depth)
+        }
+        "echo" => Ok(Some(args.join(" "))),
+        "clear" => {
+            world.resource_mut::<ConsoleState>().output.clear();
+            Ok(None)
+        }
+        _ => {
+            if let Some(command) = registry.commands.get(name) {
+                return (command.handler)(world, args);
+            }
+            let cvar = registry
+                .cvars
+                .get(name)
+                .ok_or("unknown command or cvar (try 'help')")?;
+            match args.first() {
+                None => Ok(Some(format!(
+                    "{} = {} {}\n{}",
+                    name,
+                    (cvar.get)(world),
+                    cvar.kind.describe(),
+                    cvar.help
+                ))),
+                Some(input) => {
+                    let value = cvar.kind.parse(input)?;
+                    (cvar.set)(world, value);
+                    Ok(Some(format!("{} = {}", name, (cvar.get)(world))))
+                }
+            }
+        }
+    }
+}
+
+fn help(registry: &ConsoleRegistry, name: Option<&str>) -> String {
+    if let Some(name) = name {
+        if let Some((_, usage, help)) = BUILTINS.iter().find(|(n, ..)| *n == name) {
+            return format!("{}\n  {}", usage, help);
+        }
+        if let Some(command) = registry.commands.get(name) {
+            return format!("{}\n  {}", command.usage, command.help);
+        }
+        if let Some(cvar) = registry.cvars.get(name) {
+            return format!("{} {}\n  {}", name, cvar.kind.describe(), cvar.help);
+        }
+        return format!("no command or cvar named '{}'", name);
+    }
+
+    let mut out = String::from("Commands:\n");
+    for (_, usage, help) in BUILTINS {
+        out.push_str(&format!("  {:<28} {}\n", usage, help));
+    }
+    for command in registry.commands.values() {
+        out.push_str(&format!("  {:<28} {}\n", command.usage, command.help));
+    }
+    out.push_str("Cvars (type a name to read it, 'name value' to set it):\n");
+    for (name, cvar) in &registry.cvars {
+        out.push_str(&format!("  {:<28} {}\n", name, cvar.help));
+    }
+    out
+}
+
+/// Resolve a script name: absolute paths as-is, otherwise relative to the data directory.
+pub fn script_path(file: &str) -> PathBuf {
+    let mut path = PathBuf::from(file);
+    if path.extension().is_none() {
+        path.set_extension("cfg");
+    }
+    if path.is_absolute() {
+        path
+    } else {
+        data_dir().join(path)
+    }
+}
+
+fn exec_file(
+    world: &mut World,
+    registry: &ConsoleRegistry,
+    file: &str,
+    depth: usize,
+) -> Result<Option<String>, String> {
+    if depth >= MAX_EXEC_DEPTH {
+        return Err("exec nested too deeply".into());
+    }
+    let path = script_path(file);
+    let script = std::fs::read_to_string(&path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+    for line in script.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        execute(world, registry, line, depth + 1);
+    }
+    info!("Executed {}", path.display());
+    Ok(None)
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConsoleState>()
+            .init_resource::<ConsoleRegistry>()
+            .add_message::<ReloadRequest>()
+            .add_systems(PostStartup, autoexec_system)
+            .add_systems(
+                PreUpdate,
+                console_toggle_system.run_if(resource_exists::<ButtonInput<KeyCode>>),
+            )
+            .add_systems(Update, (console_exec_system, console_reload_system));
+
+        register_builtin_cvars(app);
+        register_builtin_commands(app);
+    }
+}
+
+/// Run condition: true while the console is closed, so gameplay keys are ignored while typing.
+pub fn console_closed(console: Res<ConsoleState>) -> bool {
+    !console.open
+}
+
+pub fn console_toggle_system(keys: Res<ButtonInput<KeyCode>>, mut console: ResMut<ConsoleState>) {
+    console.just_opened = false;
+    if keys.just_pressed(console.toggle_key) {
+        console.open = !console.open;
+        console.just_opened = console.open;
+    }
+}
+
+/// Execute queued console lines.
+pub fn console_exec_system(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<ConsoleState>().pending);
+    if pending.is_empty() {
+        return;
+    }
+
+    world.resource_scope(|world, registry: Mut<ConsoleRegistry>| {
+        for line in pending {
+            world
+                .resource_mut::<ConsoleState>()
+                .print(ConsoleLineKind::Input, format!("> {}", line));
+            execute(world, &registry, &line, 0);
+        }
+    });
+}
+
+/// Feeds stdin lines into the console and prints its output, for headless runs.
+pub struct StdinConsolePlugin;
+
+#[derive(Resource)]
+pub struct StdinConsole {
+    pub receiver: Receiver<String>,
+}
+
+impl Plugin for StdinConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        std::thread::Builder::new()
+            .name("stdin console".into())
+            .spawn(move || {
+                for line in std::io::stdin().lines().map_while(Result::ok) {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn stdin reader");
+
+        app.insert_resource(StdinConsole { receiver }).add_systems(
+            Update,
+            (
+                stdin_console_input_system.before(console_exec_system),
+                stdin_console_output_system.after(console_exec_system),
+            ),
+        );
+    }
+}
+
+pub fn stdin_console_input_system(stdin: Res<StdinConsole>, mut console: ResMut<ConsoleState>) {
+    for line in stdin.receiver.try_iter() {
+        console.submit(line);
+    }
+}
+
+/// Print new console output to stdout; there is no scrollback to keep without a UI.
+pub fn stdin_console_output_system(mut console: ResMut<ConsoleState>) {
+    if console.output.is_empty() {
+        return;
+    }
+    for (kind, line) in console.output.drain(..) {
+        match kind {
+            ConsoleLineKind::Error => eprintln!("{}", line),
+            ConsoleLineKind::Input | ConsoleLineKind::Output => println!("{}", line),
+        }
+    }
+}
+
+fn queue_autoexec(console: &mut ConsoleState) {
+    if script_path(AUTOEXEC_CFG).exists() {
+        console.pending.push(format!("exec {}", AUTOEXEC_CFG));
+    }
+}
+
+pub fn autoexec_system(mut console: ResMut<ConsoleState>) {
+    queue_autoexec(&mut console);
+}
+
+/// Resolve the config layers again, apply them, then re-run autoexec.cfg on top.
+pub fn console_reload_system(
+    mut commands: Commands,
+    mut reloads: MessageReader<ReloadRequest>,
+    mut console: ResMut<ConsoleState>,
+    cli: Option<Res<CliArgs>>,
+    mut settings: ResMut<GlobalSettings>,
+    mut fps_cap: ResMut<FpsCap>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if reloads.read().count() == 0 {
+        return;
+    }
+    if let Some(cli) = cli {
+        let config = ResolvedConfig::resolve(&cli);
+        config.report();
+        config.apply_to_settings(&mut settings, &mut fps_cap);
+        if let Ok(mut window) = windows.single_mut() {
+            config.apply_to_window(&mut window);
+        }
+        commands.insert_resource(config);
+    }
+    queue_autoexec(&mut console);
+}
+
+fn primary_window(world: &mut World) -> Option<Mut<'_, Window>> {
+    world
+        .query_filtered::<&mut Window, With<PrimaryWindow>>()
+        .single_mut(world)
+        .ok()
+}
+
+fn set_fps_mode(world: &mut World, mode: FpsMode) {
+    world.resource_mut::<FpsCap>().mode = mode;
+    let present_mode = mode.present_mode();
+    world
+        .resource_mut::<GlobalSettings>()
+        .game_settings
+        .present_mode = present_mode;
+    if let Some(mut window) = primary_window(world) {
+        window.present_mode = present_mode;
+    }
+}
+
+fn register_builtin_cvars(app: &mut App) {
+    app.add_cvar(
+        "fps_cap",
+        "Frame rate cap, 0 for uncapped",
+        CvarKind::Int { min: 0, max: 1000 },
+        |world| match world.resource::<FpsCap>().mode {
+            FpsMode::Manual(n) => CvarValue::Int(n as i64),
+            FpsMode::VSync | FpsMode::Uncapped => CvarValue::Int(0),
+        },
+        |world, value| {
+            let mode = match value.as_int() {
+                0 => FpsMode::Uncapped,
+                n => FpsMode::Manual(n as u32),
+            };
+            set_fps_mode(world, mode);
+        },
+    )
+    .add_cvar(
+        "vsync",
+        "Lock the frame rate to the display",
+        CvarKind::Bool,
+        |world| CvarValue::Bool(world.resource::<FpsCap>().mode == FpsMode::VSync),
+        |world, value| {
+            let mode = if value.as_bool() {
+                FpsMode::VSync
+            } else {
+                FpsMode::Uncapped
+            };
+            set_fps_mode(world, mode);
+        },
+    )
+    .add_cvar(
+        "present_mode",
+        "Swapchain present mode",
+        CvarKind::Choice(&PRESENT_MODE_NAMES),
+        |world| {
+            let mode = world
+                .resource::<GlobalSettings>()
+                .game_settings
+                .present_mode;
+            let name = PRESENT_MODES
+                .iter()
+                .find(|(_, m)| *m == mode)
+                .map(|(name, _)| *name)
+                .unwrap_or("fifo");
+            CvarValue::Text(name.to_string())
+        },
+        |world, value| {
+            if let Some((_, mode)) = PRESENT_MODES.iter().find(|(n, _)| *n == value.as_str()) {
+                world
+                    .resource_mut::<GlobalSettings>()
+                    .game_settings
+                    .present_mode = *mode;
+                if let Some(mut window) = primary_window(world) {
+                    window.present_mode = *mode;
+                }
+            }
+        },
+    )
+    .add_cvar(
+        "render_distance",
+        "View distance in chunks",
+        CvarKind::Int { min: 4, max: 96 },
+        |world| {
+            CvarValue::Int(
+                world
+                    .resource::<GlobalSettings>()
+                    .game_settings
+                    .render_distance as i64,
+            )
+        },
+        |world, value| {
+            world
+                .resource_mut::<GlobalSettings>()
+                .game_settings
+                .render_distance = value.as_int() as u8;
+        },
+    )
+    .add_cvar(
+        "window_mode",
+        "Windowed, borderless or exclusive fullscreen",
+        CvarKind::Choice(WINDOW_MODES),
+        |world| {
+            let name = match primary_window(world).map(|w| w.mode) {
+                Some(WindowMode::BorderlessFullscreen(_)) => "borderless",
+                Some(WindowMode::Fullscreen(..)) => "fullscreen",
+                _ => "windowed",
+            };
+            CvarValue::Text(name.to_string())
+        },
+        |world, value| {
+            let mode = match value.as_str() {
+                "borderless" => WindowMode::BorderlessFullscreen(MonitorSelection::Current),
+                "fullscreen" => {
+                    WindowMode::Fullscreen(MonitorSelection::Current, VideoModeSelection::Current)
+                }
+                _ => WindowMode::Windowed,
+            };
+            if let Some(mut window) = primary_window(world) {
+                window.mode = mode;
+            }
+        },
+    )
+    .add_cvar(
+        "debug_overlay",
+        "Show the F3 debug overlay",
+        CvarKind::Bool,
+        |world| {
+            CvarValue::Bool(
+                world
+                    .resource::<GlobalSettings>()
+                    .flags
+                    .contains(GlobalFlags::DEBUG_OVERLAY),
+            )
+        },
+        |world, value| {
+            world
+                .resource_mut::<GlobalSettings>()
+                .flags
+                .set(GlobalFlags::DEBUG_OVERLAY, value.as_bool());
+        },
+    )
+    .add_cvar(
+        "console_key",
+        "Key that opens this console",
+        CvarKind::Choice(&CONSOLE_KEY_NAMES),
+        |world| {
+            let key = world.resource::<ConsoleState>().toggle_key;
+            let name = CONSOLE_KEYS
+                .iter()
+                .find(|(_, k)| *k == key)
+                .map(|(name, _)| *name)
+                .unwrap_or("backquote");
+            CvarValue::Text(name.to_string())
+        },
+        |world, value| {
+            if let Some((_, key)) = CONSOLE_KEYS.iter().find(|(n, _)| *n == value.as_str()) {
+                world.resource_mut::<ConsoleState>().toggle_key = *key;
+            }
+        },
+    );
+
+    // one boolean per debug flag, e.g. `debug.chunk_borders`
+    for (name, flag) in DebugFlags::all().iter_names() {
+        app.add_cvar(
+            format!("debug.{}", name.to_ascii_lowercase()),
+            "Debug overlay toggle",
+            CvarKind::Bool,
+            move |world| {
+                CvarValue::Bool(world.resource::<GlobalSettings>().dbg_flags.contains(flag))
+            },
+            move |world, value| {
+                world
+                    .resource_mut::<GlobalSettings>()
+                    .dbg_flags
+                    .set(flag, value.as_bool());
+            },
+        );
+    }
+}
+
+fn register_builtin_commands(app: &mut App) {
+    app.add_console_command(
+        "connect",
+        "connect <host[:port]> [bedrock|java]",
+        "Join a server",
+        |world, args| {
+            let address = args
+                .first()
+                .ok_or("usage: connect <host[:port]> [edition]")?;
+            let edition = match args.get(1) {
+                Some(edition) => edition.parse::<Edition>()?,
+                None => world
+                    .get_resource::<ResolvedConfig>()
+                    .map(ResolvedConfig::edition)
+                    .unwrap_or_default(),
+            };
+            let address = ServerAddress::parse(address, edition)?;
+            let message = format!("Connecting to {} ({})", address, edition);
+            world.write_message(ConnectRequest { address, edition });
+            Ok(Some(message))
+        },
+    )
+    .add_console_command(
+        "say",
+        "say <message>",
+        "Send a chat message, or a command if it starts with /",
+        |world, args| {
+            if args.is_empty() {
+                return Err("usage: say <message>".into());
+            }
+            let message = args.join(" ");
+            let action = match message.strip_prefix('/') {
+                Some(command) => PlayerAction::Command(command.to_string()),
+                None => PlayerAction::Chat(message),
+            };
+            world.write_message(SendAction(action));
+            Ok(None)
+        },
+    )
+    .add_console_command(
+        "disconnect",
+        "disconnect",
+        "Leave the current server",
+        |world, _| {
+            world.write_message(DisconnectRequest);
+            Ok(None)
+        },
+    )
+    .add_console_command(
+        "screenshot",
+        "screenshot",
+        "Save a screenshot to the screenshots folder",
+        |world, _| {
+            if primary_window(world).is_none() {
+                return Err("no window to capture".into());
+            }
+            let path =
+                screenshots_dir().join(format!("{}.png", Local::now().format("%Y-%m-%d_%H.%M.%S")));
+            std::fs::create_dir_all(screenshots_dir()).map_err(|err| err.to_string())?;
+            world
+                .spawn(Screenshot::primary_window())
+                .observe(save_to_disk(path.clone()));
+            Ok(Some(format!("Saving screenshot to {}", path.display())))
+        },
+    )
+    .add_console_command(
+        "reload",
+        "reload",
+        "Reload configuration and re-run autoexec.cfg",
+        |world, _| {
+            world.write_message(ReloadRequest);
+            Ok(Some("Reloading".into()))
+        },
+    )
+    .add_console_command("quit", "quit", "Exit the game", |world, _| {
+        world.write_message(AppExit::Success);
+        Ok(None)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigLayer;
+
+    fn words(commands: &[&[&str]]) -> Vec<Vec<String>> {
+        commands
+            .iter()
+            .map(|words| words.iter().map(|w| w.to_string()).collect())
+            .collect()
+    }
+
+    fn registry() -> ConsoleRegistry {
+        let mut registry = ConsoleRegistry::default();
+        for (name, kind) in [
+            ("present_mode", CvarKind::Choice(&PRESENT_MODE_NAMES)),
+            ("vsync", CvarKind::Bool),
+            ("fps_cap", CvarKind::Int { min: 0, max: 1000 }),
+        ] {
+            registry.cvars.insert(
+                name.into(),
+                Cvar {
+                    help: "",
+                    kind,
+                    get: Box::new(|_| CvarValue::Bool(false)),
+                    set: Box::new(|_, _| {}),
+                },
+            );
+        }
+        registry.commands.insert(
+            "connect".into(),
+            ConsoleCommand {
+                usage: "",
+                help: "",
+                handler: Box::new(|_, _| Ok(None)),
+            },
+        );
+        registry
+    }
+
+    #[test]
+    fn tokenize_splits_commands_and_words() {
+        assert_eq!(tokenize(""), words(&[]));
+        assert_eq!(tokenize("   ;; "), words(&[]));
+        assert_eq!(
+            tokenize("fps_cap 60; vsync   off"),
+            words(&[&["fps_cap", "60"], &["vsync", "off"]])
+        );
+        assert_eq!(tokenize("echo hi // not this"), words(&[&["echo", "hi"]]));
+    }
+
+    #[test]
+    fn tokenize_quotes() {
+        assert_eq!(
+            tokenize(r#"echo "a; b // c" d"#),
+            words(&[&["echo", "a; b // c", "d"]])
+        );
+        assert_eq!(tokenize(r#"say a"b c"d"#), words(&[&["say", "ab cd"]]));
+        // an empty quoted word is dropped, an unclosed quote runs to the end
+        assert_eq!(tokenize(r#"echo "" x"#), words(&[&["echo", "x"]]));
+        assert_eq!(tokenize(r#"echo "a; b"#), words(&[&["echo", "a; b"]]));
+    }
+
+    #[test]
+    fn complete_names() {
+        let registry = registry();
+        assert_eq!(registry.complete("con"), ("connect ".into(), vec![]));
+        assert_eq!(registry.complete("zzz"), ("zzz".into(), vec![]));
+        let (input, candidates) = registry.complete("c");
+        assert_eq!(input, "c");
+        assert_eq!(candidates, ["clear", "connect", "cvars"]);
+        let (input, candidates) = registry.complete("");
+        assert_eq!(input, "");
+        assert_eq!(candidates.len(), BUILTINS.len() + 4);
+    }
+
+    #[test]
+    fn complete_values() {
+        let registry = registry();
+        assert_eq!(registry.complete("vsync t"), ("vsync true ".into(), vec![]));
+        assert_eq!(
+            registry.complete("present_mode fifo"),
+            (
+                "present_mode fifo".into(),
+                vec!["fifo".into(), "fifo_relaxed".into()]
+            )
+        );
+        let (input, candidates) = registry.complete("present_mode auto");
+        assert_eq!(input, "present_mode auto_");
+        assert_eq!(candidates, ["auto_vsync", "auto_no_vsync"]);
+        let (_, candidates) = registry.complete("present_mode ");
+        assert_eq!(candidates, PRESENT_MODE_NAMES);
+        // numbers and commands have nothing to offer
+        assert_eq!(registry.complete("fps_cap 6"), ("fps_cap 6".into(), vec![]));
+        assert_eq!(registry.complete("connect "), ("connect ".into(), vec![]));
+    }
+
+    #[test]
+    fn choice_names_follow_their_tables() {
+        assert_eq!(PRESENT_MODE_NAMES.len(), PRESENT_MODES.len());
+        assert_eq!(CONSOLE_KEY_NAMES[0], "backquote");
+        assert_eq!(CONSOLE_KEY_NAMES.last(), Some(&"f12"));
+    }
+
+    #[test]
+    fn reload_resolves_the_config_again() {
+        let settings =
+            std::env::temp_dir().join(format!("rustcraft-reload-{}.toml", std::process::id()));
+        std::fs::write(&settings, "username = \"Before\"\n").unwrap();
+        let mut app = App::new();
+        app.add_message::<ReloadRequest>()
+            .init_resource::<ConsoleState>()
+            .insert_resource(GlobalSettings::default())
+            .insert_resource(FpsCap::default())
+            .insert_resource(CliArgs {
+                config: Some(settings.clone()),
+                ..default()
+            })
+            .add_systems(Update, console_reload_system);
+
+        // changed on disk after startup
+        std::fs::write(&settings, "fps_cap = 60\nusername = \"After\"\n").unwrap();
+        app.world_mut().write_message(ReloadRequest);
+        app.update();
+        std::fs::remove_file(&settings).unwrap();
+
+        let world = app.world();
+        assert_eq!(world.resource::<FpsCap>().mode, FpsMode::Manual(60));
+        assert_eq!(
+            world.resource::<GlobalSettings>().game_settings.username,
+            "After"
+        );
+        let config = world.resource::<ResolvedConfig>();
+        assert_eq!(config.entry("fps_cap").source, ConfigLayer::UserSettings);
+        assert!(config.files.iter().any(|(_, path)| *path == settings));
+    }
+}
@@ -0,0 +1,595 @@
+use bevy::prelude::*;
+use bevy::render::renderer::RenderAdapterInfo;
+use bevy_egui::{EguiContexts, EguiPlugin, EguiPrimaryContextPass, egui};
+use chrono::{DateTime, Local};
+use std::backtrace::Backtrace;
+use std::cell::UnsafeCell;
+use std::fmt::Write;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use sysinfo::System;
+
+use crate::data::{FpsCap, GlobalFlags, GlobalSettings, SysInfo};
+use crate::logging::recent_logs;
+use crate::paths::crash_reports_dir;
+
+/// Marker file pointing at the report the user hasn't seen yet.
+const PENDING_MARKER: &str = ".pending";
+/// Opened at startup for the signal handler to write into; it is only non-empty after a
+/// fatal signal, and becomes a normal report on the next launch.
+const SIGNAL_REPORT: &str = ".signal-report";
+/// Room for the crash context in the buffer the signal handler reads.
+const SIGNAL_CONTEXT_SIZE: usize = 16 * 1024;
+
+/// Everything the crash handlers need, kept up to date while the game runs
+/// so nothing has to touch the ECS world while crashing.
+struct CrashContext {
+    system: String,
+    settings: String,
+    game_state: String,
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    system: String::new(),
+    settings: String::new(),
+    game_state: String::new(),
+});
+
+/// Set once the first report has been written, so a panic that turns into an
+/// abort doesn't produce two reports.
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+/// What the signal handler writes, prepared before any signal arrives: a signal handler
+/// may only call async-signal-safe functions, so it can't allocate, lock, format or open
+/// files itself.
+struct SignalReport {
+    fd: libc::c_int,
+    header: String,
+}
+
+static SIGNAL_REPORT_FILE: OnceLock<SignalReport> = OnceLock::new();
+
+/// The crash context as text, for the signal handler. The context system bumps `sequence`
+/// to an odd number while it copies, so a handler that interrupts a copy leaves it out.
+struct SignalContext {
+    sequence: AtomicUsize,
+    len: AtomicUsize,
+    text: UnsafeCell<[u8; SIGNAL_CONTEXT_SIZE]>,
+}
+
+// SAFETY: only `crash_context_system` writes `text`, and readers check `sequence`.
+unsafe impl Sync for SignalContext {}
+
+static SIGNAL_CONTEXT: SignalContext = SignalContext {
+    sequence: AtomicUsize::new(0),
+    len: AtomicUsize::new(0),
+    text: UnsafeCell::new([0; SIGNAL_CONTEXT_SIZE]),
+};
+
+impl SignalContext {
+    fn publish(&self, text: &str) {
+        let len = text.len().min(SIGNAL_CONTEXT_SIZE);
+        self.sequence.fetch_add(1, Ordering::SeqCst);
+        // SAFETY: this is the only writer, and the odd sequence keeps handlers out.
+        unsafe { std::ptr::copy_nonoverlapping(text.as_ptr(), self.text.get().cast(), len) };
+        self.len.store(len, Ordering::SeqCst);
+        self.sequence.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// The published text, unless a copy is in progress.
+    fn get(&self) -> Option<&[u8]> {
+        if self.sequence.load(Ordering::SeqCst) % 2 == 1 {
+            return None;
+        }
+        let len = self.len.load(Ordering::SeqCst);
+        // SAFETY: `len` bytes were fully written before the sequence became even.
+        Some(unsafe { std::slice::from_raw_parts(self.text.get().cast(), len) })
+    }
+}
+
+/// Report left behind by the previous session, shown in a dialog on launch.
+#[derive(Resource, Default)]
+pub struct PendingCrashReport {
+    pub report: Option<(PathBuf, String)>,
+}
+
+/// Debug crash shortcut state (Ctrl+F3 to panic, Ctrl+Alt+F3 to abort).
+#[derive(Resource, Default)]
+pub struct CrashTest {
+    pub countdown: Option<Timer>,
+    pub abort: bool,
+}
+
+impl CrashTest {
+    pub const SECONDS: u64 = 10;
+
+    /// Start the countdown unless one is already running.
+    pub fn start(&mut self, abort: bool) {
+        if self.countdown.is_some() {
+            return;
+        }
+        warn!(
+            "CRASH TEST: {} in {} seconds...",
+            if abort { "aborting" } else { "panicking" },
+            Self::SECONDS
+        );
+        self.countdown = Some(Timer::new(
+            Duration::from_secs(Self::SECONDS),
+            TimerMode::Once,
+        ));
+        self.abort = abort;
+    }
+}
+
+pub struct CrashPlugin;
+
+impl Plugin for CrashPlugin {
+    fn build(&self, app: &mut App) {
+        // before the signal handlers truncate the last session's signal report
+        let report = load_pending_report();
+        install_panic_hook();
+        install_signal_handlers();
+
+        app.insert_resource(PendingCrashReport { report })
+            .init_resource::<CrashTest>()
+            .add_systems(Update, (crash_context_system, crash_test_system));
+
+        // headless runs have no UI to show the dialog in
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.add_systems(EguiPrimaryContextPass, crash_dialog_system);
+        } else {
+            app.add_systems(Startup, crash_notice_system);
+        }
+    }
+}
+
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        let reason = format!(
+            "Panic in thread '{}': {}",
+            thread.name().unwrap_or("<unnamed>"),
+            info
+        );
+        write_crash_report(&reason, &Backtrace::force_capture());
+        default_hook(info);
+    }));
+}
+
+#[cfg(unix)]
+const FATAL_SIGNALS: &[libc::c_int] = &[
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+];
+
+#[cfg(windows)]
+const FATAL_SIGNALS: &[libc::c_int] = &[libc::SIGSEGV, libc::SIGILL, libc::SIGFPE, libc::SIGABRT];
+
+fn install_signal_handlers() {
+    if let Some(report) = open_signal_report() {
+        let _ = SIGNAL_REPORT_FILE.set(report);
+    }
+    for &signal in FATAL_SIGNALS {
+        // SAFETY: `fatal_signal_handler` has the signature `signal` expects.
+        unsafe {
+            libc::signal(
+                signal,
+                fatal_signal_handler as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+/// Open the signal report file and format everything the handler can know in advance.
+fn open_signal_report() -> Option<SignalReport> {
+    let dir = crash_reports_dir();
+    let file = std::fs::create_dir_all(&dir)
+        .and_then(|_| File::create(dir.join(SIGNAL_REPORT)))
+        .inspect_err(|err| warn!("Cannot prepare the signal crash report: {}", err))
+        .ok()?;
+
+    let mut header = String::new();
+    let _ = writeln!(header, "---- RustCraft Crash Report ----");
+    let _ = writeln!(
+        header,
+        "Session started: {}",
+        Local::now().format("%Y-%m-%d %H:%M:%S %z")
+    );
+    let _ = writeln!(header, "Version: {}", env!("CARGO_PKG_VERSION"));
+    Some(SignalReport {
+        fd: into_raw_fd(file)?,
+        header,
+    })
+}
+
+#[cfg(unix)]
+fn into_raw_fd(file: File) -> Option<libc::c_int> {
+    use std::os::fd::IntoRawFd;
+    Some(file.into_raw_fd())
+}
+
+#[cfg(windows)]
+fn into_raw_fd(file: File) -> Option<libc::c_int> {
+    use std::os::windows::io::IntoRawHandle;
+    // SAFETY: the handle is owned and handed over to the C runtime for good.
+    let fd = unsafe { libc::open_osfhandle(file.into_raw_handle() as libc::intptr_t, 0) };
+    (fd >= 0).then_some(fd)
+}
+
+/// Write the prepared report and let the default handler terminate the process. Only
+/// async-signal-safe calls are made here: `write` on the file opened at startup, then
+/// restoring the default disposition and re-raising.
+extern "C" fn fatal_signal_handler(signal: libc::c_int) {
+    if !CRASHED.swap(true, Ordering::SeqCst)
+        && let Some(report) = SIGNAL_REPORT_FILE.get()
+    {
+        write_signal_report(report.fd, report.header.as_bytes(), signal);
+    }
+
+    // SAFETY: restoring the default disposition and re-raising is the documented way
+    // to terminate with the original signal.
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+fn write_signal_report(fd: libc::c_int, header: &[u8], signal: libc::c_int) {
+    let write = |bytes: &[u8]| {
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            // SAFETY: `rest` is valid for its length; `write` is async-signal-safe.
+            let written = unsafe { libc::write(fd, rest.as_ptr().cast(), rest.len() as _) };
+            if written <= 0 {
+                return;
+            }
+            rest = &rest[written as usize..];
+        }
+    };
+
+    let mut number = [0u8; 12];
+    write(header);
+    write(b"Description: Fatal signal ");
+    write(format_decimal(signal, &mut number));
+    if let Some(name) = signal_name(signal) {
+        write(b" (");
+        write(name.as_bytes());
+        write(b")");
+    }
+    write(b"\n\n-- Backtrace --\n<not available from a signal handler>\n\n");
+    match SIGNAL_CONTEXT.get() {
+        Some(context) => write(context),
+        None => write(b"-- System Details --\n<unavailable>\n\n"),
+    }
+    write(b"-- Recent Log --\n<see latest.log>\n");
+}
+
+/// `value` in decimal, without allocating.
+fn format_decimal(value: libc::c_int, buf: &mut [u8; 12]) -> &[u8] {
+    let mut rest = (value as i64).unsigned_abs();
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    if value < 0 {
+        start -= 1;
+        buf[start] = b'-';
+    }
+    &buf[start..]
+}
+
+fn signal_name(signal: libc::c_int) -> Option<&'static str> {
+    Some(match signal {
+        libc::SIGSEGV => "SIGSEGV",
+        #[cfg(unix)]
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGILL => "SIGILL",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGABRT => "SIGABRT",
+        _ => return None,
+    })
+}
+
+/// Turn a report the signal handler wrote last session into a normal, pending one.
+fn adopt_signal_report() {
+    let dir = crash_reports_dir();
+    let signal_report = dir.join(SIGNAL_REPORT);
+    let Ok(metadata) = std::fs::metadata(&signal_report) else {
+        return;
+    };
+    if metadata.len() == 0 {
+        return;
+    }
+    let crashed_at: DateTime<Local> = metadata
+        .modified()
+        .map(DateTime::from)
+        .unwrap_or_else(|_| Local::now());
+    let path = dir.join(format!(
+        "crash-{}.txt",
+        crashed_at.format("%Y-%m-%d_%H.%M.%S")
+    ));
+    let adopted = std::fs::rename(&signal_report, &path)
+        .and_then(|_| std::fs::write(dir.join(PENDING_MARKER), path.to_string_lossy().as_bytes()));
+    if let Err(err) = adopted {
+        warn!("Cannot keep the last session's crash report: {}", err);
+    }
+}
+
+/// Write a crash report to the crash report directory and mark it as pending.
+pub fn write_crash_report(reason: &str, backtrace: &Backtrace) -> Option<PathBuf> {
+    if CRASHED.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+
+    let now = Local::now();
+    let mut report = String::new();
+    let _ = writeln!(report, "---- RustCraft Crash Report ----");
+    let _ = writeln!(report, "Time: {}", now.format("%Y-%m-%d %H:%M:%S %z"));
+    let _ = writeln!(report, "Version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "Description: {}", reason);
+
+    let _ = writeln!(report, "\n-- Backtrace --\n{}", backtrace);
+
+    // the context lock may be held by the crashing thread; don't wait for it
+    match CONTEXT.try_lock() {
+        Ok(context) => {
+            let _ = writeln!(report, "-- System Details --\n{}", context.system);
+            let _ = writeln!(report, "-- Game State --\n{}", context.game_state);
+            let _ = writeln!(report, "-- Settings --\n{}", context.settings);
+        }
+        Err(_) => {
+            let _ = writeln!(report, "-- System Details --\n<unavailable>\n");
+        }
+    }
+
+    let _ = writeln!(report, "-- Recent Log --");
+    for line in recent_logs() {
+        let _ = writeln!(report, "{}", line);
+    }
+
+    let dir = crash_reports_dir();
+    let path = dir.join(format!("crash-{}.txt", now.format("%Y-%m-%d_%H.%M.%S")));
+    let written = std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&path, &report))
+        .and_then(|_| std::fs::write(dir.join(PENDING_MARKER), path.to_string_lossy().as_bytes()));
+
+    match written {
+        Ok(()) => {
+            eprintln!("Crash report saved to {}", path.display());
+            Some(path)
+        }
+        Err(err) => {
+            eprintln!("Could not save crash report: {}\n{}", err, report);
+            None
+        }
+    }
+}
+
+/// Load the report the previous session left behind, if any.
+fn load_pending_report() -> Option<(PathBuf, String)> {
+    adopt_signal_report();
+    let marker = crash_reports_dir().join(PENDING_MARKER);
+    let path = PathBuf::from(std::fs::read_to_string(&marker).ok()?.trim());
+    match std::fs::read_to_string(&path) {
+        Ok(report) => Some((path, report)),
+        Err(_) => {
+            // the report itself is gone, so there's nothing left to offer
+            let _ = std::fs::remove_file(&marker);
+            None
+        }
+    }
+}
+
+/// Keep the crash context in sync with the game.
+pub fn crash_context_system(
+    sysinfo: Option<Res<SysInfo>>,
+    adapter: Option<Res<RenderAdapterInfo>>,
+    global_settings: Res<GlobalSettings>,
+    cap: Res<FpsCap>,
+    windows: Query<Ref<Window>>,
+) {
+    let system_changed = sysinfo.as_ref().is_some_and(|s| s.is_added())
+        || adapter.as_ref().is_some_and(|a| a.is_added());
+    let state_changed =
+        global_settings.is_changed() || cap.is_changed() || windows.iter().any(|w| w.is_changed());
+    if !system_changed && !state_changed {
+        return;
+    }
+
+    let Ok(mut context) = CONTEXT.lock() else {
+        return;
+    };
+
+    if system_changed {
+        let mut system = String::new();
+        let _ = writeln!(
+            system,
+            "OS: {} ({})",
+            System::long_os_version().unwrap_or_default(),
+            System::cpu_arch()
+        );
+        let _ = writeln!(
+            system,
+            "Kernel: {}",
+            System::kernel_version().unwrap_or_default()
+        );
+        if let Some(sysinfo) = &sysinfo {
+            let cpus = sysinfo.sys.cpus();
+            let _ = writeln!(
+                system,
+                "CPU: {} ({} threads, {} cores)",
+                cpus.first().map(|c| c.brand()).unwrap_or("unknown"),
+                cpus.len(),
+                System::physical_core_count().unwrap_or_default()
+            );
+            let _ = writeln!(
+                system,
+                "Memory: {} MB",
+                sysinfo.sys.total_memory() / 1048576
+            );
+        }
+        if let Some(adapter) = &adapter {
+            let _ = writeln!(
+                system,
+                "GPU: {} ({:?}, {} {})",
+                adapter.name, adapter.backend, adapter.driver, adapter.driver_info
+            );
+        }
+        context.system = system;
+    }
+
+    if state_changed {
+        let mut game_state = String::new();
+        let _ = writeln!(
+            game_state,
+            "In game: {}",
+            global_settings.flags.contains(GlobalFlags::IN_GAME)
+        );
+        let _ = writeln!(
+            game_state,
+            "Loading: {}",
+            global_settings.flags.contains(GlobalFlags::IS_LOADING)
+        );
+        let _ = writeln!(game_state, "FPS cap: {:?}", cap.mode);
+        for window in &windows {
+            let _ = writeln!(
+                game_state,
+                "Window: {}x{} {:?} {:?}",
+                window.resolution.width(),
+                window.resolution.height(),
+                window.mode,
+                window.present_mode
+            );
+        }
+        context.game_state = game_state;
+        context.settings = format!("{:#?}", *global_settings);
+    }
+
+    SIGNAL_CONTEXT.publish(&format!(
+        "-- System Details --\n{}\n-- Game State --\n{}\n-- Settings --\n{}\n",
+        context.system, context.game_state, context.settings
+    ));
+}
+
+/// Count down a requested crash test and then crash on purpose.
+pub fn crash_test_system(time: Res<Time>, mut crash_test: ResMut<CrashTest>) {
+    let abort = crash_test.abort;
+    let Some(countdown) = crash_test.countdown.as_mut() else {
+        return;
+    };
+
+    let before = countdown.remaining_secs().ceil();
+    countdown.tick(time.delta());
+    let after = countdown.remaining_secs().ceil();
+    if after < before && after > 0.0 {
+        warn!("CRASH TEST: Crashing in {}s", after);
+    }
+
+    if countdown.is_finished() {
+        if abort {
+            std::process::abort();
+        }
+        panic!("Manually triggered debug crash");
+    }
+}
+
+/// Headless replacement for the crash dialog: point at the report in the log.
+pub fn crash_notice_system(mut pending: ResMut<PendingCrashReport>) {
+    if let Some((path, _)) = pending.report.take() {
+        warn!(
+            "RustCraft crashed during the last session; see {}",
+            path.display()
+        );
+        let _ = std::fs::remove_file(crash_reports_dir().join(PENDING_MARKER));
+    }
+}
+
+/// Offer the previous session's crash report to the user.
+pub fn crash_dialog_system(mut contexts: EguiContexts, mut pending: ResMut<PendingCrashReport>) {
+    let Some((path, report)) = &pending.report else {
+        return;
+    };
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+
+    let mut dismissed = false;
+    egui::Window::new("RustCraft crashed")
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .default_size(egui::vec2(640.0, 420.0))
+        .show(ctx, |ui| {
+            ui.label("RustCraft crashed during the last session. A crash report was saved to:");
+            ui.monospace(path.display().to_string());
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    ui.monospace(report.as_str());
+                });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Copy Report").clicked() {
+                    ui.ctx().copy_text(report.clone());
+                }
+                if ui.button("Dismiss").clicked() {
+                    dismissed = true;
+                }
+            });
+        });
+
+    if dismissed {
+        let _ = std::fs::remove_file(crash_reports_dir().join(PENDING_MARKER));
+        pending.report = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimals_without_allocating() {
+        let mut buf = [0u8; 12];
+        assert_eq!(format_decimal(0, &mut buf), b"0");
+        assert_eq!(format_decimal(11, &mut buf), b"11");
+        assert_eq!(format_decimal(-7, &mut buf), b"-7");
+        assert_eq!(
+            format_decimal(libc::c_int::MIN, &mut buf),
+            libc::c_int::MIN.to_string().as_bytes()
+        );
+    }
+
+    #[test]
+    fn signal_report_goes_to_the_prepared_file() {
+        let path =
+            std::env::temp_dir().join(format!("rustcraft-signal-{}.txt", std::process::id()));
+        let fd = into_raw_fd(File::create(&path).unwrap()).unwrap();
+        SIGNAL_CONTEXT.publish("-- System Details --\nOS: test\n");
+        write_signal_report(fd, b"---- RustCraft Crash Report ----\n", libc::SIGSEGV);
+        // SAFETY: the descriptor was opened above and is not used again.
+        unsafe { libc::close(fd) };
+
+        let report = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(report.starts_with("---- RustCraft Crash Report ----\n"));
+        assert!(report.contains(&format!(
+            "Description: Fatal signal {} (SIGSEGV)\n",
+            libc::SIGSEGV
+        )));
+        assert!(report.contains("-- System Details --\nOS: test\n"));
+        assert!(report.ends_with("-- Recent Log --\n<see latest.log>\n"));
+    }
+}
@@ -4,6 +4,7 @@ use std::time::{Duration, Instant};
 use sysinfo::System;
 
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct GlobalFlags: u8 {
         const IS_DEBUG = 1 << 0;
         const IS_LOADING = 1 << 1;
@@ -17,7 +18,8 @@ bitflags! {
 }
 
 bitflags! {
-    pub struct DebugFlags: u8 {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DebugFlags: u16 {
         const VSYNC = 1 << 0;
         const FPS = 1 << 1;
         const CPU = 1 << 2;
@@ -26,10 +28,14 @@ bitflags! {
         const DISK = 1 << 5;
         const FILES = 1 << 6;
         const RUNTIME = 1 << 7;
+        const CHUNK_BORDERS = 1 << 8;
+        const HITBOXES = 1 << 9;
+        const LIGHT_LEVELS = 1 << 10;
+        const WIREFRAME = 1 << 11;
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Debug)]
 pub struct GlobalSettings {
     pub flags: GlobalFlags,
     pub dbg_flags: DebugFlags,
@@ -49,15 +55,19 @@ impl Default for GlobalSettings {
                 render_distance: 16,
                 fps_cap: 0, // 0 means unlimited
                 present_mode: PresentMode::Fifo,
+                username: "Player".into(),
             },
         }
     }
 }
 
+#[derive(Debug)]
 pub struct GameSettings {
     pub render_distance: u8,
     pub fps_cap: u8,
     pub present_mode: PresentMode,
+    /// Name used when joining servers in offline mode.
+    pub username: String,
 }
 
 #[derive(Debug, Clone)]
@@ -67,6 +77,14 @@ pub struct RecentFile {
     pub extension: Option<String>,
 }
 
+/// Collision box of an entity, anchored at its feet. Drawn by the F3+B hitbox overlay.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Hitbox {
+    pub width: f32,
+    pub height: f32,
+    pub eye_height: f32,
+}
+
 #[derive(Resource)]
 pub struct SysInfo {
     pub sys: System,
@@ -129,6 +147,17 @@ pub enum FpsMode {
     Manual(u32), // cap to N FPS by sleeping + spinning
 }
 
+impl FpsMode {
+    /// Present mode that goes with the cap: Fifo for vsync, Immediate otherwise so the cap
+    /// paces frames itself.
+    pub fn present_mode(self) -> PresentMode {
+        match self {
+            FpsMode::VSync => PresentMode::Fifo,
+            FpsMode::Uncapped | FpsMode::Manual(_) => PresentMode::Immediate,
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct FpsCap {
     pub mode: FpsMode,
@@ -0,0 +1,187 @@
+use bevy::color::palettes::css::{AQUA, BLUE, RED, WHITE, YELLOW};
+use bevy::pbr::wireframe::{WireframeConfig, WireframePlugin};
+use bevy::prelude::*;
+
+use crate::data::{DebugFlags, GlobalSettings, Hitbox};
+use crate::diagnostics::DiagnosticsAppExt;
+use crate::world::{CHUNK_WIDTH, ChunkMap, ChunkPos, SECTION_HEIGHT};
+
+/// How many blocks around the camera get a light-level marker.
+const LIGHT_OVERLAY_RADIUS: i32 = 12;
+
+/// Minecraft style F3+G / F3+B debug visualisations drawn with gizmos.
+pub struct DebugGizmosPlugin;
+
+impl Plugin for DebugGizmosPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(WireframePlugin::default())
+            .init_resource::<ChunkMap>()
+            .add_diagnostics_toggle("Visualisations", "Chunk Borders", DebugFlags::CHUNK_BORDERS)
+            .add_diagnostics_toggle("Visualisations", "Hitboxes", DebugFlags::HITBOXES)
+            .add_diagnostics_toggle("Visualisations", "Light Levels", DebugFlags::LIGHT_LEVELS)
+            .add_diagnostics_toggle("Visualisations", "Wireframes", DebugFlags::WIREFRAME)
+            .add_systems(
+                Update,
+                (
+                    chunk_border_gizmos,
+                    hitbox_gizmos,
+                    light_level_gizmos,
+                    wireframe_toggle_system,
+                ),
+            );
+    }
+}
+
+/// Position the debug overlays are centred on: the first 3D camera, if any.
+fn viewer_position(cameras: &Query<&GlobalTransform, With<Camera3d>>) -> Option<Vec3> {
+    cameras.iter().next().map(|t| t.translation())
+}
+
+/// Draw the borders of the chunk the viewer is in (yellow, with section lines)
+/// and the corners of the surrounding chunks (red).
+pub fn chunk_border_gizmos(
+    mut gizmos: Gizmos,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    chunk_map: Res<ChunkMap>,
+    global_settings: Res<GlobalSettings>,
+) {
+    if !global_settings
+        .dbg_flags
+        .contains(DebugFlags::CHUNK_BORDERS)
+    {
+        return;
+    }
+    let Some(viewer) = viewer_position(&cameras) else {
+        return;
+    };
+
+    let (min_y, max_y) = (chunk_map.min_y as f32, chunk_map.max_y() as f32);
+    let center = ChunkPos::from_world(viewer);
+    let width = CHUNK_WIDTH as f32;
+
+    // neighbouring chunk corners
+    for dx in -1..=2 {
+        for dz in -1..=2 {
+            let corner = ChunkPos::new(center.x + dx, center.z + dz)
+                .origin()
+                .as_vec3();
+            gizmos.line(
+                Vec3::new(corner.x, min_y, corner.z),
+                Vec3::new(corner.x, max_y, corner.z),
+                RED,
+            );
+        }
+    }
+
+    // current chunk: every other block along the edges, plus section boundaries
+    let origin = center.origin().as_vec3();
+    for step in (0..=CHUNK_WIDTH).step_by(2) {
+        let s = step as f32;
+        for (x, z) in [(s, 0.0), (s, width), (0.0, s), (width, s)] {
+            gizmos.line(
+                Vec3::new(origin.x + x, min_y, origin.z + z),
+                Vec3::new(origin.x + x, max_y, origin.z + z),
+                YELLOW,
+            );
+        }
+    }
+
+    for y in (chunk_map.min_y..=chunk_map.max_y()).step_by(SECTION_HEIGHT as usize) {
+        let y = y as f32;
+        gizmos.linestrip(
+            [
+                Vec3::new(origin.x, y, origin.z),
+                Vec3::new(origin.x + width, y, origin.z),
+                Vec3::new(origin.x + width, y, origin.z + width),
+                Vec3::new(origin.x, y, origin.z + width),
+                Vec3::new(origin.x, y, origin.z),
+            ],
+            AQUA,
+        );
+    }
+}
+
+/// Draw entity bounding boxes, eye height and look vectors.
+pub fn hitbox_gizmos(
+    mut gizmos: Gizmos,
+    entities: Query<(&GlobalTransform, &Hitbox)>,
+    global_settings: Res<GlobalSettings>,
+) {
+    if !global_settings.dbg_flags.contains(DebugFlags::HITBOXES) {
+        return;
+    }
+
+    for (transform, hitbox) in &entities {
+        // entity positions are at the feet, centred horizontally
+        let feet = transform.translation();
+        let half = Vec3::new(hitbox.width, hitbox.height, hitbox.width) / 2.0;
+        gizmos.cube(
+            Transform::from_translation(feet + Vec3::Y * half.y).with_scale(half * 2.0),
+            WHITE,
+        );
+
+        let eye = feet + Vec3::Y * hitbox.eye_height;
+        gizmos.rect(
+            Isometry3d::new(eye, Quat::from_rotation_x(std::f32::consts::FRAC_PI_2)),
+            Vec2::splat(hitbox.width),
+            RED,
+        );
+        gizmos.ray(eye, transform.forward() * 2.0, BLUE);
+    }
+}
+
+/// Mark the top of every solid block near the viewer with its spawn light level:
+/// red when dark enough for mobs, yellow when only lit by the sky, green otherwise.
+pub fn light_level_gizmos(
+    mut gizmos: Gizmos,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    chunk_map: Res<ChunkMap>,
+    global_settings: Res<GlobalSettings>,
+) {
+    if !global_settings.dbg_flags.contains(DebugFlags::LIGHT_LEVELS) {
+        return;
+    }
+    let Some(viewer) = viewer_position(&cameras) else {
+        return;
+    };
+
+    let center = viewer.floor().as_ivec3();
+    let (min_y, max_y) = (chunk_map.min_y, chunk_map.max_y() - 1);
+    let top = Quat::from_rotation_x(std::f32::consts::FRAC_PI_2);
+
+    for x in -LIGHT_OVERLAY_RADIUS..=LIGHT_OVERLAY_RADIUS {
+        for z in -LIGHT_OVERLAY_RADIUS..=LIGHT_OVERLAY_RADIUS {
+            for y in -LIGHT_OVERLAY_RADIUS..=LIGHT_OVERLAY_RADIUS {
+                let pos = center + IVec3::new(x, y, z);
+                if pos.y < min_y || pos.y >= max_y {
+                    continue;
+                }
+                let above = pos + IVec3::Y;
+                if chunk_map.block(pos).is_air() || !chunk_map.block(above).is_air() {
+                    continue;
+                }
+
+                let block_light = chunk_map.block_light(above);
+                let sky_light = chunk_map.sky_light(above);
+                let color = match (block_light, sky_light) {
+                    (0, 0) => Color::srgb(1.0, 0.0, 0.0),
+                    (0, _) => Color::srgb(1.0, 1.0, 0.0),
+                    _ => Color::srgb(0.0, 1.0, 0.0),
+                };
+                let centre = above.as_vec3() + Vec3::new(0.5, 0.01, 0.5);
+                gizmos.rect(Isometry3d::new(centre, top), Vec2::splat(0.6), color);
+            }
+        }
+    }
+}
+
+/// Mirror the wireframe debug flag into Bevy's global wireframe config.
+pub fn wireframe_toggle_system(
+    global_settings: Res<GlobalSettings>,
+    mut config: ResMut<WireframeConfig>,
+) {
+    let enabled = global_settings.dbg_flags.contains(DebugFlags::WIREFRAME);
+    if config.global != enabled {
+        config.global = enabled;
+    }
+}
@@ -0,0 +1,97 @@
+use bevy::ecs::system::SystemId;
+use bevy::prelude::*;
+use bevy_egui::egui;
+use std::collections::HashSet;
+
+use crate::data::DebugFlags;
+
+/// A panel body: a one-shot system that draws into the section's `egui::Ui`.
+pub type PanelSystemId = SystemId<InMut<'static, egui::Ui>>;
+
+/// A checkbox bound to a `DebugFlags` bit.
+pub struct DiagnosticsToggle {
+    pub label: &'static str,
+    pub flag: DebugFlags,
+}
+
+/// One collapsible section of the debug overlay.
+pub struct DiagnosticsPanel {
+    pub name: &'static str,
+    pub toggles: Vec<DiagnosticsToggle>,
+    pub system: Option<PanelSystemId>,
+}
+
+/// Every section registered with the debug overlay, in registration order.
+#[derive(Resource, Default)]
+pub struct DiagnosticsRegistry {
+    pub panels: Vec<DiagnosticsPanel>,
+    /// Names of the sections currently expanded.
+    pub open: HashSet<&'static str>,
+}
+
+impl DiagnosticsRegistry {
+    /// Get a panel by name, creating an (open) empty one if it doesn't exist yet.
+    pub fn panel_mut(&mut self, name: &'static str) -> &mut DiagnosticsPanel {
+        let index = match self.panels.iter().position(|p| p.name == name) {
+            Some(index) => index,
+            None => {
+                self.open.insert(name);
+                self.panels.push(DiagnosticsPanel {
+                    name,
+                    toggles: Vec::new(),
+                    system: None,
+                });
+                self.panels.len() - 1
+            }
+        };
+        &mut self.panels[index]
+    }
+}
+
+/// Lets any plugin add its own sections and toggles to the debug overlay.
+/// Panel systems take `InMut<egui::Ui>` as input and draw into it.
+pub trait DiagnosticsAppExt {
+    /// Register `system` as the body of the section called `name`.
+    fn add_diagnostics_panel<M>(
+        &mut self,
+        name: &'static str,
+        system: impl IntoSystem<InMut<'static, egui::Ui>, (), M> + 'static,
+    ) -> &mut Self;
+
+    /// Add a checkbox for `flag` at the top of the section called `panel`.
+    fn add_diagnostics_toggle(
+        &mut self,
+        panel: &'static str,
+        label: &'static str,
+        flag: DebugFlags,
+    ) -> &mut Self;
+}
+
+impl DiagnosticsAppExt for App {
+    fn add_diagnostics_panel<M>(
+        &mut self,
+        name: &'static str,
+        system: impl IntoSystem<InMut<'static, egui::Ui>, (), M> + 'static,
+    ) -> &mut Self {
+        let id = self.world_mut().register_system(system);
+        self.world_mut()
+            .get_resource_or_init::<DiagnosticsRegistry>()
+            .panel_mut(name)
+            .system = Some(id);
+        self
+    }
+
+    fn add_diagnostics_toggle(
+        &mut self,
+        panel: &'static str,
+        label: &'static str,
+        flag: DebugFlags,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<DiagnosticsRegistry>()
+            .panel_mut(panel)
+            .toggles
+            .push(DiagnosticsToggle { label, flag });
+        self
+    }
+}
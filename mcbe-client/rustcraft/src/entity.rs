@@ -0,0 +1,489 @@
+//! Other entities the server told us about, as ECS entities that move smoothly between the
+//! server's updates.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy_egui::egui;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::data::Hitbox;
+use crate::diagnostics::DiagnosticsAppExt;
+use crate::net::ServerEvent;
+use crate::net::session::{MetadataValue, SessionEvent};
+
+/// Simulation ticks per second of both editions; entities move on this fixed tick.
+pub const TICK_RATE: f64 = 20.0;
+/// Ticks a position update is spread over, like the vanilla clients.
+const INTERPOLATION_TICKS: u32 = 3;
+/// Ticks an entity keeps its last velocity once updates stop, before it settles back on the
+/// last position the server sent.
+const MAX_EXTRAPOLATION_TICKS: u32 = 5;
+
+/// Tracks the server's entities and moves them on the fixed tick.
+pub struct EntityPlugin;
+
+impl Plugin for EntityPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .init_resource::<EntityMap>()
+            .add_diagnostics_panel("Entities", entities_panel)
+            .add_systems(Update, entity_event_system)
+            .add_systems(FixedUpdate, entity_tick_system)
+            .add_systems(
+                PostUpdate,
+                entity_transform_system.before(TransformSystems::Propagate),
+            );
+    }
+}
+
+/// ECS entities by server entity id.
+#[derive(Resource, Default, Debug)]
+pub struct EntityMap {
+    entities: HashMap<i64, Entity>,
+}
+
+impl EntityMap {
+    pub fn get(&self, id: i64) -> Option<Entity> {
+        self.entities.get(&id).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// An entity that exists on the server. `kind` is the type name on Bedrock and the number of
+/// the type on Java.
+#[derive(Component, Debug, Clone)]
+pub struct ServerEntity {
+    pub id: i64,
+    pub kind: String,
+}
+
+/// The latest metadata values of an entity, by key.
+#[derive(Component, Debug, Clone, Default)]
+pub struct EntityMetadata(pub HashMap<u32, MetadataValue>);
+
+/// Where an entity is on the current tick, and where the server last put it.
+#[derive(Component, Debug, Clone)]
+pub struct EntityMotion {
+    /// Feet position.
+    pub position: DVec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Blocks per tick, as set by the server or measured between its updates.
+    pub velocity: DVec3,
+    /// Position on the previous tick; frames between ticks blend from it.
+    pub previous: DVec3,
+    server_position: DVec3,
+    server_yaw: f32,
+    server_pitch: f32,
+    /// Ticks left to reach the server's position.
+    lerp_ticks: u32,
+    ticks_since_update: u32,
+}
+
+impl EntityMotion {
+    pub fn new(position: DVec3, yaw: f32, pitch: f32, velocity: DVec3) -> Self {
+        Self {
+            position,
+            yaw,
+            pitch,
+            velocity,
+            previous: position,
+            server_position: position,
+            server_yaw: yaw,
+            server_pitch: pitch,
+            lerp_ticks: 0,
+            ticks_since_update: 0,
+        }
+    }
+
+    /// The server moved the entity: head there over the next few ticks.
+    pub fn move_to(&mut self, position: DVec3, yaw: f32, pitch: f32) {
+        self.velocity = (position - self.server_position) / self.ticks_since_update.max(1) as f64;
+        self.server_position = position;
+        self.server_yaw = yaw;
+        self.server_pitch = pitch;
+        self.lerp_ticks = INTERPOLATION_TICKS;
+        self.ticks_since_update = 0;
+    }
+
+    /// The server teleported the entity: be there at once, without passing in between.
+    pub fn teleport(&mut self, position: DVec3, yaw: f32, pitch: f32) {
+        *self = Self::new(position, yaw, pitch, DVec3::ZERO);
+    }
+
+    /// Advance one tick: interpolate toward the server's position, then extrapolate with
+    /// the velocity for a few ticks if no update follows.
+    pub fn tick(&mut self) {
+        self.previous = self.position;
+        self.ticks_since_update += 1;
+        if self.lerp_ticks > 0 {
+            let steps = self.lerp_ticks as f32;
+            self.position += (self.server_position - self.position) / steps as f64;
+            self.yaw += wrap_degrees(self.server_yaw - self.yaw) / steps;
+            self.pitch += (self.server_pitch - self.pitch) / steps;
+            self.lerp_ticks -= 1;
+        } else if self.ticks_since_update <= INTERPOLATION_TICKS + MAX_EXTRAPOLATION_TICKS {
+            self.position += self.velocity;
+        } else if self.position != self.server_position {
+            // updates stopped, so did the entity; go back to where the server saw it last
+            self.lerp_ticks = INTERPOLATION_TICKS;
+        }
+    }
+
+    /// Position between the previous and the current tick, `fraction` of the way.
+    pub fn interpolated(&self, fraction: f64) -> DVec3 {
+        self.previous.lerp(self.position, fraction)
+    }
+}
+
+/// An angle difference in degrees, brought into [-180, 180).
+fn wrap_degrees(degrees: f32) -> f32 {
+    (degrees + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Rotation of an entity looking at `yaw` and `pitch`, in degrees, where yaw 0 faces +Z and
+/// positive pitch looks down.
+pub fn look_rotation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_euler(
+        EulerRot::YXZ,
+        PI - yaw.to_radians(),
+        -pitch.to_radians(),
+        0.0,
+    )
+}
+
+/// Collision boxes of common entity types, by Bedrock type name.
+fn hitbox(kind: &str) -> Option<Hitbox> {
+    let (width, height, eye_height) = match kind {
+        "minecraft:player" | "minecraft:zombie" | "minecraft:skeleton" | "minecraft:villager" => {
+            (0.6, 1.8, 1.62)
+        }
+        "minecraft:creeper" => (0.6, 1.7, 1.3),
+        "minecraft:spider" => (1.4, 0.9, 0.65),
+        "minecraft:enderman" => (0.6, 2.9, 2.55),
+        "minecraft:cow" | "minecraft:sheep" => (0.9, 1.3, 1.2),
+        "minecraft:pig" => (0.9, 0.9, 0.6),
+        "minecraft:chicken" => (0.4, 0.7, 0.6),
+        "minecraft:item" => (0.25, 0.25, 0.2),
+        _ => return None,
+    };
+    Some(Hitbox {
+        width,
+        height,
+        eye_height,
+    })
+}
+
+/// Components of entities spawned by this run of `entity_event_system`, whose commands
+/// haven't been applied yet.
+type Spawned = HashMap<Entity, (EntityMotion, EntityMetadata)>;
+
+/// The motion and metadata of `entity`, wherever they are.
+fn components<'a>(
+    entity: Entity,
+    spawned: &'a mut Spawned,
+    entities: &'a mut Query<(&mut EntityMotion, &mut EntityMetadata)>,
+) -> Option<(&'a mut EntityMotion, &'a mut EntityMetadata)> {
+    match spawned.get_mut(&entity) {
+        Some((motion, metadata)) => Some((motion, metadata)),
+        None => entities
+            .get_mut(entity)
+            .ok()
+            .map(|(motion, metadata)| (motion.into_inner(), metadata.into_inner())),
+    }
+}
+
+/// Spawn, update and despawn entities as the server says.
+pub fn entity_event_system(
+    mut commands: Commands,
+    mut events: MessageReader<ServerEvent>,
+    mut entity_map: ResMut<EntityMap>,
+    mut entities: Query<(&mut EntityMotion, &mut EntityMetadata)>,
+) {
+    let mut spawned = Spawned::new();
+    for ServerEvent(event) in events.read() {
+        let target = |entity_id: &i64| entity_map.get(*entity_id);
+        match event {
+            SessionEvent::EntitySpawned {
+                entity_id,
+                kind,
+                position,
+                yaw,
+                pitch,
+                velocity,
+            } => {
+                let mut entity = commands.spawn((
+                    Name::new(format!("{} #{}", kind, entity_id)),
+                    ServerEntity {
+                        id: *entity_id,
+                        kind: kind.clone(),
+                    },
+                    Transform::from_translation(position.as_vec3())
+                        .with_rotation(look_rotation(*yaw, *pitch)),
+                ));
+                if let Some(hitbox) = hitbox(kind) {
+                    entity.insert(hitbox);
+                }
+                let motion = EntityMotion::new(*position, *yaw, *pitch, *velocity);
+                spawned.insert(entity.id(), (motion, EntityMetadata::default()));
+                if let Some(old) = entity_map.entities.insert(*entity_id, entity.id()) {
+                    spawned.remove(&old);
+                    commands.entity(old).despawn();
+                }
+            }
+            SessionEvent::EntityMoved {
+                entity_id,
+                position,
+                yaw,
+                pitch,
+            } => {
+                if let Some(entity) = target(entity_id)
+                    && let Some((motion, _)) = components(entity, &mut spawned, &mut entities)
+                {
+                    motion.move_to(*position, *yaw, *pitch);
+                }
+            }
+            SessionEvent::EntityTeleported {
+                entity_id,
+                position,
+                yaw,
+                pitch,
+            } => {
+                if let Some(entity) = target(entity_id)
+                    && let Some((motion, _)) = components(entity, &mut spawned, &mut entities)
+                {
+                    motion.teleport(*position, *yaw, *pitch);
+                }
+            }
+            SessionEvent::EntityVelocity {
+                entity_id,
+                velocity,
+            } => {
+                if let Some(entity) = target(entity_id)
+                    && let Some((motion, _)) = components(entity, &mut spawned, &mut entities)
+                {
+                    motion.velocity = *velocity;
+                }
+            }
+            SessionEvent::EntityMetadata { entity_id, values } => {
+                if let Some(entity) = target(entity_id)
+                    && let Some((_, metadata)) = components(entity, &mut spawned, &mut entities)
+                {
+                    metadata.0.extend(values.iter().cloned());
+                }
+            }
+            SessionEvent::EntitiesRemoved { entity_ids } => {
+                for id in entity_ids {
+                    if let Some(entity) = entity_map.entities.remove(id) {
+                        spawned.remove(&entity);
+                        commands.entity(entity).despawn();
+                    }
+                }
+            }
+            SessionEvent::DimensionChanged { .. } | SessionEvent::Disconnected { .. } => {
+                spawned.clear();
+                for (_, entity) in entity_map.entities.drain() {
+                    commands.entity(entity).despawn();
+                }
+            }
+            _ => {}
+        }
+    }
+    for (entity, components) in spawned {
+        commands.entity(entity).insert(components);
+    }
+}
+
+/// Move every entity by one tick.
+pub fn entity_tick_system(mut entities: Query<&mut EntityMotion>) {
+    for mut motion in &mut entities {
+        motion.tick();
+    }
+}
+
+/// Place entities between their last two ticks, so they move smoothly at any frame rate.
+pub fn entity_transform_system(
+    time: Res<Time<Fixed>>,
+    mut entities: Query<(&EntityMotion, &mut Transform)>,
+) {
+    let fraction = time.overstep_fraction_f64();
+    for (motion, mut transform) in &mut entities {
+        transform.translation = motion.interpolated(fraction).as_vec3();
+        transform.rotation = look_rotation(motion.yaw, motion.pitch);
+    }
+}
+
+/// Counts of the tracked entities, by type.
+pub fn entities_panel(
+    InMut(ui): InMut<egui::Ui>,
+    entity_map: Res<EntityMap>,
+    entities: Query<&ServerEntity>,
+) {
+    ui.label(format!("Tracked: {}", entity_map.len()));
+    let mut kinds: HashMap<&str, usize> = HashMap::new();
+    for entity in &entities {
+        *kinds.entry(entity.kind.as_str()).or_default() += 1;
+    }
+    let mut kinds: Vec<_> = kinds.into_iter().collect();
+    kinds.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (kind, count) in kinds {
+        ui.label(format!("{} x{}", kind, count));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: DVec3, expected: DVec3) {
+        assert!(
+            actual.distance(expected) < 1.0e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    /// Still at the origin three ticks after spawning, then sent to x = 3.
+    fn moved() -> EntityMotion {
+        let mut motion = EntityMotion::new(DVec3::ZERO, 0.0, 0.0, DVec3::ZERO);
+        for _ in 0..3 {
+            motion.tick();
+        }
+        motion.move_to(DVec3::new(3.0, 0.0, 0.0), 90.0, 30.0);
+        motion
+    }
+
+    #[test]
+    fn moves_are_spread_over_ticks() {
+        let mut motion = moved();
+        assert_eq!(motion.velocity, DVec3::new(1.0, 0.0, 0.0));
+        for x in [1.0, 2.0, 3.0] {
+            motion.tick();
+            assert_near(motion.position, DVec3::new(x, 0.0, 0.0));
+            assert_near(motion.previous, DVec3::new(x - 1.0, 0.0, 0.0));
+            assert_near(motion.interpolated(0.25), DVec3::new(x - 0.75, 0.0, 0.0));
+        }
+        assert!((motion.yaw - 90.0).abs() < 1.0e-4);
+        assert!((motion.pitch - 30.0).abs() < 1.0e-4);
+        assert_eq!(motion.interpolated(0.0), motion.previous);
+        assert_eq!(motion.interpolated(1.0), motion.position);
+    }
+
+    #[test]
+    fn yaw_turns_the_short_way() {
+        let mut motion = EntityMotion::new(DVec3::ZERO, 170.0, 0.0, DVec3::ZERO);
+        motion.move_to(DVec3::ZERO, -160.0, 0.0);
+        motion.tick();
+        assert!((motion.yaw - 180.0).abs() < 1.0e-4, "{}", motion.yaw);
+    }
+
+    #[test]
+    fn extrapolation_stops_and_settles_back() {
+        let mut motion = moved();
+        for _ in 0..INTERPOLATION_TICKS {
+            motion.tick();
+        }
+        for x in 1..=MAX_EXTRAPOLATION_TICKS {
+            motion.tick();
+            assert_near(motion.position, DVec3::new(3.0 + x as f64, 0.0, 0.0));
+        }
+        let furthest = motion.position;
+
+        // no update came: stop, then head back to the last known position
+        motion.tick();
+        assert_eq!(motion.position, furthest);
+        for _ in 0..INTERPOLATION_TICKS {
+            motion.tick();
+        }
+        assert_near(motion.position, DVec3::new(3.0, 0.0, 0.0));
+        for _ in 0..10 {
+            motion.tick();
+            assert_near(motion.position, DVec3::new(3.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn teleports_snap() {
+        let mut motion = moved();
+        motion.tick();
+
+        // a long way off: even a move gets there within the interpolation ticks
+        let far = DVec3::new(500.0, 64.0, -500.0);
+        motion.move_to(far, 0.0, 0.0);
+        for _ in 0..INTERPOLATION_TICKS {
+            motion.tick();
+        }
+        assert_near(motion.position, far);
+
+        let target = DVec3::new(-20.0, 70.0, 8.0);
+        motion.teleport(target, 45.0, 0.0);
+        assert_eq!(motion.position, target);
+        assert_eq!(motion.interpolated(0.5), target);
+        assert_eq!(motion.velocity, DVec3::ZERO);
+        for _ in 0..20 {
+            motion.tick();
+            assert_eq!(motion.position, target);
+            assert_eq!(motion.previous, target);
+        }
+    }
+
+    fn spawned(entity_id: i64) -> SessionEvent {
+        SessionEvent::EntitySpawned {
+            entity_id,
+            kind: "minecraft:pig".into(),
+            position: DVec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            velocity: DVec3::ZERO,
+        }
+    }
+
+    #[test]
+    fn events_spawn_teleport_and_despawn() {
+        let mut app = App::new();
+        app.add_message::<ServerEvent>()
+            .init_resource::<EntityMap>()
+            .add_systems(Update, entity_event_system);
+        let send = |app: &mut App, event| {
+            app.world_mut().write_message(ServerEvent(event));
+            app.update();
+        };
+
+        send(&mut app, spawned(1));
+        send(&mut app, spawned(2));
+        let target = DVec3::new(100.0, 5.0, 0.0);
+        send(
+            &mut app,
+            SessionEvent::EntityTeleported {
+                entity_id: 1,
+                position: target,
+                yaw: 0.0,
+                pitch: 0.0,
+            },
+        );
+        let pig = app.world().resource::<EntityMap>().get(1).unwrap();
+        let motion = app.world().get::<EntityMotion>(pig).unwrap();
+        assert_eq!((motion.previous, motion.position), (target, target));
+        assert!(app.world().get::<Hitbox>(pig).is_some());
+
+        send(
+            &mut app,
+            SessionEvent::EntitiesRemoved {
+                entity_ids: vec![1, 3],
+            },
+        );
+        let entity_map = app.world().resource::<EntityMap>();
+        assert_eq!(entity_map.get(1), None);
+        assert_eq!(entity_map.len(), 1);
+        assert!(app.world().get_entity(pig).is_err());
+    }
+}
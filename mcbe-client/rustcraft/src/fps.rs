@@ -1,4 +1,4 @@
-use crate::data::{FpsCap, FpsMode, FpsState, FrameStart, GlobalFlags, GlobalSettings};
+use crate::data::{DebugFlags, FpsCap, FpsMode, FpsState, FrameStart, GlobalFlags, GlobalSettings};
 use bevy::prelude::{Query, Res, ResMut, Time, Window};
 use std::hint::spin_loop;
 use std::time::Duration;
@@ -20,6 +20,26 @@ pub fn frame_start_system(mut frame_start: ResMut<FrameStart>) {
     frame_start.set_now();
 }
 
+/// While the VSync debug toggle is on, keep the FPS cap and present mode locked to VSync.
+pub fn vsync_lock_system(
+    mut windows: Query<&mut Window>,
+    mut cap: ResMut<FpsCap>,
+    global_settings: Res<GlobalSettings>,
+) {
+    if !global_settings.dbg_flags.contains(DebugFlags::VSYNC) {
+        return;
+    }
+
+    if cap.mode != FpsMode::VSync {
+        cap.mode = FpsMode::VSync;
+    }
+    for mut window in &mut windows {
+        if window.present_mode != FpsMode::VSync.present_mode() {
+            window.present_mode = FpsMode::VSync.present_mode();
+        }
+    }
+}
+
 /// Improved frame cap: sleep + short spin for precision.
 /// Runs in PostUpdate so it measures nearly the whole frame's work time.
 pub fn frame_cap_system_improved(frame_start: Res<FrameStart>, cap: Res<FpsCap>) {
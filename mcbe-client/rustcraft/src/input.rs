@@ -2,24 +2,53 @@ use bevy::input::ButtonInput;
 use bevy::window::{MonitorSelection, PresentMode, VideoModeSelection};
 use bevy::{prelude::*, window::WindowMode};
 use log::info;
-use std::sync::{
-    Arc,
-    atomic::{AtomicBool, Ordering},
-};
+use std::collections::HashSet;
 
+use crate::crash::CrashTest;
 use crate::data::{DebugFlags, FpsCap, FpsMode, GlobalFlags, GlobalSettings};
+use crate::ui::log_console::LogConsoleState;
+
+/// Keys pressed while F3 was held. They belong to a debug chord, so gameplay bindings ignore
+/// them until they are let go: F3+W toggles wireframes without walking forward.
+#[derive(Resource, Default, Debug)]
+pub struct ChordKeys(HashSet<KeyCode>);
+
+impl ChordKeys {
+    /// Note the keys pressed along with F3 this frame, and forget the ones released.
+    pub fn update(&mut self, keys: &ButtonInput<KeyCode>) {
+        if keys.pressed(KeyCode::F3) {
+            self.0
+                .extend(keys.get_just_pressed().filter(|key| **key != KeyCode::F3));
+        }
+        self.0.retain(|key| keys.pressed(*key));
+    }
+
+    /// Whether `key` is held for gameplay, rather than as part of a chord.
+    pub fn pressed(&self, keys: &ButtonInput<KeyCode>, key: KeyCode) -> bool {
+        keys.pressed(key) && !self.0.contains(&key)
+    }
+}
+
+/// Keep `ChordKeys` current; runs before the gameplay input systems read it.
+pub fn chord_keys_system(keys: Res<ButtonInput<KeyCode>>, mut chords: ResMut<ChordKeys>) {
+    chords.update(&keys);
+}
 
 /// Input handling:
 /// - F11 toggles maximize
 /// - F3 toggles debug overlay
 /// - F2 cycles present modes
 /// - F1 cycles FPS cap presets
+/// - F6 toggles the log console
+/// - F3+G / F3+B / F3+L / F3+W toggle chunk borders, hitboxes, light levels and wireframes
 pub fn input_system(
     mut windows: Query<&mut Window>,
     keys: Res<ButtonInput<KeyCode>>,
     mut cap: ResMut<FpsCap>,
     mut global_settings: ResMut<GlobalSettings>,
-    _time: Res<Time>,
+    mut crash_test: ResMut<CrashTest>,
+    mut log_console: ResMut<LogConsoleState>,
+    mut f3_chord_used: Local<bool>,
 ) {
     // assume single primary window
     if let Ok(mut window) = windows.single_mut() {
@@ -31,8 +60,30 @@ pub fn input_system(
             info!("Window maximized: {}", maximized);
         }
 
-        // toggle debug overlay with F3
+        // F3 + key chords toggle the debug visualisations
+        const F3_CHORDS: &[(KeyCode, DebugFlags)] = &[
+            (KeyCode::KeyG, DebugFlags::CHUNK_BORDERS),
+            (KeyCode::KeyB, DebugFlags::HITBOXES),
+            (KeyCode::KeyL, DebugFlags::LIGHT_LEVELS),
+            (KeyCode::KeyW, DebugFlags::WIREFRAME),
+        ];
+        if keys.pressed(KeyCode::F3) {
+            for (key, flag) in F3_CHORDS {
+                if keys.just_pressed(*key) {
+                    global_settings.dbg_flags.toggle(*flag);
+                    *f3_chord_used = true;
+                    info!(
+                        "{:?} is set to {}.",
+                        flag,
+                        global_settings.dbg_flags.contains(*flag)
+                    );
+                }
+            }
+        }
+
+        // toggle debug overlay with F3 (unless it was used as part of a chord)
         if keys.just_released(KeyCode::F3)
+            && !*f3_chord_used
             && !keys.pressed(KeyCode::KeyT)
             && !keys.just_pressed(KeyCode::KeyT)
             && !keys.pressed(KeyCode::ControlLeft)
@@ -44,39 +95,23 @@ pub fn input_system(
                 global_settings.flags.contains(GlobalFlags::DEBUG_OVERLAY)
             );
         }
+        if keys.just_released(KeyCode::F3) {
+            *f3_chord_used = false;
+        }
 
-        let crash_started = Arc::new(AtomicBool::new(false));
-
-        if keys.all_pressed([KeyCode::F3, KeyCode::ControlLeft])
-            && !crash_started.swap(true, Ordering::SeqCst)
-        {
-            // let flag = crash_started.clone();
-
-            std::thread::spawn(move || {
-                warn!(
-                    "CRASH TEST: {:?} + {:?} pressed, crashing in 10 seconds...",
-                    KeyCode::F3,
-                    KeyCode::ControlLeft
-                );
-
-                std::thread::sleep(std::time::Duration::from_secs(1));
-
-                for i in 0..10 {
-                    warn!("CRASH TEST: Crashing in {}s", 10 - i);
-                    std::thread::sleep(std::time::Duration::from_secs(1));
-                }
-
-                std::process::abort();
-
-                // (never reached, but if you had cleanup logic)
-                // flag.store(false, Ordering::SeqCst);
-            });
+        // debug crash test: Ctrl+F3 panics, Ctrl+Alt+F3 aborts, after a countdown
+        if keys.all_pressed([KeyCode::F3, KeyCode::ControlLeft]) {
+            crash_test.start(keys.pressed(KeyCode::AltLeft));
         }
 
         if keys.all_pressed([KeyCode::ControlLeft, KeyCode::KeyQ]) {
             std::process::exit(0);
         }
 
+        if keys.just_pressed(KeyCode::F6) {
+            log_console.open = !log_console.open;
+        }
+
         if keys.just_pressed(KeyCode::F4) {
             window.mode = match window.mode {
                 WindowMode::Windowed => {
@@ -136,17 +171,7 @@ pub fn input_system(
                 .unwrap_or(PRESETS[0]);
             cap.mode = next;
 
-            // Choose an appropriate present mode for VSync vs not-VSync:
-            match cap.mode {
-                FpsMode::VSync => {
-                    // explicit vsync (Fifo) is preferred to avoid tearing
-                    window.present_mode = PresentMode::Fifo;
-                }
-                FpsMode::Uncapped | FpsMode::Manual(_) => {
-                    // immediate allows manual sleeping/spin to control pacing
-                    window.present_mode = PresentMode::Immediate;
-                }
-            }
+            window.present_mode = cap.mode.present_mode();
 
             if global_settings.dbg_flags.contains(DebugFlags::VSYNC) {
                 cap.mode = FpsMode::VSync;
@@ -156,3 +181,37 @@ pub fn input_system(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chord_keys_are_held_back_until_released() {
+        let mut keys = ButtonInput::<KeyCode>::default();
+        let mut chords = ChordKeys::default();
+
+        // walking before F3 goes down keeps walking
+        keys.press(KeyCode::KeyS);
+        chords.update(&keys);
+        keys.clear();
+        keys.press(KeyCode::F3);
+        keys.press(KeyCode::KeyW);
+        chords.update(&keys);
+        assert!(chords.pressed(&keys, KeyCode::KeyS));
+        assert!(!chords.pressed(&keys, KeyCode::KeyW));
+
+        // still held back after F3 is let go, until W is too
+        keys.clear();
+        keys.release(KeyCode::F3);
+        chords.update(&keys);
+        assert!(!chords.pressed(&keys, KeyCode::KeyW));
+        keys.clear();
+        keys.release(KeyCode::KeyW);
+        chords.update(&keys);
+        keys.clear();
+        keys.press(KeyCode::KeyW);
+        chords.update(&keys);
+        assert!(chords.pressed(&keys, KeyCode::KeyW));
+    }
+}
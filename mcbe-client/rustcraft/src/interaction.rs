@@ -0,0 +1,719 @@
+//! What the local player looks at and does to it: a voxel raycast from the eyes, breaking the
+//! targeted block over time while the attack button is held, and placing against its face.
+
+use bevy::asset::RenderAssetUsages;
+use bevy::image::ImageSampler;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_egui::egui;
+
+use crate::diagnostics::DiagnosticsAppExt;
+use crate::net::SendAction;
+use crate::net::session::{BlockFace, PlayerAction};
+use crate::physics::{
+    Aabb, BlockPhysicsTable, BlockSource, LocalPlayer, PLAYER_EYE_HEIGHT, PlayerBody, WorldBlocks,
+    player_physics_system,
+};
+use crate::world::{BlockState, ChunkMap};
+
+/// How far away blocks can be reached.
+pub const REACH: f64 = 4.5;
+/// Ticks after breaking a block before the next one starts.
+const DESTROY_DELAY_TICKS: u32 = 5;
+/// Ticks between uses while the use button is held.
+const USE_DELAY_TICKS: u32 = 4;
+/// Crack textures shown while breaking, from barely started to almost broken.
+pub const CRACK_STAGES: usize = 10;
+const CRACK_TEXTURE_SIZE: u32 = 16;
+/// How far outlines and cracks stand off the block, so they don't fight with its faces.
+const OVERLAY_INSET: f64 = 0.002;
+
+/// Targeting, breaking and placing on the game tick.
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InteractInput>()
+            .init_resource::<BlockTarget>()
+            .init_resource::<BlockBreaking>()
+            .add_diagnostics_panel("Target", target_panel)
+            .add_systems(
+                FixedUpdate,
+                (targeting_system, interaction_tick_system)
+                    .chain()
+                    .after(player_physics_system),
+            );
+    }
+}
+
+/// The targeted block's outline and the cracks of the block being broken.
+pub struct BlockOverlayPlugin;
+
+impl Plugin for BlockOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_crack_overlay)
+            .add_systems(Update, (block_outline_gizmos, crack_overlay_system));
+    }
+}
+
+/// Mouse buttons held this tick.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct InteractInput {
+    pub attack: bool,
+    pub use_item: bool,
+}
+
+/// Where a ray met a block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockHit {
+    pub pos: IVec3,
+    pub face: BlockFace,
+    /// The point hit, in world coordinates.
+    pub point: DVec3,
+    pub distance: f64,
+}
+
+impl BlockHit {
+    /// Where a block placed against the hit face goes.
+    pub fn placement(&self) -> IVec3 {
+        self.pos + self.face.offset()
+    }
+}
+
+/// The block the local player looks at, within reach.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct BlockTarget(pub Option<BlockHit>);
+
+/// The block being broken, and how far along it is.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct BlockBreaking {
+    pub target: Option<(IVec3, BlockFace)>,
+    /// 0 when started, broken at 1.
+    pub progress: f32,
+    destroy_delay: u32,
+    use_delay: u32,
+}
+
+impl BlockBreaking {
+    /// Crack texture to show, while breaking.
+    pub fn stage(&self) -> Option<usize> {
+        self.target
+            .map(|_| ((self.progress * CRACK_STAGES as f32) as usize).min(CRACK_STAGES - 1))
+    }
+}
+
+/// The face a ray moving along `axis` in direction `positive` enters a box through.
+fn entry_face(axis: usize, positive: bool) -> BlockFace {
+    match (axis, positive) {
+        (0, true) => BlockFace::West,
+        (0, false) => BlockFace::East,
+        (1, true) => BlockFace::Down,
+        (1, false) => BlockFace::Up,
+        (_, true) => BlockFace::North,
+        (_, false) => BlockFace::South,
+    }
+}
+
+/// Distance along `direction` at which a ray from `origin` enters `aabb`, and the face it
+/// enters through. Rays starting inside hit right away, on the face that looks back at them
+/// along the axis they mostly travel, as in vanilla.
+fn ray_aabb(origin: DVec3, direction: DVec3, aabb: &Aabb) -> Option<(f64, BlockFace)> {
+    let mut near = f64::NEG_INFINITY;
+    let mut far = f64::INFINITY;
+    let mut face = None;
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < aabb.min[axis] || origin[axis] > aabb.max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let a = (aabb.min[axis] - origin[axis]) / direction[axis];
+        let b = (aabb.max[axis] - origin[axis]) / direction[axis];
+        if a.min(b) > near {
+            near = a.min(b);
+            face = Some(entry_face(axis, direction[axis] > 0.0));
+        }
+        far = far.min(a.max(b));
+    }
+    if near > far || far < 0.0 {
+        return None;
+    }
+    if near < 0.0 {
+        let axis = direction.abs().max_position();
+        return Some((0.0, entry_face(axis, direction[axis] > 0.0)));
+    }
+    face.map(|face| (near, face))
+}
+
+/// The first block shape a ray from `origin` along `direction` hits within `reach`, walking
+/// the voxels it crosses in order (Amanatides and Woo).
+pub fn raycast(
+    blocks: &impl BlockSource,
+    origin: DVec3,
+    direction: DVec3,
+    reach: f64,
+) -> Option<BlockHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == DVec3::ZERO {
+        return None;
+    }
+    let mut pos = origin.floor().as_ivec3();
+    let step = IVec3::new(
+        direction.x.signum() as i32,
+        direction.y.signum() as i32,
+        direction.z.signum() as i32,
+    );
+    let mut next = DVec3::ZERO;
+    let mut delta = DVec3::ZERO;
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            next[axis] = f64::INFINITY;
+            delta[axis] = f64::INFINITY;
+            continue;
+        }
+        let boundary = if direction[axis] > 0.0 {
+            pos[axis] as f64 + 1.0
+        } else {
+            pos[axis] as f64
+        };
+        next[axis] = (boundary - origin[axis]) / direction[axis];
+        delta[axis] = 1.0 / direction[axis].abs();
+    }
+
+    loop {
+        let hit = blocks
+            .block_physics(pos)
+            .shape
+            .iter()
+            .filter_map(|shape| ray_aabb(origin, direction, &shape.offset(pos.as_dvec3())))
+            .filter(|(distance, _)| *distance <= reach)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((distance, face)) = hit {
+            return Some(BlockHit {
+                pos,
+                face,
+                point: origin + direction * distance,
+                distance,
+            });
+        }
+        let axis = if next.x < next.y && next.x < next.z {
+            0
+        } else if next.y < next.z {
+            1
+        } else {
+            2
+        };
+        if next[axis] > reach {
+            return None;
+        }
+        pos[axis] += step[axis];
+        next[axis] += delta[axis];
+    }
+}
+
+/// Unit vector of where `yaw` and `pitch` look, in degrees, yaw 0 facing +Z.
+pub fn look_direction(yaw: f32, pitch: f32) -> DVec3 {
+    let (yaw, pitch) = (yaw.to_radians() as f64, pitch.to_radians() as f64);
+    DVec3::new(
+        -yaw.sin() * pitch.cos(),
+        -pitch.sin(),
+        yaw.cos() * pitch.cos(),
+    )
+}
+
+/// Breaking progress made per tick on a block of `hardness`, by hand. Tools and whether the
+/// hand can harvest the block aren't known yet, so every block breaks as if it could.
+fn destroy_progress(hardness: f32, body: &PlayerBody) -> f32 {
+    if hardness < 0.0 {
+        return 0.0;
+    }
+    if hardness == 0.0 {
+        return 1.0;
+    }
+    let mut speed = 1.0;
+    if body.in_water {
+        speed /= 5.0;
+    }
+    if !body.on_ground {
+        speed /= 5.0;
+    }
+    speed / hardness / 30.0
+}
+
+/// Raycast from the local player's eyes.
+pub fn targeting_system(
+    chunks: Res<ChunkMap>,
+    table: Res<BlockPhysicsTable>,
+    players: Query<&PlayerBody, With<LocalPlayer>>,
+    mut target: ResMut<BlockTarget>,
+) {
+    let blocks = WorldBlocks {
+        chunks: &chunks,
+        table: &table,
+    };
+    target.0 = players.single().ok().and_then(|body| {
+        let eyes = body.position + DVec3::Y * PLAYER_EYE_HEIGHT;
+        raycast(&blocks, eyes, look_direction(body.yaw, body.pitch), REACH)
+    });
+}
+
+/// Break the targeted block while attacking, and use the held item on it while using.
+pub fn interaction_tick_system(
+    input: Res<InteractInput>,
+    target: Res<BlockTarget>,
+    table: Res<BlockPhysicsTable>,
+    mut chunks: ResMut<ChunkMap>,
+    mut breaking: ResMut<BlockBreaking>,
+    mut actions: MessageWriter<SendAction>,
+    players: Query<&PlayerBody, With<LocalPlayer>>,
+) {
+    let Ok(body) = players.single() else {
+        *breaking = BlockBreaking::default();
+        return;
+    };
+    let mut send = |action| {
+        actions.write(SendAction(action));
+    };
+    breaking.destroy_delay = breaking.destroy_delay.saturating_sub(1);
+    breaking.use_delay = breaking.use_delay.saturating_sub(1);
+
+    let aimed = target.0.filter(|_| input.attack);
+    let aimed_at = aimed.map(|hit| (hit.pos, hit.face));
+    if breaking.target.is_some() && breaking.target != aimed_at {
+        if let Some((pos, face)) = breaking.target.take() {
+            send(PlayerAction::CancelBreaking { pos, face });
+        }
+        breaking.progress = 0.0;
+    }
+    if let Some((pos, face)) = aimed_at
+        && breaking.target.is_none()
+        && breaking.destroy_delay == 0
+    {
+        send(PlayerAction::StartBreaking { pos, face });
+        breaking.target = aimed_at;
+    }
+    if let Some((pos, face)) = breaking.target {
+        breaking.progress += destroy_progress(table.get(chunks.block(pos)).hardness, body);
+        send(PlayerAction::SwingArm);
+        if breaking.progress >= 1.0 {
+            send(PlayerAction::FinishBreaking { pos, face });
+            // predicted; the server's block update corrects it if it disagrees
+            chunks.set_block(pos, BlockState::AIR);
+            breaking.target = None;
+            breaking.progress = 0.0;
+            breaking.destroy_delay = DESTROY_DELAY_TICKS;
+        } else {
+            send(PlayerAction::ContinueBreaking { pos, face });
+        }
+    }
+
+    if input.use_item
+        && breaking.use_delay == 0
+        && let Some(hit) = target.0
+    {
+        breaking.use_delay = USE_DELAY_TICKS;
+        let placed = hit.placement().as_dvec3();
+        // a block there would push us out of it; the server refuses it anyway
+        if !Aabb::new(placed, placed + DVec3::ONE).intersects(&body.aabb()) {
+            send(PlayerAction::Place {
+                pos: hit.pos,
+                face: hit.face,
+                cursor: (hit.point - hit.pos.as_dvec3()).as_vec3(),
+            });
+            send(PlayerAction::SwingArm);
+        }
+    }
+}
+
+/// Left and right mouse buttons into `InteractInput`.
+pub fn interaction_input_system(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut input: ResMut<InteractInput>,
+) {
+    *input = InteractInput {
+        attack: mouse.pressed(MouseButton::Left),
+        use_item: mouse.pressed(MouseButton::Right),
+    };
+}
+
+/// Outline the shape of the targeted block.
+pub fn block_outline_gizmos(
+    mut gizmos: Gizmos,
+    target: Res<BlockTarget>,
+    chunks: Res<ChunkMap>,
+    table: Res<BlockPhysicsTable>,
+) {
+    let Some(hit) = target.0 else {
+        return;
+    };
+    for shape in &table.get(chunks.block(hit.pos)).shape {
+        let shape = shape.offset(hit.pos.as_dvec3()).deflate(-OVERLAY_INSET);
+        gizmos.cube(
+            Transform::from_translation(((shape.min + shape.max) / 2.0).as_vec3())
+                .with_scale((shape.max - shape.min).as_vec3()),
+            Color::BLACK,
+        );
+    }
+}
+
+/// Crack patterns for each destroy stage, as RGBA pixels; every stage keeps the cracks of the
+/// ones before and adds more.
+pub fn crack_textures() -> Vec<Vec<u8>> {
+    let size = CRACK_TEXTURE_SIZE as i32;
+    // xorshift, so the pattern is the same on every run
+    let mut seed: u32 = 0x2545_F491;
+    let mut random = |below: i32| {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        (seed % below as u32) as i32
+    };
+    let mut pixels = vec![0; (size * size * 4) as usize];
+    let mut stages = Vec::with_capacity(CRACK_STAGES);
+    for stage in 0..CRACK_STAGES as i32 {
+        // a crack wanders out from near the middle, longer at every stage
+        let (mut x, mut y) = (size / 2 - 3 + random(6), size / 2 - 3 + random(6));
+        for _ in 0..3 + stage * 2 {
+            let i = ((y * size + x) * 4) as usize;
+            pixels[i..i + 4].copy_from_slice(&[24, 24, 24, 200]);
+            x = (x + random(3) - 1).clamp(0, size - 1);
+            y = (y + random(3) - 1).clamp(0, size - 1);
+        }
+        stages.push(pixels.clone());
+    }
+    stages
+}
+
+/// The cube drawn over the block being broken, with a material per destroy stage.
+#[derive(Component, Debug)]
+pub struct CrackOverlay {
+    stages: Vec<Handle<StandardMaterial>>,
+}
+
+pub fn setup_crack_overlay(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let size = Extent3d {
+        width: CRACK_TEXTURE_SIZE,
+        height: CRACK_TEXTURE_SIZE,
+        depth_or_array_layers: 1,
+    };
+    let stages: Vec<_> = crack_textures()
+        .into_iter()
+        .map(|pixels| {
+            let mut image = Image::new(
+                size,
+                TextureDimension::D2,
+                pixels,
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::RENDER_WORLD,
+            );
+            image.sampler = ImageSampler::nearest();
+            materials.add(StandardMaterial {
+                base_color_texture: Some(images.add(image)),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            })
+        })
+        .collect();
+    let side = 1.0 + 2.0 * OVERLAY_INSET as f32;
+    commands.spawn((
+        Name::new("Crack overlay"),
+        Mesh3d(meshes.add(Cuboid::new(side, side, side))),
+        MeshMaterial3d(stages[0].clone()),
+        Transform::default(),
+        Visibility::Hidden,
+        CrackOverlay { stages },
+    ));
+}
+
+/// Show the crack stage of the block being broken over it.
+pub fn crack_overlay_system(
+    breaking: Res<BlockBreaking>,
+    mut overlays: Query<(
+        &CrackOverlay,
+        &mut MeshMaterial3d<StandardMaterial>,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    for (overlay, mut material, mut transform, mut visibility) in &mut overlays {
+        match (breaking.target, breaking.stage()) {
+            (Some((pos, _)), Some(stage)) => {
+                transform.translation = pos.as_vec3() + Vec3::splat(0.5);
+                if material.0 != overlay.stages[stage] {
+                    material.0 = overlay.stages[stage].clone();
+                }
+                *visibility = Visibility::Visible;
+            }
+            _ => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+pub fn target_panel(
+    InMut(ui): InMut<egui::Ui>,
+    target: Res<BlockTarget>,
+    breaking: Res<BlockBreaking>,
+    chunks: Res<ChunkMap>,
+) {
+    match target.0 {
+        Some(hit) => {
+            ui.label(format!(
+                "Block: {}, {}, {} ({:?})",
+                hit.pos.x, hit.pos.y, hit.pos.z, hit.face
+            ));
+            ui.label(format!("State: {}", chunks.block(hit.pos).0));
+            ui.label(format!("Distance: {:.2}", hit.distance));
+        }
+        None => {
+            ui.label("Block: none");
+        }
+    }
+    if let Some(stage) = breaking.stage() {
+        ui.label(format!(
+            "Breaking: {:.0}% (stage {})",
+            breaking.progress * 100.0,
+            stage
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockRegistry;
+    use crate::physics::BlockPhysics;
+
+    /// Full cubes at `solid`, a bottom slab at `slab`, air elsewhere.
+    struct TestBlocks {
+        solid: Vec<IVec3>,
+        slab: Option<IVec3>,
+        cube: BlockPhysics,
+        half: BlockPhysics,
+        air: BlockPhysics,
+    }
+
+    impl TestBlocks {
+        fn new(solid: &[IVec3]) -> Self {
+            Self {
+                solid: solid.to_vec(),
+                slab: None,
+                cube: BlockPhysics::solid(),
+                half: BlockPhysics {
+                    shape: vec![Aabb::new(DVec3::ZERO, DVec3::new(1.0, 0.5, 1.0))],
+                    ..BlockPhysics::solid()
+                },
+                air: BlockPhysics::empty(),
+            }
+        }
+    }
+
+    impl BlockSource for TestBlocks {
+        fn block_physics(&self, pos: IVec3) -> &BlockPhysics {
+            if self.solid.contains(&pos) {
+                &self.cube
+            } else if self.slab == Some(pos) {
+                &self.half
+            } else {
+                &self.air
+            }
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1.0e-9,
+            "{} instead of {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn axis_aligned_rays() {
+        let origin = DVec3::splat(0.5);
+        let cases = [
+            (DVec3::X, BlockFace::West),
+            (DVec3::NEG_X, BlockFace::East),
+            (DVec3::Y, BlockFace::Down),
+            (DVec3::NEG_Y, BlockFace::Up),
+            (DVec3::Z, BlockFace::North),
+            (DVec3::NEG_Z, BlockFace::South),
+        ];
+        for (direction, face) in cases {
+            let pos = (direction * 3.0).as_ivec3();
+            let blocks = TestBlocks::new(&[pos]);
+            let hit = raycast(&blocks, origin, direction, REACH).unwrap();
+            assert_eq!(
+                (hit.pos, hit.face),
+                (pos, face),
+                "looking along {}",
+                direction
+            );
+            assert_close(hit.distance, 2.5);
+            assert_close(hit.point.distance(origin), 2.5);
+            // a block placed against the face goes back towards the viewer
+            assert_eq!(hit.placement(), pos - direction.as_ivec3());
+        }
+    }
+
+    #[test]
+    fn nearest_block_wins() {
+        let blocks = TestBlocks::new(&[IVec3::new(3, 0, 0), IVec3::new(2, 0, 0)]);
+        let hit = raycast(&blocks, DVec3::splat(0.5), DVec3::X, REACH).unwrap();
+        assert_eq!(hit.pos, IVec3::new(2, 0, 0));
+    }
+
+    #[test]
+    fn diagonal_ray_placement() {
+        // looking down onto the top of a block two below and one ahead
+        let blocks = TestBlocks::new(&[IVec3::new(0, -2, 1)]);
+        let origin = DVec3::new(0.5, 0.5, 0.5);
+        let hit = raycast(&blocks, origin, DVec3::new(0.0, -1.0, 0.5), REACH).unwrap();
+        assert_eq!((hit.pos, hit.face), (IVec3::new(0, -2, 1), BlockFace::Up));
+        assert_close(hit.point.y, -1.0);
+        assert_eq!(hit.placement(), IVec3::new(0, -1, 1));
+    }
+
+    #[test]
+    fn partial_shapes() {
+        // over a bottom slab the ray carries on to the block behind it
+        let mut blocks = TestBlocks::new(&[IVec3::new(2, 0, 0)]);
+        blocks.slab = Some(IVec3::new(1, 0, 0));
+        let over = raycast(&blocks, DVec3::new(0.5, 0.75, 0.5), DVec3::X, REACH).unwrap();
+        assert_eq!(over.pos, IVec3::new(2, 0, 0));
+        let into = raycast(&blocks, DVec3::new(0.5, 0.25, 0.5), DVec3::X, REACH).unwrap();
+        assert_eq!(
+            (into.pos, into.face),
+            (IVec3::new(1, 0, 0), BlockFace::West)
+        );
+
+        let from_above = raycast(&blocks, DVec3::new(1.5, 2.0, 0.5), DVec3::NEG_Y, REACH);
+        let from_above = from_above.unwrap();
+        assert_eq!(from_above.face, BlockFace::Up);
+        assert_close(from_above.distance, 1.5);
+        assert_eq!(from_above.placement(), IVec3::new(1, 1, 0));
+    }
+
+    #[test]
+    fn rays_starting_inside_a_block() {
+        let blocks = TestBlocks::new(&[IVec3::ZERO, IVec3::new(1, 0, 0)]);
+        let hit = raycast(
+            &blocks,
+            DVec3::new(0.5, 0.5, 0.5),
+            DVec3::new(1.0, 0.2, 0.0),
+            REACH,
+        );
+        let hit = hit.unwrap();
+        assert_eq!((hit.pos, hit.face), (IVec3::ZERO, BlockFace::West));
+        assert_eq!(hit.distance, 0.0);
+
+        let aabb = Aabb::new(DVec3::ZERO, DVec3::ONE);
+        assert_eq!(
+            ray_aabb(DVec3::splat(0.5), DVec3::NEG_Y, &aabb),
+            Some((0.0, BlockFace::Up))
+        );
+        // behind the ray
+        assert_eq!(ray_aabb(DVec3::new(2.0, 0.5, 0.5), DVec3::X, &aabb), None);
+        // alongside it
+        assert_eq!(ray_aabb(DVec3::new(0.5, 1.5, -1.0), DVec3::Z, &aabb), None);
+    }
+
+    #[test]
+    fn reach_limits_the_ray() {
+        let origin = DVec3::new(0.5, 0.5, 0.5);
+        // faces are reached up to and including the reach distance
+        let near = TestBlocks::new(&[IVec3::new(0, 0, 4)]);
+        assert_close(
+            raycast(&near, origin, DVec3::Z, REACH).unwrap().distance,
+            3.5,
+        );
+        assert!(raycast(&near, origin, DVec3::Z, 3.4).is_none());
+        let edge = TestBlocks::new(&[IVec3::new(0, 0, 5)]);
+        assert_close(
+            raycast(&edge, origin, DVec3::Z, REACH).unwrap().distance,
+            REACH,
+        );
+        let far = TestBlocks::new(&[IVec3::new(0, 0, 6)]);
+        assert!(raycast(&far, origin, DVec3::Z, REACH).is_none());
+        assert!(raycast(&near, origin, DVec3::ZERO, REACH).is_none());
+    }
+
+    #[test]
+    fn look_directions() {
+        let cases = [
+            (0.0, 0.0, DVec3::Z),
+            (90.0, 0.0, DVec3::NEG_X),
+            (180.0, 0.0, DVec3::NEG_Z),
+            (-90.0, 0.0, DVec3::X),
+            (0.0, 90.0, DVec3::NEG_Y),
+            (0.0, -90.0, DVec3::Y),
+        ];
+        for (yaw, pitch, expected) in cases {
+            let direction = look_direction(yaw, pitch);
+            assert!(
+                direction.distance(expected) < 1.0e-6,
+                "yaw {} pitch {}: {}",
+                yaw,
+                pitch,
+                direction
+            );
+        }
+        let down_ahead = look_direction(0.0, 45.0);
+        assert_close(down_ahead.length(), 1.0);
+        assert!(down_ahead.y < 0.0 && down_ahead.z > 0.0);
+    }
+
+    /// Ticks to break a block of `hardness`.
+    fn ticks_to_break(hardness: f32, body: &PlayerBody) -> u32 {
+        let per_tick = destroy_progress(hardness, body);
+        if per_tick <= 0.0 {
+            return u32::MAX;
+        }
+        (1.0 / per_tick).round() as u32
+    }
+
+    #[test]
+    fn breaking_takes_longer_on_harder_blocks() {
+        let mut body = PlayerBody::new(DVec3::ZERO, 0.0, 0.0);
+        body.on_ground = true;
+
+        // hardness as the Bedrock block states describe it
+        let registry = BlockRegistry::bedrock();
+        let table = BlockPhysicsTable::from_registry(&registry);
+        let hardness = |id: &str| {
+            let (state, _) = registry
+                .iter()
+                .find(|(_, block)| block.id() == id)
+                .unwrap_or_else(|| panic!("{} isn't described", id));
+            table.get(state).hardness
+        };
+        let dirt = ticks_to_break(hardness("dirt"), &body);
+        let stone = ticks_to_break(hardness("stone"), &body);
+        let obsidian = ticks_to_break(hardness("obsidian"), &body);
+        assert!(
+            dirt < stone && stone < obsidian,
+            "{} {} {}",
+            dirt,
+            stone,
+            obsidian
+        );
+        assert_eq!(stone, 45);
+        assert_eq!(ticks_to_break(hardness("bedrock"), &body), u32::MAX);
+        assert_eq!(destroy_progress(hardness("bedrock"), &body), 0.0);
+        assert_eq!(destroy_progress(hardness("short_grass"), &body), 1.0);
+        assert_eq!(destroy_progress(hardness("torch"), &body), 1.0);
+
+        body.on_ground = false;
+        assert_eq!(ticks_to_break(hardness("stone"), &body), 225);
+        body.in_water = true;
+        assert_eq!(ticks_to_break(hardness("stone"), &body), 1125);
+    }
+}
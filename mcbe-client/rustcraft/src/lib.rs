@@ -0,0 +1,27 @@
+#![recursion_limit = "256"]
+
+pub mod blocks;
+pub mod bot;
+pub mod cli;
+pub mod config;
+pub mod console;
+pub mod crash;
+pub mod data;
+pub mod debug_gizmos;
+pub mod diagnostics;
+pub mod egui_dbg;
+pub mod entity;
+pub mod fps;
+pub mod input;
+pub mod interaction;
+pub mod lighting;
+pub mod logging;
+pub mod net;
+pub mod paths;
+pub mod physics;
+pub mod prediction;
+pub mod setup;
+pub mod ui;
+pub mod update;
+pub mod window;
+pub mod world;
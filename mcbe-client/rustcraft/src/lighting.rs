@@ -0,0 +1,970 @@
+//! Sky light and block light. Columns that arrive without light (all of them on Bedrock) are
+//! flood-filled on the compute task pool, one task per column, and their light is then spread
+//! over the borders to the columns around them. A changed block only relights the blocks its
+//! light reached. Java servers send light with their chunks, which is kept as it comes.
+
+use bevy::prelude::*;
+use bevy::tasks::ComputeTaskPool;
+use bevy_egui::egui;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::blocks::{BlockDescription, BlockRegistry, block_registry_system};
+use crate::console::ConsoleAppExt;
+use crate::diagnostics::DiagnosticsAppExt;
+use crate::net::ServerEvent;
+use crate::net::session::{BlockFace, SessionEvent};
+use crate::physics::BlockPhysics;
+use crate::world::{
+    BlockState, CHUNK_WIDTH, Chunk, ChunkMap, ChunkPos, SECTION_HEIGHT, chunk_event_system,
+};
+
+/// Brightest light level, that of open sky.
+pub const MAX_LIGHT: u8 = 15;
+/// Columns lit per frame at most, so joining a world doesn't stall a frame.
+const COLUMNS_PER_FRAME: usize = 32;
+
+/// The horizontal sides of a column, which light crosses into its neighbours.
+const SIDES: [BlockFace; 4] = [
+    BlockFace::North,
+    BlockFace::South,
+    BlockFace::West,
+    BlockFace::East,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightKind {
+    Sky,
+    Block,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 2] = [LightKind::Sky, LightKind::Block];
+
+    fn get(self, chunk: &Chunk, pos: IVec3) -> u8 {
+        match self {
+            LightKind::Sky => chunk.sky_light(pos),
+            LightKind::Block => chunk.block_light(pos),
+        }
+    }
+
+    fn set(self, chunk: &mut Chunk, pos: IVec3, value: u8) {
+        match self {
+            LightKind::Sky => chunk.set_sky_light(pos, value),
+            LightKind::Block => chunk.set_block_light(pos, value),
+        }
+    }
+
+    fn emission(self, light: BlockLight) -> u8 {
+        match self {
+            LightKind::Sky => 0,
+            LightKind::Block => light.emission,
+        }
+    }
+
+    /// Light reaching a neighbour in direction `dir` from a block lit `level`. Full sky light
+    /// goes straight down through clear blocks without fading.
+    fn propagated(self, level: u8, dir: IVec3, opacity: u8) -> u8 {
+        if self == LightKind::Sky && dir == IVec3::NEG_Y && level == MAX_LIGHT && opacity == 0 {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(opacity.max(1))
+        }
+    }
+}
+
+/// How a block state interacts with light.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockLight {
+    /// Block light it gives off: 15 for glowstone, 14 for torches.
+    pub emission: u8,
+    /// Light lost passing through it, on top of the one level every block costs: 0 for glass,
+    /// 1 for leaves and water, 15 for solid blocks.
+    pub opacity: u8,
+}
+
+impl BlockLight {
+    pub const CLEAR: BlockLight = BlockLight {
+        emission: 0,
+        opacity: 0,
+    };
+    pub const OPAQUE: BlockLight = BlockLight {
+        emission: 0,
+        opacity: MAX_LIGHT,
+    };
+
+    pub fn is_opaque(self) -> bool {
+        self.opacity >= MAX_LIGHT
+    }
+
+    /// How `block` treats light, from its name and properties in either edition. Blocks
+    /// without a collision shape let light through, and so do the see-through ones that have
+    /// one; fluids, leaves and the like dim it a little.
+    pub fn of(block: &BlockDescription) -> Self {
+        let id = block.id();
+        let physics = BlockPhysics::of(block);
+        let opacity = if id.ends_with("_leaves") || FILTERING.contains(&id) {
+            1
+        } else if physics.shape.is_empty() {
+            if physics.water || physics.lava { 1 } else { 0 }
+        } else if TRANSPARENT.contains(&id)
+            || TRANSPARENT_SUFFIXES
+                .iter()
+                .any(|suffix| id.ends_with(suffix))
+        {
+            0
+        } else {
+            MAX_LIGHT
+        };
+        Self {
+            emission: emission(block),
+            opacity,
+        }
+    }
+}
+
+/// Blocks that take one level more off the light passing through them than air does.
+const FILTERING: &[&str] = &[
+    "ice",
+    "frosted_ice",
+    "cobweb",
+    "web",
+    "slime_block",
+    "slime",
+    "honey_block",
+];
+/// Blocks with a shape that still let light through unchanged.
+const TRANSPARENT: &[&str] = &[
+    "glass",
+    "glass_pane",
+    "ladder",
+    "lantern",
+    "soul_lantern",
+    "iron_bars",
+    "chain",
+    "beacon",
+    "barrier",
+];
+const TRANSPARENT_SUFFIXES: &[&str] = &["stained_glass", "_glass_pane"];
+
+/// Block light given off by `block`, as in vanilla.
+fn emission(block: &BlockDescription) -> u8 {
+    let lit = block.property("lit") == Some("true");
+    match block.id() {
+        "glowstone"
+        | "sea_lantern"
+        | "lantern"
+        | "jack_o_lantern"
+        | "lit_pumpkin"
+        | "beacon"
+        | "shroomlight"
+        | "lava"
+        | "flowing_lava"
+        | "fire"
+        | "conduit"
+        | "end_gateway"
+        | "end_portal"
+        | "lit_redstone_lamp"
+        | "ochre_froglight"
+        | "verdant_froglight"
+        | "pearlescent_froglight" => 15,
+        "torch" | "wall_torch" | "end_rod" => 14,
+        "lit_furnace" | "lit_blast_furnace" | "lit_smoker" => 13,
+        "soul_torch" | "soul_wall_torch" | "soul_lantern" | "soul_fire" | "crying_obsidian" => 10,
+        "glow_lichen" => 7,
+        "magma_block" | "magma" => 3,
+        "brown_mushroom" | "end_portal_frame" => 1,
+        // Java keeps these in one block with a `lit` property, Bedrock has a block for each
+        "redstone_lamp" if lit => 15,
+        "furnace" | "blast_furnace" | "smoker" if lit => 13,
+        "redstone_torch" | "redstone_wall_torch" => {
+            if block.property("lit") == Some("false") {
+                0
+            } else {
+                7
+            }
+        }
+        _ => 0,
+    }
+}
+
+/// Light properties by block state, from the `BlockRegistry`. States it doesn't describe are
+/// clear if they are air and opaque otherwise.
+#[derive(Resource, Default)]
+pub struct BlockLightTable {
+    states: HashMap<BlockState, BlockLight>,
+}
+
+impl BlockLightTable {
+    pub fn get(&self, state: BlockState) -> BlockLight {
+        match self.states.get(&state) {
+            Some(light) => *light,
+            None if state.is_air() => BlockLight::CLEAR,
+            None => BlockLight::OPAQUE,
+        }
+    }
+
+    pub fn set(&mut self, state: BlockState, light: BlockLight) {
+        self.states.insert(state, light);
+    }
+
+    pub fn from_registry(registry: &BlockRegistry) -> Self {
+        let mut table = Self::default();
+        for (state, block) in registry.iter() {
+            table.set(state, BlockLight::of(block));
+        }
+        table
+    }
+}
+
+/// Light storage the flood fills run over: one column while it is lit on its own, or the lit
+/// part of the world.
+trait LightStorage {
+    /// Light at `pos`, or `None` if it lies outside what is being lit. Above the world there is
+    /// full sky light, which can't be changed.
+    fn light(&self, kind: LightKind, pos: IVec3) -> Option<u8>;
+    fn set_light(&mut self, kind: LightKind, pos: IVec3, value: u8);
+    fn block(&self, pos: IVec3) -> BlockState;
+}
+
+/// Light above the top of the world.
+fn above_world(kind: LightKind) -> Option<u8> {
+    match kind {
+        LightKind::Sky => Some(MAX_LIGHT),
+        LightKind::Block => None,
+    }
+}
+
+/// A single column, lit apart from its neighbours.
+struct Column<'a> {
+    pos: ChunkPos,
+    chunk: &'a mut Chunk,
+}
+
+impl Column<'_> {
+    fn min_y(&self) -> i32 {
+        self.chunk.min_section * SECTION_HEIGHT
+    }
+
+    fn max_y(&self) -> i32 {
+        self.min_y() + self.chunk.sections.len() as i32 * SECTION_HEIGHT
+    }
+}
+
+impl LightStorage for Column<'_> {
+    fn light(&self, kind: LightKind, pos: IVec3) -> Option<u8> {
+        if ChunkPos::from_block(pos) != self.pos || pos.y < self.min_y() {
+            None
+        } else if pos.y >= self.max_y() {
+            above_world(kind)
+        } else {
+            Some(kind.get(self.chunk, pos))
+        }
+    }
+
+    fn set_light(&mut self, kind: LightKind, pos: IVec3, value: u8) {
+        kind.set(self.chunk, pos, value);
+    }
+
+    fn block(&self, pos: IVec3) -> BlockState {
+        self.chunk.block(pos)
+    }
+}
+
+/// The columns whose light is known. Columns still waiting to be lit are left out so their
+/// placeholder light doesn't leak into the rest.
+struct LitWorld<'a> {
+    chunks: &'a mut ChunkMap,
+    lit: &'a HashSet<ChunkPos>,
+}
+
+impl LightStorage for LitWorld<'_> {
+    fn light(&self, kind: LightKind, pos: IVec3) -> Option<u8> {
+        let column = ChunkPos::from_block(pos);
+        if !self.lit.contains(&column) || pos.y < self.chunks.min_y {
+            None
+        } else if pos.y >= self.chunks.max_y() {
+            above_world(kind)
+        } else {
+            self.chunks.chunk(column).map(|chunk| kind.get(chunk, pos))
+        }
+    }
+
+    fn set_light(&mut self, kind: LightKind, pos: IVec3, value: u8) {
+        if let Some(chunk) = self.chunks.chunks.get_mut(&ChunkPos::from_block(pos)) {
+            kind.set(chunk, pos, value);
+        }
+    }
+
+    fn block(&self, pos: IVec3) -> BlockState {
+        self.chunks.block(pos)
+    }
+}
+
+/// Spread light outwards from the blocks in `queue`, raising every neighbour it reaches.
+/// Returns the number of blocks visited.
+fn spread(
+    storage: &mut impl LightStorage,
+    table: &BlockLightTable,
+    kind: LightKind,
+    queue: &mut VecDeque<IVec3>,
+) -> usize {
+    let mut visited = 0;
+    while let Some(pos) = queue.pop_front() {
+        visited += 1;
+        let Some(level) = storage.light(kind, pos) else {
+            continue;
+        };
+        if level <= 1 {
+            continue;
+        }
+        for face in BlockFace::ALL {
+            let dir = face.offset();
+            let next = pos + dir;
+            let Some(current) = storage.light(kind, next) else {
+                continue;
+            };
+            let opacity = table.get(storage.block(next)).opacity;
+            let value = kind.propagated(level, dir, opacity);
+            if value > current {
+                storage.set_light(kind, next, value);
+                queue.push_back(next);
+            }
+        }
+    }
+    visited
+}
+
+/// Take away the light that came from the blocks in `removed`, each with the level it had.
+/// Neighbours lit from elsewhere, and emitters in the darkened area, go into `queue` so
+/// `spread` can fill the area in again.
+fn unspread(
+    storage: &mut impl LightStorage,
+    table: &BlockLightTable,
+    kind: LightKind,
+    removed: &mut VecDeque<(IVec3, u8)>,
+    queue: &mut VecDeque<IVec3>,
+) {
+    while let Some((pos, level)) = removed.pop_front() {
+        for face in BlockFace::ALL {
+            let dir = face.offset();
+            let next = pos + dir;
+            let Some(current) = storage.light(kind, next) else {
+                continue;
+            };
+            if current == 0 {
+                continue;
+            }
+            let straight_sky = kind == LightKind::Sky
+                && dir == IVec3::NEG_Y
+                && level == MAX_LIGHT
+                && current == MAX_LIGHT;
+            if current < level || straight_sky {
+                storage.set_light(kind, next, 0);
+                removed.push_back((next, current));
+                let emission = kind.emission(table.get(storage.block(next)));
+                if emission > 0 {
+                    storage.set_light(kind, next, emission);
+                    queue.push_back(next);
+                }
+            } else {
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+/// Compute the light of one column from its blocks alone, as if no column were next to it.
+fn light_column(pos: ChunkPos, chunk: &mut Chunk, table: &BlockLightTable) {
+    let mut column = Column { pos, chunk };
+    let (min_y, max_y) = (column.min_y(), column.max_y());
+    let origin = pos.origin();
+
+    // sky light straight down, noting how high the shade of any block goes
+    let mut shaded_to = min_y;
+    for z in 0..CHUNK_WIDTH {
+        for x in 0..CHUNK_WIDTH {
+            let mut level = MAX_LIGHT;
+            for y in (min_y..max_y).rev() {
+                let at = origin + IVec3::new(x, y, z);
+                let opacity = table.get(column.block(at)).opacity;
+                level = LightKind::Sky.propagated(level, IVec3::NEG_Y, opacity);
+                if level < MAX_LIGHT {
+                    shaded_to = shaded_to.max(y + 1);
+                }
+                column.set_light(LightKind::Sky, at, level);
+            }
+        }
+    }
+
+    // then sideways into the shade, and block light out from every emitter
+    let mut sky = VecDeque::new();
+    let mut block = VecDeque::new();
+    for y in min_y..max_y {
+        for z in 0..CHUNK_WIDTH {
+            for x in 0..CHUNK_WIDTH {
+                let at = origin + IVec3::new(x, y, z);
+                if y <= shaded_to && column.light(LightKind::Sky, at).unwrap_or(0) > 1 {
+                    sky.push_back(at);
+                }
+                let emission = table.get(column.block(at)).emission;
+                column.set_light(LightKind::Block, at, emission);
+                if emission > 0 {
+                    block.push_back(at);
+                }
+            }
+        }
+    }
+    spread(&mut column, table, LightKind::Sky, &mut sky);
+    spread(&mut column, table, LightKind::Block, &mut block);
+}
+
+/// Each block from `min_y` to `max_y` on the `side` border of column `pos`.
+fn border(
+    pos: ChunkPos,
+    side: BlockFace,
+    min_y: i32,
+    max_y: i32,
+) -> impl Iterator<Item = IVec3> + use<> {
+    let origin = pos.origin();
+    let dir = side.offset();
+    let edge = move |d: i32| if d > 0 { CHUNK_WIDTH - 1 } else { 0 };
+    (min_y..max_y).flat_map(move |y| {
+        (0..CHUNK_WIDTH).map(move |i| {
+            let (x, z) = match side {
+                BlockFace::West | BlockFace::East => (edge(dir.x), i),
+                _ => (i, edge(dir.z)),
+            };
+            origin + IVec3::new(x, y, z)
+        })
+    })
+}
+
+/// Keeps the light of the chunk map up to date.
+#[derive(Resource, Default)]
+pub struct LightEngine {
+    /// Columns whose light is known, computed or sent.
+    lit: HashSet<ChunkPos>,
+    /// Columns to light from scratch.
+    dirty: HashSet<ChunkPos>,
+    /// Blocks changed in lit columns since the last update.
+    changed: Vec<IVec3>,
+    pub columns_lit: u64,
+    pub blocks_relit: u64,
+    /// Time taken by the last update that did anything.
+    pub last_update: Duration,
+}
+
+impl LightEngine {
+    pub fn pending(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// Light every loaded column again from its blocks.
+    pub fn relight_all(&mut self, chunks: &ChunkMap) {
+        self.dirty.extend(chunks.chunks.keys().copied());
+    }
+
+    /// Light up to `COLUMNS_PER_FRAME` dirty columns, then follow block changes.
+    fn update(&mut self, chunks: &mut ChunkMap, table: &BlockLightTable) {
+        self.dirty.retain(|pos| chunks.chunk(*pos).is_some());
+        let batch: Vec<ChunkPos> = self.dirty.iter().copied().take(COLUMNS_PER_FRAME).collect();
+        if batch.is_empty() && self.changed.is_empty() {
+            return;
+        }
+        let started = Instant::now();
+        if !batch.is_empty() {
+            self.light_columns(&batch, chunks, table);
+        }
+        for pos in std::mem::take(&mut self.changed) {
+            self.relight_block(pos, chunks, table);
+        }
+        self.last_update = started.elapsed();
+    }
+
+    fn light_columns(
+        &mut self,
+        batch: &[ChunkPos],
+        chunks: &mut ChunkMap,
+        table: &BlockLightTable,
+    ) {
+        let (min_y, max_y) = (chunks.min_y, chunks.max_y());
+        let mut queues = [VecDeque::new(), VecDeque::new()];
+
+        // light the neighbours had from a column being relit may be stale: take away all they
+        // could have got from it, and refill from what remains
+        let relit: Vec<ChunkPos> = batch
+            .iter()
+            .copied()
+            .filter(|pos| self.lit.remove(pos))
+            .collect();
+        for (kind, queue) in LightKind::ALL.into_iter().zip(&mut queues) {
+            let mut removed: VecDeque<(IVec3, u8)> = relit
+                .iter()
+                .flat_map(|pos| {
+                    SIDES
+                        .into_iter()
+                        .flat_map(move |side| border(*pos, side, min_y, max_y))
+                })
+                .map(|at| (at, MAX_LIGHT))
+                .collect();
+            let mut world = LitWorld {
+                chunks,
+                lit: &self.lit,
+            };
+            unspread(&mut world, table, kind, &mut removed, queue);
+        }
+
+        ComputeTaskPool::get().scope(|scope| {
+            for (pos, chunk) in chunks.chunks.iter_mut() {
+                if batch.contains(pos) {
+                    scope.spawn(async move { light_column(*pos, chunk, table) });
+                }
+            }
+        });
+        for pos in batch {
+            self.dirty.remove(pos);
+            self.lit.insert(*pos);
+        }
+        self.columns_lit += batch.len() as u64;
+
+        // let light cross each border of the new columns, from whichever side is brighter
+        let mut world = LitWorld {
+            chunks,
+            lit: &self.lit,
+        };
+        for pos in batch {
+            for side in SIDES {
+                for at in border(*pos, side, min_y, max_y) {
+                    let next = at + side.offset();
+                    for (kind, queue) in LightKind::ALL.into_iter().zip(&mut queues) {
+                        let (Some(here), Some(there)) =
+                            (world.light(kind, at), world.light(kind, next))
+                        else {
+                            continue;
+                        };
+                        if here > there + 1 {
+                            queue.push_back(at);
+                        } else if there > here + 1 {
+                            queue.push_back(next);
+                        }
+                    }
+                }
+            }
+        }
+        for (kind, queue) in LightKind::ALL.into_iter().zip(&mut queues) {
+            spread(&mut world, table, kind, queue);
+        }
+    }
+
+    /// Update the light around a block that changed.
+    fn relight_block(&mut self, pos: IVec3, chunks: &mut ChunkMap, table: &BlockLightTable) {
+        let mut world = LitWorld {
+            chunks,
+            lit: &self.lit,
+        };
+        let block = table.get(world.block(pos));
+        for kind in LightKind::ALL {
+            let Some(level) = world.light(kind, pos) else {
+                continue;
+            };
+            let mut removed = VecDeque::from([(pos, level)]);
+            let mut queue = VecDeque::new();
+            world.set_light(kind, pos, 0);
+            unspread(&mut world, table, kind, &mut removed, &mut queue);
+            let emission = kind.emission(block);
+            if emission > world.light(kind, pos).unwrap_or(0) {
+                world.set_light(kind, pos, emission);
+                queue.push_back(pos);
+            }
+            self.blocks_relit += spread(&mut world, table, kind, &mut queue) as u64;
+        }
+    }
+}
+
+/// Smooth light at a corner of a block face, as levels from 0 to 15 for the mesher to
+/// interpolate across the face.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VertexLight {
+    pub sky: f32,
+    pub block: f32,
+}
+
+/// Light of the `face` of the block at `pos` at `corner`, whose components are 0 or 1 for the
+/// low or high side of the block along each axis. It is the average of the four blocks in
+/// front of the face touching the corner, leaving out opaque ones, and the diagonal one when
+/// both blocks beside it are opaque since the corner can't see it then.
+pub fn vertex_light(
+    chunks: &ChunkMap,
+    table: &BlockLightTable,
+    pos: IVec3,
+    face: BlockFace,
+    corner: IVec3,
+) -> VertexLight {
+    let normal = face.offset();
+    let front = pos + normal;
+    let mut sides = [IVec3::ZERO; 2];
+    for (side, axis) in sides
+        .iter_mut()
+        .zip((0..3).filter(|axis| normal[*axis] == 0))
+    {
+        side[axis] = if corner[axis] > 0 { 1 } else { -1 };
+    }
+    let opaque = |at: IVec3| table.get(chunks.block(at)).is_opaque();
+    let side_opaque = sides.map(|side| opaque(front + side));
+
+    let mut samples = vec![front];
+    for (side, is_opaque) in sides.iter().zip(side_opaque) {
+        if !is_opaque {
+            samples.push(front + *side);
+        }
+    }
+    let diagonal = front + sides[0] + sides[1];
+    if !(side_opaque[0] && side_opaque[1]) && !opaque(diagonal) {
+        samples.push(diagonal);
+    }
+
+    let count = samples.len() as f32;
+    VertexLight {
+        sky: samples
+            .iter()
+            .map(|at| chunks.sky_light(*at) as f32)
+            .sum::<f32>()
+            / count,
+        block: samples
+            .iter()
+            .map(|at| chunks.block_light(*at) as f32)
+            .sum::<f32>()
+            / count,
+    }
+}
+
+/// Lights the chunk map: computes columns sent without light and follows block changes.
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockRegistry>()
+            .init_resource::<BlockLightTable>()
+            .init_resource::<LightEngine>()
+            .add_diagnostics_panel("Lighting", lighting_panel)
+            .add_systems(
+                PreUpdate,
+                block_light_table_system
+                    .after(block_registry_system)
+                    .run_if(resource_changed::<BlockRegistry>),
+            )
+            .add_systems(
+                Update,
+                (lighting_event_system, lighting_system)
+                    .chain()
+                    .after(chunk_event_system),
+            )
+            .add_console_command(
+                "relight",
+                "relight",
+                "Compute the light of every loaded chunk again",
+                |world, _| {
+                    world.resource_scope(|world, mut engine: Mut<LightEngine>| {
+                        engine.relight_all(world.resource::<ChunkMap>());
+                        Ok(Some(format!("Relighting {} chunks", engine.pending())))
+                    })
+                },
+            );
+    }
+}
+
+/// Rebuild the light table when the block registry changes edition, and relight what is
+/// loaded with it.
+pub fn block_light_table_system(
+    registry: Res<BlockRegistry>,
+    chunks: Res<ChunkMap>,
+    mut table: ResMut<BlockLightTable>,
+    mut engine: ResMut<LightEngine>,
+) {
+    *table = BlockLightTable::from_registry(&registry);
+    engine.relight_all(&chunks);
+}
+
+/// Note which columns need light and which blocks changed.
+pub fn lighting_event_system(
+    mut events: MessageReader<ServerEvent>,
+    mut engine: ResMut<LightEngine>,
+) {
+    for ServerEvent(event) in events.read() {
+        match event {
+            SessionEvent::ChunkLoaded { pos, chunk } => {
+                let pos = ChunkPos::new(pos.x, pos.y);
+                if chunk.as_ref().is_some_and(|chunk| chunk.has_light) {
+                    engine.dirty.remove(&pos);
+                    engine.lit.insert(pos);
+                } else {
+                    engine.dirty.insert(pos);
+                }
+            }
+            SessionEvent::SectionLoaded { pos, .. } => {
+                engine.dirty.insert(ChunkPos::new(pos.x, pos.y));
+            }
+            SessionEvent::LightUpdated { pos, .. } => {
+                let pos = ChunkPos::new(pos.x, pos.y);
+                engine.dirty.remove(&pos);
+                engine.lit.insert(pos);
+            }
+            SessionEvent::ChunkUnloaded { pos } => {
+                let pos = ChunkPos::new(pos.x, pos.y);
+                engine.dirty.remove(&pos);
+                engine.lit.remove(&pos);
+            }
+            SessionEvent::BlockChanged { pos, .. } => {
+                if engine.lit.contains(&ChunkPos::from_block(*pos)) {
+                    engine.changed.push(*pos);
+                }
+            }
+            SessionEvent::DimensionChanged { .. } | SessionEvent::Disconnected { .. } => {
+                *engine = LightEngine {
+                    columns_lit: engine.columns_lit,
+                    blocks_relit: engine.blocks_relit,
+                    ..default()
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Light dirty columns and relight around changed blocks.
+pub fn lighting_system(
+    mut chunks: ResMut<ChunkMap>,
+    table: Res<BlockLightTable>,
+    mut engine: ResMut<LightEngine>,
+) {
+    engine.update(&mut chunks, &table);
+}
+
+pub fn lighting_panel(InMut(ui): InMut<egui::Ui>, engine: Res<LightEngine>) {
+    ui.label(format!("Lit chunks: {}", engine.lit.len()));
+    ui.label(format!("Waiting for light: {}", engine.pending()));
+    ui.label(format!("Chunks lit: {}", engine.columns_lit));
+    ui.label(format!("Blocks relit: {}", engine.blocks_relit));
+    ui.label(format!(
+        "Last update: {:.2} ms",
+        engine.last_update.as_secs_f64() * 1000.0
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::tasks::TaskPool;
+
+    /// Two lit columns side by side, (0, 0) and (1, 0), 32 blocks high with a stone floor up
+    /// to y = 4, described by the Bedrock block data.
+    struct TestWorld {
+        registry: BlockRegistry,
+        table: BlockLightTable,
+        chunks: ChunkMap,
+        engine: LightEngine,
+    }
+
+    impl TestWorld {
+        fn new() -> Self {
+            ComputeTaskPool::get_or_init(TaskPool::default);
+            let registry = BlockRegistry::bedrock();
+            let table = BlockLightTable::from_registry(&registry);
+            let mut world = Self {
+                registry,
+                table,
+                chunks: ChunkMap {
+                    min_y: 0,
+                    height: 32,
+                    ..default()
+                },
+                engine: LightEngine::default(),
+            };
+            let stone = world.state("stone");
+            for pos in [ChunkPos::new(0, 0), ChunkPos::new(1, 0)] {
+                let origin = pos.origin();
+                let chunk = world.chunks.chunk_or_empty(pos);
+                for y in 0..4 {
+                    for z in 0..CHUNK_WIDTH {
+                        for x in 0..CHUNK_WIDTH {
+                            chunk.set_block(origin + IVec3::new(x, y, z), stone);
+                        }
+                    }
+                }
+                world.engine.dirty.insert(pos);
+            }
+            world.update();
+            world
+        }
+
+        fn state(&self, id: &str) -> BlockState {
+            self.registry
+                .iter()
+                .filter(|(_, block)| block.id() == id)
+                .map(|(state, _)| state)
+                .min_by_key(|state| state.0)
+                .unwrap_or_else(|| panic!("{} isn't described", id))
+        }
+
+        fn update(&mut self) {
+            self.engine.update(&mut self.chunks, &self.table);
+            assert_eq!(self.engine.pending(), 0);
+        }
+
+        fn set(&mut self, blocks: impl IntoIterator<Item = IVec3>, id: &str) {
+            let state = self.state(id);
+            for pos in blocks {
+                self.chunks.set_block(pos, state);
+                self.engine.changed.push(pos);
+            }
+            self.update();
+        }
+
+        /// Light the current blocks from scratch, and check that following the changes came
+        /// to the same light.
+        fn assert_matches_relight(&mut self) {
+            let before: Vec<(u8, u8)> = self.light_everywhere();
+            self.engine.relight_all(&self.chunks);
+            self.update();
+            assert!(
+                before == self.light_everywhere(),
+                "relighting changed the light"
+            );
+        }
+
+        fn light_everywhere(&self) -> Vec<(u8, u8)> {
+            let mut light = Vec::new();
+            for y in 0..32 {
+                for z in 0..CHUNK_WIDTH {
+                    for x in 0..2 * CHUNK_WIDTH {
+                        let pos = IVec3::new(x, y, z);
+                        light.push((self.chunks.sky_light(pos), self.chunks.block_light(pos)));
+                    }
+                }
+            }
+            light
+        }
+    }
+
+    /// The blocks from `min` to `max`, inclusive.
+    fn cuboid(min: IVec3, max: IVec3) -> Vec<IVec3> {
+        let mut blocks = Vec::new();
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    blocks.push(IVec3::new(x, y, z));
+                }
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn light_from_block_data() {
+        let world = TestWorld::new();
+        let light = |id| world.table.get(world.state(id));
+        assert!(light("stone").is_opaque());
+        assert!(light("obsidian").is_opaque());
+        assert_eq!(light("glass").opacity, 0);
+        assert_eq!(light("oak_leaves").opacity, 1);
+        assert_eq!(light("water").opacity, 1);
+        assert_eq!(
+            light("torch"),
+            BlockLight {
+                emission: 14,
+                opacity: 0
+            }
+        );
+        assert_eq!(light("glowstone").emission, 15);
+        assert_eq!(light("lantern").emission, 15);
+        assert_eq!(light("soul_lantern").emission, 10);
+        assert_eq!(light("air"), BlockLight::CLEAR);
+    }
+
+    #[test]
+    fn placing_and_removing_a_torch() {
+        let mut world = TestWorld::new();
+        let torch = IVec3::new(8, 4, 8);
+        world.set([torch], "torch");
+        assert_eq!(world.chunks.block_light(torch), 14);
+        assert_eq!(world.chunks.block_light(torch + IVec3::new(3, 0, 0)), 11);
+        assert_eq!(world.chunks.block_light(torch + IVec3::new(2, 3, -1)), 8);
+        // the floor is opaque
+        assert_eq!(world.chunks.block_light(torch - IVec3::Y), 0);
+        world.assert_matches_relight();
+
+        world.set([torch], "air");
+        assert!(
+            world
+                .light_everywhere()
+                .iter()
+                .all(|(_, block)| *block == 0)
+        );
+        world.assert_matches_relight();
+    }
+
+    #[test]
+    fn placing_and_removing_a_roof() {
+        let mut world = TestWorld::new();
+        let under = IVec3::new(6, 4, 6);
+        assert_eq!(world.chunks.sky_light(under), MAX_LIGHT);
+
+        // sky light comes in from the sides, one level less per block
+        let roof = cuboid(IVec3::new(2, 8, 2), IVec3::new(10, 8, 10));
+        world.set(roof.clone(), "stone");
+        assert_eq!(world.chunks.sky_light(under), 10);
+        assert_eq!(world.chunks.sky_light(IVec3::new(3, 4, 6)), MAX_LIGHT - 2);
+        world.assert_matches_relight();
+
+        // glass lets the sky through; leaves and water take a level off, and the light below
+        // them fades like any other
+        world.set(roof.clone(), "glass");
+        assert_eq!(world.chunks.sky_light(under), MAX_LIGHT);
+        for id in ["oak_leaves", "water"] {
+            world.set(roof.clone(), id);
+            assert_eq!(world.chunks.sky_light(IVec3::new(6, 8, 6)), MAX_LIGHT - 1);
+            assert_eq!(world.chunks.sky_light(IVec3::new(6, 7, 6)), MAX_LIGHT - 2);
+        }
+        world.assert_matches_relight();
+
+        world.set(roof, "air");
+        assert_eq!(world.chunks.sky_light(under), MAX_LIGHT);
+        world.assert_matches_relight();
+    }
+
+    #[test]
+    fn light_crosses_column_borders() {
+        let mut world = TestWorld::new();
+        // a torch next to the border lights the column beside it
+        let torch = IVec3::new(CHUNK_WIDTH - 2, 4, 8);
+        world.set([torch], "torch");
+        assert_eq!(
+            world.chunks.block_light(IVec3::new(CHUNK_WIDTH + 1, 4, 8)),
+            11
+        );
+
+        // a roof over the border shades both sides alike
+        let roof = cuboid(
+            IVec3::new(CHUNK_WIDTH - 6, 10, 2),
+            IVec3::new(CHUNK_WIDTH + 5, 10, 12),
+        );
+        world.set(roof, "stone");
+        let (west, east) = (
+            IVec3::new(CHUNK_WIDTH - 1, 6, 7),
+            IVec3::new(CHUNK_WIDTH, 6, 7),
+        );
+        assert_eq!(world.chunks.sky_light(west), world.chunks.sky_light(east));
+        assert!(world.chunks.sky_light(west) < MAX_LIGHT);
+        world.assert_matches_relight();
+
+        // lighting one column again keeps what came over the border from the other
+        world.engine.dirty.insert(ChunkPos::new(1, 0));
+        world.update();
+        assert_eq!(
+            world.chunks.block_light(IVec3::new(CHUNK_WIDTH + 1, 4, 8)),
+            11
+        );
+        world.assert_matches_relight();
+    }
+}
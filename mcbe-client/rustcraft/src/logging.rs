@@ -0,0 +1,419 @@
+use bevy::app::App;
+use bevy::log::BoxedLayer;
+use bevy::log::tracing::field::{Field, Visit};
+use bevy::log::tracing::level_filters::LevelFilter;
+use bevy::log::tracing::subscriber::Interest;
+use bevy::log::tracing::{Event, Level, Metadata, Subscriber};
+use bevy::log::tracing_subscriber::Layer;
+use bevy::log::tracing_subscriber::layer::Context;
+use chrono::{DateTime, Local};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError, RwLock};
+use tracing_log::NormalizeEvent;
+
+use crate::paths::logs_dir;
+
+/// How many log lines are kept in memory.
+pub const RECENT_LOG_CAPACITY: usize = 2048;
+/// Number of rotated log files kept next to `latest.log`.
+pub const MAX_LOG_FILES: usize = 10;
+/// `latest.log` is rotated once it grows past this size.
+pub const MAX_LOG_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The most recent log lines, oldest first.
+pub static RECENT_LOGS: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+
+/// Levels currently applied to every log output, editable at runtime.
+pub static LOG_LEVELS: RwLock<LogLevels> = RwLock::new(LogLevels {
+    default: LevelFilter::INFO,
+    modules: Vec::new(),
+});
+
+static LOG_FILE: Mutex<Option<RotatingFile>> = Mutex::new(None);
+
+thread_local! {
+    /// Set while this thread is inside the sink, so a panic there (whose hook logs and reads
+    /// `RECENT_LOGS`) doesn't wait for locks the thread already holds.
+    static IN_SINK: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for LogLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {:>5} {}: {}",
+            self.time.format("%H:%M:%S%.3f"),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// Snapshot of the in-memory log buffer.
+pub fn recent_logs() -> Vec<LogLine> {
+    if IN_SINK.get() {
+        return Vec::new();
+    }
+    RECENT_LOGS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .cloned()
+        .collect()
+}
+
+/// A default level plus per-module overrides (longest matching prefix wins).
+#[derive(Debug, Clone)]
+pub struct LogLevels {
+    pub default: LevelFilter,
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogLevels {
+    /// Parse `RUST_LOG` style directives, e.g. `info,rustcraft::net=debug`.
+    pub fn parse(directives: &str) -> Self {
+        let mut levels = LogLevels {
+            default: LevelFilter::INFO,
+            modules: Vec::new(),
+        };
+        for directive in directives
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+        {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Ok(level) = level.parse() {
+                        levels.set(Some(target), level);
+                    }
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        levels.default = level;
+                    }
+                }
+            }
+        }
+        levels
+    }
+
+    /// Set the level for `target`, or the default level when `target` is `None`.
+    pub fn set(&mut self, target: Option<&str>, level: LevelFilter) {
+        match target {
+            None => self.default = level,
+            Some(target) => match self.modules.iter_mut().find(|(t, _)| t == target) {
+                Some((_, existing)) => *existing = level,
+                None => self.modules.push((target.to_string(), level)),
+            },
+        }
+    }
+
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    pub fn enabled(&self, target: &str, level: &Level) -> bool {
+        self.level_for(target) >= *level
+    }
+}
+
+/// Change a log level at runtime; `None` sets the default level.
+pub fn set_log_level(target: Option<&str>, level: LevelFilter) {
+    if let Ok(mut levels) = LOG_LEVELS.write() {
+        levels.set(target, level);
+    }
+}
+
+/// `latest.log` in the logs directory, rotated on startup and when it gets too big.
+struct RotatingFile {
+    dir: PathBuf,
+    /// `None` only while rotating.
+    file: Option<LineWriter<File>>,
+    size: u64,
+    max_size: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        archive_latest(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file: Some(create_latest(dir)?),
+            size: 0,
+            max_size: MAX_LOG_FILE_SIZE,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        writeln!(file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    /// Close `latest.log` before it is renamed; Windows can't rename open files.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        archive_latest(&self.dir)?;
+        self.file = Some(create_latest(&self.dir)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn create_latest(dir: &Path) -> std::io::Result<LineWriter<File>> {
+    Ok(LineWriter::new(File::create(dir.join("latest.log"))?))
+}
+
+/// Rename `latest.log` after its last write time and prune old logs.
+fn archive_latest(dir: &Path) -> std::io::Result<()> {
+    let latest = dir.join("latest.log");
+    if let Ok(modified) = std::fs::metadata(&latest).and_then(|m| m.modified()) {
+        // every archive is numbered after the last one of its second, so several from the
+        // same second sort in order too, even once the first were pruned
+        let stamp = DateTime::<Local>::from(modified)
+            .format("%Y-%m-%d_%H.%M.%S-")
+            .to_string();
+        let next = std::fs::read_dir(dir)?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                let n = name.strip_prefix(&stamp)?.strip_suffix(".log")?;
+                n.parse::<u32>().ok()
+            })
+            .max()
+            .map_or(0, |n| n + 1);
+        std::fs::rename(&latest, dir.join(format!("{}{:03}.log", stamp, next)))?;
+    }
+
+    // timestamped, numbered names sort chronologically
+    let mut archives: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "log")
+                && path.file_name().is_some_and(|name| name != "latest.log")
+        })
+        .collect();
+    archives.sort();
+    let excess = archives.len().saturating_sub(MAX_LOG_FILES);
+    for old in &archives[..excess] {
+        let _ = std::fs::remove_file(old);
+    }
+    Ok(())
+}
+
+/// Tracing layer that applies `LOG_LEVELS` to all output and copies every
+/// event into `RECENT_LOGS` and the log file.
+pub struct LogSinkLayer;
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else if !field.name().starts_with("log.") {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogSinkLayer {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // levels can change at any time, so never let the decision be cached
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        LOG_LEVELS
+            .read()
+            .map(|levels| levels.enabled(metadata.target(), metadata.level()))
+            .unwrap_or(true)
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // events forwarded from the `log` crate carry their real target in fields
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let line = LogLine {
+            time: Local::now(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.0,
+        };
+
+        // an event logged from inside the sink would wait on locks this thread holds
+        if IN_SINK.replace(true) {
+            return;
+        }
+        let _leave = LeaveSink;
+
+        {
+            let mut file = LOG_FILE.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(writer) = file.as_mut() {
+                let stamped = format!("{} {}", line.time.format("%Y-%m-%d"), line);
+                if let Err(err) = writer.write_line(&stamped) {
+                    eprintln!("Log file disabled: {}", err);
+                    *file = None;
+                }
+            }
+        }
+
+        {
+            let mut logs = RECENT_LOGS.lock().unwrap_or_else(PoisonError::into_inner);
+            if logs.len() >= RECENT_LOG_CAPACITY {
+                logs.pop_front();
+            }
+            logs.push_back(line);
+        }
+    }
+}
+
+/// Clears `IN_SINK` when the sink returns, or unwinds.
+struct LeaveSink;
+
+impl Drop for LeaveSink {
+    fn drop(&mut self) {
+        IN_SINK.set(false);
+    }
+}
+
+/// `LogPlugin::custom_layer` hook: opens the log file and installs the sink.
+pub fn log_layer(_app: &mut App) -> Option<BoxedLayer> {
+    if let Ok(directives) = std::env::var("RUST_LOG")
+        && let Ok(mut levels) = LOG_LEVELS.write()
+    {
+        *levels = LogLevels::parse(&directives);
+    }
+
+    match RotatingFile::open(&logs_dir()) {
+        Ok(file) => {
+            if let Ok(mut slot) = LOG_FILE.lock() {
+                *slot = Some(file);
+            }
+        }
+        Err(err) => eprintln!(
+            "Could not open log file in {}: {}",
+            logs_dir().display(),
+            err
+        ),
+    }
+
+    Some(Box::new(LogSinkLayer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_keeps_every_line() {
+        let dir = std::env::temp_dir().join(format!("rustcraft-logs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut file = RotatingFile::open(&dir).unwrap();
+        file.max_size = 64;
+        let lines: Vec<String> = (0..20).map(|i| format!("line {:02}", i)).collect();
+        for line in &lines {
+            file.write_line(line).unwrap();
+        }
+        drop(file);
+
+        let mut logs: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        // archives in name order, then the file still being written
+        logs.sort_by_key(|path| (path.ends_with("latest.log"), path.clone()));
+        let written: Vec<String> = logs
+            .into_iter()
+            .flat_map(|path| {
+                std::fs::read_to_string(path)
+                    .unwrap()
+                    .lines()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, lines);
+    }
+
+    #[test]
+    fn pruning_keeps_the_newest_archives() {
+        let dir =
+            std::env::temp_dir().join(format!("rustcraft-log-archives-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // all written within the same second
+        let modified = std::time::SystemTime::now();
+        for i in 0..MAX_LOG_FILES + 5 {
+            let file = File::create(dir.join("latest.log")).unwrap();
+            (&file).write_all(format!("log {}", i).as_bytes()).unwrap();
+            file.set_modified(modified).unwrap();
+            drop(file);
+            archive_latest(&dir).unwrap();
+        }
+
+        let mut archives: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        archives.sort();
+        let kept: Vec<String> = archives
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let expected: Vec<String> = (5..MAX_LOG_FILES + 5)
+            .map(|i| format!("log {}", i))
+            .collect();
+        assert_eq!(kept, expected);
+        assert!(archives[0].to_string_lossy().ends_with("-005.log"));
+    }
+
+    #[test]
+    fn longest_module_prefix_wins() {
+        let levels = LogLevels::parse("warn, rustcraft=info ,rustcraft::net=trace,bad=nope");
+        assert_eq!(levels.level_for("wgpu"), LevelFilter::WARN);
+        assert_eq!(levels.level_for("rustcraft"), LevelFilter::INFO);
+        assert_eq!(levels.level_for("rustcraft::net::java"), LevelFilter::TRACE);
+        assert_eq!(levels.level_for("rustcraft_net"), LevelFilter::WARN);
+        assert_eq!(levels.level_for("bad"), LevelFilter::WARN);
+    }
+}
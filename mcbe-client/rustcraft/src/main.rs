@@ -1,97 +1,210 @@
 #![recursion_limit = "256"]
 
 use bevy::{
+    app::{ScheduleRunnerPlugin, TerminalCtrlCHandlerPlugin},
+    log::{Level, LogPlugin},
     prelude::*,
-    window::{CompositeAlphaMode, CursorOptions, ExitCondition, PresentMode},
+    window::{CompositeAlphaMode, CursorOptions, ExitCondition},
 };
 use bevy_egui::EguiPlugin;
 use bevy_egui_kbgp::KbgpPlugin;
+use std::time::Duration;
 
-mod data;
-mod egui_dbg;
-mod fps;
-mod input;
-mod setup;
-mod ui;
-mod update;
-mod window;
-
-use crate::input::input_system;
-use crate::setup::setup;
-use crate::update::update;
-use crate::window::BevyWindowPlugin;
-use crate::{
-    data::GlobalSettings,
-    fps::{fps_counter_system, fps_title_system, frame_cap_system_improved, frame_start_system},
-};
-use crate::{
+use rustcraft::blocks::BlocksPlugin;
+use rustcraft::cli::{CliAction, CliArgs, USAGE, cli_startup_system};
+use rustcraft::config::{ConfigPlugin, ResolvedConfig};
+use rustcraft::console::{ConsolePlugin, StdinConsolePlugin, console_closed};
+use rustcraft::crash::CrashPlugin;
+use rustcraft::debug_gizmos::DebugGizmosPlugin;
+use rustcraft::egui_dbg::EguiDebugPlugin;
+use rustcraft::entity::EntityPlugin;
+use rustcraft::input::{ChordKeys, chord_keys_system, input_system};
+use rustcraft::interaction::{BlockOverlayPlugin, InteractionPlugin, interaction_input_system};
+use rustcraft::lighting::LightingPlugin;
+use rustcraft::logging::log_layer;
+use rustcraft::net::NetworkPlugin;
+use rustcraft::physics::{PhysicsPlugin, movement_input_system};
+use rustcraft::prediction::PredictionPlugin;
+use rustcraft::setup::setup;
+use rustcraft::update::update;
+use rustcraft::window::BevyWindowPlugin;
+use rustcraft::world::WorldPlugin;
+use rustcraft::{
     data::{FpsCap, FpsState, FrameStart},
     ui::GameUIPlugin,
 };
+use rustcraft::{
+    data::{FpsMode, GlobalSettings},
+    fps::{
+        fps_counter_system, fps_title_system, frame_cap_system_improved, frame_start_system,
+        vsync_lock_system,
+    },
+};
+
+/// Tick rate of `--headless` runs unless a manual fps cap is set.
+const HEADLESS_TICK_RATE: u32 = 60;
+
+fn log_plugin() -> LogPlugin {
+    LogPlugin {
+        // most verbose level that can be enabled at runtime; `log_layer`
+        // applies the actual per-module levels and writes the log file
+        level: Level::DEBUG,
+        custom_layer: log_layer,
+        ..Default::default()
+    }
+}
+
+/// Window, renderer, egui and everything drawn with them.
+fn add_windowed_plugins(app: &mut App, config: &ResolvedConfig) {
+    let mut primary_window = Window {
+        // start on vsync like the default FPS cap; the config and the F1/F2 keys change it
+        // Hide the OS titlebar at startup — we'll restore it after 5 seconds in the `update` system.
+        present_mode: FpsMode::VSync.present_mode(),
+        title: "RustCraft".into(),
+        resize_constraints: WindowResizeConstraints {
+            min_width: 800.0,
+            min_height: 600.0,
+            max_width: f32::INFINITY,
+            max_height: f32::INFINITY,
+        },
+        resizable: true,
+        fullsize_content_view: true,
+        titlebar_shown: true,
+        titlebar_show_title: true,
+        titlebar_show_buttons: true,
+        titlebar_transparent: true,
+        transparent: false,
+        composite_alpha_mode: CompositeAlphaMode::PostMultiplied,
+        ..Default::default()
+    };
+    config.apply_to_window(&mut primary_window);
+
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_cursor_options: Some(CursorOptions {
+                    visible: true,
+                    ..Default::default()
+                }),
+                exit_condition: ExitCondition::OnAllClosed,
+                close_when_requested: true,
+                primary_window: Some(primary_window),
+            })
+            .set(log_plugin()),
+    )
+    // add egui overlay
+    // .add_plugins(EguiPlugin {
+    //     enable_multipass_for_primary_context: false, // deprecated
+    //     ui_render_order: bevy_egui::UiRenderOrder::EguiAboveBevyUi,
+    //     bindless_mode_array_size: NonZero::new(1024),
+    // })
+    .add_plugins(EguiPlugin::default())
+    .add_plugins(KbgpPlugin)
+    .add_plugins(BevyWindowPlugin)
+    .add_plugins(GameUIPlugin)
+    .add_plugins(EguiDebugPlugin)
+    .add_plugins(DebugGizmosPlugin)
+    .add_plugins(BlockOverlayPlugin)
+    .init_resource::<ChordKeys>()
+    .add_systems(
+        PreUpdate,
+        (
+            // tracked even while the console is open, so a chord pressed then still holds
+            // its keys back once it closes
+            chord_keys_system,
+            (
+                input_system,
+                movement_input_system,
+                interaction_input_system,
+            )
+                .run_if(console_closed),
+        )
+            .chain(),
+    )
+    .add_systems(Update, fps_title_system)
+    // frame cap runs late in the frame
+    // UI system is registered by `EguiUIPlugin`; do not duplicate scheduling here
+    .add_systems(PostUpdate, frame_cap_system_improved);
+}
+
+/// No window or renderer: the schedule runner ticks the app and the console reads stdin.
+fn add_headless_plugins(app: &mut App, config: &ResolvedConfig) {
+    let tick_rate = match config.fps_cap() {
+        FpsMode::Manual(n) => n,
+        FpsMode::VSync | FpsMode::Uncapped => HEADLESS_TICK_RATE,
+    };
+    app.add_plugins(
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / tick_rate as f64,
+        ))),
+    )
+    .add_plugins(log_plugin())
+    .add_plugins(TerminalCtrlCHandlerPlugin)
+    .add_plugins(StdinConsolePlugin);
+}
 
 /// Application entry
 fn main() {
-    App::new()
-        .insert_resource(ClearColor(Color::srgba(1.0, 0.2, 0.25, 0.75)))
+    let cli = match CliArgs::from_env() {
+        Ok(CliAction::Run(cli)) => *cli,
+        Ok(CliAction::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(CliAction::Version) => {
+            println!("rustcraft {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(2);
+        }
+    };
+
+    // defaults < game.config < user settings < RUSTCRAFT_* < command line
+    let config = ResolvedConfig::resolve(&cli);
+    let mut global_settings = GlobalSettings::default();
+    let mut fps_cap = FpsCap::default();
+    config.apply_to_settings(&mut global_settings, &mut fps_cap);
+
+    let mut app = App::new();
+    if cli.headless {
+        add_headless_plugins(&mut app, &config);
+    } else {
+        add_windowed_plugins(&mut app, &config);
+    }
+
+    app.insert_resource(ClearColor(Color::srgba(1.0, 0.2, 0.25, 0.75)))
         // FPS tracking resource
         .insert_resource(FpsState::default())
         // debug toggle (starts disabled)
-        .insert_resource(GlobalSettings::default())
+        .insert_resource(global_settings)
         // FPS cap resource (start with VSync)
-        .insert_resource(FpsCap::default())
+        .insert_resource(fps_cap)
+        .insert_resource(cli)
+        .insert_resource(config)
         // frame start timestamp resource (initialized to now)
         .insert_resource(FrameStart::now())
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_cursor_options: Some(CursorOptions {
-                visible: true,
-                ..Default::default()
-            }),
-            exit_condition: ExitCondition::OnAllClosed,
-            close_when_requested: true,
-            primary_window: Some(Window {
-                // default to AutoVsync; switching to explicit Fifo/Immediate is handled by the input handler
-                // Hide the OS titlebar at startup — we'll restore it after 5 seconds in the `update` system.
-                present_mode: PresentMode::AutoVsync,
-                title: "RustCraft".into(),
-                resize_constraints: WindowResizeConstraints {
-                    min_width: 800.0,
-                    min_height: 600.0,
-                    max_width: f32::INFINITY,
-                    max_height: f32::INFINITY,
-                },
-                resizable: true,
-                fullsize_content_view: true,
-                titlebar_shown: true,
-                titlebar_show_title: true,
-                titlebar_show_buttons: true,
-                titlebar_transparent: true,
-                transparent: false,
-                composite_alpha_mode: CompositeAlphaMode::PostMultiplied,
-                ..Default::default()
-            }),
-        }))
-        // add egui overlay
-        // .add_plugins(EguiPlugin {
-        //     enable_multipass_for_primary_context: false, // deprecated
-        //     ui_render_order: bevy_egui::UiRenderOrder::EguiAboveBevyUi,
-        //     bindless_mode_array_size: NonZero::new(1024),
-        // })
-        .add_plugins(EguiPlugin::default())
-        .add_plugins(KbgpPlugin)
-        .add_plugins(BevyWindowPlugin)
-        .add_plugins(GameUIPlugin)
+        // shared by windowed and headless runs; added after egui so they can detect it
+        .add_plugins(CrashPlugin)
+        .add_plugins(NetworkPlugin)
+        .add_plugins(WorldPlugin)
+        .add_plugins(EntityPlugin)
+        .add_plugins(BlocksPlugin)
+        .add_plugins(PhysicsPlugin)
+        .add_plugins(PredictionPlugin)
+        .add_plugins(InteractionPlugin)
+        .add_plugins(LightingPlugin)
+        .add_plugins(ConsolePlugin)
+        .add_plugins(ConfigPlugin)
         // startup
         .add_systems(PreStartup, setup)
+        .add_systems(Startup, cli_startup_system)
         // record frame start early in the frame
         .add_systems(PreUpdate, frame_start_system)
         // main update systems
-        .add_systems(PreUpdate, input_system)
         .add_systems(Update, fps_counter_system)
-        .add_systems(Update, fps_title_system)
+        .add_systems(Update, vsync_lock_system)
         .add_systems(Update, update)
-        // .add_systems(PostUpdate, (egui_debug_system, ui::ui_system))
-        // frame cap runs late in the frame
-        // UI system is registered by `EguiUIPlugin`; do not duplicate scheduling here
-        .add_systems(PostUpdate, frame_cap_system_improved)
         .run();
 }
@@ -0,0 +1,542 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::console::{ConsoleAppExt, CvarKind, CvarValue};
+use crate::data::GlobalSettings;
+use crate::diagnostics::DiagnosticsAppExt;
+use bedrock::blob_cache::BlobCacheSettings;
+use capture::{CaptureMode, CaptureSettings};
+use conditions::{NetworkConditions, SimulatedNetwork, simulation_panel};
+use lan::{LanServers, lan_discovery_system};
+use session::{PlayerAction, Session, SessionEvent, SessionOptions};
+use stats::network_stats_panel;
+
+pub mod backend;
+pub mod bedrock;
+pub mod capture;
+pub mod codec;
+pub mod conditions;
+pub mod java;
+pub mod lan;
+pub mod nbt;
+pub mod proxy;
+pub mod raknet;
+pub mod session;
+pub mod stats;
+
+/// Anything that can end or prevent a session.
+#[derive(Debug)]
+pub enum NetError {
+    Io(std::io::Error),
+    /// The server sent something we couldn't make sense of.
+    Protocol(String),
+    /// The server closed the connection, with its reason.
+    Disconnected(String),
+    Timeout(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Io(err) => write!(f, "I/O error: {}", err),
+            NetError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            NetError::Disconnected(reason) => write!(f, "disconnected: {}", reason),
+            NetError::Timeout(msg) => write!(f, "timed out: {}", msg),
+            NetError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl From<std::io::Error> for NetError {
+    fn from(err: std::io::Error) -> Self {
+        NetError::Io(err)
+    }
+}
+
+/// Which game protocol a server speaks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Edition {
+    #[default]
+    Bedrock,
+    Java,
+}
+
+impl Edition {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Edition::Bedrock => 19132,
+            Edition::Java => 25565,
+        }
+    }
+}
+
+impl FromStr for Edition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bedrock" | "be" | "mcbe" => Ok(Edition::Bedrock),
+            "java" | "je" => Ok(Edition::Java),
+            other => Err(format!(
+                "unknown edition '{}' (expected bedrock or java)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Edition::Bedrock => "bedrock",
+            Edition::Java => "java",
+        })
+    }
+}
+
+/// `host[:port]` of a server.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: u16,
+}
+
+impl ServerAddress {
+    /// Parse `host`, `host:port` or `[v6]:port`, using the edition's default port if omitted.
+    pub fn parse(input: &str, edition: Edition) -> Result<Self, String> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err("empty server address".into());
+        }
+
+        let (host, port) = if let Some(rest) = input.strip_prefix('[') {
+            let (host, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| format!("unterminated IPv6 address '{}'", input))?;
+            (host, rest.strip_prefix(':'))
+        } else {
+            match input.rsplit_once(':') {
+                // a bare IPv6 address has several colons and no port
+                Some((host, port)) if !host.contains(':') => (host, Some(port)),
+                _ => (input, None),
+            }
+        };
+
+        let port = match port {
+            Some(port) => port
+                .parse::<u16>()
+                .map_err(|_| format!("invalid port '{}' in '{}'", port, input))?,
+            None => edition.default_port(),
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in '{}'", input));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for ServerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Ask the network layer to join a server.
+#[derive(Message, Clone, Debug)]
+pub struct ConnectRequest {
+    pub address: ServerAddress,
+    pub edition: Edition,
+}
+
+/// Ask the network layer to play back a capture file instead of joining a server.
+#[derive(Message, Clone, Debug)]
+pub struct ReplayRequest {
+    pub path: PathBuf,
+    /// Playback speed relative to the recording.
+    pub speed: f64,
+}
+
+/// Ask the network layer to leave the current server.
+#[derive(Message, Clone, Debug, Default)]
+pub struct DisconnectRequest;
+
+/// Connection to the current server, if any.
+#[derive(Resource, Debug, Default, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Disconnected,
+    Connecting {
+        address: ServerAddress,
+        edition: Edition,
+    },
+    Connected {
+        address: ServerAddress,
+        edition: Edition,
+    },
+}
+
+/// Everything the server sends, re-emitted as a message each frame.
+#[derive(Message, Clone, Debug)]
+pub struct ServerEvent(pub SessionEvent);
+
+/// Ask the network layer to perform an action on the server.
+#[derive(Message, Clone, Debug)]
+pub struct SendAction(pub PlayerAction);
+
+/// The session behind `ConnectionState`, if any.
+#[derive(Resource, Default)]
+pub struct ActiveSession(pub Option<Session>);
+
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<ConnectRequest>()
+            .add_message::<ReplayRequest>()
+            .add_message::<DisconnectRequest>()
+            .add_message::<ServerEvent>()
+            .add_message::<SendAction>()
+            .init_resource::<ConnectionState>()
+            .init_resource::<ActiveSession>()
+            .init_resource::<CaptureSettings>()
+            .init_resource::<SimulatedNetwork>()
+            .init_resource::<LanServers>()
+            .init_resource::<BlobCacheSettings>()
+            .add_diagnostics_panel("Network", network_stats_panel)
+            .add_diagnostics_panel("Network Simulation", simulation_panel)
+            .add_systems(
+                PreUpdate,
+                (connection_request_system, session_event_system).chain(),
+            )
+            .add_systems(Update, lan_discovery_system)
+            .add_systems(PostUpdate, (view_distance_system, send_action_system))
+            .add_cvar(
+                "net.lan_discovery",
+                "Look for Bedrock and Java games on the local network",
+                CvarKind::Bool,
+                |world| CvarValue::Bool(world.resource::<LanServers>().enabled),
+                |world, value| world.resource_mut::<LanServers>().enabled = value.as_bool(),
+            )
+            .add_cvar(
+                "net.blob_cache",
+                "Cache Bedrock chunk data on disk for new sessions",
+                CvarKind::Bool,
+                |world| CvarValue::Bool(world.resource::<BlobCacheSettings>().enabled),
+                |world, value| world.resource_mut::<BlobCacheSettings>().enabled = value.as_bool(),
+            )
+            .add_cvar(
+                "net.blob_cache_size",
+                "Most disk space the Bedrock blob cache may use, in MB",
+                CvarKind::Int {
+                    min: 1,
+                    max: 100_000,
+                },
+                |world| CvarValue::Int(world.resource::<BlobCacheSettings>().budget_mb as i64),
+                |world, value| {
+                    world.resource_mut::<BlobCacheSettings>().budget_mb = value.as_int() as u32
+                },
+            );
+        register_capture_console(app);
+        register_simulation_console(app);
+    }
+}
+
+const CAPTURE_MODES: &[&str] = &["raw", "decoded", "full"];
+
+fn register_capture_console(app: &mut App) {
+    app.add_cvar(
+        "net.capture",
+        "Record packets of new sessions to the captures folder",
+        CvarKind::Bool,
+        |world| CvarValue::Bool(world.resource::<CaptureSettings>().enabled),
+        |world, value| world.resource_mut::<CaptureSettings>().enabled = value.as_bool(),
+    )
+    .add_cvar(
+        "net.capture_mode",
+        "What captures store: raw payloads, decoded summaries or both",
+        CvarKind::Choice(CAPTURE_MODES),
+        |world| CvarValue::Text(world.resource::<CaptureSettings>().mode.to_string()),
+        |world, value| {
+            if let Ok(mode) = value.as_str().parse::<CaptureMode>() {
+                world.resource_mut::<CaptureSettings>().mode = mode;
+            }
+        },
+    )
+    .add_console_command(
+        "replay",
+        "replay <file> [speed]",
+        "Play back a capture as if its server was live",
+        |world, args| {
+            let path = PathBuf::from(args.first().ok_or("usage: replay <file> [speed]")?);
+            let speed = match args.get(1) {
+                Some(speed) => speed
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|s| *s > 0.0)
+                    .ok_or_else(|| format!("bad speed '{}'", speed))?,
+                None => 1.0,
+            };
+            if !path.is_file() {
+                return Err(format!("'{}' does not exist", path.display()));
+            }
+            let message = format!("Replaying {}", path.display());
+            world.write_message(ReplayRequest { path, speed });
+            Ok(Some(message))
+        },
+    );
+}
+
+/// Integer view of one field of the simulated network, for console variables.
+type SimulationField = (
+    &'static str,
+    &'static str,
+    i64,
+    fn(&NetworkConditions) -> i64,
+    fn(&mut NetworkConditions, i64),
+);
+
+const SIMULATION_FIELDS: &[SimulationField] = &[
+    (
+        "net.sim.latency",
+        "Simulated latency added each way, in milliseconds",
+        5000,
+        |c| c.latency.as_millis() as i64,
+        |c, v| c.latency = Duration::from_millis(v as u64),
+    ),
+    (
+        "net.sim.jitter",
+        "Random extra latency of up to this many milliseconds, either way",
+        5000,
+        |c| c.jitter.as_millis() as i64,
+        |c, v| c.jitter = Duration::from_millis(v as u64),
+    ),
+    (
+        "net.sim.loss",
+        "Percentage of simulated packet loss",
+        100,
+        |c| (c.loss * 100.0).round() as i64,
+        |c, v| c.loss = v as f32 / 100.0,
+    ),
+    (
+        "net.sim.duplicate",
+        "Percentage of datagrams delivered twice",
+        100,
+        |c| (c.duplicate * 100.0).round() as i64,
+        |c, v| c.duplicate = v as f32 / 100.0,
+    ),
+    (
+        "net.sim.reorder",
+        "Percentage of datagrams delivered late, after later ones",
+        100,
+        |c| (c.reorder * 100.0).round() as i64,
+        |c, v| c.reorder = v as f32 / 100.0,
+    ),
+    (
+        "net.sim.bandwidth",
+        "Simulated bandwidth cap in kB/s, 0 for none",
+        1_000_000,
+        |c| c.bandwidth.map_or(0, |b| b as i64 / 1000),
+        |c, v| c.bandwidth = (v > 0).then_some(v as u32 * 1000),
+    ),
+];
+
+fn register_simulation_console(app: &mut App) {
+    for &(name, help, max, get, set) in SIMULATION_FIELDS {
+        app.add_cvar(
+            name,
+            help,
+            CvarKind::Int { min: 0, max },
+            move |world| CvarValue::Int(get(&world.resource::<SimulatedNetwork>().get())),
+            move |world, value| {
+                world
+                    .resource::<SimulatedNetwork>()
+                    .update(|c| set(c, value.as_int()))
+            },
+        );
+    }
+    app.add_console_command(
+        "netsim",
+        "netsim [preset]",
+        "Apply a simulated network preset, or list them",
+        |world, args| {
+            let names: Vec<&str> = NetworkConditions::PRESETS.iter().map(|(n, _)| *n).collect();
+            let Some(name) = args.first() else {
+                return Ok(Some(format!("presets: {}", names.join(", "))));
+            };
+            let conditions = NetworkConditions::preset(name).ok_or_else(|| {
+                format!("unknown preset '{}' (one of {})", name, names.join(", "))
+            })?;
+            world.resource::<SimulatedNetwork>().set(conditions);
+            Ok(Some(format!("Simulating a {} network", name)))
+        },
+    );
+}
+
+/// How often the app's sessions measure their round trip time, for the Network panel.
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Resources that shape new sessions.
+#[derive(SystemParam)]
+pub struct SessionSettings<'w> {
+    pub global_settings: Res<'w, GlobalSettings>,
+    pub capture: Res<'w, CaptureSettings>,
+    pub network: Res<'w, SimulatedNetwork>,
+    pub blob_cache: Res<'w, BlobCacheSettings>,
+}
+
+impl SessionSettings<'_> {
+    /// Options from the player's settings, without capture or simulation.
+    pub fn options(&self) -> SessionOptions {
+        SessionOptions {
+            username: self.global_settings.game_settings.username.clone(),
+            view_distance: self.global_settings.game_settings.render_distance,
+            ping_interval: Some(PING_INTERVAL),
+            blob_cache: self.blob_cache.options(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Handle connect/disconnect requests.
+pub fn connection_request_system(
+    mut connects: MessageReader<ConnectRequest>,
+    mut replays: MessageReader<ReplayRequest>,
+    mut disconnects: MessageReader<DisconnectRequest>,
+    mut state: ResMut<ConnectionState>,
+    mut session: ResMut<ActiveSession>,
+    settings: SessionSettings,
+) {
+    for _ in disconnects.read() {
+        if let Some(session) = session.0.take() {
+            info!("Disconnecting from {}", session.address);
+            // joining would stall the frame until the server lets go
+            session.close();
+        }
+        *state = ConnectionState::Disconnected;
+    }
+
+    if let Some(request) = connects.read().last() {
+        // dropping the old session closes it
+        session.0 = None;
+        info!("Connecting to {} ({})", request.address, request.edition);
+        let options = SessionOptions {
+            capture: settings.capture.options_for(&request.address.host),
+            network: Some(settings.network.clone()),
+            ..settings.options()
+        };
+        if let Some(capture) = &options.capture {
+            info!("Capturing packets to {}", capture.path.display());
+        }
+        session.0 = Some(Session::connect(
+            request.address.clone(),
+            request.edition,
+            options,
+        ));
+        *state = ConnectionState::Connecting {
+            address: request.address.clone(),
+            edition: request.edition,
+        };
+    }
+
+    if let Some(request) = replays.read().last() {
+        session.0 = None;
+        match Session::replay(&request.path, request.speed, settings.options()) {
+            Ok(replay) => {
+                info!(
+                    "Replaying {} (recorded from {})",
+                    request.path.display(),
+                    replay.address
+                );
+                *state = ConnectionState::Connecting {
+                    address: replay.address.clone(),
+                    edition: replay.edition,
+                };
+                session.0 = Some(replay);
+            }
+            Err(err) => {
+                error!("Cannot replay {}: {}", request.path.display(), err);
+                *state = ConnectionState::Disconnected;
+            }
+        }
+    }
+}
+
+/// Forward session events into the app and keep `ConnectionState` current.
+pub fn session_event_system(
+    mut session: ResMut<ActiveSession>,
+    mut state: ResMut<ConnectionState>,
+    mut events: MessageWriter<ServerEvent>,
+) {
+    let Some(active) = &session.0 else {
+        return;
+    };
+
+    let mut closed = false;
+    for event in active.poll() {
+        match &event {
+            SessionEvent::Joined { .. } => {
+                info!("Joined {}", active.address);
+                *state = ConnectionState::Connected {
+                    address: active.address.clone(),
+                    edition: active.edition,
+                };
+            }
+            SessionEvent::Chat { message, .. } => info!("[chat] {}", message),
+            SessionEvent::Disconnected { reason } => {
+                warn!("Lost connection to {}: {}", active.address, reason);
+                *state = ConnectionState::Disconnected;
+                closed = true;
+            }
+            _ => {}
+        }
+        events.write(ServerEvent(event));
+    }
+    if closed {
+        session.0 = None;
+    }
+}
+
+/// Ask the server for more or fewer chunks when the render distance changes mid-session.
+pub fn view_distance_system(
+    global_settings: Res<GlobalSettings>,
+    session: Res<ActiveSession>,
+    mut requested: Local<Option<u8>>,
+) {
+    let render_distance = global_settings.game_settings.render_distance;
+    if *requested == Some(render_distance) {
+        return;
+    }
+    if let Some(session) = &session.0 {
+        if requested.is_some() {
+            session.send(PlayerAction::SetViewDistance(render_distance));
+        }
+        *requested = Some(render_distance);
+    } else {
+        *requested = None;
+    }
+}
+
+pub fn send_action_system(mut actions: MessageReader<SendAction>, session: Res<ActiveSession>) {
+    let Some(session) = &session.0 else {
+        actions.clear();
+        return;
+    };
+    for SendAction(action) in actions.read() {
+        session.send(action.clone());
+    }
+}
@@ -0,0 +1,89 @@
+//! The one interface the session thread drives, whatever game the server speaks.
+//!
+//! A backend owns its connection and turns wire packets into `SessionEvent`s and
+//! `PlayerAction`s into wire packets, so nothing above the session ever sees edition
+//! specific packets. Adding a game (Hytale, say) means adding an implementation here.
+
+use crate::net::bedrock::BedrockClient;
+use crate::net::capture::{CaptureHeader, CaptureRecord};
+use crate::net::java::JavaClient;
+use crate::net::session::{PlayerAction, SessionEvent, SessionOptions};
+use crate::net::stats::NetStats;
+use crate::net::{Edition, NetError, ServerAddress};
+
+/// A logged in connection speaking one game protocol.
+pub trait ProtocolBackend: Send {
+    fn edition(&self) -> Edition;
+
+    /// Send the packets for one player action. Actions the protocol can't express yet are
+    /// ignored rather than treated as errors.
+    fn perform(&mut self, action: &PlayerAction) -> Result<(), NetError>;
+
+    /// Handle whatever arrived since the last poll, blocking briefly at most, and append the
+    /// resulting events.
+    fn poll(&mut self, events: &mut Vec<SessionEvent>) -> Result<(), NetError>;
+}
+
+impl ProtocolBackend for JavaClient {
+    fn edition(&self) -> Edition {
+        Edition::Java
+    }
+
+    fn perform(&mut self, action: &PlayerAction) -> Result<(), NetError> {
+        JavaClient::perform(self, action)
+    }
+
+    fn poll(&mut self, events: &mut Vec<SessionEvent>) -> Result<(), NetError> {
+        JavaClient::poll(self, events)
+    }
+}
+
+impl ProtocolBackend for BedrockClient {
+    fn edition(&self) -> Edition {
+        Edition::Bedrock
+    }
+
+    fn perform(&mut self, action: &PlayerAction) -> Result<(), NetError> {
+        BedrockClient::perform(self, action)
+    }
+
+    fn poll(&mut self, events: &mut Vec<SessionEvent>) -> Result<(), NetError> {
+        BedrockClient::poll(self, events)
+    }
+}
+
+/// Connect and log in with the backend for `edition`.
+pub fn connect(
+    edition: Edition,
+    address: &ServerAddress,
+    options: &SessionOptions,
+    stats: &NetStats,
+) -> Result<Box<dyn ProtocolBackend>, NetError> {
+    Ok(match edition {
+        Edition::Java => Box::new(JavaClient::login(address, options, stats)?),
+        Edition::Bedrock => Box::new(BedrockClient::login(address, options, stats)?),
+    })
+}
+
+/// Play back the server side of a capture with the backend for its edition.
+pub fn replay(
+    header: &CaptureHeader,
+    records: &[CaptureRecord],
+    speed: f64,
+    address: &ServerAddress,
+    options: &SessionOptions,
+    stats: &NetStats,
+) -> Result<Box<dyn ProtocolBackend>, NetError> {
+    let edition: Edition = header.edition.parse().map_err(NetError::Protocol)?;
+    Ok(match edition {
+        Edition::Java => Box::new(JavaClient::replay(records, speed, address, options, stats)?),
+        Edition::Bedrock => Box::new(BedrockClient::replay(
+            records,
+            header.protocol,
+            speed,
+            address,
+            options,
+            stats,
+        )?),
+    })
+}
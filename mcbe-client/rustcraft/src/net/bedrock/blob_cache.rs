@@ -0,0 +1,400 @@
+//! Bedrock's client blob cache: chunk data the server refers to by the xxHash64 of its
+//! contents, kept on disk between sessions so unchanged sub-chunks aren't downloaded again.
+
+use bevy::log::{debug, warn};
+use bevy::prelude::Resource;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::paths::blob_cache_dir;
+
+/// Whether new Bedrock sessions use the blob cache, and how big it may get.
+#[derive(Resource, Debug, Clone)]
+pub struct BlobCacheSettings {
+    pub enabled: bool,
+    pub budget_mb: u32,
+}
+
+impl Default for BlobCacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            budget_mb: 256,
+        }
+    }
+}
+
+impl BlobCacheSettings {
+    pub fn options(&self) -> Option<BlobCacheOptions> {
+        self.enabled.then(|| BlobCacheOptions {
+            dir: blob_cache_dir(),
+            budget: self.budget_mb as u64 * 1_000_000,
+        })
+    }
+}
+
+/// Where a session keeps its blobs.
+#[derive(Clone, Debug)]
+pub struct BlobCacheOptions {
+    pub dir: PathBuf,
+    /// Bytes kept at most; the least recently used blobs go first.
+    pub budget: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    size: u64,
+    used: SystemTime,
+}
+
+/// Blobs stored one per file, named by hash. Use times are the files' modification times, so
+/// eviction order survives restarts.
+pub struct BlobCache {
+    dir: PathBuf,
+    budget: u64,
+    entries: HashMap<u64, Entry>,
+    size: u64,
+}
+
+impl BlobCache {
+    /// Index the blobs already in `options.dir`, creating it if needed.
+    pub fn open(options: &BlobCacheOptions) -> io::Result<Self> {
+        fs::create_dir_all(&options.dir)?;
+        let mut entries = HashMap::new();
+        let mut size = 0;
+        for entry in fs::read_dir(&options.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(hash) = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".blob"))
+                .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            else {
+                continue;
+            };
+            let metadata = entry.metadata()?;
+            let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            size += metadata.len();
+            entries.insert(
+                hash,
+                Entry {
+                    size: metadata.len(),
+                    used,
+                },
+            );
+        }
+        let mut cache = Self {
+            dir: options.dir.clone(),
+            budget: options.budget,
+            entries,
+            size,
+        };
+        cache.evict();
+        Ok(cache)
+    }
+
+    fn path(&self, hash: u64) -> PathBuf {
+        blob_path(&self.dir, hash)
+    }
+
+    pub fn contains(&self, hash: u64) -> bool {
+        self.entries.contains_key(&hash)
+    }
+
+    /// Bytes stored.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The blob with `hash`, if stored and intact.
+    pub fn get(&mut self, hash: u64) -> Option<Vec<u8>> {
+        self.entries.get(&hash)?;
+        let path = self.path(hash);
+        let data = match fs::read(&path) {
+            Ok(data) if xxhash64(&data, 0) == hash => data,
+            Ok(_) => {
+                warn!("Blob {:016x} is corrupt, dropping it", hash);
+                self.remove(hash);
+                return None;
+            }
+            Err(err) => {
+                debug!("Cannot read blob {:016x}: {}", hash, err);
+                self.remove(hash);
+                return None;
+            }
+        };
+        let now = SystemTime::now();
+        if let Some(entry) = self.entries.get_mut(&hash) {
+            entry.used = now;
+        }
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(now);
+        }
+        Some(data)
+    }
+
+    /// Store a blob; its hash must already have been checked.
+    pub fn insert(&mut self, hash: u64, data: &[u8]) {
+        if self.contains(hash) {
+            return;
+        }
+        let path = self.path(hash);
+        let temp = path.with_extension("tmp");
+        if let Err(err) = fs::write(&temp, data).and_then(|()| fs::rename(&temp, &path)) {
+            warn!("Cannot store blob {:016x}: {}", hash, err);
+            let _ = fs::remove_file(&temp);
+            return;
+        }
+        self.size += data.len() as u64;
+        self.entries.insert(
+            hash,
+            Entry {
+                size: data.len() as u64,
+                used: SystemTime::now(),
+            },
+        );
+        self.evict();
+    }
+
+    fn remove(&mut self, hash: u64) {
+        if let Some(entry) = self.entries.remove(&hash) {
+            self.size -= entry.size;
+            let _ = fs::remove_file(self.path(hash));
+        }
+    }
+
+    /// Drop least recently used blobs until the cache fits its budget.
+    fn evict(&mut self) {
+        if self.size <= self.budget {
+            return;
+        }
+        let mut by_age: Vec<(SystemTime, u64)> = self
+            .entries
+            .iter()
+            .map(|(hash, entry)| (entry.used, *hash))
+            .collect();
+        by_age.sort_unstable();
+        for (_, hash) in by_age {
+            if self.size <= self.budget {
+                break;
+            }
+            self.remove(hash);
+        }
+    }
+}
+
+fn blob_path(dir: &Path, hash: u64) -> PathBuf {
+    dir.join(format!("{:016x}.blob", hash))
+}
+
+const PRIME_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn xxh_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME_1)
+}
+
+fn xxh_merge(acc: u64, value: u64) -> u64 {
+    (acc ^ xxh_round(0, value))
+        .wrapping_mul(PRIME_1)
+        .wrapping_add(PRIME_4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"))
+}
+
+/// XXH64, the hash blob ids are made of (with seed 0).
+pub fn xxhash64(data: &[u8], seed: u64) -> u64 {
+    let mut rest = data;
+    let mut hash = if data.len() >= 32 {
+        let mut lanes = [
+            seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+            seed.wrapping_add(PRIME_2),
+            seed,
+            seed.wrapping_sub(PRIME_1),
+        ];
+        while rest.len() >= 32 {
+            for (i, lane) in lanes.iter_mut().enumerate() {
+                *lane = xxh_round(*lane, read_u64(&rest[i * 8..]));
+            }
+            rest = &rest[32..];
+        }
+        let mut hash = lanes[0]
+            .rotate_left(1)
+            .wrapping_add(lanes[1].rotate_left(7))
+            .wrapping_add(lanes[2].rotate_left(12))
+            .wrapping_add(lanes[3].rotate_left(18));
+        for lane in lanes {
+            hash = xxh_merge(hash, lane);
+        }
+        hash
+    } else {
+        seed.wrapping_add(PRIME_5)
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+
+    while rest.len() >= 8 {
+        hash ^= xxh_round(0, read_u64(rest));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(PRIME_1)
+            .wrapping_add(PRIME_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let value = u32::from_le_bytes(rest[..4].try_into().expect("4 bytes")) as u64;
+        hash ^= value.wrapping_mul(PRIME_1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(PRIME_2)
+            .wrapping_add(PRIME_3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash ^= (byte as u64).wrapping_mul(PRIME_5);
+        hash = hash.rotate_left(11).wrapping_mul(PRIME_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME_3);
+    hash ^ (hash >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn xxhash64_known_answers() {
+        // from the reference implementation
+        let long: Vec<u8> = (0..100u32).map(|i| (i * 7 + 3) as u8).collect();
+        let cases: &[(&[u8], u64, u64)] = &[
+            (b"", 0, 0xEF46_DB37_51D8_E999),
+            (b"a", 0, 0xD24E_C4F1_A98C_6E5B),
+            (b"abc", 0, 0x44BC_2CF5_AD77_0999),
+            (b"abcd", 0, 0xDE03_27B0_D25D_92CC),
+            (b"xxhash", 0, 0x32DD_3895_2C4B_C720),
+            (b"abcdefg", 0, 0x1860_940E_2902_822D),
+            (b"abcdefgh", 0, 0x3AD3_5177_5B46_34B7),
+            (&long[..31], 0, 0xA2AA_5F33_CC4A_6119),
+            (&long[..32], 0, 0x23C3_C17E_F790_FD97),
+            (
+                b"Nobody inspects the spammish repetition",
+                0,
+                0xFBCE_A83C_8A37_8BF1,
+            ),
+            (&long, 0, 0xA61F_8D4C_170F_E531),
+            (b"", 1, 0xD5AF_BA13_36A3_BE4B),
+            (b"abc", 20, 0xD8A7_6DCA_A7DB_FDA4),
+            (&long, 0x9E37_79B9_7F4A_7C15, 0xF6D8_F65C_625A_BB4F),
+        ];
+        for (data, seed, expected) in cases {
+            assert_eq!(
+                xxhash64(data, *seed),
+                *expected,
+                "{} bytes, seed {}",
+                data.len(),
+                seed
+            );
+        }
+    }
+
+    /// A cache in a fresh directory, removed again when dropped.
+    struct TestCache {
+        cache: BlobCache,
+        dir: PathBuf,
+    }
+
+    impl TestCache {
+        fn new(name: &str, budget: u64) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "rustcraft-blobs-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            let cache = BlobCache::open(&BlobCacheOptions {
+                dir: dir.clone(),
+                budget,
+            })
+            .unwrap();
+            Self { cache, dir }
+        }
+
+        /// Store `data` under its hash, a moment after whatever happened last so use times
+        /// differ.
+        fn insert(&mut self, data: &[u8]) -> u64 {
+            std::thread::sleep(Duration::from_millis(5));
+            let hash = xxhash64(data, 0);
+            self.cache.insert(hash, data);
+            hash
+        }
+
+        fn get(&mut self, hash: u64) -> Option<Vec<u8>> {
+            std::thread::sleep(Duration::from_millis(5));
+            self.cache.get(hash)
+        }
+    }
+
+    impl Drop for TestCache {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn least_recently_used_blob_goes_first() {
+        let mut test = TestCache::new("lru", 25);
+        let a = test.insert(&[1; 10]);
+        let b = test.insert(&[2; 10]);
+        assert_eq!(test.get(a), Some(vec![1; 10]));
+
+        // over budget: b was used longest ago
+        let c = test.insert(&[3; 10]);
+        assert!(test.cache.contains(a) && test.cache.contains(c));
+        assert!(!test.cache.contains(b));
+        assert!(!blob_path(&test.dir, b).exists());
+        assert_eq!(test.cache.size(), 20);
+
+        // use times are kept in the files, so a smaller cache opened later keeps the newest
+        test.get(a).unwrap();
+        let reopened = BlobCache::open(&BlobCacheOptions {
+            dir: test.dir.clone(),
+            budget: 15,
+        })
+        .unwrap();
+        assert!(reopened.contains(a) && !reopened.contains(c));
+        assert_eq!(reopened.size(), 10);
+    }
+
+    #[test]
+    fn damaged_blobs_are_dropped() {
+        let mut test = TestCache::new("corrupt", 1000);
+        let good = test.insert(b"intact");
+        let bad = test.insert(b"about to be damaged");
+        let gone = test.insert(b"about to be deleted");
+        fs::write(blob_path(&test.dir, bad), b"about to be damagee").unwrap();
+        fs::remove_file(blob_path(&test.dir, gone)).unwrap();
+
+        assert_eq!(test.get(good).as_deref(), Some(&b"intact"[..]));
+        assert_eq!(test.get(bad), None);
+        assert!(!test.cache.contains(bad));
+        assert!(!blob_path(&test.dir, bad).exists());
+        assert_eq!(test.get(gone), None);
+        assert!(!test.cache.contains(gone));
+        assert_eq!(test.cache.size(), 6);
+        assert_eq!(test.get(0x1234), None);
+    }
+}
@@ -0,0 +1,389 @@
+//! Bedrock world data: sub-chunks in the paletted network format, 3D biomes and block
+//! entities, decoded into the client's chunk types.
+
+use bevy::math::IVec3;
+
+use crate::net::NetError;
+use crate::net::codec::PacketReader;
+use crate::net::nbt::{Nbt, read_bedrock_nbt};
+use crate::world::{
+    BIOME_CELL, BlockEntity, BlockState, CHUNK_WIDTH, Chunk, SECTION_HEIGHT, SECTION_VOLUME,
+    Section,
+};
+
+/// `level_chunk` sub-chunk counts that mean "ask for the sections with `sub_chunk_request`".
+pub const SUB_CHUNK_REQUESTS_UNLIMITED: u32 = u32::MAX;
+/// Like `SUB_CHUNK_REQUESTS_UNLIMITED`, but followed by the number of sections worth asking for.
+pub const SUB_CHUNK_REQUESTS_LIMITED: u32 = u32::MAX - 1;
+
+/// `sub_chunk` entry results.
+pub mod sub_chunk_result {
+    pub const SUCCESS: u8 = 1;
+    pub const SUCCESS_ALL_AIR: u8 = 6;
+}
+
+/// The block state compound of air as hashed for network ids: `{name: "minecraft:air",
+/// states: {}}` in little-endian NBT.
+const AIR_STATE_NBT: &[u8] =
+    b"\x0a\x00\x00\x08\x04\x00name\x0d\x00minecraft:air\x0a\x06\x00states\x00\x00";
+
+/// Runtime id of air. Servers are expected to send hashed block ids
+/// (`block-network-ids-are-hashes`, on by default), as the palette order isn't known here.
+pub const AIR_RUNTIME_ID: u32 = fnv1a_32(AIR_STATE_NBT);
+
+pub const fn fnv1a_32(bytes: &[u8]) -> u32 {
+    let mut hash = 0x811C_9DC5_u32;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u32).wrapping_mul(0x0100_0193);
+        i += 1;
+    }
+    hash
+}
+
+/// The `BlockState` of a runtime id. Air and id 0 trade places, so that air is 0 like in the
+/// other editions.
+pub fn block_state(runtime_id: u32) -> BlockState {
+    BlockState(match runtime_id {
+        AIR_RUNTIME_ID => 0,
+        0 => AIR_RUNTIME_ID,
+        id => id,
+    })
+}
+
+/// Lowest Y and height of a dimension: 0 overworld, 1 nether, 2 the end.
+pub fn dimension_bounds(dimension: i32) -> (i32, i32) {
+    match dimension {
+        1 => (0, 128),
+        2 => (0, 256),
+        _ => (-64, 384),
+    }
+}
+
+/// Bedrock orders the values of a sub-chunk X, then Z, then Y.
+fn storage_index(x: i32, y: i32, z: i32) -> usize {
+    ((x << 8) | (z << 4) | y) as usize
+}
+
+/// One paletted storage: a value for each block of a sub-chunk, in Bedrock order. `None` is
+/// the marker biomes use for "same as the section below".
+fn read_storage(r: &mut PacketReader) -> Result<Option<Box<[u32; SECTION_VOLUME]>>, NetError> {
+    let header = r.u8()?;
+    let bits = (header >> 1) as usize;
+    if bits == 0x7F {
+        return Ok(None);
+    }
+    if header & 1 == 0 {
+        return Err(NetError::Protocol(
+            "sub-chunk palette is stored as NBT, not runtime ids".into(),
+        ));
+    }
+    if !matches!(bits, 0..=6 | 8 | 16) {
+        return Err(NetError::Protocol(format!(
+            "bad sub-chunk palette size {}",
+            bits
+        )));
+    }
+
+    // values don't straddle words, so 3, 5 and 6 bits leave some unused
+    let per_word = 32_usize.checked_div(bits);
+    let mut words = Vec::new();
+    if let Some(per_word) = per_word {
+        for _ in 0..SECTION_VOLUME.div_ceil(per_word) {
+            words.push(r.u32_le()?);
+        }
+    }
+    let palette_len = if bits == 0 { 1 } else { r.zigzag32()? };
+    if palette_len <= 0 || palette_len as usize > r.remaining() {
+        return Err(NetError::Protocol(format!(
+            "bad sub-chunk palette length {}",
+            palette_len
+        )));
+    }
+    let palette = (0..palette_len)
+        .map(|_| r.zigzag32().map(|id| id as u32))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut values = Box::new([palette[0]; SECTION_VOLUME]);
+    if let Some(per_word) = per_word {
+        let mask = (1u32 << bits) - 1;
+        for (i, value) in values.iter_mut().enumerate() {
+            let index = (words[i / per_word] >> ((i % per_word) * bits)) & mask;
+            *value = *palette.get(index as usize).ok_or_else(|| {
+                NetError::Protocol(format!("palette index {} out of range", index))
+            })?;
+        }
+    }
+    Ok(Some(values))
+}
+
+/// A serialized sub-chunk: the blocks of its first layer, in `Section` order. Later layers hold
+/// water inside blocks, which the other editions don't have, and are skipped.
+pub fn read_sub_chunk(r: &mut PacketReader) -> Result<Box<[BlockState; SECTION_VOLUME]>, NetError> {
+    let layers = match r.u8()? {
+        1 => 1,
+        8 => r.u8()?,
+        9 => {
+            let layers = r.u8()?;
+            let _y = r.i8()?;
+            layers
+        }
+        version => {
+            return Err(NetError::Unsupported(format!(
+                "sub-chunk version {}",
+                version
+            )));
+        }
+    };
+
+    let mut blocks = Box::new([BlockState::AIR; SECTION_VOLUME]);
+    for layer in 0..layers {
+        let storage = read_storage(r)?
+            .ok_or_else(|| NetError::Protocol("block storage refers to another".into()))?;
+        if layer > 0 {
+            continue;
+        }
+        for y in 0..SECTION_HEIGHT {
+            for z in 0..CHUNK_WIDTH {
+                for x in 0..CHUNK_WIDTH {
+                    blocks[Section::index(x, y, z)] = block_state(storage[storage_index(x, y, z)]);
+                }
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+/// Block entity compounds up to the end of the data, keyed by their position.
+pub fn read_block_entities(r: &mut PacketReader) -> Result<Vec<(IVec3, BlockEntity)>, NetError> {
+    let mut entities = Vec::new();
+    while r.remaining() > 0 {
+        let data = read_bedrock_nbt(r)?;
+        let coordinate = |key| data.get(key).and_then(Nbt::as_int);
+        let (Some(x), Some(y), Some(z)) = (coordinate("x"), coordinate("y"), coordinate("z"))
+        else {
+            continue;
+        };
+        let id = data
+            .get("id")
+            .and_then(Nbt::as_str)
+            .unwrap_or_default()
+            .to_string();
+        entities.push((IVec3::new(x, y, z), BlockEntity { id, data }));
+    }
+    Ok(entities)
+}
+
+/// The payload of `level_chunk`: `sub_chunks` sections from the bottom of the dimension, the
+/// biomes of every section, border blocks (Education Edition, ignored) and block entities.
+pub fn read_level_chunk(
+    payload: &[u8],
+    sub_chunks: usize,
+    min_section: i32,
+    section_count: usize,
+) -> Result<Chunk, NetError> {
+    if sub_chunks > section_count {
+        return Err(NetError::Protocol(format!(
+            "{} sub-chunks in a column of {}",
+            sub_chunks, section_count
+        )));
+    }
+    let mut r = PacketReader::new(payload);
+    let mut chunk = Chunk::new(min_section, section_count);
+
+    for section in &mut chunk.sections[..sub_chunks] {
+        section.blocks = read_sub_chunk(&mut r)?;
+    }
+
+    let mut previous: Option<Box<[u32; SECTION_VOLUME]>> = None;
+    for section in &mut chunk.sections {
+        if r.remaining() == 0 {
+            break;
+        }
+        let biomes = match read_storage(&mut r)? {
+            Some(biomes) => previous.insert(biomes),
+            None => previous.as_mut().ok_or_else(|| {
+                NetError::Protocol("first biome storage refers to the one below".into())
+            })?,
+        };
+        // one sample per 4x4x4 cell, like Java
+        for y in (0..SECTION_HEIGHT).step_by(BIOME_CELL as usize) {
+            for z in (0..CHUNK_WIDTH).step_by(BIOME_CELL as usize) {
+                for x in (0..CHUNK_WIDTH).step_by(BIOME_CELL as usize) {
+                    section.biomes[Section::biome_index(x, y, z)] = biomes[storage_index(x, y, z)];
+                }
+            }
+        }
+    }
+
+    if r.remaining() > 0 {
+        let border_blocks = r.u8()? as usize;
+        r.bytes(border_blocks)?;
+        chunk.block_entities = read_block_entities(&mut r)?.into_iter().collect();
+    }
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::codec::PacketWriter;
+
+    /// A runtime-id storage of `bits` per value: its header, the words (with the bits a word
+    /// has left over set, which readers must ignore), then the palette.
+    fn storage(w: &mut PacketWriter, bits: usize, indices: &[u32], palette: &[u32]) {
+        w.u8((bits << 1 | 1) as u8);
+        if let Some(per_word) = 32_usize.checked_div(bits) {
+            for values in indices.chunks(per_word) {
+                let unused = u32::MAX.checked_shl((per_word * bits) as u32).unwrap_or(0);
+                let word = values
+                    .iter()
+                    .enumerate()
+                    .fold(unused, |word, (i, v)| word | v << (i * bits));
+                w.u32_le(word);
+            }
+            w.zigzag32(palette.len() as i32);
+        }
+        for id in palette {
+            w.zigzag32(*id as i32);
+        }
+    }
+
+    /// Palette indices with `index` at (x, y, z) and 0 everywhere else.
+    fn one_block(x: i32, y: i32, z: i32, index: u32) -> Vec<u32> {
+        let mut indices = vec![0; SECTION_VOLUME];
+        indices[storage_index(x, y, z)] = index;
+        indices
+    }
+
+    #[test]
+    fn air_swaps_with_zero() {
+        assert_eq!(block_state(AIR_RUNTIME_ID), BlockState::AIR);
+        assert_eq!(block_state(0), BlockState(AIR_RUNTIME_ID));
+        assert_eq!(block_state(77), BlockState(77));
+    }
+
+    #[test]
+    fn version_8_with_two_layers() {
+        let mut w = PacketWriter::new();
+        w.u8(8).u8(2);
+        let palette = [AIR_RUNTIME_ID, 0, 12_345];
+        let mut indices = one_block(1, 2, 3, 2);
+        indices[storage_index(15, 15, 15)] = 1;
+        storage(&mut w, 4, &indices, &palette);
+        // the water layer is read past but not kept
+        storage(&mut w, 1, &one_block(1, 2, 3, 1), &[AIR_RUNTIME_ID, 999]);
+        let mut r = PacketReader::new(&w.buf);
+        let blocks = read_sub_chunk(&mut r).unwrap();
+        assert_eq!(r.remaining(), 0);
+
+        assert_eq!(blocks[Section::index(1, 2, 3)], BlockState(12_345));
+        // X, Z, Y order is turned around into the section's Y, Z, X
+        assert_eq!(blocks[Section::index(3, 2, 1)], BlockState::AIR);
+        assert_eq!(
+            blocks[Section::index(15, 15, 15)],
+            BlockState(AIR_RUNTIME_ID)
+        );
+        let placed = blocks.iter().filter(|b| !b.is_air()).count();
+        assert_eq!(placed, 2);
+        assert!(!blocks.contains(&BlockState(999)));
+    }
+
+    #[test]
+    fn version_9_with_an_uneven_word() {
+        // 5 bits: six values per word and two bits over
+        let mut w = PacketWriter::new();
+        w.u8(9).u8(1).i8(-3);
+        let palette: Vec<u32> = (100..120).collect();
+        let indices: Vec<u32> = (0..SECTION_VOLUME as u32).map(|i| i % 20).collect();
+        storage(&mut w, 5, &indices, &palette);
+        let mut r = PacketReader::new(&w.buf);
+        let blocks = read_sub_chunk(&mut r).unwrap();
+        assert_eq!(r.remaining(), 0);
+        for (x, y, z) in [(0, 0, 0), (0, 5, 0), (4, 7, 9), (15, 15, 15)] {
+            let expected = 100 + storage_index(x, y, z) as u32 % 20;
+            assert_eq!(blocks[Section::index(x, y, z)], BlockState(expected));
+        }
+    }
+
+    #[test]
+    fn single_entry_palette_has_no_words() {
+        let mut w = PacketWriter::new();
+        w.u8(1);
+        storage(&mut w, 0, &[], &[4242]);
+        let mut r = PacketReader::new(&w.buf);
+        let blocks = read_sub_chunk(&mut r).unwrap();
+        assert_eq!(r.remaining(), 0);
+        assert!(blocks.iter().all(|b| *b == BlockState(4242)));
+
+        // all air in version 8
+        let mut w = PacketWriter::new();
+        w.u8(8).u8(1);
+        storage(&mut w, 0, &[], &[AIR_RUNTIME_ID]);
+        let blocks = read_sub_chunk(&mut PacketReader::new(&w.buf)).unwrap();
+        assert!(blocks.iter().all(|b| b.is_air()));
+    }
+
+    #[test]
+    fn bad_sub_chunks() {
+        let read = |w: &PacketWriter| read_sub_chunk(&mut PacketReader::new(&w.buf));
+
+        let mut w = PacketWriter::new();
+        w.u8(2);
+        assert!(matches!(read(&w), Err(NetError::Unsupported(_))));
+
+        // palette as NBT
+        let mut w = PacketWriter::new();
+        w.u8(8).u8(1).u8(4 << 1);
+        assert!(read(&w).is_err());
+
+        // no such size
+        let mut w = PacketWriter::new();
+        w.u8(8).u8(1).u8(7 << 1 | 1);
+        assert!(read(&w).is_err());
+
+        // index past the palette
+        let mut w = PacketWriter::new();
+        w.u8(8).u8(1);
+        storage(&mut w, 2, &one_block(0, 0, 0, 3), &[AIR_RUNTIME_ID, 1]);
+        assert!(read(&w).is_err());
+
+        // blocks can't copy the storage below
+        let mut w = PacketWriter::new();
+        w.u8(8).u8(1).u8(0xFF);
+        assert!(read(&w).is_err());
+
+        // cut short
+        let mut w = PacketWriter::new();
+        w.u8(8).u8(1);
+        storage(&mut w, 4, &one_block(0, 0, 0, 1), &[AIR_RUNTIME_ID, 1]);
+        w.buf.truncate(100);
+        assert!(read(&w).is_err());
+    }
+
+    #[test]
+    fn level_chunk_with_biomes() {
+        let mut w = PacketWriter::new();
+        // one sub-chunk sent of two sections
+        w.u8(8).u8(1);
+        storage(&mut w, 1, &one_block(8, 0, 8, 1), &[AIR_RUNTIME_ID, 5]);
+        // biomes: a palette in the first section, the second the same
+        storage(&mut w, 1, &one_block(4, 8, 12, 1), &[1, 2]);
+        w.u8(0xFF);
+        // no border blocks, no block entities
+        w.u8(0);
+
+        let chunk = read_level_chunk(&w.buf, 1, -4, 2).unwrap();
+        assert_eq!(chunk.block(IVec3::new(8, -64, 8)), BlockState(5));
+        assert!(chunk.sections[1].blocks.iter().all(|b| b.is_air()));
+        for section in &chunk.sections {
+            assert_eq!(section.biomes[Section::biome_index(4, 8, 12)], 2);
+            assert_eq!(section.biomes[Section::biome_index(0, 0, 0)], 1);
+        }
+
+        assert!(read_level_chunk(&w.buf, 3, -4, 2).is_err());
+        let mut w = PacketWriter::new();
+        w.u8(0xFF);
+        assert!(read_level_chunk(&w.buf, 0, -4, 2).is_err());
+    }
+}
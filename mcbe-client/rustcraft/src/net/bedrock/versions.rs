@@ -0,0 +1,100 @@
+//! The Bedrock protocol versions the client speaks, and what differs between them.
+
+/// One supported protocol version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BedrockVersion {
+    pub protocol: i32,
+    pub game_version: &'static str,
+}
+
+/// Oldest first.
+pub const SUPPORTED: &[BedrockVersion] = &[
+    BedrockVersion::new(748, "1.21.40"),
+    BedrockVersion::new(766, "1.21.50"),
+    BedrockVersion::new(776, "1.21.60"),
+    BedrockVersion::new(786, "1.21.70"),
+    BedrockVersion::new(800, "1.21.80"),
+    BedrockVersion::new(818, "1.21.90"),
+];
+
+pub const LATEST: BedrockVersion = SUPPORTED[SUPPORTED.len() - 1];
+
+/// Packets that appeared after the oldest supported version, with the protocol that added
+/// them; everything in `PACKET_NAMES` exists in every supported version.
+const ADDED_PACKETS: &[(u32, &str, i32)] = &[
+    (0x140, "camera_aim_assist_presets", 766),
+    (0x141, "client_camera_aim_assist", 766),
+    (0x142, "client_movement_prediction_sync", 766),
+    (0x143, "update_client_options", 776),
+    (0x144, "player_video_capture", 776),
+    (0x145, "player_update_entity_overrides", 776),
+    (0x146, "player_location", 786),
+    (0x147, "clientbound_controls_scheme", 786),
+    (0x148, "server_script_debug_drawer", 800),
+    (0x149, "serverbound_pack_setting_change", 818),
+];
+
+impl BedrockVersion {
+    const fn new(protocol: i32, game_version: &'static str) -> Self {
+        Self {
+            protocol,
+            game_version,
+        }
+    }
+
+    pub fn find(protocol: i32) -> Option<&'static BedrockVersion> {
+        SUPPORTED.iter().find(|v| v.protocol == protocol)
+    }
+
+    /// The supported version before this one.
+    pub fn older(&self) -> Option<&'static BedrockVersion> {
+        SUPPORTED.iter().rev().find(|v| v.protocol < self.protocol)
+    }
+
+    /// The supported version after this one.
+    pub fn newer(&self) -> Option<&'static BedrockVersion> {
+        SUPPORTED.iter().find(|v| v.protocol > self.protocol)
+    }
+
+    /// `1.21.40 (748) to 1.21.90 (818)`, for error messages.
+    pub fn supported_range() -> String {
+        let oldest = SUPPORTED[0];
+        format!(
+            "{} ({}) to {} ({})",
+            oldest.game_version, oldest.protocol, LATEST.game_version, LATEST.protocol
+        )
+    }
+
+    pub fn packet_name(&self, id: u32) -> Option<&'static str> {
+        super::PACKET_NAMES
+            .iter()
+            .find(|(known, _)| *known == id)
+            .map(|(_, name)| *name)
+            .or_else(|| {
+                ADDED_PACKETS
+                    .iter()
+                    .find(|(known, _, since)| *known == id && *since <= self.protocol)
+                    .map(|(_, name, _)| *name)
+            })
+    }
+
+    /// From 1.21.50 `player_auth_input` ends with the raw move vector.
+    pub fn auth_input_raw_move_vector(&self) -> bool {
+        self.protocol >= 766
+    }
+
+    /// From 1.21.70 `sub_chunk` entries carry a second height map, for rendering.
+    pub fn sub_chunk_render_heightmap(&self) -> bool {
+        self.protocol >= 786
+    }
+
+    /// From 1.21.90 the login identity chain is wrapped in a certificate object.
+    pub fn login_certificate(&self) -> bool {
+        self.protocol >= 818
+    }
+
+    /// From 1.21.90 `command_request` carries its version as a string.
+    pub fn command_version_string(&self) -> bool {
+        self.protocol >= 818
+    }
+}
@@ -0,0 +1,465 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::net::NetError;
+use crate::paths::captures_dir;
+
+/// First field of every capture file, so other JSON lines files are rejected.
+pub const CAPTURE_FORMAT: &str = "rustcraft-capture";
+pub const CAPTURE_VERSION: u32 = 1;
+/// Captures are flushed at least this often, so a crash loses little.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Which way a packet went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    /// Server to client.
+    #[serde(rename = "in")]
+    Inbound,
+    /// Client to server.
+    #[serde(rename = "out")]
+    Outbound,
+}
+
+/// What is stored for each packet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// The payload bytes; enough to replay the session.
+    Raw,
+    /// A readable summary of known packets; small, but can't be replayed.
+    Decoded,
+    /// Both of the above.
+    #[default]
+    Full,
+}
+
+impl CaptureMode {
+    pub const ALL: [CaptureMode; 3] = [CaptureMode::Raw, CaptureMode::Decoded, CaptureMode::Full];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CaptureMode::Raw => "raw",
+            CaptureMode::Decoded => "decoded",
+            CaptureMode::Full => "full",
+        }
+    }
+
+    fn raw(self) -> bool {
+        self != CaptureMode::Decoded
+    }
+
+    fn decoded(self) -> bool {
+        self != CaptureMode::Raw
+    }
+}
+
+impl FromStr for CaptureMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CaptureMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown capture mode '{}' (expected raw, decoded or full)",
+                    s
+                )
+            })
+    }
+}
+
+impl fmt::Display for CaptureMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Where and how a session is recorded.
+#[derive(Clone, Debug)]
+pub struct CaptureOptions {
+    pub path: PathBuf,
+    pub mode: CaptureMode,
+}
+
+/// First line of a capture file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    pub format: String,
+    pub version: u32,
+    pub edition: String,
+    pub protocol: i32,
+    pub address: String,
+    /// Wall clock time the capture started, RFC 3339.
+    pub started: String,
+}
+
+/// One packet in a capture file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Milliseconds since the capture started.
+    pub time_ms: f64,
+    pub direction: Direction,
+    /// Protocol state the packet was sent in, e.g. `play`.
+    pub state: String,
+    pub id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Payload size in bytes, without the packet id.
+    pub length: usize,
+    /// Payload as lowercase hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<String>,
+}
+
+impl CaptureRecord {
+    pub fn time(&self) -> Duration {
+        Duration::from_secs_f64(self.time_ms.max(0.0) / 1000.0)
+    }
+
+    /// The raw payload, if the capture kept it.
+    pub fn payload_bytes(&self) -> Result<Option<Vec<u8>>, NetError> {
+        self.payload.as_deref().map(from_hex).transpose()
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    use fmt::Write;
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, NetError> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(NetError::Protocol("odd number of hex digits".into()));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| NetError::Protocol(format!("bad hex byte '{}'", &hex[i..i + 2])))
+        })
+        .collect()
+}
+
+/// Appends packets to a capture file as JSON lines.
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    mode: CaptureMode,
+    started: Instant,
+    last_flush: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create(options: &CaptureOptions, header: &CaptureHeader) -> Result<Self, NetError> {
+        if let Some(parent) = options.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut out = BufWriter::new(File::create(&options.path)?);
+        serde_json::to_writer(&mut out, header).map_err(std::io::Error::from)?;
+        out.write_all(b"\n")?;
+        let now = Instant::now();
+        Ok(Self {
+            out,
+            mode: options.mode,
+            started: now,
+            last_flush: now,
+        })
+    }
+
+    /// Record one packet; `describe` is only called when decoded output is wanted.
+    pub fn record(
+        &mut self,
+        direction: Direction,
+        state: &str,
+        id: i32,
+        payload: &[u8],
+        describe: impl FnOnce() -> (Option<&'static str>, Option<String>),
+    ) -> Result<(), NetError> {
+        let (name, decoded) = if self.mode.decoded() {
+            describe()
+        } else {
+            (None, None)
+        };
+        let record = CaptureRecord {
+            time_ms: self.started.elapsed().as_secs_f64() * 1000.0,
+            direction,
+            state: state.to_string(),
+            id,
+            name: name.map(str::to_string),
+            length: payload.len(),
+            payload: self.mode.raw().then(|| to_hex(payload)),
+            decoded,
+        };
+        serde_json::to_writer(&mut self.out, &record).map_err(std::io::Error::from)?;
+        self.out.write_all(b"\n")?;
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.out.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+/// Read a whole capture file.
+pub fn read_capture(path: &Path) -> Result<(CaptureHeader, Vec<CaptureRecord>), NetError> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let bad = |line: usize, err: serde_json::Error| {
+        NetError::Protocol(format!("{} line {}: {}", path.display(), line, err))
+    };
+
+    let header_line = lines
+        .next()
+        .ok_or_else(|| NetError::Protocol(format!("{} is empty", path.display())))??;
+    let header: CaptureHeader = serde_json::from_str(&header_line).map_err(|err| bad(1, err))?;
+    if header.format != CAPTURE_FORMAT {
+        return Err(NetError::Protocol(format!(
+            "{} is not a capture file",
+            path.display()
+        )));
+    }
+    if header.version > CAPTURE_VERSION {
+        return Err(NetError::Unsupported(format!(
+            "capture version {} is newer than this client supports ({})",
+            header.version, CAPTURE_VERSION
+        )));
+    }
+
+    let mut records = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str(&line).map_err(|err| bad(index + 2, err))?);
+    }
+    Ok((header, records))
+}
+
+/// Default file for a new capture, e.g. `captures/2026-01-31_12-00-00_localhost.jsonl`.
+pub fn default_capture_path(host: &str) -> PathBuf {
+    let host: String = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let stamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+    captures_dir().join(format!("{}_{}.jsonl", stamp, host))
+}
+
+/// Whether new sessions are recorded; changed from the console or command line.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct CaptureSettings {
+    pub enabled: bool,
+    pub mode: CaptureMode,
+    /// File or directory to write to; the captures directory when unset.
+    pub path: Option<PathBuf>,
+}
+
+impl CaptureSettings {
+    /// Capture options for a session with `host`, if capturing is on.
+    pub fn options_for(&self, host: &str) -> Option<CaptureOptions> {
+        if !self.enabled {
+            return None;
+        }
+        let path = match &self.path {
+            Some(dir) if dir.is_dir() => dir.join(
+                default_capture_path(host)
+                    .file_name()
+                    .expect("capture paths have a file name"),
+            ),
+            Some(file) => file.clone(),
+            None => default_capture_path(host),
+        };
+        Some(CaptureOptions {
+            path,
+            mode: self.mode,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::ServerAddress;
+    use crate::net::java::{self, JavaClient, JavaState};
+    use crate::net::session::{SessionEvent, SessionOptions};
+    use crate::net::stats::NetStats;
+
+    fn fixture() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/java/session.jsonl")
+    }
+
+    fn scratch_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rustcraft-{}-{}.jsonl", name, std::process::id()))
+    }
+
+    fn header() -> CaptureHeader {
+        CaptureHeader {
+            format: CAPTURE_FORMAT.into(),
+            version: CAPTURE_VERSION,
+            edition: "java".into(),
+            protocol: 770,
+            address: "localhost:25565".into(),
+            started: "2026-01-31T12:00:00+00:00".into(),
+        }
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(from_hex(&to_hex(&bytes)).unwrap(), bytes);
+        assert_eq!(to_hex(&[0x0A, 0xFF]), "0aff");
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("zz").is_err());
+    }
+
+    #[test]
+    fn written_captures_read_back() {
+        let packets: [(Direction, &str, i32, &[u8]); 3] = [
+            (Direction::Outbound, "handshake", 0x00, &[0x82, 0x06, 0x00]),
+            (Direction::Inbound, "play", 0x72, &[]),
+            (Direction::Inbound, "play", 0x26, &[0, 0, 0, 0, 0, 0, 0, 99]),
+        ];
+        for mode in CaptureMode::ALL {
+            let path = scratch_file(mode.name());
+            let options = CaptureOptions {
+                path: path.clone(),
+                mode,
+            };
+            let mut writer = CaptureWriter::create(&options, &header()).unwrap();
+            for (direction, state, id, payload) in packets {
+                writer
+                    .record(direction, state, id, payload, || {
+                        (Some("name"), Some(format!("id={}", id)))
+                    })
+                    .unwrap();
+            }
+            drop(writer);
+
+            let (read_header, records) = read_capture(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(read_header.protocol, 770);
+            assert_eq!(read_header.address, "localhost:25565");
+            assert_eq!(records.len(), packets.len());
+            let mut last_time = 0.0;
+            for (record, (direction, state, id, payload)) in records.iter().zip(packets) {
+                assert_eq!(record.direction, direction);
+                assert_eq!(record.state, state);
+                assert_eq!(record.id, id);
+                assert_eq!(record.length, payload.len());
+                assert!(record.time_ms >= last_time);
+                last_time = record.time_ms;
+                let raw = record.payload_bytes().unwrap();
+                assert_eq!(raw.as_deref(), mode.raw().then_some(payload));
+                assert_eq!(record.decoded.is_some(), mode.decoded());
+                assert_eq!(record.name.is_some(), mode.decoded());
+            }
+        }
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let path = scratch_file("not-a-capture");
+        std::fs::write(&path, "{\"format\":\"other\",\"version\":1,\"edition\":\"java\",\"protocol\":0,\"address\":\"\",\"started\":\"\"}\n").unwrap();
+        assert!(read_capture(&path).is_err());
+
+        let newer = CaptureHeader {
+            version: CAPTURE_VERSION + 1,
+            ..header()
+        };
+        std::fs::write(&path, serde_json::to_string(&newer).unwrap()).unwrap();
+        assert!(matches!(read_capture(&path), Err(NetError::Unsupported(_))));
+
+        std::fs::write(&path, "").unwrap();
+        assert!(read_capture(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fixture_decodes_as_recorded() {
+        let (header, records) = read_capture(&fixture()).unwrap();
+        assert_eq!(header.edition, "java");
+        assert_eq!(header.protocol, java::PROTOCOL_VERSION);
+        let states = [
+            JavaState::Handshake,
+            JavaState::Status,
+            JavaState::Login,
+            JavaState::Configuration,
+            JavaState::Play,
+        ];
+        for record in &records {
+            let state = *states
+                .iter()
+                .find(|state| state.name() == record.state)
+                .unwrap();
+            let payload = record.payload_bytes().unwrap().unwrap();
+            assert_eq!(payload.len(), record.length);
+            assert_eq!(
+                java::packet_name(state, record.direction, record.id),
+                record.name.as_deref()
+            );
+            assert_eq!(
+                java::describe_packet(state, record.direction, record.id, &payload),
+                record.decoded
+            );
+        }
+    }
+
+    #[test]
+    fn fixture_replays_through_the_client() {
+        let (header, records) = read_capture(&fixture()).unwrap();
+        let address = ServerAddress {
+            host: header.address,
+            port: 25565,
+        };
+        let mut client = JavaClient::replay(
+            &records,
+            100.0,
+            &address,
+            &SessionOptions::default(),
+            &NetStats::default(),
+        )
+        .unwrap();
+        let mut events = Vec::new();
+        let err = loop {
+            if let Err(err) = client.poll(&mut events) {
+                break err;
+            }
+        };
+        assert!(matches!(err, NetError::Disconnected(reason) if reason == "Server closed"));
+
+        assert!(matches!(events[0], SessionEvent::Joined { entity_id: 42 }));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            SessionEvent::Spawned { position, yaw, .. }
+                if *position == bevy::math::DVec3::new(8.5, 64.0, -3.5) && *yaw == 90.0
+        )));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            SessionEvent::Chat { sender: None, message } if message == "Welcome to the fixture"
+        )));
+    }
+}
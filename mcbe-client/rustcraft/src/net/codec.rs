@@ -0,0 +1,383 @@
+use bevy::math::IVec3;
+
+use crate::net::NetError;
+
+/// Cursor over a received packet body. Java fields are big-endian;
+/// the `_le` and zigzag readers are for Bedrock and RakNet.
+pub struct PacketReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], NetError> {
+        if self.remaining() < len {
+            return Err(NetError::Protocol(format!(
+                "packet too short: wanted {} more bytes, {} left",
+                len,
+                self.remaining()
+            )));
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Everything that hasn't been read yet.
+    pub fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.buf[self.pos..];
+        self.pos = self.buf.len();
+        rest
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], NetError> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.bytes(N)?);
+        Ok(out)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, NetError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn i8(&mut self) -> Result<i8, NetError> {
+        Ok(self.u8()? as i8)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, NetError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, NetError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn i16(&mut self) -> Result<i16, NetError> {
+        Ok(i16::from_be_bytes(self.array()?))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, NetError> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, NetError> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, NetError> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, NetError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, NetError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub fn u16_le(&mut self) -> Result<u16, NetError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn i16_le(&mut self) -> Result<i16, NetError> {
+        Ok(i16::from_le_bytes(self.array()?))
+    }
+
+    /// 24-bit little-endian integer, used for RakNet sequence numbers.
+    pub fn u24_le(&mut self) -> Result<u32, NetError> {
+        let [a, b, c] = self.array()?;
+        Ok(u32::from_le_bytes([a, b, c, 0]))
+    }
+
+    pub fn i32_le(&mut self) -> Result<i32, NetError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32_le(&mut self) -> Result<u32, NetError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn i64_le(&mut self) -> Result<i64, NetError> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64_le(&mut self) -> Result<u64, NetError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32_le(&mut self) -> Result<f32, NetError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, NetError> {
+        Ok(f64::from_be_bytes(self.array()?))
+    }
+
+    pub fn f64_le(&mut self) -> Result<f64, NetError> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    pub fn varint(&mut self) -> Result<i32, NetError> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7F) as u32) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(value as i32);
+            }
+        }
+        Err(NetError::Protocol("VarInt is too long".into()))
+    }
+
+    pub fn varlong(&mut self) -> Result<i64, NetError> {
+        let mut value = 0u64;
+        for i in 0..10 {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7F) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(value as i64);
+            }
+        }
+        Err(NetError::Protocol("VarLong is too long".into()))
+    }
+
+    /// Unsigned VarInt, as used by Bedrock for lengths and packet headers.
+    pub fn var_u32(&mut self) -> Result<u32, NetError> {
+        Ok(self.varint()? as u32)
+    }
+
+    /// Unsigned VarLong (Bedrock runtime ids).
+    pub fn var_u64(&mut self) -> Result<u64, NetError> {
+        Ok(self.varlong()? as u64)
+    }
+
+    /// Zigzag-encoded signed VarInt (Bedrock `varint32`).
+    pub fn zigzag32(&mut self) -> Result<i32, NetError> {
+        let raw = self.var_u32()?;
+        Ok((raw >> 1) as i32 ^ -((raw & 1) as i32))
+    }
+
+    /// Zigzag-encoded signed VarLong (Bedrock `varint64`).
+    pub fn zigzag64(&mut self) -> Result<i64, NetError> {
+        let raw = self.varlong()? as u64;
+        Ok((raw >> 1) as i64 ^ -((raw & 1) as i64))
+    }
+
+    /// Unsigned VarInt-prefixed string (Bedrock).
+    pub fn string_le(&mut self) -> Result<String, NetError> {
+        let len = self.var_u32()? as usize;
+        if len > self.remaining() {
+            return Err(NetError::Protocol(format!("bad string length {}", len)));
+        }
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    /// VarInt-prefixed length, checked against what is left in the packet.
+    pub fn len_prefix(&mut self) -> Result<usize, NetError> {
+        let len = self.varint()?;
+        if len < 0 || len as usize > self.remaining() {
+            return Err(NetError::Protocol(format!("bad length prefix {}", len)));
+        }
+        Ok(len as usize)
+    }
+
+    pub fn string(&mut self) -> Result<String, NetError> {
+        let len = self.len_prefix()?;
+        String::from_utf8(self.bytes(len)?.to_vec())
+            .map_err(|_| NetError::Protocol("string is not valid UTF-8".into()))
+    }
+
+    pub fn uuid(&mut self) -> Result<u128, NetError> {
+        Ok(u128::from_be_bytes(self.array()?))
+    }
+
+    /// Block position packed as x:26, z:26, y:12.
+    pub fn position(&mut self) -> Result<IVec3, NetError> {
+        let packed = self.i64()?;
+        Ok(IVec3::new(
+            (packed >> 38) as i32,
+            (packed << 52 >> 52) as i32,
+            (packed << 26 >> 38) as i32,
+        ))
+    }
+}
+
+/// Builder for an outgoing packet body.
+#[derive(Default)]
+pub struct PacketWriter {
+    pub buf: Vec<u8>,
+}
+
+impl PacketWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn i8(&mut self, value: i8) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn i32(&mut self, value: i32) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn i64(&mut self, value: i64) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn f32(&mut self, value: f32) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn f64(&mut self, value: f64) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn varint(&mut self, value: i32) -> &mut Self {
+        write_varint(&mut self.buf, value);
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn u16_le(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u24_le(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes()[..3])
+    }
+
+    pub fn i32_le(&mut self, value: i32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u32_le(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u64_le(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn f32_le(&mut self, value: f32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn var_u32(&mut self, value: u32) -> &mut Self {
+        self.varint(value as i32)
+    }
+
+    pub fn zigzag32(&mut self, value: i32) -> &mut Self {
+        self.var_u32(((value << 1) ^ (value >> 31)) as u32)
+    }
+
+    /// Unsigned VarLong, as used by Bedrock for runtime ids and ticks.
+    pub fn var_u64(&mut self, value: u64) -> &mut Self {
+        let mut value = value;
+        loop {
+            if value & !0x7F == 0 {
+                return self.u8(value as u8);
+            }
+            self.u8((value & 0x7F) as u8 | 0x80);
+            value >>= 7;
+        }
+    }
+
+    pub fn zigzag64(&mut self, value: i64) -> &mut Self {
+        self.var_u64(((value << 1) ^ (value >> 63)) as u64)
+    }
+
+    pub fn string_le(&mut self, value: &str) -> &mut Self {
+        self.var_u32(value.len() as u32).bytes(value.as_bytes())
+    }
+
+    pub fn string(&mut self, value: &str) -> &mut Self {
+        self.varint(value.len() as i32).bytes(value.as_bytes())
+    }
+
+    pub fn uuid(&mut self, value: u128) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn position(&mut self, pos: IVec3) -> &mut Self {
+        let packed = ((pos.x as i64 & 0x3FF_FFFF) << 38)
+            | ((pos.z as i64 & 0x3FF_FFFF) << 12)
+            | (pos.y as i64 & 0xFFF);
+        self.i64(packed)
+    }
+}
+
+pub fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+}
+
+pub fn varint_len(value: i32) -> usize {
+    let mut buf = Vec::with_capacity(5);
+    write_varint(&mut buf, value);
+    buf.len()
+}
+
+/// Try to read a VarInt from the start of `buf`; `None` if more bytes are needed.
+pub fn peek_varint(buf: &[u8]) -> Result<Option<(i32, usize)>, NetError> {
+    let mut value = 0u32;
+    for (i, byte) in buf.iter().take(5).enumerate() {
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value as i32, i + 1)));
+        }
+    }
+    if buf.len() >= 5 {
+        return Err(NetError::Protocol("VarInt is too long".into()));
+    }
+    Ok(None)
+}
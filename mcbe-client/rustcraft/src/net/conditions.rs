@@ -0,0 +1,432 @@
+//! Simulated bad networks: latency, jitter, loss, duplication, reordering and bandwidth caps,
+//! applied to whatever units a transport hands over (TCP reads and frames, or datagrams).
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Lost units of a reliable stream arrive after at least this long, like a TCP retransmission.
+const MIN_RETRANSMIT_DELAY: Duration = Duration::from_millis(200);
+
+/// What the simulated network does to traffic, in each direction.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// Added to every unit.
+    pub latency: Duration,
+    /// Random extra delay of up to this much, either way.
+    pub jitter: Duration,
+    /// Chance, 0 to 1, that a unit is lost.
+    pub loss: f32,
+    /// Chance that a unit arrives twice (datagrams only).
+    pub duplicate: f32,
+    /// Chance that a unit is held back behind later ones (datagrams only).
+    pub reorder: f32,
+    /// Bytes per second; `None` for unlimited.
+    pub bandwidth: Option<u32>,
+}
+
+impl NetworkConditions {
+    /// Conditions that leave traffic untouched.
+    pub fn is_ideal(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Named presets for the overlay and console.
+    pub const PRESETS: &[(&str, NetworkConditions)] = &[
+        ("ideal", NetworkConditions::new(0, 0, 0.0)),
+        ("lan", NetworkConditions::new(2, 1, 0.0)),
+        ("broadband", NetworkConditions::new(30, 5, 0.001)),
+        ("wifi", NetworkConditions::new(50, 25, 0.01)),
+        ("mobile", NetworkConditions::new(120, 60, 0.03)),
+        ("terrible", NetworkConditions::new(400, 200, 0.1)),
+    ];
+
+    const fn new(latency_ms: u64, jitter_ms: u64, loss: f32) -> Self {
+        Self {
+            latency: Duration::from_millis(latency_ms),
+            jitter: Duration::from_millis(jitter_ms),
+            loss,
+            duplicate: 0.0,
+            reorder: 0.0,
+            bandwidth: None,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        Self::PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, conditions)| *conditions)
+    }
+}
+
+/// Conditions shared between the app and session threads; changes apply immediately.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct SimulatedNetwork(Arc<RwLock<NetworkConditions>>);
+
+impl SimulatedNetwork {
+    pub fn new(conditions: NetworkConditions) -> Self {
+        Self(Arc::new(RwLock::new(conditions)))
+    }
+
+    pub fn get(&self) -> NetworkConditions {
+        self.0.read().map(|c| *c).unwrap_or_default()
+    }
+
+    pub fn set(&self, conditions: NetworkConditions) {
+        if let Ok(mut current) = self.0.write() {
+            *current = conditions;
+        }
+    }
+
+    pub fn update(&self, change: impl FnOnce(&mut NetworkConditions)) {
+        if let Ok(mut current) = self.0.write() {
+            change(&mut current);
+        }
+    }
+}
+
+/// What a shaper did so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShaperStats {
+    pub passed: u64,
+    pub lost: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+}
+
+/// Delays, drops and reorders the units of one direction of a transport.
+///
+/// A stream shaper (`ordered`) behaves like TCP: lost units are retransmitted late instead of
+/// dropped, nothing is duplicated, and units never overtake each other.
+pub struct Shaper {
+    network: SimulatedNetwork,
+    ordered: bool,
+    /// Units waiting for their due time, kept sorted by it.
+    queue: VecDeque<(Instant, Vec<u8>)>,
+    /// When the simulated link finishes sending what it was given, for bandwidth caps.
+    link_free_at: Option<Instant>,
+    rng: fastrand::Rng,
+    pub stats: ShaperStats,
+}
+
+impl Shaper {
+    pub fn new(network: SimulatedNetwork, ordered: bool) -> Self {
+        Self::with_rng(network, ordered, fastrand::Rng::new())
+    }
+
+    /// A shaper whose random choices repeat from run to run.
+    pub fn with_seed(network: SimulatedNetwork, ordered: bool, seed: u64) -> Self {
+        Self::with_rng(network, ordered, fastrand::Rng::with_seed(seed))
+    }
+
+    fn with_rng(network: SimulatedNetwork, ordered: bool, rng: fastrand::Rng) -> Self {
+        Self {
+            network,
+            ordered,
+            queue: VecDeque::new(),
+            link_free_at: None,
+            rng,
+            stats: ShaperStats::default(),
+        }
+    }
+
+    /// Units still in flight.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// When the next unit is due, if any are in flight.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.queue.front().map(|(due, _)| *due)
+    }
+
+    /// Hand a unit to the simulated network at `now`.
+    pub fn push(&mut self, data: Vec<u8>, now: Instant) {
+        let conditions = self.network.get();
+        if conditions.is_ideal() && self.queue.is_empty() {
+            self.stats.passed += 1;
+            self.queue.push_back((now, data));
+            return;
+        }
+
+        // time on the wire for the bandwidth cap, starting once earlier units have gone out
+        let mut sent = now;
+        if let Some(bandwidth) = conditions.bandwidth.filter(|b| *b > 0) {
+            let start = self.link_free_at.map_or(now, |free| free.max(now));
+            sent = start + Duration::from_secs_f64(data.len() as f64 / bandwidth as f64);
+            self.link_free_at = Some(sent);
+        }
+
+        let lost = self.chance(conditions.loss);
+        if lost && !self.ordered {
+            self.stats.lost += 1;
+            return;
+        }
+        let mut due = sent + self.delay(&conditions);
+        if lost {
+            // the sender notices after a round trip and sends it again
+            self.stats.lost += 1;
+            due += (conditions.latency * 2).max(MIN_RETRANSMIT_DELAY);
+        }
+
+        if self.ordered {
+            let due = self.last_due().map_or(due, |last| last.max(due));
+            self.stats.passed += 1;
+            self.queue.push_back((due, data));
+            return;
+        }
+
+        if self.chance(conditions.reorder) {
+            self.stats.reordered += 1;
+            due += conditions.latency.max(Duration::from_millis(20));
+        }
+        if self.chance(conditions.duplicate) {
+            self.stats.duplicated += 1;
+            let copy_due = sent + self.delay(&conditions);
+            self.insert(copy_due, data.clone());
+        }
+        self.stats.passed += 1;
+        self.insert(due, data);
+    }
+
+    /// The next unit whose time has come.
+    pub fn pop_due(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if self.queue.front()?.0 > now {
+            return None;
+        }
+        self.queue.pop_front().map(|(_, data)| data)
+    }
+
+    fn last_due(&self) -> Option<Instant> {
+        self.queue.back().map(|(due, _)| *due)
+    }
+
+    fn insert(&mut self, due: Instant, data: Vec<u8>) {
+        let index = self.queue.partition_point(|(other, _)| *other <= due);
+        self.queue.insert(index, (due, data));
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        probability > 0.0 && self.rng.f32() < probability
+    }
+
+    /// Latency plus jitter, never negative.
+    fn delay(&mut self, conditions: &NetworkConditions) -> Duration {
+        let jitter = conditions.jitter.as_secs_f64() * (self.rng.f64() * 2.0 - 1.0);
+        Duration::from_secs_f64((conditions.latency.as_secs_f64() + jitter).max(0.0))
+    }
+}
+
+/// A shaper for each direction of one connection.
+pub struct ShapedLink {
+    pub inbound: Shaper,
+    pub outbound: Shaper,
+}
+
+impl ShapedLink {
+    pub fn new(network: &SimulatedNetwork, ordered: bool) -> Self {
+        Self {
+            inbound: Shaper::new(network.clone(), ordered),
+            outbound: Shaper::new(network.clone(), ordered),
+        }
+    }
+
+    /// A link whose random choices repeat from run to run.
+    pub fn with_seed(network: &SimulatedNetwork, ordered: bool, seed: u64) -> Self {
+        Self {
+            inbound: Shaper::with_seed(network.clone(), ordered, seed),
+            outbound: Shaper::with_seed(network.clone(), ordered, seed.wrapping_add(1)),
+        }
+    }
+}
+
+/// Debug overlay section for the simulated network; changes apply to current sessions too.
+pub fn simulation_panel(InMut(ui): InMut<egui::Ui>, network: Res<SimulatedNetwork>) {
+    let current = network.get();
+    let mut conditions = current;
+
+    ui.horizontal_wrapped(|ui| {
+        for (name, preset) in NetworkConditions::PRESETS {
+            if ui.selectable_label(conditions == *preset, *name).clicked() {
+                conditions = *preset;
+            }
+        }
+    });
+
+    let mut latency = conditions.latency.as_millis() as u64;
+    let mut jitter = conditions.jitter.as_millis() as u64;
+    ui.add(egui::Slider::new(&mut latency, 0..=1000).text("latency (ms)"));
+    ui.add(egui::Slider::new(&mut jitter, 0..=500).text("jitter (ms)"));
+    conditions.latency = Duration::from_millis(latency);
+    conditions.jitter = Duration::from_millis(jitter);
+
+    for (value, label) in [
+        (&mut conditions.loss, "loss (%)"),
+        (&mut conditions.duplicate, "duplicate (%)"),
+        (&mut conditions.reorder, "reorder (%)"),
+    ] {
+        let mut percent = *value * 100.0;
+        ui.add(egui::Slider::new(&mut percent, 0.0..=50.0).text(label));
+        *value = percent / 100.0;
+    }
+
+    let mut kilobytes = conditions.bandwidth.map_or(0, |b| b / 1000);
+    ui.add(
+        egui::Slider::new(&mut kilobytes, 0..=10_000)
+            .logarithmic(true)
+            .text("bandwidth (kB/s, 0 = unlimited)"),
+    );
+    conditions.bandwidth = (kilobytes > 0).then_some(kilobytes * 1000);
+
+    if conditions != current {
+        network.set(conditions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::raknet::{self, RakClient};
+    use crate::net::stats::NetStats;
+
+    fn lossy() -> NetworkConditions {
+        NetworkConditions {
+            latency: Duration::from_millis(20),
+            jitter: Duration::from_millis(10),
+            loss: 0.1,
+            duplicate: 0.05,
+            reorder: 0.2,
+            bandwidth: None,
+        }
+    }
+
+    /// Game message `index`; every tenth is split across several datagrams.
+    fn message(index: usize) -> Vec<u8> {
+        let len = if index.is_multiple_of(10) {
+            3000
+        } else {
+            20 + index
+        };
+        let mut body = vec![(index % 251) as u8; len];
+        body[0] = raknet::ids::GAME_PACKET;
+        body[1..5].copy_from_slice(&(index as u32).to_le_bytes());
+        body
+    }
+
+    /// Pump both ends until `to` has received `count` messages.
+    fn deliver(from: &mut RakClient, to: &mut RakClient, count: usize) -> Vec<Vec<u8>> {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let mut received = Vec::new();
+        while received.len() < count {
+            assert!(
+                Instant::now() < deadline,
+                "only {} of {} arrived",
+                received.len(),
+                count
+            );
+            // the sender has to keep running to see ACKs and NACKs and resend
+            while from.recv().unwrap().is_some() {}
+            while let Some(body) = to.recv().unwrap() {
+                received.push(body);
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn lossy_link_delivers_everything_in_order() {
+        let network = SimulatedNetwork::new(lossy());
+        let stats = NetStats::default();
+        let (mut client, mut server) = RakClient::loopback_pair(&network, 7, &stats).unwrap();
+        const COUNT: usize = 200;
+        for index in 0..COUNT {
+            client.send(&message(index)).unwrap();
+        }
+        let received = deliver(&mut client, &mut server, COUNT);
+        assert_eq!(received.len(), COUNT);
+        for (index, body) in received.iter().enumerate() {
+            assert_eq!(*body, message(index), "message {} out of place", index);
+        }
+        let snapshot = stats.snapshot();
+        assert!(
+            snapshot.resends + snapshot.nacks > 0,
+            "nothing was lost and resent"
+        );
+    }
+
+    #[test]
+    fn both_directions_survive_loss() {
+        let network = SimulatedNetwork::new(lossy());
+        let stats = NetStats::default();
+        let (mut client, mut server) = RakClient::loopback_pair(&network, 11, &stats).unwrap();
+        for round in 0..10 {
+            client.send(&message(round)).unwrap();
+            assert_eq!(deliver(&mut client, &mut server, 1), [message(round)]);
+            server.send(&message(round + 1000)).unwrap();
+            assert_eq!(
+                deliver(&mut server, &mut client, 1),
+                [message(round + 1000)]
+            );
+        }
+    }
+
+    #[test]
+    fn latency_holds_messages_back() {
+        let latency = Duration::from_millis(50);
+        let network = SimulatedNetwork::new(NetworkConditions {
+            latency,
+            ..NetworkConditions::default()
+        });
+        let stats = NetStats::default();
+        let (mut client, mut server) = RakClient::loopback_pair(&network, 3, &stats).unwrap();
+        let sent = Instant::now();
+        client.send(&message(1)).unwrap();
+        assert_eq!(deliver(&mut client, &mut server, 1), [message(1)]);
+        // out through the sender's shaper and in through the receiver's
+        assert!(sent.elapsed() >= latency * 2);
+        assert_eq!(stats.snapshot().resends, 0);
+    }
+
+    #[test]
+    fn ordered_shaper_retransmits_instead_of_dropping() {
+        let network = SimulatedNetwork::new(NetworkConditions {
+            loss: 0.3,
+            ..lossy()
+        });
+        let mut shaper = Shaper::with_seed(network, true, 5);
+        let start = Instant::now();
+        for index in 0..100u8 {
+            shaper.push(vec![index], start);
+        }
+        assert!(shaper.stats.lost > 0);
+        assert_eq!(shaper.stats.duplicated + shaper.stats.reordered, 0);
+        let mut arrived = Vec::new();
+        while let Some(data) = shaper.pop_due(start + Duration::from_secs(10)) {
+            arrived.extend(data);
+        }
+        assert_eq!(arrived, (0..100).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn datagram_shaper_repeats_with_its_seed() {
+        let run = || {
+            let mut shaper = Shaper::with_seed(SimulatedNetwork::new(lossy()), false, 9);
+            let start = Instant::now();
+            for index in 0..100u8 {
+                shaper.push(vec![index], start);
+            }
+            (shaper.stats, shaper.len())
+        };
+        let (stats, len) = run();
+        assert!(stats.lost > 0 && stats.reordered > 0 && stats.duplicated > 0);
+        assert_eq!(len as u64, stats.passed + stats.duplicated);
+        assert_eq!(run(), (stats, len));
+    }
+}
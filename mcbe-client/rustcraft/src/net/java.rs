@@ -0,0 +1,1672 @@
+use bevy::log::warn;
+use bevy::math::{DVec3, IVec2, Vec3};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::net::capture::{
+    CAPTURE_FORMAT, CAPTURE_VERSION, CaptureHeader, CaptureOptions, CaptureRecord, CaptureWriter,
+    Direction,
+};
+use crate::net::codec::{PacketReader, PacketWriter, peek_varint, varint_len, write_varint};
+use crate::net::conditions::{ShapedLink, SimulatedNetwork};
+use crate::net::java::chunk::{
+    dimension_type_bounds, read_chunk, read_light, read_section_blocks_update,
+    vanilla_dimension_bounds,
+};
+use crate::net::nbt::{read_network_nbt, text_to_plain};
+use crate::net::session::{
+    ItemStack, MetadataValue, PlayerAction, PlayerTick, RawPacket, SessionEvent, SessionOptions,
+};
+use crate::net::stats::NetStats;
+use crate::net::{NetError, ServerAddress};
+use crate::world::SECTION_HEIGHT;
+
+pub mod chunk;
+
+/// Java Edition 1.21.5.
+pub const PROTOCOL_VERSION: i32 = 770;
+pub const GAME_VERSION: &str = "1.21.5";
+
+/// How long a poll waits for data before giving control back to the session loop.
+const READ_TIMEOUT: Duration = Duration::from_millis(5);
+/// Largest packet vanilla accepts (2^21 bytes).
+const MAX_PACKET_SIZE: usize = 1 << 21;
+/// Most packets handled per poll, so queued actions aren't starved by chunk floods.
+const MAX_PACKETS_PER_POLL: usize = 256;
+/// Ticks after which the position is sent again even if it didn't change.
+const POSITION_REMINDER_TICKS: u32 = 20;
+/// Smallest move worth sending.
+const MIN_REPORTED_MOVE: f64 = 2.0e-4;
+
+/// Packet ids used by the client, per protocol state.
+pub mod ids {
+    pub mod handshake {
+        pub const INTENTION: i32 = 0x00;
+    }
+
+    pub mod status {
+        // clientbound
+        pub const STATUS_RESPONSE: i32 = 0x00;
+        pub const PONG_RESPONSE: i32 = 0x01;
+        // serverbound
+        pub const STATUS_REQUEST: i32 = 0x00;
+        pub const PING_REQUEST: i32 = 0x01;
+    }
+
+    pub mod login {
+        // clientbound
+        pub const DISCONNECT: i32 = 0x00;
+        pub const ENCRYPTION_REQUEST: i32 = 0x01;
+        pub const LOGIN_SUCCESS: i32 = 0x02;
+        pub const SET_COMPRESSION: i32 = 0x03;
+        pub const PLUGIN_REQUEST: i32 = 0x04;
+        pub const COOKIE_REQUEST: i32 = 0x05;
+        // serverbound
+        pub const LOGIN_START: i32 = 0x00;
+        pub const ENCRYPTION_RESPONSE: i32 = 0x01;
+        pub const PLUGIN_RESPONSE: i32 = 0x02;
+        pub const LOGIN_ACKNOWLEDGED: i32 = 0x03;
+        pub const COOKIE_RESPONSE: i32 = 0x04;
+    }
+
+    pub mod config {
+        // clientbound
+        pub const COOKIE_REQUEST: i32 = 0x00;
+        pub const DISCONNECT: i32 = 0x02;
+        pub const FINISH_CONFIGURATION: i32 = 0x03;
+        pub const KEEP_ALIVE: i32 = 0x04;
+        pub const PING: i32 = 0x05;
+        pub const REGISTRY_DATA: i32 = 0x07;
+        pub const ADD_RESOURCE_PACK: i32 = 0x09;
+        pub const KNOWN_PACKS: i32 = 0x0E;
+        // serverbound
+        pub const CLIENT_INFORMATION: i32 = 0x00;
+        pub const COOKIE_RESPONSE: i32 = 0x01;
+        pub const PLUGIN_MESSAGE: i32 = 0x02;
+        pub const ACKNOWLEDGE_FINISH: i32 = 0x03;
+        pub const KEEP_ALIVE_RESPONSE: i32 = 0x04;
+        pub const PONG: i32 = 0x05;
+        pub const RESOURCE_PACK_RESPONSE: i32 = 0x06;
+        pub const KNOWN_PACKS_RESPONSE: i32 = 0x07;
+    }
+
+    pub mod play {
+        // clientbound
+        pub const ADD_ENTITY: i32 = 0x01;
+        pub const BLOCK_UPDATE: i32 = 0x08;
+        pub const CHUNK_BATCH_FINISHED: i32 = 0x0B;
+        pub const CONTAINER_SET_SLOT: i32 = 0x14;
+        pub const DISCONNECT: i32 = 0x1C;
+        pub const ENTITY_POSITION_SYNC: i32 = 0x1F;
+        pub const FORGET_LEVEL_CHUNK: i32 = 0x21;
+        pub const KEEP_ALIVE: i32 = 0x26;
+        pub const LEVEL_CHUNK_WITH_LIGHT: i32 = 0x27;
+        pub const LIGHT_UPDATE: i32 = 0x2A;
+        pub const LOGIN: i32 = 0x2B;
+        pub const MOVE_ENTITY_POS: i32 = 0x2E;
+        pub const MOVE_ENTITY_POS_ROT: i32 = 0x2F;
+        pub const MOVE_ENTITY_ROT: i32 = 0x31;
+        pub const PING: i32 = 0x36;
+        pub const PONG_RESPONSE: i32 = 0x37;
+        pub const PLAYER_CHAT: i32 = 0x3A;
+        pub const SYNCHRONIZE_POSITION: i32 = 0x41;
+        pub const REMOVE_ENTITIES: i32 = 0x46;
+        pub const RESPAWN: i32 = 0x4B;
+        pub const SECTION_BLOCKS_UPDATE: i32 = 0x4D;
+        pub const SET_ENTITY_DATA: i32 = 0x5C;
+        pub const SET_ENTITY_MOTION: i32 = 0x5E;
+        pub const SET_PLAYER_INVENTORY: i32 = 0x65;
+        pub const START_CONFIGURATION: i32 = 0x6F;
+        pub const SYSTEM_CHAT: i32 = 0x72;
+        pub const TELEPORT_ENTITY: i32 = 0x76;
+        // serverbound
+        pub const CONFIRM_TELEPORT: i32 = 0x00;
+        pub const CHAT_COMMAND: i32 = 0x05;
+        pub const CHAT_MESSAGE: i32 = 0x07;
+        pub const CHUNK_BATCH_RECEIVED: i32 = 0x09;
+        pub const CLIENT_INFORMATION: i32 = 0x0C;
+        pub const ACKNOWLEDGE_CONFIGURATION: i32 = 0x0E;
+        pub const KEEP_ALIVE_RESPONSE: i32 = 0x1A;
+        pub const MOVE_POSITION: i32 = 0x1C;
+        pub const MOVE_POSITION_ROTATION: i32 = 0x1D;
+        pub const MOVE_ROTATION: i32 = 0x1E;
+        pub const MOVE_STATUS_ONLY: i32 = 0x1F;
+        pub const PING_REQUEST: i32 = 0x24;
+        pub const PLAYER_ACTION: i32 = 0x27;
+        pub const PLAYER_COMMAND: i32 = 0x28;
+        pub const PLAYER_INPUT: i32 = 0x29;
+        pub const PLAYER_LOADED: i32 = 0x2A;
+        pub const PONG: i32 = 0x2B;
+        pub const SWING_ARM: i32 = 0x3B;
+        pub const USE_ITEM_ON: i32 = 0x3E;
+    }
+}
+
+/// `player_input` flags.
+mod input_keys {
+    pub const FORWARD: u8 = 0x01;
+    pub const BACKWARD: u8 = 0x02;
+    pub const LEFT: u8 = 0x04;
+    pub const RIGHT: u8 = 0x08;
+    pub const JUMP: u8 = 0x10;
+    pub const SNEAK: u8 = 0x20;
+    pub const SPRINT: u8 = 0x40;
+}
+
+/// `player_command` actions.
+mod player_command {
+    pub const PRESS_SHIFT_KEY: i32 = 0;
+    pub const RELEASE_SHIFT_KEY: i32 = 1;
+    pub const START_SPRINTING: i32 = 3;
+    pub const STOP_SPRINTING: i32 = 4;
+}
+
+/// Flags of the `move_player_*` packets.
+mod move_flags {
+    pub const ON_GROUND: u8 = 0x01;
+    pub const HORIZONTAL_COLLISION: u8 = 0x02;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JavaState {
+    Handshake,
+    Status,
+    Login,
+    Configuration,
+    Play,
+}
+
+impl JavaState {
+    pub fn name(self) -> &'static str {
+        match self {
+            JavaState::Handshake => "handshake",
+            JavaState::Status => "status",
+            JavaState::Login => "login",
+            JavaState::Configuration => "configuration",
+            JavaState::Play => "play",
+        }
+    }
+}
+
+/// Where a connection's packets come from.
+enum Link {
+    Tcp(TcpStream),
+    /// Server packets from a capture, delivered at their recorded times.
+    Replay(Replay),
+}
+
+struct Replay {
+    packets: VecDeque<(Duration, i32, Vec<u8>)>,
+    started: Instant,
+    speed: f64,
+}
+
+impl Replay {
+    fn next(&mut self) -> Result<Option<(i32, Vec<u8>)>, NetError> {
+        let Some((time, _, _)) = self.packets.front() else {
+            return Err(NetError::Disconnected("end of capture".into()));
+        };
+        let due = self.started + time.div_f64(self.speed);
+        let now = Instant::now();
+        if now < due {
+            std::thread::sleep((due - now).min(READ_TIMEOUT));
+            return Ok(None);
+        }
+        Ok(self
+            .packets
+            .pop_front()
+            .map(|(_, id, payload)| (id, payload)))
+    }
+}
+
+/// Length-prefixed packet framing over TCP, with optional zlib compression.
+pub struct JavaConnection {
+    link: Link,
+    read_buf: Vec<u8>,
+    /// Packets at least this big are compressed, once the server enables it.
+    compression: Option<usize>,
+    /// Protocol state, tracked here so captures can record it.
+    state: JavaState,
+    capture: Option<CaptureWriter>,
+    /// Simulated network conditions, applied to TCP reads and written frames.
+    shaping: Option<ShapedLink>,
+    stats: NetStats,
+}
+
+impl JavaConnection {
+    pub fn connect(address: &ServerAddress, timeout: Duration) -> Result<Self, NetError> {
+        let addrs = (address.host.as_str(), address.port)
+            .to_socket_addrs()
+            .map_err(|err| NetError::Protocol(format!("could not resolve {}: {}", address, err)))?;
+
+        let mut last_err = None;
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                    return Ok(Self {
+                        link: Link::Tcp(stream),
+                        read_buf: Vec::new(),
+                        compression: None,
+                        state: JavaState::Handshake,
+                        capture: None,
+                        shaping: None,
+                        stats: NetStats::default(),
+                    });
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.map(NetError::Io).unwrap_or_else(|| {
+            NetError::Protocol(format!("{} did not resolve to any address", address))
+        }))
+    }
+
+    /// A connection that plays back the server side of a capture; sent packets are dropped.
+    /// `speed` scales the recorded timing, e.g. 2.0 replays twice as fast.
+    pub fn replay(records: &[CaptureRecord], speed: f64) -> Result<Self, NetError> {
+        let mut packets = VecDeque::new();
+        for record in records.iter().filter(|r| r.direction == Direction::Inbound) {
+            let payload = record.payload_bytes()?.ok_or_else(|| {
+                NetError::Unsupported("capture was recorded without raw payloads".into())
+            })?;
+            packets.push_back((record.time(), record.id, payload));
+        }
+        Ok(Self {
+            link: Link::Replay(Replay {
+                packets,
+                started: Instant::now(),
+                speed: speed.max(0.01),
+            }),
+            read_buf: Vec::new(),
+            compression: None,
+            state: JavaState::Handshake,
+            capture: None,
+            shaping: None,
+            stats: NetStats::default(),
+        })
+    }
+
+    /// Record every packet from now on.
+    pub fn start_capture(
+        &mut self,
+        options: &CaptureOptions,
+        address: &ServerAddress,
+    ) -> Result<(), NetError> {
+        let header = CaptureHeader {
+            format: CAPTURE_FORMAT.into(),
+            version: CAPTURE_VERSION,
+            edition: "java".into(),
+            protocol: PROTOCOL_VERSION,
+            address: address.to_string(),
+            started: chrono::Local::now().to_rfc3339(),
+        };
+        self.capture = Some(CaptureWriter::create(options, &header)?);
+        Ok(())
+    }
+
+    /// Count traffic into `stats` from now on.
+    pub fn set_stats(&mut self, stats: NetStats) {
+        self.stats = stats;
+    }
+
+    pub fn stats(&self) -> &NetStats {
+        &self.stats
+    }
+
+    fn count(&self, direction: Direction, id: i32, wire_bytes: usize, uncompressed_bytes: usize) {
+        match packet_name(self.state, direction, id) {
+            Some(name) => self
+                .stats
+                .record_packet(direction, name, wire_bytes, uncompressed_bytes),
+            None => self.stats.record_packet(
+                direction,
+                &format!("{} 0x{:02X}", self.state.name(), id),
+                wire_bytes,
+                uncompressed_bytes,
+            ),
+        }
+    }
+
+    /// Send and receive through a simulated network from now on.
+    pub fn simulate(&mut self, network: &SimulatedNetwork) {
+        self.shaping = Some(ShapedLink::new(network, true));
+    }
+
+    pub fn state(&self) -> JavaState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: JavaState) {
+        self.state = state;
+    }
+
+    pub fn set_compression(&mut self, threshold: Option<usize>) {
+        self.compression = threshold;
+    }
+
+    fn record(&mut self, direction: Direction, id: i32, payload: &[u8]) -> Result<(), NetError> {
+        let state = self.state;
+        match &mut self.capture {
+            Some(capture) => capture.record(direction, state.name(), id, payload, || {
+                (
+                    packet_name(state, direction, id),
+                    describe_packet(state, direction, id, payload),
+                )
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn send(&mut self, id: i32, body: &[u8]) -> Result<(), NetError> {
+        self.record(Direction::Outbound, id, body)?;
+        if matches!(self.link, Link::Replay(_)) {
+            return Ok(());
+        }
+        let frame = encode_frame(id, body, self.compression)?;
+        self.count(
+            Direction::Outbound,
+            id,
+            frame.len(),
+            varint_len(id) + body.len(),
+        );
+        match (&mut self.shaping, &mut self.link) {
+            (Some(shaping), _) => {
+                shaping.outbound.push(frame, Instant::now());
+                self.pump_shaping()
+            }
+            (None, Link::Tcp(stream)) => {
+                stream.write_all(&frame)?;
+                Ok(())
+            }
+            (None, Link::Replay(_)) => Ok(()),
+        }
+    }
+
+    /// Next complete packet, waiting at most the read timeout for more data.
+    pub fn recv(&mut self) -> Result<Option<(i32, Vec<u8>)>, NetError> {
+        let packet = match &mut self.link {
+            Link::Tcp(_) => self.read_packet()?,
+            Link::Replay(replay) => {
+                let packet = replay.next()?;
+                if let Some((id, payload)) = &packet {
+                    let len = varint_len(*id) + payload.len();
+                    self.count(Direction::Inbound, *id, len, len);
+                }
+                return Ok(packet);
+            }
+        };
+        if let Some((id, payload)) = &packet {
+            self.record(Direction::Inbound, *id, payload)?;
+        }
+        Ok(packet)
+    }
+
+    fn read_packet(&mut self) -> Result<Option<(i32, Vec<u8>)>, NetError> {
+        self.pump_shaping()?;
+        if let Some(packet) = self.next_frame()? {
+            return Ok(Some(packet));
+        }
+
+        let Link::Tcp(stream) = &mut self.link else {
+            return Ok(None);
+        };
+        let mut chunk = [0u8; 16 * 1024];
+        match stream.read(&mut chunk) {
+            // let what the simulated network still holds arrive first
+            Ok(0) if self.shaping.as_ref().is_some_and(|s| !s.inbound.is_empty()) => Ok(None),
+            Ok(0) => Err(NetError::Disconnected("connection closed by server".into())),
+            Ok(n) => {
+                match &mut self.shaping {
+                    Some(shaping) => shaping.inbound.push(chunk[..n].to_vec(), Instant::now()),
+                    None => self.read_buf.extend_from_slice(&chunk[..n]),
+                }
+                self.pump_shaping()?;
+                self.next_frame()
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Deliver whatever the simulated network has let through by now.
+    fn pump_shaping(&mut self) -> Result<(), NetError> {
+        let (Some(shaping), Link::Tcp(stream)) = (&mut self.shaping, &mut self.link) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        while let Some(data) = shaping.outbound.pop_due(now) {
+            stream.write_all(&data)?;
+        }
+        while let Some(data) = shaping.inbound.pop_due(now) {
+            self.read_buf.extend_from_slice(&data);
+        }
+        self.stats.set_queue("simulated in", shaping.inbound.len());
+        self.stats
+            .set_queue("simulated out", shaping.outbound.len());
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<(i32, Vec<u8>)>, NetError> {
+        let Some(frame) = take_frame(&mut self.read_buf, self.compression)? else {
+            return Ok(None);
+        };
+        self.count(
+            Direction::Inbound,
+            frame.id,
+            frame.bytes.len(),
+            varint_len(frame.id) + frame.payload.len(),
+        );
+        Ok(Some((frame.id, frame.payload)))
+    }
+}
+
+/// Frame one packet: length prefix, then the id and body, compressed if big enough.
+pub fn encode_frame(id: i32, body: &[u8], compression: Option<usize>) -> Result<Vec<u8>, NetError> {
+    let mut data = Vec::with_capacity(body.len() + 5);
+    write_varint(&mut data, id);
+    data.extend_from_slice(body);
+
+    let mut inner = Vec::with_capacity(data.len() + 5);
+    match compression {
+        Some(threshold) if data.len() >= threshold => {
+            write_varint(&mut inner, data.len() as i32);
+            let mut encoder = ZlibEncoder::new(inner, Compression::default());
+            encoder.write_all(&data)?;
+            inner = encoder.finish()?;
+        }
+        Some(_) => {
+            write_varint(&mut inner, 0);
+            inner.extend_from_slice(&data);
+        }
+        None => inner = data,
+    }
+
+    let mut frame = Vec::with_capacity(inner.len() + 5);
+    write_varint(&mut frame, inner.len() as i32);
+    frame.extend_from_slice(&inner);
+    Ok(frame)
+}
+
+/// A packet taken off the wire.
+pub struct Frame {
+    /// The frame exactly as received, length prefix included.
+    pub bytes: Vec<u8>,
+    pub id: i32,
+    pub payload: Vec<u8>,
+}
+
+/// Remove the next complete frame from the front of `buf`, if there is one.
+pub fn take_frame(
+    buf: &mut Vec<u8>,
+    compression: Option<usize>,
+) -> Result<Option<Frame>, NetError> {
+    let Some((len, header)) = peek_varint(buf)? else {
+        return Ok(None);
+    };
+    let len = len as usize;
+    if len > MAX_PACKET_SIZE {
+        return Err(NetError::Protocol(format!(
+            "packet of {} bytes is too big",
+            len
+        )));
+    }
+    if buf.len() < header + len {
+        return Ok(None);
+    }
+    let bytes: Vec<u8> = buf.drain(..header + len).collect();
+    let frame = &bytes[header..];
+
+    let data = match compression {
+        Some(_) => {
+            let mut r = PacketReader::new(frame);
+            let data_len = r.varint()?;
+            if data_len == 0 {
+                r.rest().to_vec()
+            } else {
+                if data_len < 0 || data_len as usize > MAX_PACKET_SIZE {
+                    return Err(NetError::Protocol(format!(
+                        "bad uncompressed length {}",
+                        data_len
+                    )));
+                }
+                let mut data = Vec::with_capacity(data_len as usize);
+                ZlibDecoder::new(r.rest()).read_to_end(&mut data)?;
+                data
+            }
+        }
+        None => frame.to_vec(),
+    };
+
+    let mut r = PacketReader::new(&data);
+    let id = r.varint()?;
+    let payload = r.rest().to_vec();
+    Ok(Some(Frame { bytes, id, payload }))
+}
+
+/// An offline-mode Java client: login, configuration, and the play packets we act on.
+pub struct JavaClient {
+    conn: JavaConnection,
+    options: SessionOptions,
+    pub entity_id: Option<i32>,
+    pub position: DVec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    spawned: bool,
+    /// Block change sequence number for dig/place acknowledgements.
+    sequence: i32,
+    /// Reference point for ping payloads.
+    started: Instant,
+    last_ping: Option<Instant>,
+    /// `dimension_type` registry entries in id order, with their bounds if the server sent them.
+    dimension_types: Vec<(String, Option<(i32, i32)>)>,
+    /// Lowest Y and height of the current dimension.
+    bounds: (i32, i32),
+    dimension: String,
+    /// Where the server last put each other entity; relative moves start from there.
+    entities: HashMap<i32, RemoteEntity>,
+    /// Our movement as last sent, to send only what changed.
+    reported: ReportedMovement,
+}
+
+/// The movement state the server last heard of. Like vanilla, position and rotation are only
+/// sent when they change, except that the position is repeated every `POSITION_REMINDER_TICKS`.
+#[derive(Clone, Copy, Debug, Default)]
+struct ReportedMovement {
+    position: DVec3,
+    yaw: f32,
+    pitch: f32,
+    on_ground: bool,
+    horizontal_collision: bool,
+    ticks_since_position: u32,
+    /// `player_input` flags.
+    keys: u8,
+    sprinting: bool,
+    sneaking: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct RemoteEntity {
+    position: DVec3,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl JavaClient {
+    /// Connect and log in; returns once the server moved us to the configuration state.
+    pub fn login(
+        address: &ServerAddress,
+        options: &SessionOptions,
+        stats: &NetStats,
+    ) -> Result<Self, NetError> {
+        let mut conn = JavaConnection::connect(address, options.connect_timeout)?;
+        conn.set_stats(stats.clone());
+        if let Some(capture) = &options.capture {
+            conn.start_capture(capture, address)?;
+        }
+        if let Some(network) = &options.network {
+            conn.simulate(network);
+        }
+        Self::login_over(conn, address, options)
+    }
+
+    /// Play back a capture as if its server was live.
+    pub fn replay(
+        records: &[CaptureRecord],
+        speed: f64,
+        address: &ServerAddress,
+        options: &SessionOptions,
+        stats: &NetStats,
+    ) -> Result<Self, NetError> {
+        let mut conn = JavaConnection::replay(records, speed)?;
+        conn.set_stats(stats.clone());
+        Self::login_over(conn, address, options)
+    }
+
+    fn login_over(
+        mut conn: JavaConnection,
+        address: &ServerAddress,
+        options: &SessionOptions,
+    ) -> Result<Self, NetError> {
+        let mut handshake = PacketWriter::new();
+        handshake
+            .varint(PROTOCOL_VERSION)
+            .string(&address.host)
+            .u16(address.port)
+            .varint(2); // intent: login
+        conn.send(ids::handshake::INTENTION, &handshake.buf)?;
+        conn.set_state(JavaState::Login);
+
+        // offline servers derive the player's UUID from the name themselves
+        let mut start = PacketWriter::new();
+        start.string(&options.username).uuid(0);
+        conn.send(ids::login::LOGIN_START, &start.buf)?;
+
+        let deadline = Instant::now() + options.connect_timeout;
+        loop {
+            if Instant::now() > deadline {
+                return Err(NetError::Timeout("login timed out".into()));
+            }
+            let Some((id, payload)) = conn.recv()? else {
+                continue;
+            };
+            let mut r = PacketReader::new(&payload);
+            match id {
+                ids::login::DISCONNECT => {
+                    return Err(NetError::Disconnected(r.string()?));
+                }
+                ids::login::ENCRYPTION_REQUEST => {
+                    return Err(NetError::Unsupported(
+                        "server is in online mode; only offline login is supported".into(),
+                    ));
+                }
+                ids::login::SET_COMPRESSION => {
+                    let threshold = r.varint()?;
+                    conn.set_compression((threshold >= 0).then_some(threshold as usize));
+                }
+                ids::login::PLUGIN_REQUEST => {
+                    let message_id = r.varint()?;
+                    let mut response = PacketWriter::new();
+                    response.varint(message_id).bool(false);
+                    conn.send(ids::login::PLUGIN_RESPONSE, &response.buf)?;
+                }
+                ids::login::COOKIE_REQUEST => {
+                    let key = r.string()?;
+                    let mut response = PacketWriter::new();
+                    response.string(&key).bool(false);
+                    conn.send(ids::login::COOKIE_RESPONSE, &response.buf)?;
+                }
+                ids::login::LOGIN_SUCCESS => {
+                    conn.send(ids::login::LOGIN_ACKNOWLEDGED, &[])?;
+                    conn.set_state(JavaState::Configuration);
+                    break;
+                }
+                other => {
+                    return Err(NetError::Protocol(format!(
+                        "unexpected login packet 0x{:02X}",
+                        other
+                    )));
+                }
+            }
+        }
+
+        let mut client = Self {
+            conn,
+            options: options.clone(),
+            entity_id: None,
+            position: DVec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            spawned: false,
+            sequence: 0,
+            started: Instant::now(),
+            last_ping: None,
+            dimension_types: Vec::new(),
+            bounds: vanilla_dimension_bounds("minecraft:overworld"),
+            dimension: String::new(),
+            entities: HashMap::new(),
+            reported: ReportedMovement::default(),
+        };
+        client.send_client_information()?;
+        Ok(client)
+    }
+
+    pub fn state(&self) -> JavaState {
+        self.conn.state()
+    }
+
+    fn send_client_information(&mut self) -> Result<(), NetError> {
+        self.send_settings(ids::config::CLIENT_INFORMATION)?;
+
+        let mut brand = PacketWriter::new();
+        brand.string("minecraft:brand").string("rustcraft");
+        self.conn.send(ids::config::PLUGIN_MESSAGE, &brand.buf)
+    }
+
+    /// `client_information`, which exists in both configuration and play with different ids.
+    fn send_settings(&mut self, id: i32) -> Result<(), NetError> {
+        let mut info = PacketWriter::new();
+        info.string("en_us")
+            .i8(self.options.view_distance.clamp(2, 32) as i8)
+            .varint(0) // chat: enabled
+            .bool(true) // chat colours
+            .u8(0x7F) // all skin parts
+            .varint(1) // right handed
+            .bool(false) // text filtering
+            .bool(true) // server listings
+            .varint(0); // particles: all
+        self.conn.send(id, &info.buf)
+    }
+
+    /// Handle whatever arrived since the last poll.
+    pub fn poll(&mut self, events: &mut Vec<SessionEvent>) -> Result<(), NetError> {
+        if let Some(interval) = self.options.ping_interval
+            && self.spawned
+            && self.last_ping.is_none_or(|last| last.elapsed() >= interval)
+        {
+            self.last_ping = Some(Instant::now());
+            let mut ping = PacketWriter::new();
+            ping.i64(self.started.elapsed().as_micros() as i64);
+            self.conn.send(ids::play::PING_REQUEST, &ping.buf)?;
+        }
+
+        for _ in 0..MAX_PACKETS_PER_POLL {
+            let Some((id, payload)) = self.conn.recv()? else {
+                break;
+            };
+            if self.options.forward_packets {
+                events.push(SessionEvent::Packet(RawPacket {
+                    received_at: Instant::now(),
+                    state: self.state().name(),
+                    id,
+                    payload: payload.clone(),
+                }));
+            }
+            match self.state() {
+                JavaState::Configuration => self.handle_config(id, &payload)?,
+                JavaState::Play => self.handle_play(id, &payload, events)?,
+                state => {
+                    return Err(NetError::Protocol(format!(
+                        "packet 0x{:02X} in unexpected state {:?}",
+                        id, state
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Switch to the dimension of registry entry `dimension_type`, named `dimension`.
+    fn enter_dimension(
+        &mut self,
+        dimension_type: i32,
+        dimension: String,
+        events: &mut Vec<SessionEvent>,
+    ) {
+        let entry = usize::try_from(dimension_type)
+            .ok()
+            .and_then(|index| self.dimension_types.get(index));
+        self.bounds = match entry {
+            Some((_, Some(bounds))) => *bounds,
+            Some((name, None)) => vanilla_dimension_bounds(name),
+            None => vanilla_dimension_bounds(&dimension),
+        };
+        self.dimension = dimension;
+        self.entities.clear();
+        let (min_y, height) = self.bounds;
+        events.push(SessionEvent::DimensionChanged { min_y, height });
+    }
+
+    fn handle_config(&mut self, id: i32, payload: &[u8]) -> Result<(), NetError> {
+        let mut r = PacketReader::new(payload);
+        match id {
+            ids::config::DISCONNECT => {
+                return Err(NetError::Disconnected(text_to_plain(&read_network_nbt(
+                    &mut r,
+                )?)));
+            }
+            ids::config::FINISH_CONFIGURATION => {
+                self.conn.send(ids::config::ACKNOWLEDGE_FINISH, &[])?;
+                self.conn.set_state(JavaState::Play);
+            }
+            ids::config::KEEP_ALIVE => {
+                self.conn
+                    .send(ids::config::KEEP_ALIVE_RESPONSE, r.bytes(8)?)?;
+            }
+            ids::config::PING => self.conn.send(ids::config::PONG, r.bytes(4)?)?,
+            // same layout both ways: accept every pack the server offers
+            ids::config::KNOWN_PACKS => {
+                self.conn.send(ids::config::KNOWN_PACKS_RESPONSE, payload)?
+            }
+            ids::config::ADD_RESOURCE_PACK => {
+                let pack = r.uuid()?;
+                for result in [3, 0] {
+                    // accepted, then successfully loaded
+                    let mut response = PacketWriter::new();
+                    response.uuid(pack).varint(result);
+                    self.conn
+                        .send(ids::config::RESOURCE_PACK_RESPONSE, &response.buf)?;
+                }
+            }
+            ids::config::COOKIE_REQUEST => {
+                let key = r.string()?;
+                let mut response = PacketWriter::new();
+                response.string(&key).bool(false);
+                self.conn
+                    .send(ids::config::COOKIE_RESPONSE, &response.buf)?;
+            }
+            ids::config::REGISTRY_DATA => {
+                if r.string()? != "minecraft:dimension_type" {
+                    return Ok(());
+                }
+                self.dimension_types.clear();
+                for _ in 0..r.len_prefix()? {
+                    let name = r.string()?;
+                    let bounds = if r.bool()? {
+                        dimension_type_bounds(&read_network_nbt(&mut r)?)
+                    } else {
+                        None
+                    };
+                    self.dimension_types.push((name, bounds));
+                }
+            }
+            // other registries, tags and feature flags only matter once we render the world
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_play(
+        &mut self,
+        id: i32,
+        payload: &[u8],
+        events: &mut Vec<SessionEvent>,
+    ) -> Result<(), NetError> {
+        let mut r = PacketReader::new(payload);
+        match id {
+            ids::play::DISCONNECT => {
+                return Err(NetError::Disconnected(text_to_plain(&read_network_nbt(
+                    &mut r,
+                )?)));
+            }
+            ids::play::KEEP_ALIVE => {
+                self.conn
+                    .send(ids::play::KEEP_ALIVE_RESPONSE, r.bytes(8)?)?;
+            }
+            ids::play::PING => self.conn.send(ids::play::PONG, r.bytes(4)?)?,
+            ids::play::PONG_RESPONSE => {
+                let sent = Duration::from_micros(r.i64()?.max(0) as u64);
+                let rtt = self.started.elapsed().saturating_sub(sent);
+                self.conn.stats().record_rtt(rtt);
+                events.push(SessionEvent::Latency { rtt });
+            }
+            ids::play::LEVEL_CHUNK_WITH_LIGHT => {
+                let pos = IVec2::new(r.i32()?, r.i32()?);
+                let (min_y, height) = self.bounds;
+                let chunk = match read_chunk(
+                    &mut r,
+                    pos,
+                    min_y.div_euclid(SECTION_HEIGHT),
+                    (height / SECTION_HEIGHT) as usize,
+                ) {
+                    Ok(chunk) => Some(Box::new(chunk)),
+                    Err(err) => {
+                        warn!("Cannot decode chunk {}: {}", pos, err);
+                        None
+                    }
+                };
+                events.push(SessionEvent::ChunkLoaded { pos, chunk });
+            }
+            ids::play::FORGET_LEVEL_CHUNK => {
+                let z = r.i32()?;
+                let pos = IVec2::new(r.i32()?, z);
+                events.push(SessionEvent::ChunkUnloaded { pos });
+            }
+            ids::play::LIGHT_UPDATE => {
+                let pos = IVec2::new(r.varint()?, r.varint()?);
+                let light = read_light(&mut r, self.bounds.0.div_euclid(SECTION_HEIGHT))?;
+                events.push(SessionEvent::LightUpdated {
+                    pos,
+                    sky_light: light.sky_light,
+                    block_light: light.block_light,
+                });
+            }
+            ids::play::LOGIN => {
+                let entity_id = r.i32()?;
+                let _hardcore = r.bool()?;
+                for _ in 0..r.len_prefix()? {
+                    r.string()?; // dimension names
+                }
+                let _max_players = r.varint()?;
+                let _view_distance = r.varint()?;
+                let _simulation_distance = r.varint()?;
+                let _reduced_debug_info = r.bool()?;
+                let _respawn_screen = r.bool()?;
+                let _limited_crafting = r.bool()?;
+                let dimension_type = r.varint()?;
+                let dimension = r.string()?;
+
+                self.entity_id = Some(entity_id);
+                events.push(SessionEvent::Joined {
+                    entity_id: entity_id as i64,
+                });
+                self.enter_dimension(dimension_type, dimension, events);
+            }
+            ids::play::RESPAWN => {
+                let dimension_type = r.varint()?;
+                let dimension = r.string()?;
+                // respawning in the same dimension keeps the chunks
+                if dimension != self.dimension {
+                    self.enter_dimension(dimension_type, dimension, events);
+                }
+            }
+            ids::play::SYNCHRONIZE_POSITION => {
+                let teleport_id = r.varint()?;
+                let position = DVec3::new(r.f64()?, r.f64()?, r.f64()?);
+                let _velocity = DVec3::new(r.f64()?, r.f64()?, r.f64()?);
+                let (yaw, pitch) = (r.f32()?, r.f32()?);
+                let relative = r.i32()?;
+
+                let axis = |bit: i32, current: f64, value: f64| {
+                    if relative & (1 << bit) != 0 {
+                        current + value
+                    } else {
+                        value
+                    }
+                };
+                self.position = DVec3::new(
+                    axis(0, self.position.x, position.x),
+                    axis(1, self.position.y, position.y),
+                    axis(2, self.position.z, position.z),
+                );
+                self.yaw = axis(3, self.yaw as f64, yaw as f64) as f32;
+                self.pitch = axis(4, self.pitch as f64, pitch as f64) as f32;
+
+                let mut confirm = PacketWriter::new();
+                confirm.varint(teleport_id);
+                self.conn.send(ids::play::CONFIRM_TELEPORT, &confirm.buf)?;
+                self.send_position(true)?;
+
+                let (position, yaw, pitch) = (self.position, self.yaw, self.pitch);
+                if self.spawned {
+                    events.push(SessionEvent::Teleported {
+                        position,
+                        yaw,
+                        pitch,
+                    });
+                } else {
+                    self.spawned = true;
+                    self.conn.send(ids::play::PLAYER_LOADED, &[])?;
+                    events.push(SessionEvent::Spawned {
+                        position,
+                        yaw,
+                        pitch,
+                    });
+                }
+            }
+            ids::play::SYSTEM_CHAT => {
+                let message = text_to_plain(&read_network_nbt(&mut r)?);
+                let overlay = r.bool()?;
+                if !overlay {
+                    events.push(SessionEvent::Chat {
+                        sender: None,
+                        message,
+                    });
+                }
+            }
+            ids::play::PLAYER_CHAT => {
+                let _global_index = r.varint()?;
+                let sender = r.uuid()?;
+                let _index = r.varint()?;
+                if r.bool()? {
+                    r.bytes(256)?; // signature
+                }
+                let message = r.string()?;
+                events.push(SessionEvent::Chat {
+                    sender: Some(format!("{:032x}", sender)),
+                    message,
+                });
+            }
+            ids::play::BLOCK_UPDATE => {
+                let pos = r.position()?;
+                let state = r.varint()? as u32;
+                events.push(SessionEvent::BlockChanged { pos, state });
+            }
+            ids::play::SECTION_BLOCKS_UPDATE => {
+                for (pos, state) in read_section_blocks_update(&mut r)? {
+                    events.push(SessionEvent::BlockChanged {
+                        pos,
+                        state: state.0,
+                    });
+                }
+            }
+            ids::play::CHUNK_BATCH_FINISHED => {
+                // desired chunks per tick; the vanilla client caps this at 64
+                let mut received = PacketWriter::new();
+                received.f32(64.0);
+                self.conn
+                    .send(ids::play::CHUNK_BATCH_RECEIVED, &received.buf)?;
+            }
+            ids::play::ADD_ENTITY => {
+                let entity_id = r.varint()?;
+                let _uuid = r.uuid()?;
+                let kind = r.varint()?;
+                let position = DVec3::new(r.f64()?, r.f64()?, r.f64()?);
+                let pitch = angle(r.u8()?);
+                let yaw = angle(r.u8()?);
+                let _head_yaw = r.u8()?;
+                let _data = r.varint()?;
+                let velocity = read_velocity(&mut r)?;
+                self.entities.insert(
+                    entity_id,
+                    RemoteEntity {
+                        position,
+                        yaw,
+                        pitch,
+                    },
+                );
+                events.push(SessionEvent::EntitySpawned {
+                    entity_id: entity_id as i64,
+                    kind: kind.to_string(),
+                    position,
+                    yaw,
+                    pitch,
+                    velocity,
+                });
+            }
+            ids::play::MOVE_ENTITY_POS
+            | ids::play::MOVE_ENTITY_POS_ROT
+            | ids::play::MOVE_ENTITY_ROT => {
+                let entity_id = r.varint()?;
+                let Some(entity) = self.entities.get_mut(&entity_id) else {
+                    return Ok(());
+                };
+                if id != ids::play::MOVE_ENTITY_ROT {
+                    // 1/4096 of a block
+                    let delta = DVec3::new(r.i16()? as f64, r.i16()? as f64, r.i16()? as f64);
+                    entity.position += delta / 4096.0;
+                }
+                if id != ids::play::MOVE_ENTITY_POS {
+                    entity.yaw = angle(r.u8()?);
+                    entity.pitch = angle(r.u8()?);
+                }
+                events.push(SessionEvent::EntityMoved {
+                    entity_id: entity_id as i64,
+                    position: entity.position,
+                    yaw: entity.yaw,
+                    pitch: entity.pitch,
+                });
+            }
+            ids::play::ENTITY_POSITION_SYNC | ids::play::TELEPORT_ENTITY => {
+                let entity_id = r.varint()?;
+                let position = DVec3::new(r.f64()?, r.f64()?, r.f64()?);
+                let _velocity = DVec3::new(r.f64()?, r.f64()?, r.f64()?);
+                let (yaw, pitch) = (r.f32()?, r.f32()?);
+                let relative = if id == ids::play::TELEPORT_ENTITY {
+                    r.i32()?
+                } else {
+                    0
+                };
+                let Some(entity) = self.entities.get_mut(&entity_id) else {
+                    return Ok(());
+                };
+                let axis = |bit: i32, current: f64, value: f64| {
+                    if relative & (1 << bit) != 0 {
+                        current + value
+                    } else {
+                        value
+                    }
+                };
+                entity.position = DVec3::new(
+                    axis(0, entity.position.x, position.x),
+                    axis(1, entity.position.y, position.y),
+                    axis(2, entity.position.z, position.z),
+                );
+                entity.yaw = axis(3, entity.yaw as f64, yaw as f64) as f32;
+                entity.pitch = axis(4, entity.pitch as f64, pitch as f64) as f32;
+
+                let (entity_id, position, yaw, pitch) =
+                    (entity_id as i64, entity.position, entity.yaw, entity.pitch);
+                // position syncs correct drift and are interpolated like moves
+                events.push(if id == ids::play::TELEPORT_ENTITY {
+                    SessionEvent::EntityTeleported {
+                        entity_id,
+                        position,
+                        yaw,
+                        pitch,
+                    }
+                } else {
+                    SessionEvent::EntityMoved {
+                        entity_id,
+                        position,
+                        yaw,
+                        pitch,
+                    }
+                });
+            }
+            ids::play::SET_ENTITY_MOTION => {
+                let entity_id = r.varint()? as i64;
+                let velocity = read_velocity(&mut r)?;
+                events.push(SessionEvent::EntityVelocity {
+                    entity_id,
+                    velocity,
+                });
+            }
+            ids::play::SET_ENTITY_DATA => {
+                let entity_id = r.varint()? as i64;
+                let values = read_entity_data(&mut r)?;
+                events.push(SessionEvent::EntityMetadata { entity_id, values });
+            }
+            ids::play::REMOVE_ENTITIES => {
+                let mut entity_ids = Vec::new();
+                for _ in 0..r.len_prefix()? {
+                    let entity_id = r.varint()?;
+                    self.entities.remove(&entity_id);
+                    entity_ids.push(entity_id as i64);
+                }
+                events.push(SessionEvent::EntitiesRemoved { entity_ids });
+            }
+            ids::play::CONTAINER_SET_SLOT => {
+                let window = r.varint()?;
+                let _state_id = r.varint()?;
+                let slot = r.i16()? as i32;
+                let item = read_slot(&mut r)?;
+                events.push(SessionEvent::InventoryChanged { window, slot, item });
+            }
+            ids::play::SET_PLAYER_INVENTORY => {
+                let slot = r.varint()?;
+                let item = read_slot(&mut r)?;
+                events.push(SessionEvent::InventoryChanged {
+                    window: 0,
+                    slot,
+                    item,
+                });
+            }
+            ids::play::START_CONFIGURATION => {
+                self.conn.send(ids::play::ACKNOWLEDGE_CONFIGURATION, &[])?;
+                self.conn.set_state(JavaState::Configuration);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn send_position(&mut self, on_ground: bool) -> Result<(), NetError> {
+        self.reported = ReportedMovement {
+            position: self.position,
+            yaw: self.yaw,
+            pitch: self.pitch,
+            on_ground,
+            horizontal_collision: false,
+            ticks_since_position: 0,
+            ..self.reported
+        };
+        let mut packet = PacketWriter::new();
+        packet
+            .f64(self.position.x)
+            .f64(self.position.y)
+            .f64(self.position.z)
+            .f32(self.yaw)
+            .f32(self.pitch)
+            .u8(if on_ground { move_flags::ON_GROUND } else { 0 });
+        self.conn
+            .send(ids::play::MOVE_POSITION_ROTATION, &packet.buf)
+    }
+
+    /// The packets vanilla sends for one client tick: changed keys and sprint or sneak state,
+    /// then the smallest movement packet that carries what changed.
+    fn send_tick(&mut self, tick: &PlayerTick) -> Result<(), NetError> {
+        let mut keys = 0;
+        for (held, key) in [
+            (tick.forward > 0.0, input_keys::FORWARD),
+            (tick.forward < 0.0, input_keys::BACKWARD),
+            (tick.strafe > 0.0, input_keys::LEFT),
+            (tick.strafe < 0.0, input_keys::RIGHT),
+            (tick.jumping, input_keys::JUMP),
+            (tick.sneaking, input_keys::SNEAK),
+            (tick.sprinting, input_keys::SPRINT),
+        ] {
+            if held {
+                keys |= key;
+            }
+        }
+        if keys != self.reported.keys {
+            self.reported.keys = keys;
+            self.conn.send(ids::play::PLAYER_INPUT, &[keys])?;
+        }
+        if tick.sneaking != self.reported.sneaking {
+            self.reported.sneaking = tick.sneaking;
+            self.send_player_command(if tick.sneaking {
+                player_command::PRESS_SHIFT_KEY
+            } else {
+                player_command::RELEASE_SHIFT_KEY
+            })?;
+        }
+        if tick.sprinting != self.reported.sprinting {
+            self.reported.sprinting = tick.sprinting;
+            self.send_player_command(if tick.sprinting {
+                player_command::START_SPRINTING
+            } else {
+                player_command::STOP_SPRINTING
+            })?;
+        }
+
+        self.position = tick.position;
+        self.yaw = tick.yaw;
+        self.pitch = tick.pitch;
+        let reported = &mut self.reported;
+        reported.ticks_since_position += 1;
+        let moved = (tick.position - reported.position).length_squared()
+            > MIN_REPORTED_MOVE * MIN_REPORTED_MOVE
+            || reported.ticks_since_position >= POSITION_REMINDER_TICKS;
+        let turned = tick.yaw != reported.yaw || tick.pitch != reported.pitch;
+        let status_changed = tick.on_ground != reported.on_ground
+            || tick.horizontal_collision != reported.horizontal_collision;
+        if moved {
+            reported.position = tick.position;
+            reported.ticks_since_position = 0;
+        }
+        if turned {
+            reported.yaw = tick.yaw;
+            reported.pitch = tick.pitch;
+        }
+        reported.on_ground = tick.on_ground;
+        reported.horizontal_collision = tick.horizontal_collision;
+
+        let mut flags = 0;
+        if tick.on_ground {
+            flags |= move_flags::ON_GROUND;
+        }
+        if tick.horizontal_collision {
+            flags |= move_flags::HORIZONTAL_COLLISION;
+        }
+        let mut packet = PacketWriter::new();
+        if moved {
+            packet
+                .f64(tick.position.x)
+                .f64(tick.position.y)
+                .f64(tick.position.z);
+        }
+        if turned {
+            packet.f32(tick.yaw).f32(tick.pitch);
+        }
+        packet.u8(flags);
+        let id = match (moved, turned) {
+            (true, true) => ids::play::MOVE_POSITION_ROTATION,
+            (true, false) => ids::play::MOVE_POSITION,
+            (false, true) => ids::play::MOVE_ROTATION,
+            (false, false) if status_changed => ids::play::MOVE_STATUS_ONLY,
+            (false, false) => return Ok(()),
+        };
+        self.conn.send(id, &packet.buf)
+    }
+
+    fn send_player_command(&mut self, action: i32) -> Result<(), NetError> {
+        let Some(entity_id) = self.entity_id else {
+            return Ok(());
+        };
+        let mut packet = PacketWriter::new();
+        packet.varint(entity_id).varint(action).varint(0);
+        self.conn.send(ids::play::PLAYER_COMMAND, &packet.buf)
+    }
+
+    fn next_sequence(&mut self) -> i32 {
+        self.sequence += 1;
+        self.sequence
+    }
+
+    /// Send the packets for one player action.
+    pub fn perform(&mut self, action: &PlayerAction) -> Result<(), NetError> {
+        if self.state() != JavaState::Play {
+            // nothing can be done until we are in the world
+            return Ok(());
+        }
+        match action {
+            PlayerAction::Chat(message) => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis() as i64)
+                    .unwrap_or_default();
+                let mut packet = PacketWriter::new();
+                packet
+                    .string(message)
+                    .i64(timestamp)
+                    .i64(0) // salt
+                    .bool(false) // unsigned
+                    .varint(0) // no acknowledged messages
+                    .bytes(&[0; 3]) // acknowledgement bitset (20 bits)
+                    .u8(0); // checksum, 0 skips validation
+                self.conn.send(ids::play::CHAT_MESSAGE, &packet.buf)?;
+            }
+            PlayerAction::Command(command) => {
+                let mut packet = PacketWriter::new();
+                packet.string(command.trim_start_matches('/'));
+                self.conn.send(ids::play::CHAT_COMMAND, &packet.buf)?;
+            }
+            PlayerAction::Move {
+                position,
+                yaw,
+                pitch,
+                on_ground,
+            } => {
+                self.position = *position;
+                self.yaw = *yaw;
+                self.pitch = *pitch;
+                self.send_position(*on_ground)?;
+            }
+            PlayerAction::Tick(tick) => self.send_tick(tick)?,
+            PlayerAction::StartBreaking { pos, face }
+            | PlayerAction::CancelBreaking { pos, face }
+            | PlayerAction::FinishBreaking { pos, face } => {
+                let status = match action {
+                    PlayerAction::StartBreaking { .. } => 0,
+                    PlayerAction::CancelBreaking { .. } => 1,
+                    _ => 2,
+                };
+                let sequence = self.next_sequence();
+                let mut packet = PacketWriter::new();
+                packet
+                    .varint(status)
+                    .position(*pos)
+                    .u8(face.id())
+                    .varint(sequence);
+                self.conn.send(ids::play::PLAYER_ACTION, &packet.buf)?;
+            }
+            // Java servers time the breaking themselves
+            PlayerAction::ContinueBreaking { .. } => {}
+            PlayerAction::Place { pos, face, cursor } => {
+                let sequence = self.next_sequence();
+                let mut packet = PacketWriter::new();
+                packet
+                    .varint(0) // main hand
+                    .position(*pos)
+                    .varint(face.id() as i32)
+                    .f32(cursor.x)
+                    .f32(cursor.y)
+                    .f32(cursor.z)
+                    .bool(false) // head inside block
+                    .bool(false) // world border hit
+                    .varint(sequence);
+                self.conn.send(ids::play::USE_ITEM_ON, &packet.buf)?;
+            }
+            PlayerAction::SwingArm => {
+                let mut packet = PacketWriter::new();
+                packet.varint(0);
+                self.conn.send(ids::play::SWING_ARM, &packet.buf)?;
+            }
+            PlayerAction::SetViewDistance(chunks) => {
+                self.options.view_distance = *chunks;
+                self.send_settings(ids::play::CLIENT_INFORMATION)?;
+            }
+            PlayerAction::Disconnect => {}
+        }
+        Ok(())
+    }
+}
+
+/// Item count and id of a slot; the data components after them are left unread.
+/// A rotation sent as a byte, 256 steps per turn.
+fn angle(byte: u8) -> f32 {
+    byte as i8 as f32 * 360.0 / 256.0
+}
+
+/// A velocity sent as shorts, in 1/8000 of a block per tick.
+fn read_velocity(r: &mut PacketReader) -> Result<DVec3, NetError> {
+    Ok(DVec3::new(r.i16()? as f64, r.i16()? as f64, r.i16()? as f64) / 8000.0)
+}
+
+/// `set_entity_data` values, up to the first of a type we can't read (particles, inline
+/// registry entries), as the rest can't be found without it.
+fn read_entity_data(r: &mut PacketReader) -> Result<Vec<(u32, MetadataValue)>, NetError> {
+    let mut values = Vec::new();
+    loop {
+        let key = r.u8()?;
+        if key == 0xFF {
+            break;
+        }
+        // serializer ids of 1.21.5
+        let value = match r.varint()? {
+            0 => MetadataValue::Byte(r.i8()?),
+            1 | 12 | 14 | 15 | 20..=27 | 30 | 31 => MetadataValue::Int(r.varint()? as i64),
+            2 => MetadataValue::Int(r.varlong()?),
+            3 => MetadataValue::Float(r.f32()?),
+            4 => MetadataValue::String(r.string()?),
+            5 => MetadataValue::String(text_to_plain(&read_network_nbt(r)?)),
+            6 => {
+                if !r.bool()? {
+                    continue;
+                }
+                MetadataValue::String(text_to_plain(&read_network_nbt(r)?))
+            }
+            7 => {
+                let item = read_slot(r)?;
+                // data components would need the item's component types
+                if item.is_some() && (r.varint()?, r.varint()?) != (0, 0) {
+                    break;
+                }
+                MetadataValue::Item(item)
+            }
+            8 => MetadataValue::Bool(r.bool()?),
+            9 | 32 => MetadataValue::Vec3(Vec3::new(r.f32()?, r.f32()?, r.f32()?)),
+            10 => MetadataValue::Position(r.position()?),
+            11 => {
+                if !r.bool()? {
+                    continue;
+                }
+                MetadataValue::Position(r.position()?)
+            }
+            13 => {
+                if r.bool()? {
+                    r.uuid()?;
+                }
+                continue;
+            }
+            18 => {
+                // villager type, profession and level
+                for _ in 0..3 {
+                    r.varint()?;
+                }
+                continue;
+            }
+            19 => match r.varint()? {
+                0 => continue,
+                value => MetadataValue::Int(value as i64 - 1),
+            },
+            33 => {
+                r.bytes(16)?; // quaternion
+                continue;
+            }
+            _ => break,
+        };
+        values.push((key as u32, value));
+    }
+    Ok(values)
+}
+
+fn read_slot(r: &mut PacketReader) -> Result<Option<ItemStack>, NetError> {
+    let count = r.varint()?;
+    if count <= 0 {
+        return Ok(None);
+    }
+    Ok(Some(ItemStack {
+        id: r.varint()?,
+        count: count as u32,
+    }))
+}
+
+/// Name of a packet this client knows about, as used in captures.
+pub fn packet_name(state: JavaState, direction: Direction, id: i32) -> Option<&'static str> {
+    use Direction::{Inbound, Outbound};
+    use JavaState::{Configuration, Handshake, Login, Play, Status};
+    Some(match (state, direction, id) {
+        (Handshake, Outbound, ids::handshake::INTENTION) => "intention",
+
+        (Status, Inbound, ids::status::STATUS_RESPONSE) => "status_response",
+        (Status, Inbound, ids::status::PONG_RESPONSE) => "pong_response",
+        (Status, Outbound, ids::status::STATUS_REQUEST) => "status_request",
+        (Status, Outbound, ids::status::PING_REQUEST) => "ping_request",
+
+        (Login, Inbound, ids::login::DISCONNECT) => "login_disconnect",
+        (Login, Inbound, ids::login::ENCRYPTION_REQUEST) => "hello",
+        (Login, Inbound, ids::login::LOGIN_SUCCESS) => "login_finished",
+        (Login, Inbound, ids::login::SET_COMPRESSION) => "login_compression",
+        (Login, Inbound, ids::login::PLUGIN_REQUEST) => "custom_query",
+        (Login, Inbound, ids::login::COOKIE_REQUEST) => "cookie_request",
+        (Login, Outbound, ids::login::LOGIN_START) => "hello",
+        (Login, Outbound, ids::login::ENCRYPTION_RESPONSE) => "key",
+        (Login, Outbound, ids::login::PLUGIN_RESPONSE) => "custom_query_answer",
+        (Login, Outbound, ids::login::LOGIN_ACKNOWLEDGED) => "login_acknowledged",
+        (Login, Outbound, ids::login::COOKIE_RESPONSE) => "cookie_response",
+
+        (Configuration, Inbound, ids::config::COOKIE_REQUEST) => "cookie_request",
+        (Configuration, Inbound, ids::config::DISCONNECT) => "disconnect",
+        (Configuration, Inbound, ids::config::FINISH_CONFIGURATION) => "finish_configuration",
+        (Configuration, Inbound, ids::config::KEEP_ALIVE) => "keep_alive",
+        (Configuration, Inbound, ids::config::PING) => "ping",
+        (Configuration, Inbound, ids::config::REGISTRY_DATA) => "registry_data",
+        (Configuration, Inbound, ids::config::ADD_RESOURCE_PACK) => "resource_pack_push",
+        (Configuration, Inbound, ids::config::KNOWN_PACKS) => "select_known_packs",
+        (Configuration, Outbound, ids::config::CLIENT_INFORMATION) => "client_information",
+        (Configuration, Outbound, ids::config::COOKIE_RESPONSE) => "cookie_response",
+        (Configuration, Outbound, ids::config::PLUGIN_MESSAGE) => "custom_payload",
+        (Configuration, Outbound, ids::config::ACKNOWLEDGE_FINISH) => "finish_configuration",
+        (Configuration, Outbound, ids::config::KEEP_ALIVE_RESPONSE) => "keep_alive",
+        (Configuration, Outbound, ids::config::PONG) => "pong",
+        (Configuration, Outbound, ids::config::RESOURCE_PACK_RESPONSE) => "resource_pack",
+        (Configuration, Outbound, ids::config::KNOWN_PACKS_RESPONSE) => "select_known_packs",
+
+        (Play, Inbound, ids::play::ADD_ENTITY) => "add_entity",
+        (Play, Inbound, ids::play::BLOCK_UPDATE) => "block_update",
+        (Play, Inbound, ids::play::CHUNK_BATCH_FINISHED) => "chunk_batch_finished",
+        (Play, Inbound, ids::play::CONTAINER_SET_SLOT) => "container_set_slot",
+        (Play, Inbound, ids::play::DISCONNECT) => "disconnect",
+        (Play, Inbound, ids::play::ENTITY_POSITION_SYNC) => "entity_position_sync",
+        (Play, Inbound, ids::play::FORGET_LEVEL_CHUNK) => "forget_level_chunk",
+        (Play, Inbound, ids::play::KEEP_ALIVE) => "keep_alive",
+        (Play, Inbound, ids::play::LEVEL_CHUNK_WITH_LIGHT) => "level_chunk_with_light",
+        (Play, Inbound, ids::play::LIGHT_UPDATE) => "light_update",
+        (Play, Inbound, ids::play::LOGIN) => "login",
+        (Play, Inbound, ids::play::MOVE_ENTITY_POS) => "move_entity_pos",
+        (Play, Inbound, ids::play::MOVE_ENTITY_POS_ROT) => "move_entity_pos_rot",
+        (Play, Inbound, ids::play::MOVE_ENTITY_ROT) => "move_entity_rot",
+        (Play, Inbound, ids::play::PING) => "ping",
+        (Play, Inbound, ids::play::PONG_RESPONSE) => "pong_response",
+        (Play, Inbound, ids::play::PLAYER_CHAT) => "player_chat",
+        (Play, Inbound, ids::play::SYNCHRONIZE_POSITION) => "player_position",
+        (Play, Inbound, ids::play::REMOVE_ENTITIES) => "remove_entities",
+        (Play, Inbound, ids::play::RESPAWN) => "respawn",
+        (Play, Inbound, ids::play::SECTION_BLOCKS_UPDATE) => "section_blocks_update",
+        (Play, Inbound, ids::play::SET_ENTITY_DATA) => "set_entity_data",
+        (Play, Inbound, ids::play::SET_ENTITY_MOTION) => "set_entity_motion",
+        (Play, Inbound, ids::play::SET_PLAYER_INVENTORY) => "set_player_inventory",
+        (Play, Inbound, ids::play::START_CONFIGURATION) => "start_configuration",
+        (Play, Inbound, ids::play::SYSTEM_CHAT) => "system_chat",
+        (Play, Inbound, ids::play::TELEPORT_ENTITY) => "teleport_entity",
+        (Play, Outbound, ids::play::CONFIRM_TELEPORT) => "accept_teleportation",
+        (Play, Outbound, ids::play::CHAT_COMMAND) => "chat_command",
+        (Play, Outbound, ids::play::CHAT_MESSAGE) => "chat",
+        (Play, Outbound, ids::play::CHUNK_BATCH_RECEIVED) => "chunk_batch_received",
+        (Play, Outbound, ids::play::CLIENT_INFORMATION) => "client_information",
+        (Play, Outbound, ids::play::ACKNOWLEDGE_CONFIGURATION) => "configuration_acknowledged",
+        (Play, Outbound, ids::play::KEEP_ALIVE_RESPONSE) => "keep_alive",
+        (Play, Outbound, ids::play::MOVE_POSITION_ROTATION) => "move_player_pos_rot",
+        (Play, Outbound, ids::play::PING_REQUEST) => "ping_request",
+        (Play, Outbound, ids::play::PLAYER_ACTION) => "player_action",
+        (Play, Outbound, ids::play::PLAYER_LOADED) => "player_loaded",
+        (Play, Outbound, ids::play::PONG) => "pong",
+        (Play, Outbound, ids::play::SWING_ARM) => "swing",
+        (Play, Outbound, ids::play::USE_ITEM_ON) => "use_item_on",
+        _ => return None,
+    })
+}
+
+/// One line summary of the interesting fields of a known packet.
+pub fn describe_packet(
+    state: JavaState,
+    direction: Direction,
+    id: i32,
+    payload: &[u8],
+) -> Option<String> {
+    use Direction::{Inbound, Outbound};
+    use JavaState::{Configuration, Handshake, Login, Play};
+    let mut r = PacketReader::new(payload);
+    let r = &mut r;
+    // unknown packets and payloads that don't decode get no summary
+    let text: Result<Option<String>, NetError> = (|| {
+        Ok(Some(match (state, direction, id) {
+            (Handshake, Outbound, ids::handshake::INTENTION) => format!(
+                "protocol={} host={} port={} intent={}",
+                r.varint()?,
+                r.string()?,
+                r.u16()?,
+                r.varint()?
+            ),
+            (Login, Inbound, ids::login::DISCONNECT) => format!("reason={}", r.string()?),
+            (Login, Inbound, ids::login::LOGIN_SUCCESS) => {
+                let uuid = r.uuid()?;
+                format!("uuid={:032x} name={}", uuid, r.string()?)
+            }
+            (Login, Inbound, ids::login::SET_COMPRESSION) => format!("threshold={}", r.varint()?),
+            (Login, Outbound, ids::login::LOGIN_START) => format!("name={}", r.string()?),
+            (Configuration, Inbound, ids::config::DISCONNECT)
+            | (Play, Inbound, ids::play::DISCONNECT) => {
+                format!("reason={}", text_to_plain(&read_network_nbt(r)?))
+            }
+            (Configuration, _, ids::config::KEEP_ALIVE)
+            | (Play, Inbound, ids::play::KEEP_ALIVE) => {
+                format!("id={}", r.i64()?)
+            }
+            (Play, Outbound, ids::play::KEEP_ALIVE_RESPONSE) => format!("id={}", r.i64()?),
+            (Play, Inbound, ids::play::LOGIN) => format!("entity_id={}", r.i32()?),
+            (Play, Inbound, ids::play::SYNCHRONIZE_POSITION) => {
+                let teleport = r.varint()?;
+                format!(
+                    "teleport={} pos=({:.2}, {:.2}, {:.2})",
+                    teleport,
+                    r.f64()?,
+                    r.f64()?,
+                    r.f64()?
+                )
+            }
+            (Play, Outbound, ids::play::MOVE_POSITION_ROTATION) => format!(
+                "pos=({:.2}, {:.2}, {:.2}) yaw={:.1} pitch={:.1}",
+                r.f64()?,
+                r.f64()?,
+                r.f64()?,
+                r.f32()?,
+                r.f32()?
+            ),
+            (Play, Inbound, ids::play::SYSTEM_CHAT) => {
+                format!("text={}", text_to_plain(&read_network_nbt(r)?))
+            }
+            (Play, Inbound, ids::play::PLAYER_CHAT) => {
+                let _global_index = r.varint()?;
+                let sender = r.uuid()?;
+                let _index = r.varint()?;
+                if r.bool()? {
+                    r.bytes(256)?;
+                }
+                format!("sender={:032x} message={}", sender, r.string()?)
+            }
+            (Play, Outbound, ids::play::CHAT_MESSAGE) => format!("message={}", r.string()?),
+            (Play, Outbound, ids::play::CHAT_COMMAND) => format!("command={}", r.string()?),
+            (Play, Inbound, ids::play::BLOCK_UPDATE) => {
+                let pos = r.position()?;
+                format!("pos={} state={}", pos, r.varint()?)
+            }
+            (Play, Inbound, ids::play::LEVEL_CHUNK_WITH_LIGHT) => {
+                format!("chunk=({}, {})", r.i32()?, r.i32()?)
+            }
+            (Play, Inbound, ids::play::FORGET_LEVEL_CHUNK) => {
+                let z = r.i32()?;
+                format!("chunk=({}, {})", r.i32()?, z)
+            }
+            (Play, Inbound, ids::play::LIGHT_UPDATE) => {
+                format!("chunk=({}, {})", r.varint()?, r.varint()?)
+            }
+            (Play, Inbound, ids::play::SECTION_BLOCKS_UPDATE) => {
+                format!("blocks={}", read_section_blocks_update(r)?.len())
+            }
+            (Play, Outbound, ids::play::PLAYER_ACTION) => {
+                let status = r.varint()?;
+                let pos = r.position()?;
+                format!("status={} pos={} face={}", status, pos, r.u8()?)
+            }
+            (Play, Outbound, ids::play::USE_ITEM_ON) => {
+                let hand = r.varint()?;
+                let pos = r.position()?;
+                format!("hand={} pos={} face={}", hand, pos, r.varint()?)
+            }
+            _ => return Ok(None),
+        }))
+    })();
+    text.ok().flatten()
+}
@@ -0,0 +1,521 @@
+//! Java world data: `level_chunk_with_light` columns with their paletted sections, biomes,
+//! heightmaps, block entities and light, decoded into the client's chunk types.
+
+use bevy::math::{IVec2, IVec3};
+
+use crate::net::NetError;
+use crate::net::codec::PacketReader;
+use crate::net::nbt::{Nbt, read_network_nbt};
+use crate::world::{
+    BIOME_VOLUME, BlockEntity, BlockState, CHUNK_WIDTH, Chunk, NibbleArray, SECTION_HEIGHT,
+    SECTION_VOLUME,
+};
+
+/// Most bits per value an indirect (palette) block container uses; above, ids are global.
+const MAX_INDIRECT_BLOCK_BITS: u8 = 8;
+/// Most bits per value an indirect biome container uses.
+const MAX_INDIRECT_BIOME_BITS: u8 = 3;
+/// `MOTION_BLOCKING` in the heightmap types enum.
+const HEIGHTMAP_MOTION_BLOCKING: i32 = 4;
+
+/// Values packed into longs; since 1.16 values never straddle two longs, and since 1.21.5 the
+/// number of longs isn't sent, as it follows from `bits` and `count`.
+fn read_packed(r: &mut PacketReader, bits: u8, count: usize) -> Result<Vec<u32>, NetError> {
+    let bits = bits as usize;
+    let per_long = 64 / bits;
+    let longs = (0..count.div_ceil(per_long))
+        .map(|_| r.i64().map(|long| long as u64))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mask = (1u64 << bits) - 1;
+    Ok((0..count)
+        .map(|i| ((longs[i / per_long] >> ((i % per_long) * bits)) & mask) as u32)
+        .collect())
+}
+
+/// A paletted container of `count` values: one value, a palette with indices into it, or
+/// global ids directly.
+fn read_paletted(
+    r: &mut PacketReader,
+    count: usize,
+    max_indirect_bits: u8,
+) -> Result<Vec<u32>, NetError> {
+    let bits = r.u8()?;
+    if bits == 0 {
+        return Ok(vec![r.varint()? as u32; count]);
+    }
+    if bits > 32 {
+        return Err(NetError::Protocol(format!(
+            "bad paletted container size {}",
+            bits
+        )));
+    }
+    if bits > max_indirect_bits {
+        return read_packed(r, bits, count);
+    }
+    let len = r.len_prefix()?;
+    let palette = (0..len)
+        .map(|_| r.varint().map(|id| id as u32))
+        .collect::<Result<Vec<_>, _>>()?;
+    read_packed(r, bits, count)?
+        .into_iter()
+        .map(|index| {
+            palette
+                .get(index as usize)
+                .copied()
+                .ok_or_else(|| NetError::Protocol(format!("palette index {} out of range", index)))
+        })
+        .collect()
+}
+
+/// A `BitSet`: a length-prefixed array of longs.
+fn read_bit_set(r: &mut PacketReader) -> Result<Vec<u64>, NetError> {
+    let len = r.len_prefix()?;
+    (0..len).map(|_| r.i64().map(|long| long as u64)).collect()
+}
+
+fn bit(set: &[u64], index: usize) -> bool {
+    set.get(index / 64)
+        .is_some_and(|long| long & (1 << (index % 64)) != 0)
+}
+
+/// Light of some sections of a column, by section index. Light covers one section more than
+/// the world at either end.
+#[derive(Clone, Debug, Default)]
+pub struct LightData {
+    pub sky_light: Vec<(i32, NibbleArray)>,
+    pub block_light: Vec<(i32, NibbleArray)>,
+}
+
+/// The light part of `level_chunk_with_light` and `light_update`: which sections have light
+/// arrays, which are dark, and the arrays themselves.
+pub fn read_light(r: &mut PacketReader, min_section: i32) -> Result<LightData, NetError> {
+    let sky_mask = read_bit_set(r)?;
+    let block_mask = read_bit_set(r)?;
+    let empty_sky_mask = read_bit_set(r)?;
+    let empty_block_mask = read_bit_set(r)?;
+
+    let mut read_arrays = |mask: &[u64], empty: &[u64]| -> Result<_, NetError> {
+        let mut arrays = Vec::new();
+        let count = r.len_prefix()?;
+        let mut present = (0..mask.len() * 64).filter(|i| bit(mask, *i));
+        for _ in 0..count {
+            let index = present
+                .next()
+                .ok_or_else(|| NetError::Protocol("more light arrays than sections".into()))?;
+            let len = r.len_prefix()?;
+            let mut light = NibbleArray::filled(0);
+            if len != light.as_bytes().len() {
+                return Err(NetError::Protocol(format!("light array of {} bytes", len)));
+            }
+            light.as_bytes_mut().copy_from_slice(r.bytes(len)?);
+            arrays.push((min_section - 1 + index as i32, light));
+        }
+        for index in (0..empty.len() * 64).filter(|i| bit(empty, *i)) {
+            arrays.push((min_section - 1 + index as i32, NibbleArray::filled(0)));
+        }
+        Ok(arrays)
+    };
+    Ok(LightData {
+        sky_light: read_arrays(&sky_mask, &empty_sky_mask)?,
+        block_light: read_arrays(&block_mask, &empty_block_mask)?,
+    })
+}
+
+/// Heights of the first free block above the motion blocking ones, `x + z * 16`, from the
+/// typed long arrays sent since 1.21.5 (NBT before).
+fn read_heightmaps(
+    r: &mut PacketReader,
+    min_y: i32,
+    height: i32,
+) -> Result<Option<Box<[i32; 256]>>, NetError> {
+    let mut motion_blocking = None;
+    for _ in 0..r.len_prefix()? {
+        let kind = r.varint()?;
+        let len = r.len_prefix()?;
+        let longs = r.bytes(len * 8)?;
+        if kind != HEIGHTMAP_MOTION_BLOCKING || len == 0 {
+            continue;
+        }
+        let bits = (u32::BITS - (height as u32).leading_zeros()) as u8;
+        let heights = read_packed(&mut PacketReader::new(longs), bits, 256)?;
+        let mut map = Box::new([min_y; 256]);
+        for (column, value) in map.iter_mut().zip(heights) {
+            *column += value as i32;
+        }
+        motion_blocking = Some(map);
+    }
+    Ok(motion_blocking)
+}
+
+/// A `level_chunk_with_light` body after the position `pos`, for a world of `section_count`
+/// sections starting at section `min_section`.
+pub fn read_chunk(
+    r: &mut PacketReader,
+    pos: IVec2,
+    min_section: i32,
+    section_count: usize,
+) -> Result<Chunk, NetError> {
+    let mut chunk = Chunk::new(min_section, section_count);
+    let min_y = min_section * SECTION_HEIGHT;
+    chunk.heightmap = read_heightmaps(r, min_y, section_count as i32 * SECTION_HEIGHT)?;
+
+    let len = r.len_prefix()?;
+    let mut data = PacketReader::new(r.bytes(len)?);
+    for section in &mut chunk.sections {
+        let _non_air_blocks = data.i16()?;
+        let blocks = read_paletted(&mut data, SECTION_VOLUME, MAX_INDIRECT_BLOCK_BITS)?;
+        for (block, id) in section.blocks.iter_mut().zip(blocks) {
+            *block = BlockState(id);
+        }
+        let biomes = read_paletted(&mut data, BIOME_VOLUME, MAX_INDIRECT_BIOME_BITS)?;
+        section.biomes.copy_from_slice(&biomes);
+    }
+
+    for _ in 0..r.len_prefix()? {
+        let packed_xz = r.u8()?;
+        let y = r.i16()? as i32;
+        let kind = r.varint()?;
+        let data = read_network_nbt(r)?;
+        let at = IVec3::new(
+            pos.x * CHUNK_WIDTH + (packed_xz >> 4) as i32,
+            y,
+            pos.y * CHUNK_WIDTH + (packed_xz & 0x0F) as i32,
+        );
+        chunk.block_entities.insert(
+            at,
+            BlockEntity {
+                id: kind.to_string(),
+                data,
+            },
+        );
+    }
+
+    let light = read_light(r, min_section)?;
+    for (y, light) in light.sky_light {
+        if let Some(section) = chunk.section_mut(y) {
+            section.sky_light = light;
+        }
+    }
+    for (y, light) in light.block_light {
+        if let Some(section) = chunk.section_mut(y) {
+            section.block_light = light;
+        }
+    }
+    chunk.has_light = true;
+    Ok(chunk)
+}
+
+/// `section_blocks_update`: the changed blocks of one section, as world positions and states.
+pub fn read_section_blocks_update(
+    r: &mut PacketReader,
+) -> Result<Vec<(IVec3, BlockState)>, NetError> {
+    // x: 22 bits, z: 22 bits, y: 20 bits
+    let packed = r.i64()?;
+    let origin = IVec3::new(
+        (packed >> 42) as i32 * CHUNK_WIDTH,
+        (packed << 44 >> 44) as i32 * SECTION_HEIGHT,
+        (packed << 22 >> 42) as i32 * CHUNK_WIDTH,
+    );
+    let count = r.len_prefix()?;
+    (0..count)
+        .map(|_| {
+            let entry = r.varlong()?;
+            let local = IVec3::new(
+                (entry >> 8 & 0xF) as i32,
+                (entry & 0xF) as i32,
+                (entry >> 4 & 0xF) as i32,
+            );
+            Ok((origin + local, BlockState((entry >> 12) as u32)))
+        })
+        .collect()
+}
+
+/// `min_y` and `height` from a `dimension_type` registry entry, when the server sent it.
+pub fn dimension_type_bounds(data: &Nbt) -> Option<(i32, i32)> {
+    Some((data.get("min_y")?.as_int()?, data.get("height")?.as_int()?))
+}
+
+/// Bounds of the vanilla dimension types, for registries the server didn't send because the
+/// client knows the vanilla data pack.
+pub fn vanilla_dimension_bounds(dimension_type: &str) -> (i32, i32) {
+    match dimension_type {
+        "minecraft:the_nether" | "minecraft:the_end" => (0, 256),
+        _ => (-64, 384),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::codec::PacketWriter;
+    use crate::world::Section;
+
+    /// Pack `values` the way 1.16+ does: as many per long as fit, low bits first, the rest of
+    /// each long left over.
+    fn pack(values: &[u32], bits: usize) -> Vec<i64> {
+        let per_long = 64 / bits;
+        values
+            .chunks(per_long)
+            .map(|values| {
+                values
+                    .iter()
+                    .enumerate()
+                    .fold(0u64, |long, (i, v)| long | (*v as u64) << (i * bits))
+                    as i64
+            })
+            .collect()
+    }
+
+    fn longs(w: &mut PacketWriter, longs: &[i64]) {
+        for long in longs {
+            w.i64(*long);
+        }
+    }
+
+    fn i16(w: &mut PacketWriter, value: i16) {
+        w.bytes(&value.to_be_bytes());
+    }
+
+    #[test]
+    fn packed_values_stay_within_their_long() {
+        // sixteen 4 bit values fill a long exactly
+        let mut w = PacketWriter::new();
+        w.i64(0xFEDC_BA98_7654_3210_u64 as i64).i64(0x0F);
+        let mut r = PacketReader::new(&w.buf);
+        assert_eq!(
+            read_packed(&mut r, 4, 17).unwrap(),
+            (0..16).chain([15]).collect::<Vec<_>>()
+        );
+        assert_eq!(r.remaining(), 0);
+
+        // twelve 5 bit values per long leave 4 bits over, which don't start a 13th value
+        let values: Vec<u32> = (1..=13).collect();
+        let mut packed = pack(&values, 5);
+        assert_eq!(packed.len(), 2);
+        packed[0] |= 0xF << 60;
+        let mut w = PacketWriter::new();
+        longs(&mut w, &packed);
+        let mut r = PacketReader::new(&w.buf);
+        assert_eq!(read_packed(&mut r, 5, 13).unwrap(), values);
+        assert_eq!(r.remaining(), 0);
+
+        // too few longs
+        let mut r = PacketReader::new(&w.buf[..8]);
+        assert!(read_packed(&mut r, 5, 13).is_err());
+    }
+
+    #[test]
+    fn paletted_containers() {
+        // single value: no palette and no longs
+        let mut w = PacketWriter::new();
+        w.u8(0).varint(7);
+        let mut r = PacketReader::new(&w.buf);
+        assert_eq!(
+            read_paletted(&mut r, 64, MAX_INDIRECT_BLOCK_BITS).unwrap(),
+            vec![7; 64]
+        );
+        assert_eq!(r.remaining(), 0);
+
+        // indirect: indices into the palette
+        let indices: Vec<u32> = (0..SECTION_VOLUME as u32).map(|i| i % 3).collect();
+        let mut w = PacketWriter::new();
+        w.u8(4).varint(3).varint(0).varint(9).varint(1000);
+        longs(&mut w, &pack(&indices, 4));
+        let mut r = PacketReader::new(&w.buf);
+        let blocks = read_paletted(&mut r, SECTION_VOLUME, MAX_INDIRECT_BLOCK_BITS).unwrap();
+        assert_eq!(&blocks[..4], [0, 9, 1000, 0]);
+        assert_eq!(
+            blocks[SECTION_VOLUME - 1],
+            [0, 9, 1000][(SECTION_VOLUME - 1) % 3]
+        );
+        assert_eq!(r.remaining(), 0);
+
+        // direct: global ids, no palette, 15 bits so 4 per long
+        let ids: Vec<u32> = (0..SECTION_VOLUME as u32).map(|i| i * 7 % 30_000).collect();
+        let mut w = PacketWriter::new();
+        w.u8(15);
+        longs(&mut w, &pack(&ids, 15));
+        let mut r = PacketReader::new(&w.buf);
+        assert_eq!(
+            read_paletted(&mut r, SECTION_VOLUME, MAX_INDIRECT_BLOCK_BITS).unwrap(),
+            ids
+        );
+        assert_eq!(r.remaining(), 0);
+
+        // biomes switch to direct ids above 3 bits
+        let biomes: Vec<u32> = (0..BIOME_VOLUME as u32).map(|i| i % 12).collect();
+        let mut w = PacketWriter::new();
+        w.u8(4);
+        longs(&mut w, &pack(&biomes, 4));
+        let mut r = PacketReader::new(&w.buf);
+        assert_eq!(
+            read_paletted(&mut r, BIOME_VOLUME, MAX_INDIRECT_BIOME_BITS).unwrap(),
+            biomes
+        );
+        assert_eq!(r.remaining(), 0);
+
+        // an index past the palette, and a size no container has
+        let mut w = PacketWriter::new();
+        w.u8(1).varint(1).varint(5);
+        longs(&mut w, &pack(&[1; 64], 1));
+        assert!(read_paletted(&mut PacketReader::new(&w.buf), 64, 8).is_err());
+        let mut w = PacketWriter::new();
+        w.u8(33);
+        assert!(read_paletted(&mut PacketReader::new(&w.buf), 64, 8).is_err());
+    }
+
+    fn light_array(w: &mut PacketWriter, byte: u8) {
+        w.varint(2048).bytes(&[byte; 2048]);
+    }
+
+    #[test]
+    fn light_masks() {
+        let mut w = PacketWriter::new();
+        // sky: arrays for light sections 1 and 3, section 2 dark
+        w.varint(1).i64(0b1010);
+        // block: no arrays
+        w.varint(0);
+        w.varint(1).i64(0b0100);
+        w.varint(0);
+        w.varint(2);
+        light_array(&mut w, 0x21);
+        light_array(&mut w, 0xFF);
+        w.varint(0);
+        let mut r = PacketReader::new(&w.buf);
+        let light = read_light(&mut r, -4).unwrap();
+        assert_eq!(r.remaining(), 0);
+
+        // light section 0 is the one below the world
+        let sky: Vec<(i32, u8, u8)> = light
+            .sky_light
+            .iter()
+            .map(|(y, light)| (*y, light.get(0), light.get(1)))
+            .collect();
+        assert_eq!(sky, [(-4, 1, 2), (-2, 15, 15), (-3, 0, 0)]);
+        assert!(light.block_light.is_empty());
+
+        // more arrays than the mask has bits, and arrays of the wrong size
+        let mut w = PacketWriter::new();
+        w.varint(1).i64(0b1).varint(0).varint(0).varint(0).varint(2);
+        light_array(&mut w, 0);
+        light_array(&mut w, 0);
+        assert!(read_light(&mut PacketReader::new(&w.buf), 0).is_err());
+        let mut w = PacketWriter::new();
+        w.varint(1).i64(0b1).varint(0).varint(0).varint(0).varint(1);
+        w.varint(4).bytes(&[0; 4]);
+        assert!(read_light(&mut PacketReader::new(&w.buf), 0).is_err());
+    }
+
+    #[test]
+    fn chunk_column() {
+        // two sections from y = -16 to 16
+        let (min_section, count) = (-1, 2);
+        let mut w = PacketWriter::new();
+
+        // heightmaps: one the client ignores, then motion blocking at 6 bits (height 32)
+        let heights: Vec<u32> = (0..256).map(|i| i % 33).collect();
+        let packed = pack(&heights, 6);
+        w.varint(2);
+        w.varint(1).varint(1).i64(-1);
+        w.varint(HEIGHTMAP_MOTION_BLOCKING)
+            .varint(packed.len() as i32);
+        longs(&mut w, &packed);
+
+        let mut data = PacketWriter::new();
+        // section -1: all stone, one biome
+        i16(&mut data, 4096);
+        data.u8(0).varint(1).u8(0).varint(3);
+        // section 0: dirt at one block, biomes from a palette
+        let mut blocks = vec![0; SECTION_VOLUME];
+        blocks[Section::index(3, 5, 10)] = 1;
+        let biomes: Vec<u32> = (0..BIOME_VOLUME as u32).map(|i| i % 2).collect();
+        i16(&mut data, 1);
+        data.u8(4).varint(2).varint(0).varint(10);
+        longs(&mut data, &pack(&blocks, 4));
+        data.u8(1).varint(2).varint(5).varint(6);
+        longs(&mut data, &pack(&biomes, 1));
+        w.varint(data.buf.len() as i32).bytes(&data.buf);
+
+        // one block entity, with an empty compound
+        w.varint(1).u8(0x3A);
+        i16(&mut w, -5);
+        w.varint(8).u8(0x0A).u8(0);
+
+        // sky light: section -1 lit 7, section 0 dark
+        w.varint(1)
+            .i64(0b010)
+            .varint(0)
+            .varint(1)
+            .i64(0b100)
+            .varint(0);
+        w.varint(1);
+        light_array(&mut w, 0x77);
+        w.varint(0);
+
+        let mut r = PacketReader::new(&w.buf);
+        let chunk = read_chunk(&mut r, IVec2::new(2, -1), min_section, count).unwrap();
+        assert_eq!(r.remaining(), 0);
+
+        let origin = IVec3::new(32, 0, -16);
+        assert_eq!(chunk.block(origin + IVec3::new(0, -16, 0)), BlockState(1));
+        assert_eq!(chunk.block(origin + IVec3::new(15, -1, 15)), BlockState(1));
+        assert_eq!(chunk.block(origin + IVec3::new(3, 5, 10)), BlockState(10));
+        assert_eq!(chunk.block(origin + IVec3::new(3, 5, 11)), BlockState(0));
+        assert!(chunk.sections[0].biomes.iter().all(|b| *b == 3));
+        assert_eq!(&chunk.sections[1].biomes[..3], [5, 6, 5]);
+
+        let heightmap = chunk.heightmap.as_ref().unwrap();
+        assert_eq!(heightmap[0], -16);
+        assert_eq!(heightmap[32], 16);
+        assert_eq!(heightmap[255], -16 + (255 % 33));
+
+        let entity = &chunk.block_entities[&IVec3::new(35, -5, -6)];
+        assert_eq!(entity.id, "8");
+
+        assert!(chunk.has_light);
+        assert_eq!(chunk.sky_light(origin + IVec3::new(0, -8, 0)), 7);
+        assert_eq!(chunk.sky_light(origin + IVec3::new(0, 8, 0)), 0);
+        assert_eq!(chunk.block_light(origin + IVec3::new(0, 8, 0)), 0);
+    }
+
+    /// A section position long: x and z in 22 bits, y in the low 20.
+    fn section_pos(x: i64, y: i64, z: i64) -> i64 {
+        (x & 0x3F_FFFF) << 42 | (z & 0x3F_FFFF) << 20 | (y & 0xF_FFFF)
+    }
+
+    #[test]
+    fn section_updates() {
+        let mut w = PacketWriter::new();
+        w.i64(section_pos(-2, -4, 3)).varint(2);
+        // state << 12 | x << 8 | z << 4 | y
+        w.var_u64(1234 << 12 | 1 << 8 | 2 << 4 | 15);
+        w.var_u64(0x12_345 << 12);
+        let mut r = PacketReader::new(&w.buf);
+        let changes = read_section_blocks_update(&mut r).unwrap();
+        assert_eq!(r.remaining(), 0);
+        assert_eq!(
+            changes,
+            [
+                (IVec3::new(-31, -49, 50), BlockState(1234)),
+                (IVec3::new(-32, -64, 48), BlockState(0x12_345)),
+            ]
+        );
+
+        // every bit set is section -1 on all three axes
+        let mut w = PacketWriter::new();
+        w.i64(-1).varint(1).var_u64(0);
+        let changes = read_section_blocks_update(&mut PacketReader::new(&w.buf)).unwrap();
+        assert_eq!(changes, [(IVec3::splat(-16), BlockState(0))]);
+
+        // the far ends of each axis
+        let mut w = PacketWriter::new();
+        w.i64(section_pos(0x1F_FFFF, 0x7_FFFF, -0x20_0000))
+            .varint(1)
+            .var_u64(0);
+        let changes = read_section_blocks_update(&mut PacketReader::new(&w.buf)).unwrap();
+        assert_eq!(
+            changes[0].0,
+            IVec3::new(0x1F_FFFF * 16, 0x7_FFFF * 16, -0x20_0000 * 16)
+        );
+    }
+}
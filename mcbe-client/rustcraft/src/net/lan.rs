@@ -0,0 +1,617 @@
+//! LAN game discovery: RakNet unconnected pings for Bedrock, multicast announcements for Java.
+//! `LanAnnouncer` plays the other side, for testing without a game on the network.
+
+use bevy::prelude::*;
+use crossbeam_channel::{Receiver, Sender};
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::net::codec::{PacketReader, PacketWriter};
+use crate::net::raknet::{self, MAGIC, parse_unconnected_pong, unconnected_ping};
+use crate::net::{Edition, NetError, ServerAddress};
+
+/// Multicast group and port Java games announce themselves on.
+pub const JAVA_LAN_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 2, 60);
+pub const JAVA_LAN_PORT: u16 = 4445;
+/// How often Bedrock servers are pinged, and Java announcements are sent.
+const PING_INTERVAL: Duration = Duration::from_millis(1500);
+/// Servers not heard from for this long are removed from the list.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(6);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The `;` separated status string of a Bedrock server, as sent in unconnected pongs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BedrockMotd {
+    /// `MCPE` or `MCEE`.
+    pub edition: String,
+    pub motd: String,
+    pub protocol: i32,
+    pub version: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub server_guid: u64,
+    /// Second line, usually the world name.
+    pub sub_motd: String,
+    pub game_mode: String,
+    pub port_v4: Option<u16>,
+    pub port_v6: Option<u16>,
+}
+
+impl BedrockMotd {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = text.split(';').collect();
+        if fields.len() < 6 {
+            return Err(format!(
+                "MOTD has {} fields, expected at least 6",
+                fields.len()
+            ));
+        }
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+        Ok(Self {
+            edition: field(0).to_string(),
+            motd: field(1).to_string(),
+            protocol: field(2).trim().parse().unwrap_or_default(),
+            version: field(3).to_string(),
+            players: field(4).trim().parse().unwrap_or_default(),
+            max_players: field(5).trim().parse().unwrap_or_default(),
+            server_guid: field(6).trim().parse().unwrap_or_default(),
+            sub_motd: field(7).to_string(),
+            game_mode: field(8).to_string(),
+            port_v4: field(10).trim().parse().ok(),
+            port_v6: field(11).trim().parse().ok(),
+        })
+    }
+}
+
+impl fmt::Display for BedrockMotd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let game_mode_id = match self.game_mode.as_str() {
+            "Creative" => 1,
+            "Adventure" => 2,
+            _ => 0,
+        };
+        write!(
+            f,
+            "{};{};{};{};{};{};{};{};{};{};{};{};",
+            self.edition,
+            self.motd,
+            self.protocol,
+            self.version,
+            self.players,
+            self.max_players,
+            self.server_guid,
+            self.sub_motd,
+            self.game_mode,
+            game_mode_id,
+            self.port_v4.map(|p| p.to_string()).unwrap_or_default(),
+            self.port_v6.map(|p| p.to_string()).unwrap_or_default(),
+        )
+    }
+}
+
+/// Parse a Java LAN announcement, `[MOTD]text[/MOTD][AD]port[/AD]`.
+pub fn parse_java_announcement(text: &str) -> Option<(String, u16)> {
+    let between = |start: &str, end: &str| {
+        let from = text.find(start)? + start.len();
+        let to = from + text[from..].find(end)?;
+        Some(&text[from..to])
+    };
+    let motd = between("[MOTD]", "[/MOTD]")?;
+    let port = between("[AD]", "[/AD]")?.trim().parse().ok()?;
+    Some((motd.to_string(), port))
+}
+
+pub fn java_announcement(motd: &str, port: u16) -> String {
+    format!("[MOTD]{}[/MOTD][AD]{}[/AD]", motd, port)
+}
+
+/// What tells one LAN game from another across sightings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LanServerId {
+    /// Bedrock servers report a GUID, which stays the same whichever address answered.
+    Bedrock(u64),
+    /// Java announcements carry nothing better than where they came from.
+    Java {
+        address: ServerAddress,
+        motd: String,
+    },
+}
+
+/// A game found on the local network.
+#[derive(Clone, Debug)]
+pub struct LanServer {
+    pub id: LanServerId,
+    pub edition: Edition,
+    pub address: ServerAddress,
+    pub motd: String,
+    /// World name, game mode, version and player count, where the edition reports them.
+    pub details: Option<String>,
+    pub last_seen: Instant,
+}
+
+/// Listens for LAN games on background threads until dropped.
+pub struct LanDiscovery {
+    sightings: Receiver<LanServer>,
+    stop: Arc<AtomicBool>,
+}
+
+impl LanDiscovery {
+    /// Start both listeners. A Java listener that can't bind its port (another game already
+    /// holds it) just leaves Java discovery off.
+    pub fn start() -> Result<Self, NetError> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let bedrock = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        bedrock.set_broadcast(true)?;
+        bedrock.set_read_timeout(Some(POLL_INTERVAL))?;
+        let port = Edition::Bedrock.default_port();
+        // loopback too, since broadcasts don't reach servers on this machine everywhere
+        let targets = vec![
+            SocketAddr::from((Ipv4Addr::BROADCAST, port)),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+        ];
+        let (bedrock_tx, bedrock_stop) = (tx.clone(), stop.clone());
+        std::thread::Builder::new()
+            .name("lan-bedrock".into())
+            .spawn(move || bedrock_discovery(bedrock, targets, bedrock_tx, bedrock_stop))?;
+
+        match java_listener() {
+            Ok(java) => {
+                let java_stop = stop.clone();
+                std::thread::Builder::new()
+                    .name("lan-java".into())
+                    .spawn(move || java_discovery(java, tx, java_stop))?;
+            }
+            Err(err) => warn!("Java LAN discovery is off: {}", err),
+        }
+
+        Ok(Self {
+            sightings: rx,
+            stop,
+        })
+    }
+
+    /// Servers heard from since the last call.
+    pub fn poll(&self) -> impl Iterator<Item = LanServer> + '_ {
+        self.sightings.try_iter()
+    }
+}
+
+impl Drop for LanDiscovery {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn java_listener() -> Result<UdpSocket, NetError> {
+    let socket = shared_udp_socket(JAVA_LAN_PORT)?;
+    socket.join_multicast_v4(&JAVA_LAN_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    Ok(socket)
+}
+
+/// Bind `port` so that other programs can listen on it as well: the game itself and every
+/// other client on this machine want the Java announcements too. std has no way to set
+/// socket options before binding, so the socket is set up by hand.
+#[cfg(unix)]
+fn shared_udp_socket(port: u16) -> std::io::Result<UdpSocket> {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // owned from here on, so it is closed on every error below
+    let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+
+    let on: libc::c_int = 1;
+    for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+        let result = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                option,
+                (&raw const on).cast(),
+                size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    let mut addr: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+    addr.sin_family = libc::AF_INET as libc::sa_family_t;
+    addr.sin_port = port.to_be();
+    addr.sin_addr.s_addr = u32::from(Ipv4Addr::UNSPECIFIED).to_be();
+    let result = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            (&raw const addr).cast(),
+            size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+/// Windows lets a UDP port be shared without asking, as long as nobody binds it exclusively.
+#[cfg(not(unix))]
+fn shared_udp_socket(port: u16) -> std::io::Result<UdpSocket> {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+}
+
+fn bedrock_discovery(
+    socket: UdpSocket,
+    targets: Vec<SocketAddr>,
+    sightings: Sender<LanServer>,
+    stop: Arc<AtomicBool>,
+) {
+    let guid = fastrand::u64(..);
+    let started = Instant::now();
+    let mut last_ping: Option<Instant> = None;
+    let mut buf = [0u8; 1500];
+
+    while !stop.load(Ordering::Relaxed) {
+        if last_ping.is_none_or(|t| t.elapsed() >= PING_INTERVAL) {
+            let ping = unconnected_ping(started.elapsed().as_millis() as i64, guid);
+            for &target in &targets {
+                if let Err(err) = socket.send_to(&ping, target) {
+                    debug!("LAN ping to {} failed: {}", target, err);
+                }
+            }
+            last_ping = Some(Instant::now());
+        }
+
+        let Ok((len, from)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let motd = match parse_unconnected_pong(&buf[..len]).map(|m| BedrockMotd::parse(&m)) {
+            Ok(Ok(motd)) => motd,
+            Ok(Err(err)) => {
+                debug!("Ignoring LAN pong from {}: {}", from, err);
+                continue;
+            }
+            Err(_) => continue,
+        };
+        let details = format!(
+            "{} - {} - {} - {}/{} players",
+            motd.sub_motd, motd.game_mode, motd.version, motd.players, motd.max_players
+        );
+        let server = LanServer {
+            id: LanServerId::Bedrock(motd.server_guid),
+            edition: Edition::Bedrock,
+            address: ServerAddress {
+                host: from.ip().to_string(),
+                port: motd.port_v4.unwrap_or(from.port()),
+            },
+            motd: motd.motd,
+            details: Some(details),
+            last_seen: Instant::now(),
+        };
+        if sightings.send(server).is_err() {
+            return;
+        }
+    }
+}
+
+fn java_discovery(socket: UdpSocket, sightings: Sender<LanServer>, stop: Arc<AtomicBool>) {
+    let mut buf = [0u8; 1024];
+    while !stop.load(Ordering::Relaxed) {
+        let Ok((len, from)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(&buf[..len]);
+        let Some((motd, port)) = parse_java_announcement(&text) else {
+            debug!("Ignoring LAN announcement from {}: {:?}", from, text);
+            continue;
+        };
+        let address = ServerAddress {
+            host: from.ip().to_string(),
+            port,
+        };
+        let server = LanServer {
+            id: LanServerId::Java {
+                address: address.clone(),
+                motd: motd.clone(),
+            },
+            edition: Edition::Java,
+            address,
+            motd,
+            details: None,
+            last_seen: Instant::now(),
+        };
+        if sightings.send(server).is_err() {
+            return;
+        }
+    }
+}
+
+/// Pretends to be a LAN game until dropped.
+pub struct LanAnnouncer {
+    stop: Arc<AtomicBool>,
+}
+
+impl LanAnnouncer {
+    /// Send Java announcements for a game on `port` of this machine.
+    pub fn java(motd: &str, port: u16) -> Result<Self, NetError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_multicast_loop_v4(true)?;
+        let message = java_announcement(motd, port);
+        let target = SocketAddrV4::new(JAVA_LAN_GROUP, JAVA_LAN_PORT);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        std::thread::Builder::new()
+            .name("lan-announce-java".into())
+            .spawn(move || {
+                while !thread_stop.load(Ordering::Relaxed) {
+                    if let Err(err) = socket.send_to(message.as_bytes(), target) {
+                        warn!("LAN announcement failed: {}", err);
+                    }
+                    std::thread::sleep(PING_INTERVAL);
+                }
+            })?;
+        Ok(Self { stop })
+    }
+
+    /// Answer Bedrock unconnected pings on `port` with `motd`.
+    pub fn bedrock(motd: BedrockMotd, port: u16) -> Result<Self, NetError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        std::thread::Builder::new()
+            .name("lan-announce-bedrock".into())
+            .spawn(move || {
+                let mut buf = [0u8; 1500];
+                let status = motd.to_string();
+                while !thread_stop.load(Ordering::Relaxed) {
+                    let Ok((len, from)) = socket.recv_from(&mut buf) else {
+                        continue;
+                    };
+                    let mut r = PacketReader::new(&buf[..len]);
+                    let is_ping = matches!(
+                        r.u8(),
+                        Ok(raknet::ids::UNCONNECTED_PING
+                            | raknet::ids::UNCONNECTED_PING_OPEN_CONNECTIONS)
+                    );
+                    let Ok(time) = r.i64() else {
+                        continue;
+                    };
+                    if !is_ping {
+                        continue;
+                    }
+                    let mut pong = PacketWriter::new();
+                    pong.u8(raknet::ids::UNCONNECTED_PONG)
+                        .i64(time)
+                        .u64(motd.server_guid)
+                        .bytes(&MAGIC)
+                        .u16(status.len() as u16)
+                        .bytes(status.as_bytes());
+                    let _ = socket.send_to(&pong.buf, from);
+                }
+            })?;
+        Ok(Self { stop })
+    }
+}
+
+impl Drop for LanAnnouncer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Games currently visible on the LAN, in the order they were first seen.
+#[derive(Resource)]
+pub struct LanServers {
+    pub servers: Vec<LanServer>,
+    /// Whether to look for games; toggled from the console.
+    pub enabled: bool,
+    discovery: Option<LanDiscovery>,
+}
+
+impl Default for LanServers {
+    fn default() -> Self {
+        Self {
+            servers: Vec::new(),
+            enabled: true,
+            discovery: None,
+        }
+    }
+}
+
+impl LanServers {
+    /// Note a sighting. Known games are updated where they are so the list doesn't jump around
+    /// under the cursor; new ones go at the end.
+    pub fn record(&mut self, server: LanServer) {
+        match self.servers.iter_mut().find(|known| known.id == server.id) {
+            Some(known) => *known = server,
+            None => self.servers.push(server),
+        }
+    }
+}
+
+/// Start or stop discovery as `LanServers::enabled` says, and keep the list current.
+pub fn lan_discovery_system(mut lan: ResMut<LanServers>) {
+    let lan = &mut *lan;
+    match (&lan.discovery, lan.enabled) {
+        (None, true) => match LanDiscovery::start() {
+            Ok(discovery) => lan.discovery = Some(discovery),
+            Err(err) => {
+                error!("Cannot look for LAN games: {}", err);
+                lan.enabled = false;
+            }
+        },
+        (Some(_), false) => {
+            lan.discovery = None;
+            lan.servers.clear();
+        }
+        _ => {}
+    }
+
+    if let Some(discovery) = &lan.discovery {
+        let sightings: Vec<LanServer> = discovery.poll().collect();
+        for server in sightings {
+            lan.record(server);
+        }
+    }
+    lan.servers
+        .retain(|server| server.last_seen.elapsed() < SERVER_TIMEOUT);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn sighting(id: LanServerId, motd: &str) -> LanServer {
+        LanServer {
+            id,
+            edition: Edition::Bedrock,
+            address: ServerAddress {
+                host: "192.168.1.2".into(),
+                port: 19132,
+            },
+            motd: motd.into(),
+            details: None,
+            last_seen: Instant::now(),
+        }
+    }
+
+    fn free_port() -> u16 {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.local_addr().unwrap().port()
+    }
+
+    fn motd(name: &str, guid: u64, port: u16) -> BedrockMotd {
+        BedrockMotd {
+            edition: "MCPE".into(),
+            motd: name.into(),
+            protocol: 800,
+            version: "1.21.80".into(),
+            players: 1,
+            max_players: 10,
+            server_guid: guid,
+            sub_motd: "Bedrock level".into(),
+            game_mode: "Survival".into(),
+            port_v4: Some(port),
+            port_v6: None,
+        }
+    }
+
+    #[test]
+    fn motd_round_trips() {
+        let motd = motd("A world", 42, 19132);
+        assert_eq!(BedrockMotd::parse(&motd.to_string()), Ok(motd));
+        assert_eq!(
+            parse_java_announcement(&java_announcement("A world", 25565)),
+            Some(("A world".into(), 25565))
+        );
+    }
+
+    #[test]
+    fn sightings_update_in_place() {
+        let mut lan = LanServers::default();
+        lan.record(sighting(LanServerId::Bedrock(1), "first"));
+        lan.record(sighting(LanServerId::Bedrock(2), "second"));
+        lan.record(sighting(LanServerId::Bedrock(1), "first, renamed"));
+
+        let motds: Vec<&str> = lan.servers.iter().map(|s| s.motd.as_str()).collect();
+        assert_eq!(motds, ["first, renamed", "second"]);
+    }
+
+    #[test]
+    fn bedrock_servers_are_found_once_each() {
+        let (port_a, port_b) = (free_port(), free_port());
+        let _a = LanAnnouncer::bedrock(motd("A", 1, port_a), port_a).unwrap();
+        let _b = LanAnnouncer::bedrock(motd("B", 2, port_b), port_b).unwrap();
+
+        // asking A twice is what happens when both the broadcast and the loopback ping reach it
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.set_read_timeout(Some(POLL_INTERVAL)).unwrap();
+        let targets = [port_a, port_b, port_a]
+            .map(|port| SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+            .to_vec();
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        std::thread::spawn(move || bedrock_discovery(socket, targets, tx, thread_stop));
+
+        let mut lan = LanServers::default();
+        let mut first_seen = Vec::new();
+        for _ in 0..3 {
+            let server = rx.recv_timeout(WAIT).expect("no pong");
+            if !first_seen.contains(&server.id) {
+                first_seen.push(server.id.clone());
+            }
+            lan.record(server);
+        }
+        stop.store(true, Ordering::Relaxed);
+
+        let ids: Vec<LanServerId> = lan.servers.iter().map(|s| s.id.clone()).collect();
+        assert_eq!(ids, first_seen);
+        assert_eq!(lan.servers.len(), 2);
+        for server in &lan.servers {
+            let (name, port) = match server.id {
+                LanServerId::Bedrock(1) => ("A", port_a),
+                LanServerId::Bedrock(2) => ("B", port_b),
+                ref id => panic!("unexpected server {:?}", id),
+            };
+            assert_eq!(server.motd, name);
+            assert_eq!(server.address.port, port);
+        }
+    }
+
+    #[test]
+    fn java_announcements_reach_every_listener() {
+        // the port is shared, so a second listener (the game, another client) doesn't lock us out
+        let first = java_listener().unwrap();
+        let second = java_listener().unwrap();
+        let _announcer = LanAnnouncer::java("A Java world", 25599).unwrap();
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        std::thread::spawn(move || java_discovery(first, tx, thread_stop));
+
+        let deadline = Instant::now() + WAIT;
+        let server = loop {
+            let server = rx
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .expect("no announcement");
+            if server.address.port == 25599 {
+                break server;
+            }
+        };
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(server.motd, "A Java world");
+        assert_eq!(
+            server.id,
+            LanServerId::Java {
+                address: server.address.clone(),
+                motd: "A Java world".into(),
+            }
+        );
+
+        let mut buf = [0u8; 1024];
+        let deadline = Instant::now() + WAIT;
+        while Instant::now() < deadline {
+            let Ok((len, _)) = second.recv_from(&mut buf) else {
+                continue;
+            };
+            let text = String::from_utf8_lossy(&buf[..len]);
+            if parse_java_announcement(&text) == Some(("A Java world".into(), 25599)) {
+                return;
+            }
+        }
+        panic!("the second listener heard nothing");
+    }
+}
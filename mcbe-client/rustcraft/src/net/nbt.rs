@@ -0,0 +1,202 @@
+use crate::net::NetError;
+use crate::net::codec::PacketReader;
+
+/// Nesting limit, as enforced by vanilla.
+const MAX_DEPTH: usize = 512;
+
+/// A decoded NBT tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Nbt {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Nbt>),
+    Compound(Vec<(String, Nbt)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Nbt {
+    pub fn get(&self, key: &str) -> Option<&Nbt> {
+        match self {
+            Nbt::Compound(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Nbt::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Nbt::Byte(v) => Some(*v as i32),
+            Nbt::Short(v) => Some(*v as i32),
+            Nbt::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> &[Nbt] {
+        match self {
+            Nbt::List(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+/// How numbers and lengths are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    /// Big-endian, fixed-width (Java).
+    Java,
+    /// Little-endian, with ints, longs and lengths as VarInts (Bedrock's network format).
+    BedrockNetwork,
+}
+
+/// Read a nameless root tag, as used on the network since 1.20.2. A lone end tag, which
+/// stands for no data, reads as an empty compound.
+pub fn read_network_nbt(r: &mut PacketReader) -> Result<Nbt, NetError> {
+    match r.u8()? {
+        0 => Ok(Nbt::Compound(Vec::new())),
+        tag => read_payload(r, Encoding::Java, tag, 0),
+    }
+}
+
+/// Read a Bedrock network NBT root tag, dropping its (normally empty) name.
+pub fn read_bedrock_nbt(r: &mut PacketReader) -> Result<Nbt, NetError> {
+    let tag = r.u8()?;
+    read_nbt_string(r, Encoding::BedrockNetwork)?;
+    read_payload(r, Encoding::BedrockNetwork, tag, 0)
+}
+
+fn read_nbt_string(r: &mut PacketReader, encoding: Encoding) -> Result<String, NetError> {
+    let len = match encoding {
+        Encoding::Java => r.u16()? as usize,
+        Encoding::BedrockNetwork => r.var_u32()? as usize,
+    };
+    // modified UTF-8; the differences only matter for NUL and astral characters
+    Ok(String::from_utf8_lossy(r.bytes(len)?).into_owned())
+}
+
+fn read_int(r: &mut PacketReader, encoding: Encoding) -> Result<i32, NetError> {
+    match encoding {
+        Encoding::Java => r.i32(),
+        Encoding::BedrockNetwork => r.zigzag32(),
+    }
+}
+
+fn read_len(r: &mut PacketReader, encoding: Encoding) -> Result<usize, NetError> {
+    let len = read_int(r, encoding)?;
+    if len < 0 || len as usize > r.remaining() {
+        return Err(NetError::Protocol(format!("bad NBT length {}", len)));
+    }
+    Ok(len as usize)
+}
+
+fn read_payload(
+    r: &mut PacketReader,
+    encoding: Encoding,
+    tag: u8,
+    depth: usize,
+) -> Result<Nbt, NetError> {
+    if depth > MAX_DEPTH {
+        return Err(NetError::Protocol("NBT nested too deeply".into()));
+    }
+    let java = encoding == Encoding::Java;
+    Ok(match tag {
+        1 => Nbt::Byte(r.i8()?),
+        2 => Nbt::Short(if java { r.i16()? } else { r.i16_le()? }),
+        3 => Nbt::Int(read_int(r, encoding)?),
+        4 => Nbt::Long(if java { r.i64()? } else { r.zigzag64()? }),
+        5 => Nbt::Float(if java { r.f32()? } else { r.f32_le()? }),
+        6 => Nbt::Double(if java { r.f64()? } else { r.f64_le()? }),
+        7 => {
+            let len = read_len(r, encoding)?;
+            Nbt::ByteArray(r.bytes(len)?.to_vec())
+        }
+        8 => Nbt::String(read_nbt_string(r, encoding)?),
+        9 => {
+            let item_tag = r.u8()?;
+            let len = read_len(r, encoding)?;
+            let mut items = Vec::with_capacity(len.min(1024));
+            for _ in 0..len {
+                items.push(read_payload(r, encoding, item_tag, depth + 1)?);
+            }
+            Nbt::List(items)
+        }
+        10 => {
+            let mut entries = Vec::new();
+            loop {
+                let tag = r.u8()?;
+                if tag == 0 {
+                    break;
+                }
+                let name = read_nbt_string(r, encoding)?;
+                entries.push((name, read_payload(r, encoding, tag, depth + 1)?));
+            }
+            Nbt::Compound(entries)
+        }
+        11 => {
+            let len = read_len(r, encoding)?;
+            Nbt::IntArray(
+                (0..len)
+                    .map(|_| read_int(r, encoding))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        12 => {
+            let len = read_len(r, encoding)?;
+            Nbt::LongArray(
+                (0..len)
+                    .map(|_| if java { r.i64() } else { r.zigzag64() })
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        other => return Err(NetError::Protocol(format!("unknown NBT tag {}", other))),
+    })
+}
+
+/// Flatten a text component to plain text (translations are shown as their key and arguments).
+pub fn text_to_plain(component: &Nbt) -> String {
+    let mut out = String::new();
+    append_text(component, &mut out);
+    out
+}
+
+fn append_text(component: &Nbt, out: &mut String) {
+    match component {
+        Nbt::String(s) => out.push_str(s),
+        Nbt::List(items) => items.iter().for_each(|item| append_text(item, out)),
+        Nbt::Compound(_) => {
+            if let Some(text) = component.get("text").and_then(Nbt::as_str) {
+                out.push_str(text);
+            } else if let Some(key) = component.get("translate").and_then(Nbt::as_str) {
+                out.push_str(key);
+                let args = component.get("with").map(Nbt::as_list).unwrap_or_default();
+                if !args.is_empty() {
+                    out.push_str(" [");
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            out.push_str(", ");
+                        }
+                        append_text(arg, out);
+                    }
+                    out.push(']');
+                }
+            }
+            if let Some(extra) = component.get("extra") {
+                append_text(extra, out);
+            }
+        }
+        _ => {}
+    }
+}
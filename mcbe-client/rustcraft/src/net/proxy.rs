@@ -0,0 +1,1128 @@
+//! Relay between a client and a server that decodes the traffic and can drop or rewrite packets.
+//!
+//! Java connections are decoded until encryption starts (offline servers never enable it);
+//! Bedrock traffic is decoded down to game packets until the server starts encryption.
+
+use crossbeam_channel::{Receiver, Sender};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::net::bedrock::GamePacket;
+use crate::net::bedrock::versions::BedrockVersion;
+use crate::net::capture::{Direction, from_hex, to_hex};
+use crate::net::codec::PacketReader;
+use crate::net::java::{self, JavaState, ids};
+use crate::net::raknet::{self, Datagram, Reassembler};
+use crate::net::{Edition, NetError, ServerAddress, bedrock};
+
+/// How often blocking sockets wake up to check whether the proxy was stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Bedrock clients that send nothing for this long are forgotten.
+const UDP_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_DATAGRAM_SIZE: usize = 1500;
+
+/// Where the proxy listens and where it forwards to.
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    pub edition: Edition,
+    pub listen: SocketAddr,
+    pub upstream: ServerAddress,
+}
+
+/// What happened to a packet on its way through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Forwarded,
+    Dropped,
+    Modified,
+}
+
+impl Verdict {
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::Forwarded => "forwarded",
+            Verdict::Dropped => "dropped",
+            Verdict::Modified => "modified",
+        }
+    }
+}
+
+/// One packet seen by the proxy.
+#[derive(Clone, Debug)]
+pub struct ProxyPacket {
+    pub connection: u32,
+    /// Time since the proxy started.
+    pub time: Duration,
+    pub direction: Direction,
+    /// Protocol state, e.g. `play` for Java or `game` for Bedrock game packets.
+    pub state: &'static str,
+    pub id: i32,
+    pub name: Option<&'static str>,
+    /// Payload after any rule was applied.
+    pub payload: Vec<u8>,
+    pub summary: Option<String>,
+    pub verdict: Verdict,
+}
+
+#[derive(Clone, Debug)]
+pub enum ProxyEvent {
+    Opened {
+        connection: u32,
+        peer: SocketAddr,
+    },
+    Packet(ProxyPacket),
+    /// Something worth knowing that isn't a packet, such as encryption starting.
+    Note {
+        connection: u32,
+        message: String,
+    },
+    Closed {
+        connection: u32,
+        reason: String,
+    },
+}
+
+/// Which packets a rule applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PacketMatch {
+    Id(i32),
+    Name(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleAction {
+    Drop,
+    /// Replace every occurrence of `find` in the payload.
+    Replace {
+        find: Vec<u8>,
+        replace: Vec<u8>,
+    },
+}
+
+/// A drop or rewrite rule, written as
+/// `drop <in|out|both> <id|name>` or `replace <in|out|both> <id|name> <find-hex> <replace-hex>`.
+#[derive(Clone, Debug)]
+pub struct ProxyRule {
+    pub enabled: bool,
+    /// `None` matches both directions.
+    pub direction: Option<Direction>,
+    pub packet: PacketMatch,
+    pub action: RuleAction,
+    /// Packets this rule acted on.
+    pub hits: u64,
+}
+
+impl ProxyRule {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (action, rest) = words.split_first().ok_or("empty rule")?;
+        let [direction, packet, args @ ..] = rest else {
+            return Err(format!("'{}': expected a direction and a packet", line));
+        };
+        let direction = match *direction {
+            "in" => Some(Direction::Inbound),
+            "out" => Some(Direction::Outbound),
+            "both" => None,
+            other => {
+                return Err(format!(
+                    "unknown direction '{}' (expected in, out or both)",
+                    other
+                ));
+            }
+        };
+        let packet = parse_packet_match(packet);
+        let action = match (*action, args) {
+            ("drop", []) => RuleAction::Drop,
+            ("replace", [find, replace]) => RuleAction::Replace {
+                find: from_hex(find).map_err(|err| err.to_string())?,
+                replace: from_hex(replace).map_err(|err| err.to_string())?,
+            },
+            ("drop", _) => return Err("usage: drop <in|out|both> <id|name>".into()),
+            ("replace", _) => {
+                return Err(
+                    "usage: replace <in|out|both> <id|name> <find-hex> <replace-hex>".into(),
+                );
+            }
+            (other, _) => {
+                return Err(format!(
+                    "unknown rule '{}' (expected drop or replace)",
+                    other
+                ));
+            }
+        };
+        if let RuleAction::Replace { find, .. } = &action
+            && find.is_empty()
+        {
+            return Err("replace needs something to find".into());
+        }
+        Ok(Self {
+            enabled: true,
+            direction,
+            packet,
+            action,
+            hits: 0,
+        })
+    }
+
+    /// The rule in the syntax `parse` accepts.
+    pub fn describe(&self) -> String {
+        let direction = match self.direction {
+            Some(Direction::Inbound) => "in",
+            Some(Direction::Outbound) => "out",
+            None => "both",
+        };
+        let packet = match &self.packet {
+            PacketMatch::Id(id) => format!("0x{:02X}", id),
+            PacketMatch::Name(name) => name.clone(),
+        };
+        match &self.action {
+            RuleAction::Drop => format!("drop {} {}", direction, packet),
+            RuleAction::Replace { find, replace } => format!(
+                "replace {} {} {} {}",
+                direction,
+                packet,
+                to_hex(find),
+                to_hex(replace)
+            ),
+        }
+    }
+
+    fn matches(&self, direction: Direction, id: i32, name: Option<&str>) -> bool {
+        self.enabled
+            && self.direction.is_none_or(|d| d == direction)
+            && match &self.packet {
+                PacketMatch::Id(rule_id) => *rule_id == id,
+                PacketMatch::Name(rule_name) => name == Some(rule_name.as_str()),
+            }
+    }
+}
+
+fn parse_packet_match(text: &str) -> PacketMatch {
+    let id = match text.strip_prefix("0x") {
+        Some(hex) => i32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    };
+    id.map_or_else(|| PacketMatch::Name(text.to_string()), PacketMatch::Id)
+}
+
+/// Read rules from a file, one per line; `#` starts a comment.
+pub fn load_rules(path: &Path) -> Result<Vec<ProxyRule>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            ProxyRule::parse(line)
+                .map_err(|err| format!("{} line {}: {}", path.display(), i + 1, err))
+        })
+        .collect()
+}
+
+/// Rules shared between the proxy threads and whoever edits them.
+pub type RuleSet = Arc<Mutex<Vec<ProxyRule>>>;
+
+/// Apply the first matching rule. Without `can_modify` replace rules are left alone.
+fn apply_rules(
+    rules: &RuleSet,
+    direction: Direction,
+    id: i32,
+    name: Option<&str>,
+    payload: &mut Vec<u8>,
+    can_modify: bool,
+) -> Verdict {
+    let Ok(mut rules) = rules.lock() else {
+        return Verdict::Forwarded;
+    };
+    for rule in rules.iter_mut() {
+        if !rule.matches(direction, id, name) {
+            continue;
+        }
+        match &rule.action {
+            RuleAction::Drop => {
+                rule.hits += 1;
+                return Verdict::Dropped;
+            }
+            RuleAction::Replace { find, replace } if can_modify => {
+                if let Some(replaced) = replace_all(payload, find, replace) {
+                    *payload = replaced;
+                    rule.hits += 1;
+                    return Verdict::Modified;
+                }
+            }
+            RuleAction::Replace { .. } => {}
+        }
+    }
+    Verdict::Forwarded
+}
+
+/// `haystack` with every `find` replaced, or `None` if there was nothing to replace.
+fn replace_all(haystack: &[u8], find: &[u8], replace: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(haystack.len());
+    let mut found = false;
+    let mut i = 0;
+    while i < haystack.len() {
+        if haystack[i..].starts_with(find) {
+            out.extend_from_slice(replace);
+            i += find.len();
+            found = true;
+        } else {
+            out.push(haystack[i]);
+            i += 1;
+        }
+    }
+    found.then_some(out)
+}
+
+/// State every relay thread needs.
+struct Shared {
+    events: Sender<ProxyEvent>,
+    rules: RuleSet,
+    stop: Arc<AtomicBool>,
+    started: Instant,
+    next_connection: AtomicU32,
+}
+
+impl Shared {
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    fn new_connection(&self) -> u32 {
+        self.next_connection.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn emit(&self, event: ProxyEvent) {
+        let _ = self.events.send(event);
+    }
+
+    fn note(&self, connection: u32, message: impl Into<String>) {
+        self.emit(ProxyEvent::Note {
+            connection,
+            message: message.into(),
+        });
+    }
+}
+
+/// A running proxy; stops when dropped.
+pub struct Proxy {
+    pub config: ProxyConfig,
+    pub events: Receiver<ProxyEvent>,
+    pub rules: RuleSet,
+    stop: Arc<AtomicBool>,
+}
+
+impl Proxy {
+    pub fn start(config: ProxyConfig, rules: Vec<ProxyRule>) -> Result<Self, NetError> {
+        let upstream = (config.upstream.host.as_str(), config.upstream.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| NetError::Protocol(format!("{} did not resolve", config.upstream)))?;
+        let (event_tx, event_rx) = crossbeam_channel::unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let rules: RuleSet = Arc::new(Mutex::new(rules));
+        let shared = Arc::new(Shared {
+            events: event_tx,
+            rules: rules.clone(),
+            stop: stop.clone(),
+            started: Instant::now(),
+            next_connection: AtomicU32::new(0),
+        });
+
+        match config.edition {
+            Edition::Java => {
+                let listener = TcpListener::bind(config.listen)?;
+                listener.set_nonblocking(true)?;
+                std::thread::Builder::new()
+                    .name("proxy-java".into())
+                    .spawn(move || java_accept_loop(listener, upstream, shared))?;
+            }
+            Edition::Bedrock => {
+                let socket = UdpSocket::bind(config.listen)?;
+                socket.set_read_timeout(Some(POLL_INTERVAL))?;
+                std::thread::Builder::new()
+                    .name("proxy-bedrock".into())
+                    .spawn(move || bedrock_relay_loop(Arc::new(socket), upstream, shared))?;
+            }
+        }
+
+        Ok(Self {
+            config,
+            events: event_rx,
+            rules,
+            stop,
+        })
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Protocol state of one Java connection, shared by its two pipes.
+struct JavaLink {
+    state: JavaState,
+    compression: Option<usize>,
+    /// Once encryption starts bytes are passed through undecoded.
+    encrypted: bool,
+    closed: bool,
+}
+
+fn java_accept_loop(listener: TcpListener, upstream: SocketAddr, shared: Arc<Shared>) {
+    while !shared.stopped() {
+        let (client, peer) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                shared.note(0, format!("accept failed: {}", err));
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        let connection = shared.new_connection();
+        shared.emit(ProxyEvent::Opened { connection, peer });
+        if let Err(err) = start_java_pipes(client, upstream, connection, &shared) {
+            shared.emit(ProxyEvent::Closed {
+                connection,
+                reason: format!("cannot reach upstream: {}", err),
+            });
+        }
+    }
+}
+
+fn start_java_pipes(
+    client: TcpStream,
+    upstream: SocketAddr,
+    connection: u32,
+    shared: &Arc<Shared>,
+) -> Result<(), NetError> {
+    let server = TcpStream::connect_timeout(&upstream, CONNECT_TIMEOUT)?;
+    for stream in [&client, &server] {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    }
+    let link = Arc::new(Mutex::new(JavaLink {
+        state: JavaState::Handshake,
+        compression: None,
+        encrypted: false,
+        closed: false,
+    }));
+
+    let pipes = [
+        (
+            client.try_clone()?,
+            server.try_clone()?,
+            Direction::Outbound,
+        ),
+        (server, client, Direction::Inbound),
+    ];
+    for (from, to, direction) in pipes {
+        let link = link.clone();
+        let shared = shared.clone();
+        std::thread::Builder::new()
+            .name(format!("proxy-{}-{:?}", connection, direction))
+            .spawn(move || {
+                let reason = match java_pipe(&from, &to, direction, &link, connection, &shared) {
+                    Ok(()) => "closed".to_string(),
+                    Err(err) => err.to_string(),
+                };
+                let _ = from.shutdown(Shutdown::Both);
+                let _ = to.shutdown(Shutdown::Both);
+                let first = link
+                    .lock()
+                    .map(|mut link| !std::mem::replace(&mut link.closed, true))
+                    .unwrap_or(false);
+                if first {
+                    let side = match direction {
+                        Direction::Outbound => "client",
+                        Direction::Inbound => "server",
+                    };
+                    shared.emit(ProxyEvent::Closed {
+                        connection,
+                        reason: format!("{} side: {}", side, reason),
+                    });
+                }
+            })?;
+    }
+    Ok(())
+}
+
+/// Copy frames from one side to the other, decoding and applying rules on the way.
+fn java_pipe(
+    mut from: &TcpStream,
+    mut to: &TcpStream,
+    direction: Direction,
+    link: &Mutex<JavaLink>,
+    connection: u32,
+    shared: &Shared,
+) -> Result<(), NetError> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 16 * 1024];
+    while !shared.stopped() {
+        match from.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        loop {
+            let (state, compression, encrypted) = {
+                let link = link
+                    .lock()
+                    .map_err(|_| NetError::Protocol("proxy state poisoned".into()))?;
+                (link.state, link.compression, link.encrypted)
+            };
+            if encrypted {
+                to.write_all(&buf)?;
+                buf.clear();
+                break;
+            }
+            let Some(frame) = java::take_frame(&mut buf, compression)? else {
+                break;
+            };
+
+            let name = java::packet_name(state, direction, frame.id);
+            let mut payload = frame.payload;
+            let verdict = apply_rules(&shared.rules, direction, frame.id, name, &mut payload, true);
+            // later frames may depend on this one, so follow it before forwarding
+            if verdict != Verdict::Dropped {
+                follow_java_state(
+                    link, state, direction, frame.id, &payload, connection, shared,
+                )?;
+            }
+            match verdict {
+                Verdict::Forwarded => to.write_all(&frame.bytes)?,
+                Verdict::Modified => {
+                    to.write_all(&java::encode_frame(frame.id, &payload, compression)?)?
+                }
+                Verdict::Dropped => {}
+            }
+
+            shared.emit(ProxyEvent::Packet(ProxyPacket {
+                connection,
+                time: shared.started.elapsed(),
+                direction,
+                state: state.name(),
+                id: frame.id,
+                name,
+                summary: java::describe_packet(state, direction, frame.id, &payload),
+                payload,
+                verdict,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Track state changes and compression so the rest of the stream can be framed.
+fn follow_java_state(
+    link: &Mutex<JavaLink>,
+    state: JavaState,
+    direction: Direction,
+    id: i32,
+    payload: &[u8],
+    connection: u32,
+    shared: &Shared,
+) -> Result<(), NetError> {
+    use Direction::{Inbound, Outbound};
+    let mut link = link
+        .lock()
+        .map_err(|_| NetError::Protocol("proxy state poisoned".into()))?;
+    match (state, direction, id) {
+        (JavaState::Handshake, Outbound, ids::handshake::INTENTION) => {
+            let mut r = PacketReader::new(payload);
+            let (_protocol, _host, _port) = (r.varint()?, r.string()?, r.u16()?);
+            link.state = match r.varint()? {
+                1 => JavaState::Status,
+                _ => JavaState::Login,
+            };
+        }
+        (JavaState::Login, Inbound, ids::login::SET_COMPRESSION) => {
+            let threshold = PacketReader::new(payload).varint()?;
+            link.compression = (threshold >= 0).then_some(threshold as usize);
+        }
+        (JavaState::Login, Outbound, ids::login::ENCRYPTION_RESPONSE) => {
+            link.encrypted = true;
+            shared.note(
+                connection,
+                "encryption enabled, the rest of this connection is passed through undecoded",
+            );
+        }
+        (JavaState::Login, Outbound, ids::login::LOGIN_ACKNOWLEDGED) => {
+            link.state = JavaState::Configuration;
+        }
+        (JavaState::Configuration, Outbound, ids::config::ACKNOWLEDGE_FINISH) => {
+            link.state = JavaState::Play;
+        }
+        (JavaState::Play, Outbound, ids::play::ACKNOWLEDGE_CONFIGURATION) => {
+            link.state = JavaState::Configuration;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// What the proxy knows about one Bedrock client.
+struct BedrockPeer {
+    connection: u32,
+    upstream: Arc<UdpSocket>,
+    link: Arc<Mutex<BedrockLink>>,
+    alive: Arc<AtomicBool>,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+struct BedrockLink {
+    /// Set once `network_settings` has been seen.
+    compression: bool,
+    encrypted: bool,
+    /// Protocol the client asked for in `request_network_settings`.
+    version: Option<&'static BedrockVersion>,
+    /// Split messages being put back together, per direction.
+    inbound: Reassembler,
+    outbound: Reassembler,
+    /// Replace rules can't be applied to RakNet traffic; say so once.
+    warned_modify: bool,
+}
+
+fn bedrock_relay_loop(listener: Arc<UdpSocket>, upstream: SocketAddr, shared: Arc<Shared>) {
+    let mut peers: HashMap<SocketAddr, BedrockPeer> = HashMap::new();
+    let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+    while !shared.stopped() {
+        peers.retain(|_, peer| {
+            let alive = peer.last_seen.elapsed() < UDP_IDLE_TIMEOUT;
+            if !alive {
+                peer.alive.store(false, Ordering::Relaxed);
+                shared.emit(ProxyEvent::Closed {
+                    connection: peer.connection,
+                    reason: "idle".into(),
+                });
+            }
+            alive
+        });
+
+        let (len, from) = match listener.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue;
+            }
+            Err(err) => {
+                shared.note(0, format!("receive failed: {}", err));
+                continue;
+            }
+        };
+
+        let peer = match peers.entry(from) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match open_bedrock_peer(&listener, from, upstream, &shared) {
+                Ok(peer) => entry.insert(peer),
+                Err(err) => {
+                    shared.note(0, format!("cannot relay for {}: {}", from, err));
+                    continue;
+                }
+            },
+        };
+        peer.last_seen = Instant::now();
+        let datagram = &buf[..len];
+        if inspect_datagram(
+            datagram,
+            Direction::Outbound,
+            &peer.link,
+            peer.connection,
+            &shared,
+        ) {
+            let _ = peer.upstream.send(datagram);
+        }
+    }
+    for peer in peers.values() {
+        peer.alive.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Open an upstream socket for a new client and relay the server's replies back to it.
+fn open_bedrock_peer(
+    listener: &Arc<UdpSocket>,
+    client: SocketAddr,
+    upstream: SocketAddr,
+    shared: &Arc<Shared>,
+) -> Result<BedrockPeer, NetError> {
+    let bind: SocketAddr = if upstream.is_ipv4() {
+        "0.0.0.0:0".parse().expect("valid address")
+    } else {
+        "[::]:0".parse().expect("valid address")
+    };
+    let socket = Arc::new(UdpSocket::bind(bind)?);
+    socket.connect(upstream)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let connection = shared.new_connection();
+    let link = Arc::new(Mutex::new(BedrockLink::default()));
+    let alive = Arc::new(AtomicBool::new(true));
+    shared.emit(ProxyEvent::Opened {
+        connection,
+        peer: client,
+    });
+
+    let (listener, thread_socket, thread_link, thread_alive, shared) = (
+        listener.clone(),
+        socket.clone(),
+        link.clone(),
+        alive.clone(),
+        shared.clone(),
+    );
+    std::thread::Builder::new()
+        .name(format!("proxy-{}-upstream", connection))
+        .spawn(move || {
+            let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+            while thread_alive.load(Ordering::Relaxed) && !shared.stopped() {
+                let len = match thread_socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(err)
+                        if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                    {
+                        continue;
+                    }
+                    Err(err) => {
+                        shared.emit(ProxyEvent::Closed {
+                            connection,
+                            reason: format!("server side: {}", err),
+                        });
+                        return;
+                    }
+                };
+                let datagram = &buf[..len];
+                if inspect_datagram(
+                    datagram,
+                    Direction::Inbound,
+                    &thread_link,
+                    connection,
+                    &shared,
+                ) {
+                    let _ = listener.send_to(datagram, client);
+                }
+            }
+        })?;
+
+    Ok(BedrockPeer {
+        connection,
+        upstream: socket,
+        link,
+        alive,
+        last_seen: Instant::now(),
+    })
+}
+
+/// Decode a datagram and report its packets; `false` if a rule dropped it.
+/// Rules act on whole datagrams, so dropping a reliable message makes the sender resend it.
+fn inspect_datagram(
+    data: &[u8],
+    direction: Direction,
+    link: &Mutex<BedrockLink>,
+    connection: u32,
+    shared: &Shared,
+) -> bool {
+    let Ok(mut guard) = link.lock() else {
+        return true;
+    };
+    let link = &mut *guard;
+    let mut packets = Vec::new();
+    match Datagram::parse(data) {
+        Ok(Datagram::Offline { id }) => {
+            packets.push((
+                "offline",
+                id as i32,
+                raknet::message_name(id),
+                data[1..].to_vec(),
+            ));
+        }
+        Ok(Datagram::Ack(_) | Datagram::Nack(_)) => return true,
+        Ok(Datagram::Frames { frames, .. }) => {
+            for frame in &frames {
+                let reassembler = match direction {
+                    Direction::Inbound => &mut link.inbound,
+                    Direction::Outbound => &mut link.outbound,
+                };
+                let message = match reassembler.push(frame) {
+                    Ok(Some(message)) => message,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        shared.note(connection, err.to_string());
+                        continue;
+                    }
+                };
+                let Some((&id, body)) = message.split_first() else {
+                    continue;
+                };
+                if id != raknet::ids::GAME_PACKET || link.encrypted {
+                    packets.push(("raknet", id as i32, raknet::message_name(id), body.to_vec()));
+                    continue;
+                }
+                match bedrock::decode_batch(body, link.compression) {
+                    Ok((_, batch)) => {
+                        for packet in batch {
+                            follow_bedrock_state(link, direction, &packet, connection, shared);
+                            packets.push((
+                                "game",
+                                packet.id as i32,
+                                bedrock::packet_name(packet.id),
+                                packet.payload,
+                            ));
+                        }
+                    }
+                    Err(err) => shared.note(connection, format!("undecodable batch: {}", err)),
+                }
+            }
+        }
+        Err(err) => {
+            shared.note(connection, format!("undecodable datagram: {}", err));
+            return true;
+        }
+    }
+
+    let mut forward = true;
+    for (state, id, name, mut payload) in packets {
+        let verdict = apply_rules(&shared.rules, direction, id, name, &mut payload, false);
+        if verdict == Verdict::Dropped {
+            forward = false;
+        }
+        if !link.warned_modify && has_replace_rule(&shared.rules, direction, id, name) {
+            link.warned_modify = true;
+            shared.note(
+                connection,
+                "replace rules are not applied to Bedrock traffic, only drop rules",
+            );
+        }
+        let summary = (state == "game")
+            .then(|| {
+                let version = link.version.unwrap_or(&bedrock::versions::LATEST);
+                bedrock::describe_packet(version, id as u32, &payload)
+            })
+            .flatten();
+        shared.emit(ProxyEvent::Packet(ProxyPacket {
+            connection,
+            time: shared.started.elapsed(),
+            direction,
+            state,
+            id,
+            name,
+            payload,
+            summary,
+            verdict,
+        }));
+    }
+    forward
+}
+
+fn has_replace_rule(rules: &RuleSet, direction: Direction, id: i32, name: Option<&str>) -> bool {
+    rules.lock().is_ok_and(|rules| {
+        rules.iter().any(|rule| {
+            matches!(rule.action, RuleAction::Replace { .. }) && rule.matches(direction, id, name)
+        })
+    })
+}
+
+fn follow_bedrock_state(
+    link: &mut BedrockLink,
+    direction: Direction,
+    packet: &GamePacket,
+    connection: u32,
+    shared: &Shared,
+) {
+    match (direction, packet.id) {
+        (Direction::Outbound, bedrock::ids::REQUEST_NETWORK_SETTINGS) => {
+            let protocol = PacketReader::new(&packet.payload).i32().unwrap_or_default();
+            link.version = BedrockVersion::find(protocol);
+            if link.version.is_none() {
+                shared.note(
+                    connection,
+                    format!(
+                        "protocol {} is not supported, packets are summarized as {}",
+                        protocol,
+                        bedrock::versions::LATEST.game_version
+                    ),
+                );
+            }
+        }
+        (Direction::Inbound, bedrock::ids::NETWORK_SETTINGS) => link.compression = true,
+        (Direction::Inbound, bedrock::ids::SERVER_TO_CLIENT_HANDSHAKE) => {
+            link.encrypted = true;
+            shared.note(
+                connection,
+                "encryption enabled, game packets after the handshake can't be decoded",
+            );
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::codec::PacketWriter;
+    use crate::net::raknet::{Frame, Reliability};
+    use std::net::Ipv4Addr;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// A loopback address nothing listens on yet.
+    fn unused_address(edition: Edition) -> SocketAddr {
+        match edition {
+            Edition::Java => TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+                .and_then(|listener| listener.local_addr()),
+            Edition::Bedrock => {
+                UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).and_then(|socket| socket.local_addr())
+            }
+        }
+        .unwrap()
+    }
+
+    fn start(edition: Edition, upstream: SocketAddr, rules: &[&str]) -> Proxy {
+        let rules = rules
+            .iter()
+            .map(|rule| ProxyRule::parse(rule).unwrap())
+            .collect();
+        let config = ProxyConfig {
+            edition,
+            listen: unused_address(edition),
+            upstream: ServerAddress {
+                host: upstream.ip().to_string(),
+                port: upstream.port(),
+            },
+        };
+        Proxy::start(config, rules).unwrap()
+    }
+
+    /// Every packet the proxy reports until `done` says enough was seen.
+    fn packets_until(proxy: &Proxy, done: impl Fn(&[ProxyPacket]) -> bool) -> Vec<ProxyPacket> {
+        let mut packets = Vec::new();
+        while !done(&packets) {
+            match proxy.events.recv_timeout(WAIT) {
+                Ok(ProxyEvent::Packet(packet)) => packets.push(packet),
+                Ok(_) => {}
+                Err(_) => panic!("only saw {:?}", packets),
+            }
+        }
+        packets
+    }
+
+    fn seen(packets: &[ProxyPacket], direction: Direction) -> Vec<(&str, Option<&str>, Verdict)> {
+        packets
+            .iter()
+            .filter(|packet| packet.direction == direction)
+            .map(|packet| (packet.state, packet.name, packet.verdict))
+            .collect()
+    }
+
+    fn read_frames(stream: &mut TcpStream, count: usize) -> Vec<java::Frame> {
+        stream.set_read_timeout(Some(WAIT)).unwrap();
+        let mut buf = Vec::new();
+        let mut frames = Vec::new();
+        let mut chunk = [0u8; 1024];
+        while frames.len() < count {
+            while let Some(frame) = java::take_frame(&mut buf, None).unwrap() {
+                frames.push(frame);
+            }
+            if frames.len() < count {
+                let n = stream.read(&mut chunk).unwrap();
+                assert!(n > 0, "closed after {} frames", frames.len());
+                buf.extend_from_slice(&chunk[..n]);
+            }
+        }
+        frames
+    }
+
+    #[test]
+    fn rules_parse_and_describe() {
+        let rule = ProxyRule::parse("replace both 0x1F 00ff 01").unwrap();
+        assert_eq!(rule.direction, None);
+        assert_eq!(rule.packet, PacketMatch::Id(0x1F));
+        assert_eq!(
+            rule.action,
+            RuleAction::Replace {
+                find: vec![0x00, 0xFF],
+                replace: vec![0x01],
+            }
+        );
+        assert_eq!(rule.describe(), "replace both 0x1F 00ff 01");
+        let rule = ProxyRule::parse("drop in text").unwrap();
+        assert_eq!(rule.packet, PacketMatch::Name("text".into()));
+        assert_eq!(rule.describe(), "drop in text");
+
+        for (line, expected) in [
+            ("", "empty rule"),
+            ("drop", "expected a direction"),
+            ("drop sideways text", "unknown direction 'sideways'"),
+            ("drop in text extra", "usage: drop"),
+            ("replace out text 00", "usage: replace"),
+            ("replace out text '' 00", "bad hex"),
+            ("mangle in text", "unknown rule 'mangle'"),
+        ] {
+            let err = ProxyRule::parse(line).unwrap_err();
+            assert!(err.contains(expected), "{:?}: {}", line, err);
+        }
+        assert_eq!(replace_all(b"abcab", b"ab", b"x"), Some(b"xcx".to_vec()));
+        assert_eq!(replace_all(b"abc", b"z", b"x"), None);
+    }
+
+    #[test]
+    fn java_traffic_is_forwarded_both_ways() {
+        let server = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let rename = format!(
+            "replace out intention {} {}",
+            to_hex(b"localhost"),
+            to_hex(b"127.0.0.1")
+        );
+        let proxy = start(
+            Edition::Java,
+            server.local_addr().unwrap(),
+            &[&rename, "drop in custom_query"],
+        );
+        let mut client = TcpStream::connect(proxy.config.listen).unwrap();
+        let (mut upstream, _) = server.accept().unwrap();
+
+        let mut handshake = PacketWriter::new();
+        handshake
+            .varint(772)
+            .string("localhost")
+            .u16(25565)
+            .varint(2);
+        let mut hello = PacketWriter::new();
+        hello.string("Steve").uuid(0);
+        let hello = java::encode_frame(ids::login::LOGIN_START, &hello.buf, None).unwrap();
+        client
+            .write_all(
+                &java::encode_frame(ids::handshake::INTENTION, &handshake.buf, None).unwrap(),
+            )
+            .unwrap();
+        client.write_all(&hello).unwrap();
+
+        // client to server, with the handshake rewritten
+        let frames = read_frames(&mut upstream, 2);
+        let mut r = PacketReader::new(&frames[0].payload);
+        assert_eq!(r.varint().unwrap(), 772);
+        assert_eq!(r.string().unwrap(), "127.0.0.1");
+        assert_eq!(frames[1].bytes, hello);
+
+        // server to client, without the dropped packet
+        let mut bye = PacketWriter::new();
+        bye.string(r#"{"text":"bye"}"#);
+        let bye = java::encode_frame(ids::login::DISCONNECT, &bye.buf, None).unwrap();
+        upstream
+            .write_all(&java::encode_frame(ids::login::PLUGIN_REQUEST, &[1, 2, 3], None).unwrap())
+            .unwrap();
+        upstream.write_all(&bye).unwrap();
+        drop(upstream);
+        let mut received = Vec::new();
+        client.set_read_timeout(Some(WAIT)).unwrap();
+        client.read_to_end(&mut received).unwrap();
+        assert_eq!(received, bye);
+
+        let packets = packets_until(&proxy, |packets| packets.len() == 4);
+        assert_eq!(
+            seen(&packets, Direction::Outbound),
+            [
+                ("handshake", Some("intention"), Verdict::Modified),
+                ("login", Some("hello"), Verdict::Forwarded),
+            ]
+        );
+        assert_eq!(
+            seen(&packets, Direction::Inbound),
+            [
+                ("login", Some("custom_query"), Verdict::Dropped),
+                ("login", Some("login_disconnect"), Verdict::Forwarded),
+            ]
+        );
+        let hits: Vec<u64> = proxy.rules.lock().unwrap().iter().map(|r| r.hits).collect();
+        assert_eq!(hits, [1, 1]);
+    }
+
+    /// A datagram carrying one game packet in a reliable frame.
+    fn game_datagram(sequence: u32, id: u32, payload: &[u8]) -> Vec<u8> {
+        let packet = GamePacket {
+            id,
+            sub_clients: 0,
+            payload: payload.to_vec(),
+        };
+        Datagram::Frames {
+            sequence,
+            frames: vec![Frame {
+                reliability: Reliability::Reliable,
+                message_index: Some(sequence),
+                sequence_index: None,
+                order: None,
+                split: None,
+                body: bedrock::encode_batch(&[packet], None).unwrap(),
+            }],
+        }
+        .encode()
+    }
+
+    #[test]
+    fn bedrock_traffic_is_forwarded_both_ways() {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        server.set_read_timeout(Some(WAIT)).unwrap();
+        let proxy = start(
+            Edition::Bedrock,
+            server.local_addr().unwrap(),
+            &["drop out request_network_settings"],
+        );
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        client.set_read_timeout(Some(WAIT)).unwrap();
+        client.connect(proxy.config.listen).unwrap();
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+
+        // client to server; the dropped datagram never arrives
+        let ping = raknet::unconnected_ping(1, 2);
+        client.send(&ping).unwrap();
+        let (len, relay) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], ping);
+        let settings = game_datagram(0, bedrock::ids::REQUEST_NETWORK_SETTINGS, &[0, 0, 3, 4]);
+        client.send(&settings).unwrap();
+        let text = game_datagram(1, bedrock::ids::TEXT, b"hello");
+        client.send(&text).unwrap();
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], text);
+
+        // server to client, through the socket the proxy opened for this client
+        let reply = game_datagram(0, bedrock::ids::TEXT, b"welcome");
+        server.send_to(&reply, relay).unwrap();
+        let len = client.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], reply);
+
+        let packets = packets_until(&proxy, |packets| packets.len() == 4);
+        assert_eq!(
+            seen(&packets, Direction::Outbound),
+            [
+                ("offline", Some("unconnected_ping"), Verdict::Forwarded),
+                ("game", Some("request_network_settings"), Verdict::Dropped),
+                ("game", Some("text"), Verdict::Forwarded),
+            ]
+        );
+        assert_eq!(
+            seen(&packets, Direction::Inbound),
+            [("game", Some("text"), Verdict::Forwarded)]
+        );
+        assert_eq!(packets[3].payload, b"welcome");
+    }
+}
@@ -0,0 +1,1266 @@
+//! RakNet as used by Bedrock: offline messages, datagrams, frames, split reassembly, and a
+//! client connection built from them.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::net::codec::{PacketReader, PacketWriter};
+use crate::net::conditions::{ShapedLink, SimulatedNetwork};
+use crate::net::stats::NetStats;
+use crate::net::{NetError, ServerAddress};
+
+/// Marks offline (unconnected) messages.
+pub const MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+/// RakNet protocol version spoken by current Bedrock servers.
+pub const PROTOCOL_VERSION: u8 = 11;
+/// Most split parts accepted for one message.
+const MAX_SPLIT_COUNT: u32 = 4096;
+/// Most partially received split messages kept per connection.
+const MAX_PENDING_SPLITS: usize = 64;
+
+/// Message ids, the first byte of an offline message or of a frame body.
+pub mod ids {
+    pub const CONNECTED_PING: u8 = 0x00;
+    pub const UNCONNECTED_PING: u8 = 0x01;
+    pub const UNCONNECTED_PING_OPEN_CONNECTIONS: u8 = 0x02;
+    pub const CONNECTED_PONG: u8 = 0x03;
+    pub const OPEN_CONNECTION_REQUEST_1: u8 = 0x05;
+    pub const OPEN_CONNECTION_REPLY_1: u8 = 0x06;
+    pub const OPEN_CONNECTION_REQUEST_2: u8 = 0x07;
+    pub const OPEN_CONNECTION_REPLY_2: u8 = 0x08;
+    pub const CONNECTION_REQUEST: u8 = 0x09;
+    pub const CONNECTION_REQUEST_ACCEPTED: u8 = 0x10;
+    pub const NEW_INCOMING_CONNECTION: u8 = 0x13;
+    pub const DISCONNECT_NOTIFICATION: u8 = 0x15;
+    pub const INCOMPATIBLE_PROTOCOL_VERSION: u8 = 0x19;
+    pub const UNCONNECTED_PONG: u8 = 0x1C;
+    /// Carries a batch of Bedrock game packets.
+    pub const GAME_PACKET: u8 = 0xFE;
+}
+
+pub fn message_name(id: u8) -> Option<&'static str> {
+    Some(match id {
+        ids::CONNECTED_PING => "connected_ping",
+        ids::UNCONNECTED_PING => "unconnected_ping",
+        ids::UNCONNECTED_PING_OPEN_CONNECTIONS => "unconnected_ping_open_connections",
+        ids::CONNECTED_PONG => "connected_pong",
+        ids::OPEN_CONNECTION_REQUEST_1 => "open_connection_request_1",
+        ids::OPEN_CONNECTION_REPLY_1 => "open_connection_reply_1",
+        ids::OPEN_CONNECTION_REQUEST_2 => "open_connection_request_2",
+        ids::OPEN_CONNECTION_REPLY_2 => "open_connection_reply_2",
+        ids::CONNECTION_REQUEST => "connection_request",
+        ids::CONNECTION_REQUEST_ACCEPTED => "connection_request_accepted",
+        ids::NEW_INCOMING_CONNECTION => "new_incoming_connection",
+        ids::DISCONNECT_NOTIFICATION => "disconnect_notification",
+        ids::INCOMPATIBLE_PROTOCOL_VERSION => "incompatible_protocol_version",
+        ids::UNCONNECTED_PONG => "unconnected_pong",
+        ids::GAME_PACKET => "game_packet",
+        _ => return None,
+    })
+}
+
+/// An unconnected ping, answered by servers with their status.
+pub fn unconnected_ping(time: i64, guid: u64) -> Vec<u8> {
+    let mut w = PacketWriter::new();
+    w.u8(ids::UNCONNECTED_PING)
+        .i64(time)
+        .bytes(&MAGIC)
+        .u64(guid);
+    w.into_inner()
+}
+
+/// The MOTD of an unconnected pong.
+pub fn parse_unconnected_pong(data: &[u8]) -> Result<String, NetError> {
+    let mut r = PacketReader::new(data);
+    if r.u8()? != ids::UNCONNECTED_PONG {
+        return Err(NetError::Protocol("not an unconnected pong".into()));
+    }
+    let (_time, _guid) = (r.i64()?, r.u64()?);
+    if r.bytes(MAGIC.len())? != MAGIC {
+        return Err(NetError::Protocol("bad offline message magic".into()));
+    }
+    let len = r.u16()? as usize;
+    Ok(String::from_utf8_lossy(r.bytes(len)?).into_owned())
+}
+
+/// Datagram header bits.
+pub mod flags {
+    pub const VALID: u8 = 0x80;
+    pub const ACK: u8 = 0x40;
+    pub const NACK: u8 = 0x20;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reliability {
+    Unreliable,
+    UnreliableSequenced,
+    Reliable,
+    ReliableOrdered,
+    ReliableSequenced,
+    UnreliableWithAck,
+    ReliableWithAck,
+    ReliableOrderedWithAck,
+}
+
+impl Reliability {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 7 {
+            0 => Reliability::Unreliable,
+            1 => Reliability::UnreliableSequenced,
+            2 => Reliability::Reliable,
+            3 => Reliability::ReliableOrdered,
+            4 => Reliability::ReliableSequenced,
+            5 => Reliability::UnreliableWithAck,
+            6 => Reliability::ReliableWithAck,
+            _ => Reliability::ReliableOrderedWithAck,
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        self as u8
+    }
+
+    pub fn is_reliable(self) -> bool {
+        matches!(
+            self,
+            Reliability::Reliable
+                | Reliability::ReliableOrdered
+                | Reliability::ReliableSequenced
+                | Reliability::ReliableWithAck
+                | Reliability::ReliableOrderedWithAck
+        )
+    }
+
+    pub fn is_sequenced(self) -> bool {
+        matches!(
+            self,
+            Reliability::UnreliableSequenced | Reliability::ReliableSequenced
+        )
+    }
+
+    pub fn is_ordered(self) -> bool {
+        self.is_sequenced()
+            || matches!(
+                self,
+                Reliability::ReliableOrdered | Reliability::ReliableOrderedWithAck
+            )
+    }
+}
+
+/// Which part of a split message a frame carries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitInfo {
+    pub count: u32,
+    pub id: u16,
+    pub index: u32,
+}
+
+/// One message (or part of one) inside a datagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub reliability: Reliability,
+    pub message_index: Option<u32>,
+    pub sequence_index: Option<u32>,
+    /// Order index and channel.
+    pub order: Option<(u32, u8)>,
+    pub split: Option<SplitInfo>,
+    pub body: Vec<u8>,
+}
+
+impl Frame {
+    pub fn read(r: &mut PacketReader) -> Result<Self, NetError> {
+        let header = r.u8()?;
+        let reliability = Reliability::from_bits(header >> 5);
+        let bits = r.u16()? as usize;
+        let message_index = reliability.is_reliable().then(|| r.u24_le()).transpose()?;
+        let sequence_index = reliability.is_sequenced().then(|| r.u24_le()).transpose()?;
+        let order = if reliability.is_ordered() {
+            Some((r.u24_le()?, r.u8()?))
+        } else {
+            None
+        };
+        let split = if header & 0x10 != 0 {
+            Some(SplitInfo {
+                count: r.u32()?,
+                id: r.u16()?,
+                index: r.u32()?,
+            })
+        } else {
+            None
+        };
+        let body = r.bytes(bits.div_ceil(8))?.to_vec();
+        Ok(Self {
+            reliability,
+            message_index,
+            sequence_index,
+            order,
+            split,
+            body,
+        })
+    }
+
+    pub fn write(&self, w: &mut PacketWriter) {
+        let split_bit = if self.split.is_some() { 0x10 } else { 0 };
+        w.u8(self.reliability.bits() << 5 | split_bit)
+            .u16((self.body.len() * 8) as u16);
+        if let Some(index) = self.message_index {
+            w.u24_le(index);
+        }
+        if let Some(index) = self.sequence_index {
+            w.u24_le(index);
+        }
+        if let Some((index, channel)) = self.order {
+            w.u24_le(index).u8(channel);
+        }
+        if let Some(split) = self.split {
+            w.u32(split.count).u16(split.id).u32(split.index);
+        }
+        w.bytes(&self.body);
+    }
+}
+
+/// A parsed UDP datagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Datagram {
+    /// An unconnected message such as a ping or an open connection request.
+    Offline {
+        id: u8,
+    },
+    /// Acknowledged sequence number ranges (inclusive).
+    Ack(Vec<(u32, u32)>),
+    /// Sequence number ranges the peer is missing.
+    Nack(Vec<(u32, u32)>),
+    Frames {
+        sequence: u32,
+        frames: Vec<Frame>,
+    },
+}
+
+impl Datagram {
+    pub fn parse(data: &[u8]) -> Result<Self, NetError> {
+        let mut r = PacketReader::new(data);
+        let header = r.u8()?;
+        if header & flags::VALID == 0 {
+            return Ok(Datagram::Offline { id: header });
+        }
+        if header & (flags::ACK | flags::NACK) != 0 {
+            let count = r.u16()?;
+            let mut ranges = Vec::with_capacity(count.min(512) as usize);
+            for _ in 0..count {
+                let single = r.bool()?;
+                let start = r.u24_le()?;
+                let end = if single { start } else { r.u24_le()? };
+                ranges.push((start, end));
+            }
+            return Ok(if header & flags::ACK != 0 {
+                Datagram::Ack(ranges)
+            } else {
+                Datagram::Nack(ranges)
+            });
+        }
+
+        let sequence = r.u24_le()?;
+        let mut frames = Vec::new();
+        while r.remaining() > 0 {
+            frames.push(Frame::read(&mut r)?);
+        }
+        Ok(Datagram::Frames { sequence, frames })
+    }
+
+    /// Encode a frame set or acknowledgement (offline messages are sent as they are).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = PacketWriter::new();
+        match self {
+            Datagram::Offline { id } => {
+                w.u8(*id);
+            }
+            Datagram::Ack(ranges) | Datagram::Nack(ranges) => {
+                let kind = if matches!(self, Datagram::Ack(_)) {
+                    flags::ACK
+                } else {
+                    flags::NACK
+                };
+                w.u8(flags::VALID | kind).u16(ranges.len() as u16);
+                for &(start, end) in ranges {
+                    if start == end {
+                        w.bool(true).u24_le(start);
+                    } else {
+                        w.bool(false).u24_le(start).u24_le(end);
+                    }
+                }
+            }
+            Datagram::Frames { sequence, frames } => {
+                w.u8(flags::VALID).u24_le(*sequence);
+                for frame in frames {
+                    frame.write(&mut w);
+                }
+            }
+        }
+        w.into_inner()
+    }
+}
+
+/// Puts split messages back together.
+#[derive(Default)]
+pub struct Reassembler {
+    pending: HashMap<u16, Vec<Option<Vec<u8>>>>,
+}
+
+impl Reassembler {
+    /// The whole message once its last part arrives; unsplit frames pass straight through.
+    pub fn push(&mut self, frame: &Frame) -> Result<Option<Vec<u8>>, NetError> {
+        let Some(split) = frame.split else {
+            return Ok(Some(frame.body.clone()));
+        };
+        if split.count == 0 || split.count > MAX_SPLIT_COUNT || split.index >= split.count {
+            return Err(NetError::Protocol(format!(
+                "bad split part {} of {}",
+                split.index, split.count
+            )));
+        }
+        if !self.pending.contains_key(&split.id) && self.pending.len() >= MAX_PENDING_SPLITS {
+            return Err(NetError::Protocol(
+                "too many split messages in flight".into(),
+            ));
+        }
+
+        let parts = self
+            .pending
+            .entry(split.id)
+            .or_insert_with(|| vec![None; split.count as usize]);
+        if parts.len() != split.count as usize {
+            return Err(NetError::Protocol(format!(
+                "split message {} changed its part count",
+                split.id
+            )));
+        }
+        parts[split.index as usize] = Some(frame.body.clone());
+        if parts.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+        let parts = self.pending.remove(&split.id).unwrap_or_default();
+        Ok(Some(parts.into_iter().flatten().flatten().collect()))
+    }
+}
+
+/// Sizes tried when opening a connection, largest first.
+const MTU_SIZES: [u16; 3] = [1492, 1200, 576];
+/// IP and UDP headers, counted in the MTU.
+const UDP_HEADER_SIZE: usize = 28;
+/// Datagram header plus the largest frame header (reliable, ordered and split).
+const FRAME_OVERHEAD: usize = 4 + 3 + 3 + 4 + 10;
+/// Open connection requests sent per MTU size before trying a smaller one.
+const OPEN_ATTEMPTS_PER_MTU: usize = 4;
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+/// Reliable datagrams not acknowledged within this long are sent again.
+const RESEND_TIMEOUT: Duration = Duration::from_millis(800);
+/// The connection is considered dead after this long without a datagram.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+const READ_TIMEOUT: Duration = Duration::from_millis(5);
+/// Most datagrams read per pump.
+const MAX_DATAGRAMS_PER_PUMP: usize = 128;
+/// Reliable message indexes remembered for dropping duplicates.
+const RECEIVE_WINDOW: u32 = 2048;
+/// Most sequence numbers acknowledged or reported missing from one range.
+const MAX_RANGE: u32 = 1024;
+
+fn write_address(w: &mut PacketWriter, address: SocketAddr) {
+    match address {
+        SocketAddr::V4(address) => {
+            w.u8(4);
+            for byte in address.ip().octets() {
+                w.u8(!byte);
+            }
+            w.u16(address.port());
+        }
+        SocketAddr::V6(address) => {
+            w.u8(6)
+                .u16_le(23) // AF_INET6
+                .u16(address.port())
+                .u32(address.flowinfo())
+                .bytes(&address.ip().octets())
+                .u32(address.scope_id());
+        }
+    }
+}
+
+fn read_address(r: &mut PacketReader) -> Result<SocketAddr, NetError> {
+    match r.u8()? {
+        4 => {
+            let ip = r.bytes(4)?;
+            let ip = Ipv4Addr::new(!ip[0], !ip[1], !ip[2], !ip[3]);
+            Ok(SocketAddr::from((ip, r.u16()?)))
+        }
+        6 => {
+            let _family = r.u16_le()?;
+            let port = r.u16()?;
+            let flowinfo = r.u32()?;
+            let ip: [u8; 16] = r.bytes(16)?.try_into().unwrap_or_default();
+            let scope_id = r.u32()?;
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                ip.into(),
+                port,
+                flowinfo,
+                scope_id,
+            )))
+        }
+        other => Err(NetError::Protocol(format!(
+            "unknown address version {}",
+            other
+        ))),
+    }
+}
+
+/// Order state of one channel.
+#[derive(Default)]
+struct OrderChannel {
+    expected: u32,
+    waiting: BTreeMap<u32, Vec<u8>>,
+    highest_sequence: Option<u32>,
+}
+
+/// The client side of a RakNet connection: reliability, ordering, splitting and pings.
+/// Game data goes in and out as whole messages; RakNet's own messages are handled here.
+pub struct RakClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    mtu: u16,
+    guid: u64,
+    started: Instant,
+    connected: bool,
+    next_sequence: u32,
+    next_message_index: u32,
+    next_order_index: u32,
+    next_split_id: u16,
+    /// Reliable datagrams waiting for an ACK, by sequence number.
+    unacked: BTreeMap<u32, (Instant, Vec<Frame>)>,
+    to_ack: Vec<u32>,
+    highest_received: Option<u32>,
+    received: HashSet<u32>,
+    /// Reliable messages below this index are duplicates.
+    received_floor: u32,
+    reassembler: Reassembler,
+    channels: HashMap<u8, OrderChannel>,
+    inbox: VecDeque<Vec<u8>>,
+    last_received: Instant,
+    last_ping: Instant,
+    ping_interval: Duration,
+    rtt: Option<Duration>,
+    shaping: Option<ShapedLink>,
+    stats: NetStats,
+}
+
+impl RakClient {
+    /// Open a connection and complete RakNet's handshake.
+    pub fn connect(
+        address: &ServerAddress,
+        timeout: Duration,
+        network: Option<&SimulatedNetwork>,
+        stats: NetStats,
+    ) -> Result<Self, NetError> {
+        let socket = connect_socket(address)?;
+        let server = socket.peer_addr()?;
+        socket.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let guid = fastrand::u64(..);
+        let deadline = Instant::now() + timeout;
+        let mtu = open_connection(&socket, server, guid, deadline)?;
+        let shaping = network.map(|network| ShapedLink::new(network, false));
+        let mut client = Self::with_socket(socket, server, mtu, guid, shaping, stats);
+
+        let mut request = PacketWriter::new();
+        request
+            .u8(ids::CONNECTION_REQUEST)
+            .u64(client.guid)
+            .i64(client.time())
+            .bool(false); // no security
+        client.send_message(&request.buf, Reliability::Reliable)?;
+        while !client.connected {
+            if Instant::now() > deadline {
+                return Err(NetError::Timeout("RakNet connection timed out".into()));
+            }
+            client.pump()?;
+        }
+        Ok(client)
+    }
+
+    /// Both ends of a connection over loopback, past the handshake, with the network
+    /// simulated on each end from a fixed seed.
+    #[cfg(test)]
+    pub(crate) fn loopback_pair(
+        network: &SimulatedNetwork,
+        seed: u64,
+        stats: &NetStats,
+    ) -> Result<(Self, Self), NetError> {
+        let sockets = [
+            UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?,
+            UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?,
+        ];
+        let addresses = [sockets[0].local_addr()?, sockets[1].local_addr()?];
+        let [a, b] = sockets;
+        let mut ends = Vec::new();
+        for (index, (socket, peer)) in [(a, addresses[1]), (b, addresses[0])]
+            .into_iter()
+            .enumerate()
+        {
+            socket.connect(peer)?;
+            socket.set_read_timeout(Some(READ_TIMEOUT))?;
+            let seed = seed.wrapping_add(index as u64 * 2);
+            let shaping = ShapedLink::with_seed(network, false, seed);
+            let mut end = Self::with_socket(
+                socket,
+                peer,
+                MTU_SIZES[0],
+                seed,
+                Some(shaping),
+                stats.clone(),
+            );
+            end.connected = true;
+            ends.push(end);
+        }
+        let b = ends.pop().expect("two ends");
+        let a = ends.pop().expect("two ends");
+        Ok((a, b))
+    }
+
+    fn with_socket(
+        socket: UdpSocket,
+        server: SocketAddr,
+        mtu: u16,
+        guid: u64,
+        shaping: Option<ShapedLink>,
+        stats: NetStats,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            socket,
+            server,
+            mtu,
+            guid,
+            started: now,
+            connected: false,
+            next_sequence: 0,
+            next_message_index: 0,
+            next_order_index: 0,
+            next_split_id: 0,
+            unacked: BTreeMap::new(),
+            to_ack: Vec::new(),
+            highest_received: None,
+            received: HashSet::new(),
+            received_floor: 0,
+            reassembler: Reassembler::default(),
+            channels: HashMap::new(),
+            inbox: VecDeque::new(),
+            last_received: now,
+            last_ping: now,
+            ping_interval: Duration::from_secs(5),
+            rtt: None,
+            shaping,
+            stats,
+        }
+    }
+
+    /// How often to measure the round trip once connected.
+    pub fn set_ping_interval(&mut self, interval: Duration) {
+        self.ping_interval = interval;
+    }
+
+    /// The round trip measured since the last call, if any.
+    pub fn take_rtt(&mut self) -> Option<Duration> {
+        self.rtt.take()
+    }
+
+    /// Send a message reliably, in order.
+    pub fn send(&mut self, body: &[u8]) -> Result<(), NetError> {
+        self.send_message(body, Reliability::ReliableOrdered)
+    }
+
+    /// The next message from the server, waiting briefly for one if none are queued.
+    pub fn recv(&mut self) -> Result<Option<Vec<u8>>, NetError> {
+        if self.inbox.is_empty() {
+            self.pump()?;
+        }
+        Ok(self.inbox.pop_front())
+    }
+
+    /// Tell the server we are leaving.
+    pub fn close(&mut self) {
+        let _ = self.send_message(&[ids::DISCONNECT_NOTIFICATION], Reliability::Reliable);
+        if let Some(shaping) = &mut self.shaping {
+            while let Some(data) = shaping.outbound.pop_due(Instant::now() + IDLE_TIMEOUT) {
+                let _ = self.socket.send(&data);
+            }
+        }
+    }
+
+    fn time(&self) -> i64 {
+        self.started.elapsed().as_millis() as i64
+    }
+
+    fn send_message(&mut self, body: &[u8], reliability: Reliability) -> Result<(), NetError> {
+        let max = self.mtu as usize - UDP_HEADER_SIZE - FRAME_OVERHEAD;
+        let order = reliability.is_ordered().then(|| {
+            self.next_order_index = (self.next_order_index + 1) & 0xFF_FFFF;
+            (self.next_order_index - 1, 0)
+        });
+        let parts: Vec<&[u8]> = body.chunks(max.max(1)).collect();
+        let split_id = self.next_split_id;
+        if parts.len() > 1 {
+            self.next_split_id = self.next_split_id.wrapping_add(1);
+        }
+
+        for (index, part) in parts.iter().enumerate() {
+            let message_index = reliability.is_reliable().then(|| {
+                self.next_message_index = (self.next_message_index + 1) & 0xFF_FFFF;
+                self.next_message_index - 1
+            });
+            let frame = Frame {
+                reliability,
+                message_index,
+                sequence_index: None,
+                order,
+                split: (parts.len() > 1).then_some(SplitInfo {
+                    count: parts.len() as u32,
+                    id: split_id,
+                    index: index as u32,
+                }),
+                body: part.to_vec(),
+            };
+            self.send_frames(vec![frame])?;
+        }
+        Ok(())
+    }
+
+    fn send_frames(&mut self, frames: Vec<Frame>) -> Result<(), NetError> {
+        let sequence = self.next_sequence;
+        self.next_sequence = (self.next_sequence + 1) & 0xFF_FFFF;
+        let datagram = Datagram::Frames { sequence, frames };
+        self.transmit(datagram.encode())?;
+        if let Datagram::Frames { frames, .. } = datagram
+            && frames.iter().any(|frame| frame.reliability.is_reliable())
+        {
+            self.unacked.insert(sequence, (Instant::now(), frames));
+        }
+        Ok(())
+    }
+
+    fn transmit(&mut self, data: Vec<u8>) -> Result<(), NetError> {
+        match &mut self.shaping {
+            Some(shaping) => shaping.outbound.push(data, Instant::now()),
+            None => {
+                self.socket.send(&data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read what arrived, acknowledge it, resend what was lost and keep the connection alive.
+    fn pump(&mut self) -> Result<(), NetError> {
+        let mut buf = [0u8; 2048];
+        for _ in 0..MAX_DATAGRAMS_PER_PUMP {
+            let len = match self.socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    break;
+                }
+                Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+                    return Err(NetError::Disconnected(format!(
+                        "{} refused the connection",
+                        self.server
+                    )));
+                }
+                Err(err) => return Err(err.into()),
+            };
+            match &mut self.shaping {
+                Some(shaping) => shaping.inbound.push(buf[..len].to_vec(), Instant::now()),
+                None => self.handle_datagram(&buf[..len])?,
+            }
+        }
+
+        let now = Instant::now();
+        if let Some(shaping) = &mut self.shaping {
+            let mut arrived = Vec::new();
+            while let Some(data) = shaping.inbound.pop_due(now) {
+                arrived.push(data);
+            }
+            while let Some(data) = shaping.outbound.pop_due(now) {
+                self.socket.send(&data)?;
+            }
+            self.stats.set_queue("simulated in", shaping.inbound.len());
+            self.stats
+                .set_queue("simulated out", shaping.outbound.len());
+            for data in arrived {
+                self.handle_datagram(&data)?;
+            }
+        }
+
+        if !self.to_ack.is_empty() {
+            let ranges = ranges(std::mem::take(&mut self.to_ack));
+            self.transmit(Datagram::Ack(ranges).encode())?;
+        }
+
+        let expired: Vec<u32> = self
+            .unacked
+            .iter()
+            .filter(|(_, (sent, _))| sent.elapsed() >= RESEND_TIMEOUT)
+            .map(|(sequence, _)| *sequence)
+            .collect();
+        for sequence in expired {
+            if let Some((_, frames)) = self.unacked.remove(&sequence) {
+                self.stats.record_resend();
+                self.send_frames(frames)?;
+            }
+        }
+        self.stats.set_queue("raknet unacked", self.unacked.len());
+
+        if self.connected && self.last_ping.elapsed() >= self.ping_interval {
+            self.last_ping = Instant::now();
+            let mut ping = PacketWriter::new();
+            ping.u8(ids::CONNECTED_PING).i64(self.time());
+            self.send_message(&ping.buf, Reliability::Unreliable)?;
+        }
+
+        if self.last_received.elapsed() > IDLE_TIMEOUT {
+            return Err(NetError::Timeout(format!(
+                "nothing received from {} for {:?}",
+                self.server, IDLE_TIMEOUT
+            )));
+        }
+        Ok(())
+    }
+
+    fn handle_datagram(&mut self, data: &[u8]) -> Result<(), NetError> {
+        self.last_received = Instant::now();
+        match Datagram::parse(data)? {
+            Datagram::Offline { .. } => {}
+            Datagram::Ack(acked) => {
+                for (start, end) in acked {
+                    for sequence in start..=end.min(start + MAX_RANGE) {
+                        self.unacked.remove(&sequence);
+                    }
+                }
+            }
+            Datagram::Nack(missing) => {
+                for (start, end) in missing {
+                    for sequence in start..=end.min(start + MAX_RANGE) {
+                        if let Some((_, frames)) = self.unacked.remove(&sequence) {
+                            self.stats.record_nack();
+                            self.send_frames(frames)?;
+                        }
+                    }
+                }
+            }
+            Datagram::Frames { sequence, frames } => {
+                self.to_ack.push(sequence);
+                if let Some(highest) = self.highest_received
+                    && sequence > highest + 1
+                {
+                    let first = highest + 1;
+                    let missing = (first, (sequence - 1).min(first + MAX_RANGE));
+                    self.transmit(Datagram::Nack(vec![missing]).encode())?;
+                }
+                if self
+                    .highest_received
+                    .is_none_or(|highest| sequence > highest)
+                {
+                    self.highest_received = Some(sequence);
+                }
+                for frame in frames {
+                    self.handle_frame(frame)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_frame(&mut self, frame: Frame) -> Result<(), NetError> {
+        if let Some(index) = frame.message_index {
+            if index < self.received_floor || !self.received.insert(index) {
+                return Ok(());
+            }
+            if self.received.len() as u32 > RECEIVE_WINDOW * 2 {
+                let highest = self.received.iter().copied().max().unwrap_or_default();
+                self.received_floor = highest.saturating_sub(RECEIVE_WINDOW);
+                let floor = self.received_floor;
+                self.received.retain(|index| *index >= floor);
+            }
+        }
+        let Some(body) = self.reassembler.push(&frame)? else {
+            return Ok(());
+        };
+
+        let Some((index, channel)) = frame.order else {
+            return self.handle_message(body);
+        };
+        let state = self.channels.entry(channel).or_default();
+        if frame.reliability.is_sequenced() {
+            let sequence = frame.sequence_index.unwrap_or_default();
+            if index < state.expected
+                || state
+                    .highest_sequence
+                    .is_some_and(|highest| sequence <= highest)
+            {
+                return Ok(());
+            }
+            state.highest_sequence = Some(sequence);
+            return self.handle_message(body);
+        }
+
+        if index != state.expected {
+            if index > state.expected {
+                state.waiting.insert(index, body);
+            }
+            return Ok(());
+        }
+        state.expected += 1;
+        state.highest_sequence = None;
+        let mut ready = vec![body];
+        while let Some(body) = state.waiting.remove(&state.expected) {
+            state.expected += 1;
+            ready.push(body);
+        }
+        for body in ready {
+            self.handle_message(body)?;
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, body: Vec<u8>) -> Result<(), NetError> {
+        let mut r = PacketReader::new(&body);
+        match body.first().copied() {
+            Some(ids::CONNECTED_PING) => {
+                r.u8()?;
+                let mut pong = PacketWriter::new();
+                pong.u8(ids::CONNECTED_PONG).i64(r.i64()?).i64(self.time());
+                self.send_message(&pong.buf, Reliability::Unreliable)?;
+            }
+            Some(ids::CONNECTED_PONG) => {
+                r.u8()?;
+                let sent = r.i64()?.max(0) as u64;
+                let rtt = Duration::from_millis((self.time() as u64).saturating_sub(sent));
+                self.stats.record_rtt(rtt);
+                self.rtt = Some(rtt);
+            }
+            Some(ids::CONNECTION_REQUEST_ACCEPTED) if !self.connected => {
+                r.u8()?;
+                let _client_address = read_address(&mut r)?;
+                // the internal address list varies in length; the two timestamps end the message
+                let times = body
+                    .len()
+                    .checked_sub(16)
+                    .map(|start| PacketReader::new(&body[start..]))
+                    .ok_or_else(|| NetError::Protocol("short connection acceptance".into()));
+                let (_request_time, accepted_time) = {
+                    let mut times = times?;
+                    (times.i64()?, times.i64()?)
+                };
+                let mut reply = PacketWriter::new();
+                reply.u8(ids::NEW_INCOMING_CONNECTION);
+                write_address(&mut reply, self.server);
+                for _ in 0..20 {
+                    write_address(&mut reply, SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+                }
+                reply.i64(accepted_time).i64(self.time());
+                self.send_message(&reply.buf, Reliability::ReliableOrdered)?;
+                self.connected = true;
+            }
+            Some(ids::DISCONNECT_NOTIFICATION) => {
+                return Err(NetError::Disconnected(
+                    "the server closed the connection".into(),
+                ));
+            }
+            _ => self.inbox.push_back(body),
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RakClient {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// A UDP socket connected to the server, bound to the matching address family.
+pub fn connect_socket(address: &ServerAddress) -> Result<UdpSocket, NetError> {
+    let server = (address.host.as_str(), address.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| NetError::Protocol(format!("cannot resolve {}", address.host)))?;
+    let socket = match server {
+        SocketAddr::V4(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?,
+        SocketAddr::V6(_) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?,
+    };
+    socket.connect(server)?;
+    Ok(socket)
+}
+
+/// The two offline request/reply rounds that agree on an MTU.
+fn open_connection(
+    socket: &UdpSocket,
+    server: SocketAddr,
+    guid: u64,
+    deadline: Instant,
+) -> Result<u16, NetError> {
+    let mut attempt = 0;
+    let mut last_sent: Option<Instant> = None;
+    // the MTU the server agreed to in its first reply
+    let mut agreed: Option<u16> = None;
+    let mut buf = [0u8; 2048];
+
+    loop {
+        if Instant::now() > deadline {
+            return Err(NetError::Timeout(format!("{} did not answer", server)));
+        }
+        if last_sent.is_none_or(|sent| sent.elapsed() >= OPEN_RETRY_INTERVAL) {
+            let mut request = PacketWriter::new();
+            match agreed {
+                None => {
+                    let mtu = MTU_SIZES[(attempt / OPEN_ATTEMPTS_PER_MTU).min(MTU_SIZES.len() - 1)];
+                    request
+                        .u8(ids::OPEN_CONNECTION_REQUEST_1)
+                        .bytes(&MAGIC)
+                        .u8(PROTOCOL_VERSION);
+                    // padded so the datagram itself probes the MTU
+                    let padding =
+                        (mtu as usize - UDP_HEADER_SIZE).saturating_sub(request.buf.len());
+                    request.bytes(&vec![0; padding]);
+                    attempt += 1;
+                }
+                Some(mtu) => {
+                    request.u8(ids::OPEN_CONNECTION_REQUEST_2).bytes(&MAGIC);
+                    write_address(&mut request, server);
+                    request.u16(mtu).u64(guid);
+                }
+            }
+            socket.send(&request.buf)?;
+            last_sent = Some(Instant::now());
+        }
+
+        let len = match socket.recv(&mut buf) {
+            Ok(len) => len,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue;
+            }
+            // nothing listens yet, or the probe was too big; keep trying until the deadline
+            Err(err) if err.kind() == ErrorKind::ConnectionRefused => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let mut r = PacketReader::new(&buf[..len]);
+        match r.u8()? {
+            ids::OPEN_CONNECTION_REPLY_1 if agreed.is_none() => {
+                r.bytes(MAGIC.len())?;
+                let _server_guid = r.u64()?;
+                if r.bool()? {
+                    return Err(NetError::Unsupported(
+                        "server wants RakNet security, which is not supported".into(),
+                    ));
+                }
+                agreed = Some(r.u16()?.clamp(MTU_SIZES[2], MTU_SIZES[0]));
+                last_sent = None;
+            }
+            ids::OPEN_CONNECTION_REPLY_2 => {
+                r.bytes(MAGIC.len())?;
+                let _server_guid = r.u64()?;
+                let _client_address = read_address(&mut r)?;
+                return Ok(r.u16()?.clamp(MTU_SIZES[2], MTU_SIZES[0]));
+            }
+            ids::INCOMPATIBLE_PROTOCOL_VERSION => {
+                return Err(NetError::Unsupported(format!(
+                    "server speaks RakNet protocol {}, we speak {}",
+                    r.u8()?,
+                    PROTOCOL_VERSION
+                )));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sorted, deduplicated sequence numbers as inclusive ranges.
+fn ranges(mut sequences: Vec<u32>) -> Vec<(u32, u32)> {
+    sequences.sort_unstable();
+    sequences.dedup();
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for sequence in sequences {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == sequence => *end = sequence,
+            _ => ranges.push((sequence, sequence)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(reliability: Reliability, index: u32, body: &[u8]) -> Frame {
+        Frame {
+            reliability,
+            message_index: reliability.is_reliable().then_some(index),
+            sequence_index: reliability.is_sequenced().then_some(index),
+            order: reliability.is_ordered().then_some((index, 0)),
+            split: None,
+            body: body.to_vec(),
+        }
+    }
+
+    /// Part `index` of `count` of split message `id`.
+    fn part(id: u16, index: u32, count: u32, body: &[u8]) -> Frame {
+        Frame {
+            split: Some(SplitInfo { count, id, index }),
+            ..frame(Reliability::Reliable, index, body)
+        }
+    }
+
+    /// A connected client and the bare socket it talks to.
+    fn client() -> (RakClient, UdpSocket, NetStats) {
+        let peer = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        peer.set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.connect(peer.local_addr().unwrap()).unwrap();
+        socket.set_read_timeout(Some(READ_TIMEOUT)).unwrap();
+        peer.connect(socket.local_addr().unwrap()).unwrap();
+        let stats = NetStats::default();
+        let server = peer.local_addr().unwrap();
+        let mut client =
+            RakClient::with_socket(socket, server, MTU_SIZES[0], 1, None, stats.clone());
+        client.connected = true;
+        client.set_ping_interval(Duration::from_secs(3600));
+        (client, peer, stats)
+    }
+
+    fn send(peer: &UdpSocket, sequence: u32, frames: Vec<Frame>) {
+        peer.send(&Datagram::Frames { sequence, frames }.encode())
+            .unwrap();
+    }
+
+    /// Pump the client until it has handed out `count` messages.
+    fn receive(client: &mut RakClient, count: usize) -> Vec<Vec<u8>> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut received = Vec::new();
+        while received.len() < count {
+            assert!(Instant::now() < deadline, "only got {:?}", received);
+            if let Some(body) = client.recv().unwrap() {
+                received.push(body);
+            }
+        }
+        received
+    }
+
+    /// The next datagram the client sent that `wanted` accepts, pumping it meanwhile.
+    fn sent_by(
+        client: &mut RakClient,
+        peer: &UdpSocket,
+        wanted: impl Fn(&Datagram) -> bool,
+    ) -> Datagram {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut buf = [0u8; 2048];
+        peer.set_nonblocking(true).unwrap();
+        loop {
+            assert!(Instant::now() < deadline, "the client never sent it");
+            match peer.recv(&mut buf) {
+                Ok(len) => {
+                    let datagram = Datagram::parse(&buf[..len]).unwrap();
+                    if wanted(&datagram) {
+                        peer.set_nonblocking(false).unwrap();
+                        return datagram;
+                    }
+                }
+                Err(_) => {
+                    client.recv().unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn datagrams_round_trip() {
+        let frames = vec![
+            frame(Reliability::Unreliable, 0, &[1]),
+            frame(Reliability::ReliableOrdered, 7, &[2, 3]),
+            frame(Reliability::ReliableSequenced, 0x12_3456, &[]),
+            part(9, 1, 3, &[4; 300]),
+        ];
+        let datagram = Datagram::Frames {
+            sequence: 0xAB_CDEF,
+            frames,
+        };
+        assert_eq!(Datagram::parse(&datagram.encode()).unwrap(), datagram);
+
+        let ack = Datagram::Ack(vec![(0, 0), (2, 5), (0xFF_FFFF, 0xFF_FFFF)]);
+        let encoded = ack.encode();
+        // single numbers are flagged and written once
+        assert_eq!(encoded.len(), 1 + 2 + 4 + 7 + 4);
+        assert_eq!(Datagram::parse(&encoded).unwrap(), ack);
+        let nack = Datagram::Nack(vec![(3, 4)]);
+        assert_eq!(Datagram::parse(&nack.encode()).unwrap(), nack);
+        assert_eq!(
+            Datagram::parse(&unconnected_ping(1, 2)).unwrap(),
+            Datagram::Offline {
+                id: ids::UNCONNECTED_PING
+            }
+        );
+        assert!(Datagram::parse(&[flags::VALID, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn acknowledged_sequences_become_ranges() {
+        assert_eq!(ranges(vec![]), []);
+        assert_eq!(ranges(vec![7, 1, 3, 2, 8, 1, 5]), [(1, 3), (5, 5), (7, 8)]);
+    }
+
+    #[test]
+    fn split_messages_are_put_back_together() {
+        let mut reassembler = Reassembler::default();
+        assert_eq!(
+            reassembler
+                .push(&frame(Reliability::Reliable, 0, b"whole"))
+                .unwrap(),
+            Some(b"whole".to_vec())
+        );
+        // parts of two messages, interleaved and out of order
+        assert_eq!(reassembler.push(&part(1, 2, 3, b"ef")).unwrap(), None);
+        assert_eq!(reassembler.push(&part(2, 1, 2, b"yz")).unwrap(), None);
+        assert_eq!(reassembler.push(&part(1, 0, 3, b"ab")).unwrap(), None);
+        assert_eq!(
+            reassembler.push(&part(2, 0, 2, b"wx")).unwrap(),
+            Some(b"wxyz".to_vec())
+        );
+        assert_eq!(
+            reassembler.push(&part(1, 1, 3, b"cd")).unwrap(),
+            Some(b"abcdef".to_vec())
+        );
+        assert!(reassembler.pending.is_empty());
+
+        assert!(reassembler.push(&part(3, 2, 2, b"")).is_err());
+        assert!(reassembler.push(&part(3, 0, 0, b"")).is_err());
+        assert!(
+            reassembler
+                .push(&part(3, 0, MAX_SPLIT_COUNT + 1, b""))
+                .is_err()
+        );
+        reassembler.push(&part(4, 0, 2, b"")).unwrap();
+        let err = reassembler.push(&part(4, 1, 3, b"")).unwrap_err();
+        assert!(
+            err.to_string().contains("changed its part count"),
+            "{}",
+            err
+        );
+        for id in 5..5 + MAX_PENDING_SPLITS as u16 {
+            let _ = reassembler.push(&part(id, 0, 2, b""));
+        }
+        assert!(reassembler.push(&part(1000, 0, 2, b"")).is_err());
+    }
+
+    #[test]
+    fn reliable_ordered_messages_arrive_once_and_in_order() {
+        let (mut client, peer, _) = client();
+        let message = |index: u8| frame(Reliability::ReliableOrdered, index as u32, &[0xFE, index]);
+        send(&peer, 0, vec![message(1)]);
+        send(&peer, 1, vec![message(2)]);
+        send(&peer, 2, vec![message(0)]);
+        // a resend of something already delivered is ignored
+        send(&peer, 3, vec![message(1)]);
+        send(
+            &peer,
+            4,
+            vec![frame(Reliability::Unreliable, 0, &[0xFE, 9])],
+        );
+        assert_eq!(
+            receive(&mut client, 4),
+            [[0xFE, 0], [0xFE, 1], [0xFE, 2], [0xFE, 9]]
+        );
+        for _ in 0..5 {
+            assert_eq!(client.recv().unwrap(), None);
+        }
+
+        let mut acked = Vec::new();
+        while acked.len() < 5 {
+            if let Datagram::Ack(ranges) =
+                sent_by(&mut client, &peer, |d| matches!(d, Datagram::Ack(_)))
+            {
+                acked.extend(ranges.into_iter().flat_map(|(start, end)| start..=end));
+            }
+        }
+        acked.sort_unstable();
+        assert_eq!(acked, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn gaps_are_reported_missing() {
+        let (mut client, peer, _) = client();
+        send(&peer, 0, vec![frame(Reliability::Reliable, 0, &[0xFE, 0])]);
+        send(&peer, 3, vec![frame(Reliability::Reliable, 1, &[0xFE, 3])]);
+        assert_eq!(receive(&mut client, 2), [[0xFE, 0], [0xFE, 3]]);
+        let nack = sent_by(&mut client, &peer, |d| matches!(d, Datagram::Nack(_)));
+        assert_eq!(nack, Datagram::Nack(vec![(1, 2)]));
+    }
+
+    #[test]
+    fn nacked_datagrams_are_resent_until_acknowledged() {
+        let (mut client, peer, stats) = client();
+        client.send(&[0xFE, 7]).unwrap();
+        let first = sent_by(&mut client, &peer, |d| matches!(d, Datagram::Frames { .. }));
+        let Datagram::Frames {
+            sequence: 0,
+            frames,
+        } = first
+        else {
+            panic!("{:?}", first);
+        };
+
+        peer.send(&Datagram::Nack(vec![(0, 0)]).encode()).unwrap();
+        let again = sent_by(&mut client, &peer, |d| matches!(d, Datagram::Frames { .. }));
+        assert_eq!(
+            again,
+            Datagram::Frames {
+                sequence: 1,
+                frames
+            }
+        );
+        assert_eq!(stats.snapshot().nacks, 1);
+        assert_eq!(client.unacked.keys().copied().collect::<Vec<_>>(), [1]);
+
+        peer.send(&Datagram::Ack(vec![(1, 1)]).encode()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !client.unacked.is_empty() {
+            assert!(Instant::now() < deadline, "the ACK was not taken");
+            client.recv().unwrap();
+        }
+    }
+
+    #[test]
+    fn big_messages_are_split() {
+        let (mut client, peer, _) = client();
+        let body: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        client.send(&body).unwrap();
+        let mut reassembler = Reassembler::default();
+        let mut parts = 0;
+        let message = loop {
+            let Datagram::Frames { mut frames, .. } =
+                sent_by(&mut client, &peer, |d| matches!(d, Datagram::Frames { .. }))
+            else {
+                unreachable!();
+            };
+            let frame = frames.remove(0);
+            assert_eq!(
+                frame.order,
+                Some((0, 0)),
+                "every part keeps the order index"
+            );
+            parts += 1;
+            if let Some(message) = reassembler.push(&frame).unwrap() {
+                break message;
+            }
+        };
+        assert_eq!(parts, 3);
+        assert_eq!(message, body);
+    }
+}
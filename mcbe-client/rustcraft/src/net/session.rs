@@ -0,0 +1,484 @@
+use bevy::math::{DVec3, IVec2, IVec3, Vec3};
+use crossbeam_channel::{Receiver, Sender};
+use std::path::Path;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::net::backend::{self, ProtocolBackend};
+use crate::net::bedrock::blob_cache::BlobCacheOptions;
+use crate::net::capture::{CaptureHeader, CaptureOptions, CaptureRecord, read_capture};
+use crate::net::conditions::SimulatedNetwork;
+use crate::net::nbt::Nbt;
+use crate::net::stats::{NetStats, NetStatsSnapshot};
+use crate::net::{Edition, NetError, ServerAddress};
+use crate::world::{BlockEntity, BlockState, Chunk, NibbleArray, SECTION_VOLUME};
+
+/// Block face, in Java's protocol order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlockFace {
+    Down,
+    Up,
+    North,
+    South,
+    West,
+    East,
+}
+
+impl BlockFace {
+    pub const ALL: [BlockFace; 6] = [
+        BlockFace::Down,
+        BlockFace::Up,
+        BlockFace::North,
+        BlockFace::South,
+        BlockFace::West,
+        BlockFace::East,
+    ];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Unit offset towards the neighbouring block on this side.
+    pub fn offset(self) -> IVec3 {
+        match self {
+            BlockFace::Down => IVec3::NEG_Y,
+            BlockFace::Up => IVec3::Y,
+            BlockFace::North => IVec3::NEG_Z,
+            BlockFace::South => IVec3::Z,
+            BlockFace::West => IVec3::NEG_X,
+            BlockFace::East => IVec3::X,
+        }
+    }
+
+    pub fn opposite(self) -> BlockFace {
+        match self {
+            BlockFace::Down => BlockFace::Up,
+            BlockFace::Up => BlockFace::Down,
+            BlockFace::North => BlockFace::South,
+            BlockFace::South => BlockFace::North,
+            BlockFace::West => BlockFace::East,
+            BlockFace::East => BlockFace::West,
+        }
+    }
+}
+
+impl std::str::FromStr for BlockFace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "down" | "bottom" => Ok(BlockFace::Down),
+            "up" | "top" => Ok(BlockFace::Up),
+            "north" => Ok(BlockFace::North),
+            "south" => Ok(BlockFace::South),
+            "west" => Ok(BlockFace::West),
+            "east" => Ok(BlockFace::East),
+            other => Err(format!("unknown block face '{}'", other)),
+        }
+    }
+}
+
+/// Something the local player does; turned into packets by the protocol code.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlayerAction {
+    Chat(String),
+    /// A command without the leading `/`.
+    Command(String),
+    Move {
+        position: DVec3,
+        yaw: f32,
+        pitch: f32,
+        on_ground: bool,
+    },
+    /// One simulated tick of the local player, sent every tick while it moves under client
+    /// prediction.
+    Tick(PlayerTick),
+    StartBreaking {
+        pos: IVec3,
+        face: BlockFace,
+    },
+    CancelBreaking {
+        pos: IVec3,
+        face: BlockFace,
+    },
+    /// Still breaking the block; sent every tick between start and finish.
+    ContinueBreaking {
+        pos: IVec3,
+        face: BlockFace,
+    },
+    FinishBreaking {
+        pos: IVec3,
+        face: BlockFace,
+    },
+    /// Use the held item on the `face` of the block at `pos`.
+    Place {
+        pos: IVec3,
+        face: BlockFace,
+        cursor: Vec3,
+    },
+    SwingArm,
+    /// The render distance changed; ask the server for chunks this many columns out.
+    SetViewDistance(u8),
+    Disconnect,
+}
+
+/// The local player's input on one client tick and where it took the player.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerTick {
+    /// Client tick number; corrections from the server refer to it.
+    pub tick: u64,
+    /// Feet position after the tick.
+    pub position: DVec3,
+    /// Movement during the tick.
+    pub delta: DVec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// 1 forward, -1 backward.
+    pub forward: f32,
+    /// 1 left, -1 right.
+    pub strafe: f32,
+    pub jumping: bool,
+    pub sneaking: bool,
+    pub sprinting: bool,
+    pub on_ground: bool,
+    pub horizontal_collision: bool,
+}
+
+/// A packet as it arrived, before or alongside decoding.
+#[derive(Clone, Debug)]
+pub struct RawPacket {
+    pub received_at: Instant,
+    /// Protocol state the packet was received in, e.g. `play`.
+    pub state: &'static str,
+    pub id: i32,
+    pub payload: Vec<u8>,
+}
+
+/// The contents of an inventory slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ItemStack {
+    /// Network item id; the numbering depends on the edition and version.
+    pub id: i32,
+    pub count: u32,
+}
+
+/// A value of an entity's metadata. Keys and their meaning depend on the edition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    Byte(i8),
+    Int(i64),
+    Float(f32),
+    Bool(bool),
+    String(String),
+    Vec3(Vec3),
+    Position(IVec3),
+    Item(Option<ItemStack>),
+    Nbt(Nbt),
+}
+
+/// Something the server told us, independent of the protocol it used.
+#[derive(Clone, Debug)]
+pub enum SessionEvent {
+    /// Login finished and the player entity exists on the server.
+    Joined {
+        entity_id: i64,
+    },
+    /// The first position the server put us at.
+    Spawned {
+        position: DVec3,
+        yaw: f32,
+        pitch: f32,
+    },
+    /// The server moved us (including corrections of rejected movement).
+    Teleported {
+        position: DVec3,
+        yaw: f32,
+        pitch: f32,
+    },
+    /// The server disagreed with the movement predicted for client tick `tick`; this is where
+    /// it had us after that tick.
+    MovementCorrected {
+        tick: u64,
+        position: DVec3,
+        velocity: DVec3,
+        on_ground: bool,
+    },
+    Chat {
+        sender: Option<String>,
+        message: String,
+    },
+    BlockChanged {
+        pos: IVec3,
+        state: u32,
+    },
+    /// A chunk column arrived. `chunk` is `None` when its contents aren't decoded; sections
+    /// may also follow on their own as `SectionLoaded`.
+    ChunkLoaded {
+        pos: IVec2,
+        chunk: Option<Box<Chunk>>,
+    },
+    /// The blocks of one section of a column, `section_y` counting from y = 0.
+    SectionLoaded {
+        pos: IVec2,
+        section_y: i32,
+        blocks: Box<[BlockState; SECTION_VOLUME]>,
+        /// By world position; replaces those the section had.
+        block_entities: Vec<(IVec3, BlockEntity)>,
+    },
+    /// The column is out of view and can be forgotten.
+    ChunkUnloaded {
+        pos: IVec2,
+    },
+    /// New light for some sections of a loaded column, by section index.
+    LightUpdated {
+        pos: IVec2,
+        sky_light: Vec<(i32, NibbleArray)>,
+        block_light: Vec<(i32, NibbleArray)>,
+    },
+    /// We are in another dimension (or just joined); loaded chunks are gone.
+    DimensionChanged {
+        min_y: i32,
+        height: i32,
+    },
+    /// Another entity came into view. `kind` is its type name on Bedrock and the number of
+    /// its type on Java; `velocity` is in blocks per tick.
+    EntitySpawned {
+        entity_id: i64,
+        kind: String,
+        position: DVec3,
+        yaw: f32,
+        pitch: f32,
+        velocity: DVec3,
+    },
+    /// Another entity is now at `position` (feet, not eyes).
+    EntityMoved {
+        entity_id: i64,
+        position: DVec3,
+        yaw: f32,
+        pitch: f32,
+    },
+    /// Another entity jumped to `position`, so it shouldn't be seen moving there.
+    EntityTeleported {
+        entity_id: i64,
+        position: DVec3,
+        yaw: f32,
+        pitch: f32,
+    },
+    /// The server set an entity's velocity, in blocks per tick.
+    EntityVelocity {
+        entity_id: i64,
+        velocity: DVec3,
+    },
+    /// Some metadata values of an entity changed, by key.
+    EntityMetadata {
+        entity_id: i64,
+        values: Vec<(u32, MetadataValue)>,
+    },
+    /// Entities that died or went out of view.
+    EntitiesRemoved {
+        entity_ids: Vec<i64>,
+    },
+    /// A slot of the player's inventory (`window` 0) or an open container changed.
+    InventoryChanged {
+        window: i32,
+        slot: i32,
+        item: Option<ItemStack>,
+    },
+    /// Round trip of a ping, when `SessionOptions::ping_interval` is set.
+    Latency {
+        rtt: Duration,
+    },
+    /// Every received packet, when `SessionOptions::forward_packets` is set.
+    Packet(RawPacket),
+    Disconnected {
+        reason: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct SessionOptions {
+    pub username: String,
+    /// Render distance requested from the server, in chunks.
+    pub view_distance: u8,
+    /// Also emit `SessionEvent::Packet` for every packet (used by bots and captures).
+    pub forward_packets: bool,
+    pub connect_timeout: Duration,
+    /// Measure the round trip time this often once in the world.
+    pub ping_interval: Option<Duration>,
+    /// Record every packet of the session to a file.
+    pub capture: Option<CaptureOptions>,
+    /// Simulated latency, loss and so on, applied to the connection.
+    pub network: Option<SimulatedNetwork>,
+    /// Keep Bedrock chunk data on disk and ask the server for changed data only.
+    pub blob_cache: Option<BlobCacheOptions>,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            username: "Player".into(),
+            view_distance: 8,
+            forward_packets: false,
+            connect_timeout: Duration::from_secs(10),
+            ping_interval: None,
+            capture: None,
+            network: None,
+            blob_cache: None,
+        }
+    }
+}
+
+/// A connection to one server, running on its own thread.
+/// Usable without the ECS; `NetworkPlugin` bridges it into the app.
+pub struct Session {
+    pub address: ServerAddress,
+    pub edition: Edition,
+    actions: Sender<PlayerAction>,
+    events: Receiver<SessionEvent>,
+    stats: NetStats,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Session {
+    /// Start connecting; failures arrive as `SessionEvent::Disconnected`.
+    pub fn connect(address: ServerAddress, edition: Edition, options: SessionOptions) -> Self {
+        Self::start(address, edition, options, None)
+    }
+
+    /// Play back the server side of a capture file as a session, `speed` times as fast as
+    /// it was recorded. Actions are accepted but go nowhere.
+    pub fn replay(path: &Path, speed: f64, options: SessionOptions) -> Result<Self, NetError> {
+        let (header, records) = read_capture(path)?;
+        let edition: Edition = header.edition.parse().map_err(NetError::Protocol)?;
+        let address = ServerAddress::parse(&header.address, edition).map_err(NetError::Protocol)?;
+        Ok(Self::start(
+            address,
+            edition,
+            options,
+            Some((header, records, speed)),
+        ))
+    }
+
+    fn start(
+        address: ServerAddress,
+        edition: Edition,
+        options: SessionOptions,
+        replay: Option<(CaptureHeader, Vec<CaptureRecord>, f64)>,
+    ) -> Self {
+        let (action_tx, action_rx) = crossbeam_channel::unbounded();
+        let (event_tx, event_rx) = crossbeam_channel::unbounded();
+
+        let stats = NetStats::default();
+        let thread_stats = stats.clone();
+        let thread_address = address.clone();
+        let thread = std::thread::Builder::new()
+            .name(format!("session {}", address))
+            .spawn(move || {
+                let result = match replay {
+                    None => backend::connect(edition, &thread_address, &options, &thread_stats),
+                    Some((header, records, speed)) => backend::replay(
+                        &header,
+                        &records,
+                        speed,
+                        &thread_address,
+                        &options,
+                        &thread_stats,
+                    ),
+                }
+                .and_then(|backend| run(backend, &action_rx, &event_tx));
+                let reason = match result {
+                    Ok(()) => "Disconnected by client".to_string(),
+                    Err(err) => err.to_string(),
+                };
+                let _ = event_tx.send(SessionEvent::Disconnected { reason });
+            })
+            .expect("failed to spawn session thread");
+
+        Self {
+            address,
+            edition,
+            actions: action_tx,
+            events: event_rx,
+            stats,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn send(&self, action: PlayerAction) {
+        let _ = self.actions.send(action);
+    }
+
+    pub fn events(&self) -> &Receiver<SessionEvent> {
+        &self.events
+    }
+
+    /// Traffic metrics so far, including the depth of the queues between app and session.
+    pub fn stats(&self) -> NetStatsSnapshot {
+        let mut snapshot = self.stats.snapshot();
+        snapshot.queues.insert("actions".into(), self.actions.len());
+        snapshot.queues.insert("events".into(), self.events.len());
+        snapshot
+    }
+
+    /// Shared handle to the metrics, still readable after the session ends.
+    pub fn stats_handle(&self) -> &NetStats {
+        &self.stats
+    }
+
+    /// Events received since the last call, without blocking.
+    pub fn poll(&self) -> impl Iterator<Item = SessionEvent> + '_ {
+        self.events.try_iter()
+    }
+
+    /// Ask the server to close the connection and let the thread finish on its own, for
+    /// callers that can't wait, like systems on the main thread.
+    pub fn close(mut self) {
+        self.send(PlayerAction::Disconnect);
+        // dropping the handle detaches the thread
+        self.thread.take();
+    }
+
+    /// Ask the server to close the connection and wait for the thread to finish.
+    pub fn disconnect(mut self) {
+        self.send(PlayerAction::Disconnect);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // the thread exits once the action channel is gone
+        let _ = self.actions.send(PlayerAction::Disconnect);
+    }
+}
+
+fn run(
+    mut client: Box<dyn ProtocolBackend>,
+    actions: &Receiver<PlayerAction>,
+    events: &Sender<SessionEvent>,
+) -> Result<(), NetError> {
+    let mut pending = Vec::new();
+
+    loop {
+        loop {
+            match actions.try_recv() {
+                Ok(PlayerAction::Disconnect)
+                | Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                    return Ok(());
+                }
+                Ok(action) => client.perform(&action)?,
+                Err(crossbeam_channel::TryRecvError::Empty) => break,
+            }
+        }
+
+        // blocks for at most the socket read timeout
+        client.poll(&mut pending)?;
+        for event in pending.drain(..) {
+            if events.send(event).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,603 @@
+//! Per-session traffic metrics, recorded on the session thread and read from anywhere.
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::net::ActiveSession;
+use crate::net::capture::Direction;
+
+/// Seconds of per-second history kept for graphs.
+pub const HISTORY_SECONDS: usize = 60;
+/// Packet types listed in the overlay, busiest first.
+const PANEL_PACKET_TYPES: usize = 12;
+
+/// Traffic over some period.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TrafficSample {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub packets_in: u64,
+    pub packets_out: u64,
+}
+
+impl TrafficSample {
+    fn add(&mut self, direction: Direction, bytes: u64) {
+        match direction {
+            Direction::Inbound => {
+                self.bytes_in += bytes;
+                self.packets_in += 1;
+            }
+            Direction::Outbound => {
+                self.bytes_out += bytes;
+                self.packets_out += 1;
+            }
+        }
+    }
+}
+
+/// Traffic of one packet type in one direction.
+#[derive(Clone, Debug, Serialize)]
+pub struct PacketTypeStats {
+    pub name: String,
+    pub direction: Direction,
+    pub packets: u64,
+    pub bytes: u64,
+    /// During the last complete second.
+    pub packets_per_sec: u64,
+    pub bytes_per_sec: u64,
+}
+
+/// Round trip times measured so far, in milliseconds.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct RttStats {
+    pub last_ms: f64,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    pub samples: u64,
+}
+
+/// Everything known about a session's traffic at one moment.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NetStatsSnapshot {
+    pub uptime_secs: f64,
+    pub total: TrafficSample,
+    /// Traffic during the last complete second.
+    pub per_second: TrafficSample,
+    /// One sample per second, oldest first.
+    pub history: Vec<TrafficSample>,
+    /// Busiest (by bytes) first.
+    pub packet_types: Vec<PacketTypeStats>,
+    pub rtt: Option<RttStats>,
+    /// Recent round trip times in milliseconds, oldest first.
+    pub rtt_history: Vec<f64>,
+    /// Datagrams sent again after going unacknowledged (RakNet only).
+    pub resends: u64,
+    /// Negative acknowledgements received (RakNet only).
+    pub nacks: u64,
+    /// Chunk blobs found in the blob cache (Bedrock only).
+    pub blob_hits: u64,
+    /// Chunk blobs the server had to send.
+    pub blob_misses: u64,
+    /// Size of the blobs found in the cache.
+    pub blob_bytes_saved: u64,
+    /// Bytes on the wire per uncompressed byte; 1.0 without compression.
+    pub compression_ratio: f64,
+    /// Items waiting in each queue of the session.
+    pub queues: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Default)]
+struct TypeCounter {
+    packets: u64,
+    bytes: u64,
+    current: (u64, u64),
+    last_second: (u64, u64),
+}
+
+#[derive(Debug)]
+struct Inner {
+    started: Instant,
+    /// Whole seconds since `started` covered by `current`.
+    second: u64,
+    current: TrafficSample,
+    history: VecDeque<TrafficSample>,
+    total: TrafficSample,
+    types: HashMap<(Direction, String), TypeCounter>,
+    rtt_history: VecDeque<f64>,
+    rtt_sum: f64,
+    rtt_min: f64,
+    rtt_max: f64,
+    rtt_samples: u64,
+    resends: u64,
+    nacks: u64,
+    blob_hits: u64,
+    blob_misses: u64,
+    blob_bytes_saved: u64,
+    wire_bytes: u64,
+    uncompressed_bytes: u64,
+    queues: BTreeMap<String, usize>,
+}
+
+impl Inner {
+    /// Close the seconds that have passed, so `current` covers the present one.
+    fn roll(&mut self, now: Instant) {
+        let second = now.saturating_duration_since(self.started).as_secs();
+        let passed = second.saturating_sub(self.second);
+        for i in 0..passed.min(HISTORY_SECONDS as u64 + 1) {
+            let sample = if i == 0 {
+                std::mem::take(&mut self.current)
+            } else {
+                TrafficSample::default()
+            };
+            self.history.push_back(sample);
+            if self.history.len() > HISTORY_SECONDS {
+                self.history.pop_front();
+            }
+        }
+        if passed > 0 {
+            for counter in self.types.values_mut() {
+                counter.last_second = if passed == 1 { counter.current } else { (0, 0) };
+                counter.current = (0, 0);
+            }
+            self.current = TrafficSample::default();
+        }
+        self.second = second;
+    }
+}
+
+/// Handle to one session's metrics; clones share them.
+#[derive(Clone, Debug)]
+pub struct NetStats(Arc<Mutex<Inner>>);
+
+impl Default for NetStats {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            started: Instant::now(),
+            second: 0,
+            current: TrafficSample::default(),
+            history: VecDeque::new(),
+            total: TrafficSample::default(),
+            types: HashMap::new(),
+            rtt_history: VecDeque::new(),
+            rtt_sum: 0.0,
+            rtt_min: f64::MAX,
+            rtt_max: 0.0,
+            rtt_samples: 0,
+            resends: 0,
+            nacks: 0,
+            blob_hits: 0,
+            blob_misses: 0,
+            blob_bytes_saved: 0,
+            wire_bytes: 0,
+            uncompressed_bytes: 0,
+            queues: BTreeMap::new(),
+        })))
+    }
+}
+
+impl NetStats {
+    /// Count a packet: `wire_bytes` as sent or received, `uncompressed_bytes` before compression.
+    pub fn record_packet(
+        &self,
+        direction: Direction,
+        name: &str,
+        wire_bytes: usize,
+        uncompressed_bytes: usize,
+    ) {
+        let Ok(mut inner) = self.0.lock() else {
+            return;
+        };
+        inner.roll(Instant::now());
+        let bytes = wire_bytes as u64;
+        inner.current.add(direction, bytes);
+        inner.total.add(direction, bytes);
+        inner.wire_bytes += bytes;
+        inner.uncompressed_bytes += uncompressed_bytes as u64;
+        let counter = inner
+            .types
+            .entry((direction, name.to_string()))
+            .or_default();
+        counter.packets += 1;
+        counter.bytes += bytes;
+        counter.current.0 += 1;
+        counter.current.1 += bytes;
+    }
+
+    pub fn record_rtt(&self, rtt: Duration) {
+        let Ok(mut inner) = self.0.lock() else {
+            return;
+        };
+        let ms = rtt.as_secs_f64() * 1000.0;
+        inner.rtt_sum += ms;
+        inner.rtt_min = inner.rtt_min.min(ms);
+        inner.rtt_max = inner.rtt_max.max(ms);
+        inner.rtt_samples += 1;
+        inner.rtt_history.push_back(ms);
+        if inner.rtt_history.len() > HISTORY_SECONDS {
+            inner.rtt_history.pop_front();
+        }
+    }
+
+    pub fn record_resend(&self) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.resends += 1;
+        }
+    }
+
+    pub fn record_nack(&self) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.nacks += 1;
+        }
+    }
+
+    /// Count a blob cache lookup; `bytes` is the size of the blob when it was found.
+    pub fn record_blob(&self, hit: bool, bytes: usize) {
+        if let Ok(mut inner) = self.0.lock() {
+            if hit {
+                inner.blob_hits += 1;
+                inner.blob_bytes_saved += bytes as u64;
+            } else {
+                inner.blob_misses += 1;
+            }
+        }
+    }
+
+    /// Report how many items wait in the queue called `name`.
+    pub fn set_queue(&self, name: &str, depth: usize) {
+        if let Ok(mut inner) = self.0.lock() {
+            match inner.queues.get_mut(name) {
+                Some(current) => *current = depth,
+                None => {
+                    inner.queues.insert(name.to_string(), depth);
+                }
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> NetStatsSnapshot {
+        let Ok(mut inner) = self.0.lock() else {
+            return NetStatsSnapshot::default();
+        };
+        let now = Instant::now();
+        inner.roll(now);
+
+        let mut packet_types: Vec<PacketTypeStats> = inner
+            .types
+            .iter()
+            .map(|((direction, name), counter)| PacketTypeStats {
+                name: name.clone(),
+                direction: *direction,
+                packets: counter.packets,
+                bytes: counter.bytes,
+                packets_per_sec: counter.last_second.0,
+                bytes_per_sec: counter.last_second.1,
+            })
+            .collect();
+        packet_types.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+
+        let rtt = (inner.rtt_samples > 0).then(|| RttStats {
+            last_ms: inner.rtt_history.back().copied().unwrap_or_default(),
+            min_ms: inner.rtt_min,
+            mean_ms: inner.rtt_sum / inner.rtt_samples as f64,
+            max_ms: inner.rtt_max,
+            samples: inner.rtt_samples,
+        });
+
+        NetStatsSnapshot {
+            uptime_secs: now.saturating_duration_since(inner.started).as_secs_f64(),
+            total: inner.total,
+            per_second: inner.history.back().copied().unwrap_or_default(),
+            history: inner.history.iter().copied().collect(),
+            packet_types,
+            rtt,
+            rtt_history: inner.rtt_history.iter().copied().collect(),
+            resends: inner.resends,
+            nacks: inner.nacks,
+            blob_hits: inner.blob_hits,
+            blob_misses: inner.blob_misses,
+            blob_bytes_saved: inner.blob_bytes_saved,
+            compression_ratio: if inner.uncompressed_bytes == 0 {
+                1.0
+            } else {
+                inner.wire_bytes as f64 / inner.uncompressed_bytes as f64
+            },
+            queues: inner.queues.clone(),
+        }
+    }
+}
+
+/// `1.2 KB`-style byte counts.
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Small line graph of one or more series sharing a vertical scale.
+fn graph(ui: &mut egui::Ui, series: &[(&[f64], egui::Color32)]) {
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width().max(200.0), 48.0),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(96));
+    let max = series
+        .iter()
+        .flat_map(|(values, _)| values.iter().copied())
+        .fold(1.0, f64::max);
+    for (values, color) in series {
+        if values.len() < 2 {
+            continue;
+        }
+        let step = rect.width() / (HISTORY_SECONDS - 1) as f32;
+        let offset = (HISTORY_SECONDS - values.len()) as f32 * step;
+        let points: Vec<egui::Pos2> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                egui::pos2(
+                    rect.left() + offset + i as f32 * step,
+                    rect.bottom() - (*value / max) as f32 * rect.height(),
+                )
+            })
+            .collect();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.5_f32, *color),
+        ));
+    }
+}
+
+const IN_COLOR: egui::Color32 = egui::Color32::LIGHT_BLUE;
+const OUT_COLOR: egui::Color32 = egui::Color32::LIGHT_GREEN;
+
+/// Debug overlay section with the current session's traffic.
+pub fn network_stats_panel(InMut(ui): InMut<egui::Ui>, session: Res<ActiveSession>) {
+    let Some(session) = &session.0 else {
+        ui.label("Not connected");
+        return;
+    };
+    let stats = session.stats();
+
+    ui.label(format!(
+        "{} ({}) for {:.0}s",
+        session.address, session.edition, stats.uptime_secs
+    ));
+    ui.colored_label(
+        IN_COLOR,
+        format!(
+            "In: {}/s, {} packets/s ({} total)",
+            format_bytes(stats.per_second.bytes_in as f64),
+            stats.per_second.packets_in,
+            format_bytes(stats.total.bytes_in as f64)
+        ),
+    );
+    ui.colored_label(
+        OUT_COLOR,
+        format!(
+            "Out: {}/s, {} packets/s ({} total)",
+            format_bytes(stats.per_second.bytes_out as f64),
+            stats.per_second.packets_out,
+            format_bytes(stats.total.bytes_out as f64)
+        ),
+    );
+    let bytes_in: Vec<f64> = stats.history.iter().map(|s| s.bytes_in as f64).collect();
+    let bytes_out: Vec<f64> = stats.history.iter().map(|s| s.bytes_out as f64).collect();
+    graph(ui, &[(&bytes_in, IN_COLOR), (&bytes_out, OUT_COLOR)]);
+
+    match &stats.rtt {
+        Some(rtt) => {
+            ui.label(format!(
+                "RTT: {:.0} ms (min {:.0}, mean {:.0}, max {:.0})",
+                rtt.last_ms, rtt.min_ms, rtt.mean_ms, rtt.max_ms
+            ));
+            graph(ui, &[(&stats.rtt_history, egui::Color32::YELLOW)]);
+        }
+        None => {
+            ui.label("RTT: not measured yet");
+        }
+    }
+    ui.label(format!(
+        "Resends: {}  NACKs: {}  Compression: {:.0}%",
+        stats.resends,
+        stats.nacks,
+        stats.compression_ratio * 100.0
+    ));
+    let lookups = stats.blob_hits + stats.blob_misses;
+    if lookups > 0 {
+        ui.label(format!(
+            "Blob cache: {} hits, {} misses ({:.0}%), {} saved",
+            stats.blob_hits,
+            stats.blob_misses,
+            stats.blob_hits as f64 * 100.0 / lookups as f64,
+            format_bytes(stats.blob_bytes_saved as f64)
+        ));
+    }
+    ui.label(
+        stats
+            .queues
+            .iter()
+            .map(|(name, depth)| format!("{}: {}", name, depth))
+            .collect::<Vec<_>>()
+            .join("  "),
+    );
+
+    egui::CollapsingHeader::new("Packet types")
+        .id_salt("network_stats_types")
+        .show(ui, |ui| {
+            egui::Grid::new("network_stats_types_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    for packet in stats.packet_types.iter().take(PANEL_PACKET_TYPES) {
+                        let color = match packet.direction {
+                            Direction::Inbound => IN_COLOR,
+                            Direction::Outbound => OUT_COLOR,
+                        };
+                        ui.colored_label(color, &packet.name);
+                        ui.label(format!("{}/s", format_bytes(packet.bytes_per_sec as f64)));
+                        ui.label(format!("{}/s", packet.packets_per_sec));
+                        ui.label(format_bytes(packet.bytes as f64));
+                        ui.end_row();
+                    }
+                });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pretend `seconds` have passed since the session started counting.
+    fn wait(stats: &NetStats, seconds: u64) {
+        stats.0.lock().unwrap().started -= Duration::from_secs(seconds);
+    }
+
+    fn packet_type<'a>(
+        snapshot: &'a NetStatsSnapshot,
+        direction: Direction,
+        name: &str,
+    ) -> &'a PacketTypeStats {
+        snapshot
+            .packet_types
+            .iter()
+            .find(|packet| packet.direction == direction && packet.name == name)
+            .unwrap_or_else(|| panic!("no {:?} {}", direction, name))
+    }
+
+    #[test]
+    fn counts_bytes_and_packets_each_way() {
+        let stats = NetStats::default();
+        stats.record_packet(Direction::Inbound, "level_chunk", 1000, 4000);
+        stats.record_packet(Direction::Inbound, "level_chunk", 600, 2000);
+        stats.record_packet(Direction::Inbound, "text", 50, 50);
+        stats.record_packet(Direction::Outbound, "move_player", 40, 40);
+        stats.record_packet(Direction::Outbound, "text", 30, 30);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            snapshot.total,
+            TrafficSample {
+                bytes_in: 1650,
+                bytes_out: 70,
+                packets_in: 3,
+                packets_out: 2,
+            }
+        );
+        // the same name counts separately in each direction
+        let chunks = packet_type(&snapshot, Direction::Inbound, "level_chunk");
+        assert_eq!((chunks.packets, chunks.bytes), (2, 1600));
+        assert_eq!(packet_type(&snapshot, Direction::Inbound, "text").bytes, 50);
+        assert_eq!(
+            packet_type(&snapshot, Direction::Outbound, "text").bytes,
+            30
+        );
+        let names: Vec<&str> = snapshot
+            .packet_types
+            .iter()
+            .map(|packet| packet.name.as_str())
+            .collect();
+        assert_eq!(names, ["level_chunk", "text", "move_player", "text"]);
+        assert_eq!(snapshot.compression_ratio, 1720.0 / 6120.0);
+        assert_eq!(NetStats::default().snapshot().compression_ratio, 1.0);
+    }
+
+    #[test]
+    fn seconds_roll_into_the_history() {
+        let stats = NetStats::default();
+        stats.record_packet(Direction::Inbound, "text", 100, 100);
+        stats.record_packet(Direction::Outbound, "text", 10, 10);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.total.bytes_in, 100);
+        assert!(snapshot.history.is_empty(), "the first second isn't over");
+        assert_eq!(snapshot.per_second, TrafficSample::default());
+
+        wait(&stats, 1);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.history.len(), 1);
+        assert_eq!(snapshot.per_second.bytes_in, 100);
+        assert_eq!(snapshot.per_second.packets_out, 1);
+        let text = packet_type(&snapshot, Direction::Inbound, "text");
+        assert_eq!((text.packets_per_sec, text.bytes_per_sec), (1, 100));
+
+        // quiet seconds are recorded as empty ones
+        stats.record_packet(Direction::Inbound, "text", 20, 20);
+        wait(&stats, 3);
+        let snapshot = stats.snapshot();
+        let bytes: Vec<u64> = snapshot.history.iter().map(|s| s.bytes_in).collect();
+        assert_eq!(bytes, [100, 20, 0, 0]);
+        assert_eq!(snapshot.per_second, TrafficSample::default());
+        let text = packet_type(&snapshot, Direction::Inbound, "text");
+        assert_eq!((text.packets, text.packets_per_sec), (2, 0));
+        assert_eq!(snapshot.total.bytes_in, 120);
+
+        wait(&stats, 2 * HISTORY_SECONDS as u64);
+        assert_eq!(stats.snapshot().history.len(), HISTORY_SECONDS);
+    }
+
+    #[test]
+    fn round_trips_resends_and_queues() {
+        let stats = NetStats::default();
+        assert!(stats.snapshot().rtt.is_none());
+        for ms in [30, 10, 50] {
+            stats.record_rtt(Duration::from_millis(ms));
+        }
+        stats.record_resend();
+        stats.record_resend();
+        stats.record_nack();
+        stats.record_blob(true, 300);
+        stats.record_blob(false, 0);
+        stats.set_queue("outbound", 4);
+        stats.set_queue("outbound", 2);
+        stats.set_queue("events", 1);
+
+        let snapshot = stats.snapshot();
+        let rtt = snapshot.rtt.unwrap();
+        assert_eq!(
+            (
+                rtt.last_ms,
+                rtt.min_ms,
+                rtt.mean_ms,
+                rtt.max_ms,
+                rtt.samples
+            ),
+            (50.0, 10.0, 30.0, 50.0, 3)
+        );
+        assert_eq!(snapshot.rtt_history, [30.0, 10.0, 50.0]);
+        assert_eq!((snapshot.resends, snapshot.nacks), (2, 1));
+        assert_eq!((snapshot.blob_hits, snapshot.blob_misses), (1, 1));
+        assert_eq!(snapshot.blob_bytes_saved, 300);
+        let queues: Vec<(&str, usize)> = snapshot
+            .queues
+            .iter()
+            .map(|(name, depth)| (name.as_str(), *depth))
+            .collect();
+        assert_eq!(queues, [("events", 1), ("outbound", 2)]);
+
+        // clones share the counters
+        stats.clone().record_nack();
+        assert_eq!(stats.snapshot().nacks, 2);
+    }
+
+    #[test]
+    fn byte_counts() {
+        assert_eq!(format_bytes(0.0), "0 B");
+        assert_eq!(format_bytes(999.0), "999 B");
+        assert_eq!(format_bytes(1200.0), "1.2 KB");
+        assert_eq!(format_bytes(3_450_000.0), "3.5 MB");
+        assert_eq!(format_bytes(7.0e12), "7000.0 GB");
+    }
+}
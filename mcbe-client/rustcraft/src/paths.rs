@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+/// Per-user data directory, e.g. `~/.local/share/RustCraft` on Linux.
+/// Falls back to the working directory when the platform doesn't have one.
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("RustCraft"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Where crash reports are written.
+pub fn crash_reports_dir() -> PathBuf {
+    data_dir().join("crash-reports")
+}
+
+/// Where `latest.log` and rotated log files are written.
+pub fn logs_dir() -> PathBuf {
+    data_dir().join("logs")
+}
+
+/// Where `screenshot` saves images.
+pub fn screenshots_dir() -> PathBuf {
+    data_dir().join("screenshots")
+}
+
+/// Where packet captures are written by default.
+pub fn captures_dir() -> PathBuf {
+    data_dir().join("captures")
+}
+
+/// Where Bedrock chunk blobs are cached between sessions.
+pub fn blob_cache_dir() -> PathBuf {
+    data_dir().join("blob-cache")
+}
+
+/// The vanilla data generator's block report (`generated/reports/blocks.json`), which names
+/// Java's block state ids.
+pub fn block_report() -> PathBuf {
+    data_dir().join("blocks.json")
+}
@@ -0,0 +1,1469 @@
+//! Local player movement, simulated the way vanilla Java does it: one tick at a time at 20 TPS,
+//! with the same constants, float rounding and order of operations, so that predicted positions
+//! agree with the server's to the last digit.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::blocks::{BlockDescription, BlockRegistry, block_registry_system};
+use crate::data::Hitbox;
+use crate::entity::{TICK_RATE, look_rotation};
+use crate::input::ChordKeys;
+use crate::net::ServerEvent;
+use crate::net::session::{BlockFace, SessionEvent};
+use crate::world::{BlockState, ChunkMap, ChunkPos};
+
+pub const PLAYER_WIDTH: f64 = 0.6;
+pub const PLAYER_HEIGHT: f64 = 1.8;
+pub const PLAYER_EYE_HEIGHT: f64 = 1.62;
+/// Highest ledge walked up without jumping.
+pub const STEP_HEIGHT: f64 = 0.6;
+/// Downward acceleration, blocks per tick squared.
+pub const GRAVITY: f64 = 0.08;
+/// Vertical velocity kept each tick in the air.
+const VERTICAL_DRAG: f32 = 0.98;
+/// Horizontal velocity kept each tick in the air; on the ground it is multiplied by the
+/// friction of the block below.
+const AIR_DRAG: f32 = 0.91;
+const JUMP_POWER: f32 = 0.42;
+/// Horizontal boost of a sprint jump.
+const SPRINT_JUMP_BOOST: f64 = 0.2;
+/// The `movement_speed` attribute of players, and its sprinting multiplier.
+const MOVEMENT_SPEED: f64 = 0.1;
+const SPRINT_MULTIPLIER: f64 = 1.3;
+/// Acceleration from input in the air.
+const AIR_SPEED: f32 = 0.02;
+const SPRINT_AIR_SPEED: f32 = 0.025_999_999;
+/// Input scale while sneaking (the `sneaking_speed` attribute).
+const SNEAK_SPEED: f32 = 0.3;
+/// Velocity components smaller than this are dropped at the start of a tick.
+const MIN_VELOCITY: f64 = 0.003;
+/// Speed limit and climbing speed on ladders and vines.
+const CLIMB_LIMIT: f64 = 0.15;
+const CLIMB_SPEED: f64 = 0.2;
+/// Acceleration from input, velocity kept and upward push of swimming.
+const WATER_SPEED: f32 = 0.02;
+const WATER_DRAG: f32 = 0.8;
+const SPRINT_WATER_DRAG: f32 = 0.9;
+const WATER_VERTICAL_DRAG: f64 = 0.8;
+const SWIM_UP: f64 = 0.04;
+/// Velocity kept each tick in lava.
+const LAVA_DRAG: f64 = 0.5;
+/// Upward speed when swimming against a wall with room above, to climb out of water.
+const WATER_EXIT_SPEED: f64 = 0.3;
+/// Distance sneaking backs off from an edge per try.
+const EDGE_STEP: f64 = 0.05;
+/// Overlaps smaller than this don't count as collisions.
+const EPSILON: f64 = 1.0e-7;
+
+/// An axis-aligned box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: DVec3,
+    pub max: DVec3,
+}
+
+impl Aabb {
+    pub const fn new(min: DVec3, max: DVec3) -> Self {
+        Self { min, max }
+    }
+
+    /// A box of `width` by `height` standing on `feet`.
+    pub fn from_feet(feet: DVec3, width: f64, height: f64) -> Self {
+        let half = width / 2.0;
+        Self::new(
+            feet - DVec3::new(half, 0.0, half),
+            feet + DVec3::new(half, height, half),
+        )
+    }
+
+    pub fn offset(self, by: DVec3) -> Self {
+        Self::new(self.min + by, self.max + by)
+    }
+
+    /// Grown in the direction of `by`, to cover everything a move by it passes.
+    pub fn expand_towards(self, by: DVec3) -> Self {
+        Self::new(
+            self.min + by.min(DVec3::ZERO),
+            self.max + by.max(DVec3::ZERO),
+        )
+    }
+
+    pub fn deflate(self, by: f64) -> Self {
+        Self::new(self.min + by, self.max - by)
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmplt(other.max).all() && self.max.cmpgt(other.min).all()
+    }
+
+    /// How far along `axis` this box can move by `distance` before hitting `other`.
+    fn clip(&self, other: &Aabb, axis: usize, mut distance: f64) -> f64 {
+        let overlaps = (0..3)
+            .filter(|a| *a != axis)
+            .all(|a| other.max[a] - EPSILON > self.min[a] && other.min[a] + EPSILON < self.max[a]);
+        if !overlaps {
+            return distance;
+        }
+        if distance > 0.0 && other.min[axis] >= self.max[axis] - EPSILON {
+            distance = distance.min(other.min[axis] - self.max[axis]);
+        } else if distance < 0.0 && other.max[axis] <= self.min[axis] + EPSILON {
+            distance = distance.max(other.max[axis] - self.min[axis]);
+        }
+        distance
+    }
+}
+
+/// How a block state behaves for movement and breaking.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockPhysics {
+    /// Collision boxes, in block coordinates (0 to 1 for a full cube).
+    pub shape: Vec<Aabb>,
+    /// Slipperiness: 0.6 for most blocks, 0.98 for ice, 0.8 for slime.
+    pub friction: f32,
+    /// Horizontal velocity is scaled by this after moving on or in the block: 0.4 for soul
+    /// sand and honey.
+    pub speed_factor: f32,
+    pub climbable: bool,
+    pub water: bool,
+    pub lava: bool,
+    /// Resistance to breaking, as in vanilla; negative for unbreakable blocks.
+    pub hardness: f32,
+}
+
+impl BlockPhysics {
+    pub fn empty() -> Self {
+        Self {
+            shape: Vec::new(),
+            friction: 0.6,
+            speed_factor: 1.0,
+            climbable: false,
+            water: false,
+            lava: false,
+            hardness: 0.0,
+        }
+    }
+
+    /// A full cube as hard as stone.
+    pub fn solid() -> Self {
+        Self {
+            shape: vec![Aabb::new(DVec3::ZERO, DVec3::ONE)],
+            hardness: 1.5,
+            ..Self::empty()
+        }
+    }
+
+    /// How `block` behaves, from its name and properties in either edition.
+    pub fn of(block: &BlockDescription) -> Self {
+        let id = block.id();
+        let mut physics = if is_empty(id) {
+            Self::empty()
+        } else {
+            Self::solid()
+        };
+        physics.water = WATER.contains(&id) || block.property("waterlogged") == Some("true");
+        physics.lava = LAVA.contains(&id);
+        physics.climbable = CLIMBABLE.contains(&id);
+        physics.friction = match id {
+            "ice" | "packed_ice" | "frosted_ice" => 0.98,
+            "blue_ice" => 0.989,
+            "slime_block" => 0.8,
+            _ => physics.friction,
+        };
+        if matches!(id, "soul_sand" | "honey_block") {
+            physics.speed_factor = 0.4;
+        }
+        physics.hardness = hardness(id).unwrap_or(physics.hardness);
+
+        let half = if block.upper_half() {
+            Aabb::new(DVec3::Y * 0.5, DVec3::ONE)
+        } else {
+            Aabb::new(DVec3::ZERO, DVec3::new(1.0, 0.5, 1.0))
+        };
+        if id == "soul_sand" {
+            physics.shape = vec![Aabb::new(DVec3::ZERO, DVec3::new(1.0, 0.875, 1.0))];
+        } else if id == "honey_block" {
+            physics.shape = vec![Aabb::new(
+                DVec3::new(0.0625, 0.0, 0.0625),
+                DVec3::new(0.9375, 0.9375, 0.9375),
+            )];
+        } else if id == "ladder" {
+            physics.shape = vec![against_side(
+                block.facing().unwrap_or(BlockFace::North),
+                0.1875,
+            )];
+        } else if id.ends_with("_slab") && !block.double() {
+            physics.shape = vec![half];
+        } else if id.ends_with("_stairs") {
+            // straight stairs only; corners keep the straight shape
+            let back = against_side(block.facing().unwrap_or(BlockFace::North).opposite(), 0.5);
+            let step_y = if block.upper_half() { 0.0 } else { 0.5 };
+            let back = Aabb::new(
+                DVec3::new(back.min.x, step_y, back.min.z),
+                DVec3::new(back.max.x, step_y + 0.5, back.max.z),
+            );
+            physics.shape = vec![half, back];
+        }
+        physics
+    }
+}
+
+/// A box `depth` thick against the side of a block opposite `facing`, as ladders sit on the
+/// block behind them.
+fn against_side(facing: BlockFace, depth: f64) -> Aabb {
+    let far = 1.0 - depth;
+    match facing {
+        BlockFace::North => Aabb::new(DVec3::new(0.0, 0.0, far), DVec3::ONE),
+        BlockFace::South => Aabb::new(DVec3::ZERO, DVec3::new(1.0, 1.0, depth)),
+        BlockFace::West => Aabb::new(DVec3::new(far, 0.0, 0.0), DVec3::ONE),
+        BlockFace::East => Aabb::new(DVec3::ZERO, DVec3::new(depth, 1.0, 1.0)),
+        BlockFace::Up | BlockFace::Down => Aabb::new(DVec3::ZERO, DVec3::ONE),
+    }
+}
+
+const WATER: &[&str] = &[
+    "water",
+    "flowing_water",
+    "bubble_column",
+    "seagrass",
+    "tall_seagrass",
+    "kelp",
+    "kelp_plant",
+];
+const LAVA: &[&str] = &["lava", "flowing_lava"];
+const CLIMBABLE: &[&str] = &[
+    "ladder",
+    "vine",
+    "weeping_vines",
+    "weeping_vines_plant",
+    "twisting_vines",
+    "twisting_vines_plant",
+    "cave_vines",
+    "cave_vines_plant",
+    "cave_vines_body_with_berries",
+    "cave_vines_head_with_berries",
+    "scaffolding",
+];
+/// Blocks without collision besides air, fluids and climbables: plants and the like.
+const NO_COLLISION: &[&str] = &[
+    "short_grass",
+    "tallgrass",
+    "tall_grass",
+    "fern",
+    "large_fern",
+    "dandelion",
+    "yellow_flower",
+    "poppy",
+    "red_flower",
+    "blue_orchid",
+    "allium",
+    "azure_bluet",
+    "red_tulip",
+    "orange_tulip",
+    "white_tulip",
+    "pink_tulip",
+    "oxeye_daisy",
+    "cornflower",
+    "lily_of_the_valley",
+    "wither_rose",
+    "torchflower",
+    "sunflower",
+    "lilac",
+    "rose_bush",
+    "peony",
+    "double_plant",
+    "deadbush",
+    "dead_bush",
+    "brown_mushroom",
+    "red_mushroom",
+    "crimson_roots",
+    "warped_roots",
+    "nether_sprouts",
+    "sugar_cane",
+    "reeds",
+    "wheat",
+    "carrots",
+    "potatoes",
+    "beetroot",
+    "beetroots",
+    "torch",
+    "redstone_wire",
+    "rail",
+    "fire",
+    "cobweb",
+];
+const NO_COLLISION_SUFFIXES: &[&str] = &[
+    "_sapling",
+    "_torch",
+    "_button",
+    "_pressure_plate",
+    "_sign",
+    "_rail",
+];
+
+/// Vanilla hardness of blocks that don't take the default: nothing for blocks without a
+/// shape, stone's for solid ones.
+fn hardness(id: &str) -> Option<f32> {
+    let hardness = match id {
+        "bedrock"
+        | "barrier"
+        | "command_block"
+        | "chain_command_block"
+        | "repeating_command_block"
+        | "structure_block"
+        | "jigsaw"
+        | "end_portal_frame"
+        | "end_portal"
+        | "end_gateway"
+        | "light_block"
+        | "light" => -1.0,
+        "obsidian" | "crying_obsidian" | "respawn_anchor" => 50.0,
+        "ancient_debris" | "netherite_block" => 30.0,
+        "iron_block" | "gold_block" | "diamond_block" | "emerald_block" | "copper_block" => 5.0,
+        "lantern" | "soul_lantern" => 3.5,
+        "end_stone" | "deepslate" | "cobbled_deepslate" => 3.0,
+        "blue_ice" => 2.8,
+        "cobblestone" | "mossy_cobblestone" | "bricks" | "brick_block" => 2.0,
+        "sandstone" | "red_sandstone" => 0.8,
+        "grass_block" | "grass" | "gravel" | "clay" | "mycelium" | "podzol" | "farmland"
+        | "sponge" => 0.6,
+        "dirt" | "coarse_dirt" | "rooted_dirt" | "sand" | "red_sand" | "soul_sand" | "ice"
+        | "packed_ice" | "frosted_ice" => 0.5,
+        "ladder" | "netherrack" => 0.4,
+        "glass" | "glowstone" | "sea_lantern" | "glass_pane" => 0.3,
+        "snow" => 0.2,
+        "snow_layer" => 0.1,
+        "cobweb" | "web" => 4.0,
+        "slime_block" | "tnt" => 0.0,
+        _ if id.starts_with("deepslate_") && id.ends_with("_ore") => 4.5,
+        _ if id.ends_with("_ore") => 3.0,
+        _ if id.ends_with("_glass") || id.ends_with("_glass_pane") => 0.3,
+        _ if id.ends_with("_leaves") => 0.2,
+        _ if id.ends_with("_wool") => 0.8,
+        _ if id.ends_with("_planks")
+            || id.ends_with("_log")
+            || id.ends_with("_wood")
+            || id.ends_with("_stem")
+            || id.ends_with("_hyphae") =>
+        {
+            2.0
+        }
+        _ => return None,
+    };
+    Some(hardness)
+}
+
+fn is_empty(id: &str) -> bool {
+    matches!(id, "air" | "cave_air" | "void_air")
+        || WATER.contains(&id)
+        || LAVA.contains(&id)
+        || (CLIMBABLE.contains(&id) && id != "ladder")
+        || NO_COLLISION.contains(&id)
+        || NO_COLLISION_SUFFIXES
+            .iter()
+            .any(|suffix| id.ends_with(suffix))
+}
+
+/// Physical properties of block states, from the `BlockRegistry`. States it doesn't describe
+/// are full cubes, and air is empty.
+#[derive(Resource, Debug)]
+pub struct BlockPhysicsTable {
+    states: HashMap<BlockState, BlockPhysics>,
+    air: BlockPhysics,
+    solid: BlockPhysics,
+}
+
+impl Default for BlockPhysicsTable {
+    fn default() -> Self {
+        Self {
+            states: HashMap::new(),
+            air: BlockPhysics::empty(),
+            solid: BlockPhysics::solid(),
+        }
+    }
+}
+
+impl BlockPhysicsTable {
+    pub fn get(&self, state: BlockState) -> &BlockPhysics {
+        match self.states.get(&state) {
+            Some(physics) => physics,
+            None if state.is_air() => &self.air,
+            None => &self.solid,
+        }
+    }
+
+    pub fn set(&mut self, state: BlockState, physics: BlockPhysics) {
+        self.states.insert(state, physics);
+    }
+
+    pub fn from_registry(registry: &BlockRegistry) -> Self {
+        let mut table = Self::default();
+        for (state, block) in registry.iter() {
+            table.set(state, BlockPhysics::of(block));
+        }
+        table
+    }
+}
+
+/// Where the simulation finds blocks.
+pub trait BlockSource {
+    fn block_physics(&self, pos: IVec3) -> &BlockPhysics;
+}
+
+/// The blocks of the loaded world.
+pub struct WorldBlocks<'a> {
+    pub chunks: &'a ChunkMap,
+    pub table: &'a BlockPhysicsTable,
+}
+
+impl BlockSource for WorldBlocks<'_> {
+    fn block_physics(&self, pos: IVec3) -> &BlockPhysics {
+        self.table.get(self.chunks.block(pos))
+    }
+}
+
+/// Collision boxes of the blocks that `area` touches, plus the block below for tall shapes
+/// such as fences.
+fn collision_boxes(blocks: &impl BlockSource, area: &Aabb) -> Vec<Aabb> {
+    let min = (area.min - EPSILON).floor().as_ivec3() - IVec3::Y;
+    let max = (area.max + EPSILON).floor().as_ivec3();
+    let mut boxes = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                let pos = IVec3::new(x, y, z);
+                for shape in &blocks.block_physics(pos).shape {
+                    let shape = shape.offset(pos.as_dvec3());
+                    if shape.intersects(&area.deflate(-EPSILON)) {
+                        boxes.push(shape);
+                    }
+                }
+            }
+        }
+    }
+    boxes
+}
+
+/// `movement` cut short where `aabb` would hit `boxes`: Y first, then the larger of X and Z
+/// last, like vanilla.
+fn collide_boxes(movement: DVec3, aabb: Aabb, boxes: &[Aabb]) -> DVec3 {
+    let mut moved = DVec3::ZERO;
+    let mut aabb = aabb;
+    let z_first = movement.x.abs() < movement.z.abs();
+    let axes: [usize; 3] = if z_first { [1, 2, 0] } else { [1, 0, 2] };
+    for axis in axes {
+        let mut distance = movement[axis];
+        if distance == 0.0 {
+            continue;
+        }
+        for other in boxes {
+            distance = aabb.clip(other, axis, distance);
+        }
+        if distance.abs() < EPSILON {
+            distance = 0.0;
+        }
+        moved[axis] = distance;
+        let mut by = DVec3::ZERO;
+        by[axis] = distance;
+        aabb = aabb.offset(by);
+    }
+    moved
+}
+
+fn horizontal_length_squared(v: DVec3) -> f64 {
+    v.x * v.x + v.z * v.z
+}
+
+/// Movement input for one tick, as the keys say.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct MoveInput {
+    /// 1 forward, -1 backward.
+    pub forward: f32,
+    /// 1 left, -1 right.
+    pub strafe: f32,
+    pub jump: bool,
+    pub sneak: bool,
+    pub sprint: bool,
+}
+
+/// The simulated state of the local player.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct PlayerBody {
+    /// Feet position.
+    pub position: DVec3,
+    /// Position before the last tick; frames between ticks blend from it.
+    pub previous: DVec3,
+    /// Blocks per tick.
+    pub velocity: DVec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+    pub horizontal_collision: bool,
+    pub in_water: bool,
+    pub in_lava: bool,
+    pub on_climbable: bool,
+    pub sprinting: bool,
+    /// Ticks simulated since the body was placed.
+    pub ticks: u64,
+}
+
+impl PlayerBody {
+    pub fn new(position: DVec3, yaw: f32, pitch: f32) -> Self {
+        Self {
+            position,
+            previous: position,
+            velocity: DVec3::ZERO,
+            yaw,
+            pitch,
+            on_ground: false,
+            horizontal_collision: false,
+            in_water: false,
+            in_lava: false,
+            on_climbable: false,
+            sprinting: false,
+            ticks: 0,
+        }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_feet(self.position, PLAYER_WIDTH, PLAYER_HEIGHT)
+    }
+
+    /// Simulate one tick.
+    pub fn tick(&mut self, input: &MoveInput, blocks: &impl BlockSource) {
+        self.previous = self.position;
+        self.ticks += 1;
+        let mut velocity = self.velocity;
+        for axis in 0..3 {
+            if velocity[axis].abs() < MIN_VELOCITY {
+                velocity[axis] = 0.0;
+            }
+        }
+
+        let mut forward = input.forward * 0.98;
+        let mut strafe = input.strafe * 0.98;
+        if input.sneak {
+            forward *= SNEAK_SPEED;
+            strafe *= SNEAK_SPEED;
+        }
+        self.sprinting = input.sprint && input.forward > 0.0 && !input.sneak;
+        self.in_water = self.touches(blocks, |block| block.water);
+        self.in_lava = self.touches(blocks, |block| block.lava);
+        self.on_climbable = blocks
+            .block_physics(self.position.floor().as_ivec3())
+            .climbable;
+
+        if input.jump {
+            if self.in_water || self.in_lava {
+                velocity.y += SWIM_UP;
+            } else if self.on_ground {
+                velocity.y = JUMP_POWER as f64;
+                if self.sprinting {
+                    let yaw = self.yaw.to_radians();
+                    velocity.x -= yaw.sin() as f64 * SPRINT_JUMP_BOOST;
+                    velocity.z += yaw.cos() as f64 * SPRINT_JUMP_BOOST;
+                }
+            }
+        }
+        if (self.in_water || self.in_lava) && input.sneak {
+            velocity.y -= SWIM_UP;
+        }
+
+        self.velocity = velocity;
+        if self.in_water {
+            self.travel_in_water(strafe, forward, input, blocks);
+        } else if self.in_lava {
+            self.travel_in_lava(strafe, forward, input, blocks);
+        } else {
+            self.travel_in_air(strafe, forward, input, blocks);
+        }
+    }
+
+    fn travel_in_air(
+        &mut self,
+        strafe: f32,
+        forward: f32,
+        input: &MoveInput,
+        blocks: &impl BlockSource,
+    ) {
+        let friction = if self.on_ground {
+            blocks.block_physics(self.block_below()).friction
+        } else {
+            1.0
+        };
+        let drag = friction * AIR_DRAG;
+        let speed = if self.on_ground {
+            let attribute = if self.sprinting {
+                MOVEMENT_SPEED * SPRINT_MULTIPLIER
+            } else {
+                MOVEMENT_SPEED
+            };
+            attribute as f32 * (0.216_000_02 / (friction * friction * friction))
+        } else if self.sprinting {
+            SPRINT_AIR_SPEED
+        } else {
+            AIR_SPEED
+        };
+        self.velocity += input_vector(strafe, forward, speed, self.yaw);
+
+        if self.on_climbable {
+            self.velocity.x = self.velocity.x.clamp(-CLIMB_LIMIT, CLIMB_LIMIT);
+            self.velocity.z = self.velocity.z.clamp(-CLIMB_LIMIT, CLIMB_LIMIT);
+            self.velocity.y = self.velocity.y.max(-CLIMB_LIMIT);
+            if input.sneak && self.velocity.y < 0.0 {
+                self.velocity.y = 0.0;
+            }
+        }
+
+        self.move_by(self.velocity, input.sneak, blocks);
+        if (self.horizontal_collision || input.jump) && self.on_climbable {
+            self.velocity.y = CLIMB_SPEED;
+        }
+
+        self.velocity.y -= GRAVITY;
+        self.velocity *= DVec3::new(drag as f64, VERTICAL_DRAG as f64, drag as f64);
+    }
+
+    fn travel_in_water(
+        &mut self,
+        strafe: f32,
+        forward: f32,
+        input: &MoveInput,
+        blocks: &impl BlockSource,
+    ) {
+        let start_y = self.position.y;
+        let drag = if self.sprinting {
+            SPRINT_WATER_DRAG
+        } else {
+            WATER_DRAG
+        } as f64;
+        self.velocity += input_vector(strafe, forward, WATER_SPEED, self.yaw);
+        self.move_by(self.velocity, input.sneak, blocks);
+        if self.horizontal_collision && self.on_climbable {
+            self.velocity.y = CLIMB_SPEED;
+        }
+        self.velocity *= DVec3::new(drag, WATER_VERTICAL_DRAG, drag);
+
+        // sinking slowly, but settling when nearly still
+        let sink = GRAVITY / 16.0;
+        let falling = self.velocity.y <= 0.0;
+        self.velocity.y = if falling
+            && (self.velocity.y - 0.005).abs() >= MIN_VELOCITY
+            && (self.velocity.y - sink).abs() < MIN_VELOCITY
+        {
+            -MIN_VELOCITY
+        } else {
+            self.velocity.y - sink
+        };
+
+        self.climb_out_of_fluid(start_y, blocks);
+    }
+
+    fn travel_in_lava(
+        &mut self,
+        strafe: f32,
+        forward: f32,
+        input: &MoveInput,
+        blocks: &impl BlockSource,
+    ) {
+        let start_y = self.position.y;
+        self.velocity += input_vector(strafe, forward, WATER_SPEED, self.yaw);
+        self.move_by(self.velocity, input.sneak, blocks);
+        self.velocity *= LAVA_DRAG;
+        self.velocity.y -= GRAVITY / 4.0;
+        self.climb_out_of_fluid(start_y, blocks);
+    }
+
+    /// Push up when swimming against a wall with room above, to get out of a fluid.
+    fn climb_out_of_fluid(&mut self, start_y: f64, blocks: &impl BlockSource) {
+        let exit = DVec3::new(
+            self.velocity.x,
+            self.velocity.y + 0.6 - self.position.y + start_y,
+            self.velocity.z,
+        );
+        if self.horizontal_collision && self.is_free(self.aabb().offset(exit), blocks) {
+            self.velocity.y = WATER_EXIT_SPEED;
+        }
+    }
+
+    /// Move by `movement` as far as blocks allow, and update the collision flags. Velocity
+    /// along the axes that hit something is dropped.
+    pub fn move_by(&mut self, movement: DVec3, sneaking: bool, blocks: &impl BlockSource) {
+        let movement = if sneaking {
+            self.back_off_from_edge(movement, blocks)
+        } else {
+            movement
+        };
+        let moved = self.collide(movement, blocks);
+        self.position += moved;
+
+        let x_collision = (movement.x - moved.x).abs() >= 1.0e-5;
+        let z_collision = (movement.z - moved.z).abs() >= 1.0e-5;
+        let vertical_collision = movement.y != moved.y;
+        self.horizontal_collision = x_collision || z_collision;
+        self.on_ground = vertical_collision && movement.y < 0.0;
+
+        if x_collision {
+            self.velocity.x = 0.0;
+        }
+        if z_collision {
+            self.velocity.z = 0.0;
+        }
+        if vertical_collision {
+            self.velocity.y = 0.0;
+        }
+
+        let factor = self.speed_factor(blocks) as f64;
+        self.velocity.x *= factor;
+        self.velocity.z *= factor;
+    }
+
+    /// Speed factor of the block at the feet, or if that has none (and isn't water) of the
+    /// block below, as in vanilla.
+    fn speed_factor(&self, blocks: &impl BlockSource) -> f32 {
+        let at_feet = blocks.block_physics(self.position.floor().as_ivec3());
+        if at_feet.water || at_feet.speed_factor != 1.0 {
+            return at_feet.speed_factor;
+        }
+        blocks.block_physics(self.block_below()).speed_factor
+    }
+
+    /// `movement` after collisions, stepping up ledges when that gets further.
+    fn collide(&self, movement: DVec3, blocks: &impl BlockSource) -> DVec3 {
+        let aabb = self.aabb();
+        let area = aabb
+            .expand_towards(movement)
+            .expand_towards(DVec3::Y * STEP_HEIGHT);
+        let boxes = collision_boxes(blocks, &area);
+        let moved = collide_boxes(movement, aabb, &boxes);
+
+        let blocked = movement.x != moved.x || movement.z != moved.z;
+        let grounded = self.on_ground || (movement.y != moved.y && movement.y < 0.0);
+        if !blocked || !grounded {
+            return moved;
+        }
+
+        let mut step = collide_boxes(
+            DVec3::new(movement.x, STEP_HEIGHT, movement.z),
+            aabb,
+            &boxes,
+        );
+        let up = collide_boxes(
+            DVec3::Y * STEP_HEIGHT,
+            aabb.expand_towards(DVec3::new(movement.x, 0.0, movement.z)),
+            &boxes,
+        );
+        if up.y < STEP_HEIGHT {
+            let across = collide_boxes(
+                DVec3::new(movement.x, 0.0, movement.z),
+                aabb.offset(up),
+                &boxes,
+            ) + up;
+            if horizontal_length_squared(across) > horizontal_length_squared(step) {
+                step = across;
+            }
+        }
+        if horizontal_length_squared(step) <= horizontal_length_squared(moved) {
+            return moved;
+        }
+        step + collide_boxes(DVec3::Y * (movement.y - step.y), aabb.offset(step), &boxes)
+    }
+
+    /// Shorten a sneaking move so the player doesn't walk off an edge higher than a step.
+    fn back_off_from_edge(&self, movement: DVec3, blocks: &impl BlockSource) -> DVec3 {
+        if movement.y > 0.0 || !self.on_ground {
+            return movement;
+        }
+        let aabb = self.aabb();
+        let off_edge =
+            |x: f64, z: f64| self.is_free(aabb.offset(DVec3::new(x, -STEP_HEIGHT, z)), blocks);
+        let toward_zero = |value: f64| {
+            if value.abs() < EDGE_STEP {
+                0.0
+            } else {
+                value - EDGE_STEP * value.signum()
+            }
+        };
+
+        let (mut x, mut z) = (movement.x, movement.z);
+        while x != 0.0 && off_edge(x, 0.0) {
+            x = toward_zero(x);
+        }
+        while z != 0.0 && off_edge(0.0, z) {
+            z = toward_zero(z);
+        }
+        while x != 0.0 && z != 0.0 && off_edge(x, z) {
+            x = toward_zero(x);
+            z = toward_zero(z);
+        }
+        DVec3::new(x, movement.y, z)
+    }
+
+    fn is_free(&self, aabb: Aabb, blocks: &impl BlockSource) -> bool {
+        collision_boxes(blocks, &aabb)
+            .iter()
+            .all(|other| !other.intersects(&aabb))
+    }
+
+    fn touches(&self, blocks: &impl BlockSource, fluid: impl Fn(&BlockPhysics) -> bool) -> bool {
+        let aabb = self.aabb().deflate(0.001);
+        let (min, max) = (aabb.min.floor().as_ivec3(), aabb.max.floor().as_ivec3());
+        (min.x..=max.x).any(|x| {
+            (min.y..=max.y)
+                .any(|y| (min.z..=max.z).any(|z| fluid(blocks.block_physics(IVec3::new(x, y, z)))))
+        })
+    }
+
+    /// The block whose friction applies: the one half a block below the feet.
+    fn block_below(&self) -> IVec3 {
+        DVec3::new(
+            self.position.x,
+            self.position.y - 0.500_001,
+            self.position.z,
+        )
+        .floor()
+        .as_ivec3()
+    }
+}
+
+/// Acceleration from `strafe` and `forward` input at `speed`, turned to face `yaw`.
+fn input_vector(strafe: f32, forward: f32, speed: f32, yaw: f32) -> DVec3 {
+    let input = DVec3::new(strafe as f64, 0.0, forward as f64);
+    let length_squared = input.length_squared();
+    if length_squared < 1.0e-7 {
+        return DVec3::ZERO;
+    }
+    let input = if length_squared > 1.0 {
+        input.normalize()
+    } else {
+        input
+    } * speed as f64;
+    let (sin, cos) = yaw.to_radians().sin_cos();
+    let (sin, cos) = (sin as f64, cos as f64);
+    DVec3::new(
+        input.x * cos - input.z * sin,
+        0.0,
+        input.z * cos + input.x * sin,
+    )
+}
+
+/// Marks the entity simulated from local input.
+#[derive(Component, Debug)]
+pub struct LocalPlayer;
+
+/// Simulates the local player in `FixedUpdate`, at the game's tick rate whatever the frame
+/// rate.
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .init_resource::<BlockRegistry>()
+            .init_resource::<BlockPhysicsTable>()
+            .init_resource::<MoveInput>()
+            .add_systems(
+                PreUpdate,
+                block_physics_table_system
+                    .after(block_registry_system)
+                    .run_if(resource_changed::<BlockRegistry>),
+            )
+            .add_systems(Update, player_event_system)
+            .add_systems(FixedUpdate, player_physics_system)
+            .add_systems(
+                PostUpdate,
+                player_transform_system.before(TransformSystems::Propagate),
+            );
+    }
+}
+
+/// Rebuild the physics table when the block registry changes edition.
+pub fn block_physics_table_system(
+    registry: Res<BlockRegistry>,
+    mut table: ResMut<BlockPhysicsTable>,
+) {
+    *table = BlockPhysicsTable::from_registry(&registry);
+}
+
+/// Spawn the local player where the server puts us, and follow its teleports.
+pub fn player_event_system(
+    mut commands: Commands,
+    mut events: MessageReader<ServerEvent>,
+    mut players: Query<(Entity, &mut PlayerBody), With<LocalPlayer>>,
+) {
+    for ServerEvent(event) in events.read() {
+        match event {
+            SessionEvent::Spawned {
+                position,
+                yaw,
+                pitch,
+            }
+            | SessionEvent::Teleported {
+                position,
+                yaw,
+                pitch,
+            } => {
+                let body = PlayerBody::new(*position, *yaw, *pitch);
+                match players.single_mut() {
+                    Ok((_, mut current)) => *current = body,
+                    Err(_) => {
+                        commands.spawn((
+                            Name::new("Local player"),
+                            LocalPlayer,
+                            Transform::from_translation(position.as_vec3()),
+                            Hitbox {
+                                width: PLAYER_WIDTH as f32,
+                                height: PLAYER_HEIGHT as f32,
+                                eye_height: PLAYER_EYE_HEIGHT as f32,
+                            },
+                            body,
+                        ));
+                    }
+                }
+            }
+            SessionEvent::Disconnected { .. } => {
+                for (entity, _) in &players {
+                    commands.entity(entity).despawn();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Move the local player by one tick, once the chunk it stands in has arrived.
+pub fn player_physics_system(
+    input: Res<MoveInput>,
+    chunks: Res<ChunkMap>,
+    table: Res<BlockPhysicsTable>,
+    mut players: Query<&mut PlayerBody, With<LocalPlayer>>,
+) {
+    let blocks = WorldBlocks {
+        chunks: &chunks,
+        table: &table,
+    };
+    for mut body in &mut players {
+        let column = ChunkPos::from_block(body.position.floor().as_ivec3());
+        if chunks.chunk(column).is_none() {
+            continue;
+        }
+        body.tick(&input, &blocks);
+    }
+}
+
+/// Place the local player between its last two ticks, so it moves smoothly at any frame rate.
+pub fn player_transform_system(
+    time: Res<Time<Fixed>>,
+    mut players: Query<(&PlayerBody, &mut Transform)>,
+) {
+    let fraction = time.overstep_fraction_f64();
+    for (body, mut transform) in &mut players {
+        transform.translation = body.previous.lerp(body.position, fraction).as_vec3();
+        transform.rotation = look_rotation(body.yaw, body.pitch);
+    }
+}
+
+/// WASD, space, shift and ctrl into `MoveInput`.
+pub fn movement_input_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    chords: Res<ChordKeys>,
+    mut input: ResMut<MoveInput>,
+) {
+    let pressed = |key| chords.pressed(&keys, key);
+    let axis = |positive: KeyCode, negative: KeyCode| {
+        pressed(positive) as i32 as f32 - pressed(negative) as i32 as f32
+    };
+    *input = MoveInput {
+        forward: axis(KeyCode::KeyW, KeyCode::KeyS),
+        strafe: axis(KeyCode::KeyA, KeyCode::KeyD),
+        jump: pressed(KeyCode::Space),
+        sneak: pressed(KeyCode::ShiftLeft),
+        sprint: pressed(KeyCode::ControlLeft),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_INPUT: MoveInput = MoveInput {
+        forward: 0.0,
+        strafe: 0.0,
+        jump: false,
+        sneak: false,
+        sprint: false,
+    };
+
+    /// `ground` below y = 0 and air above; with a `ladder`, a ladder column at z = 1 against a
+    /// wall from z = 2.
+    struct TestWorld {
+        ground: BlockPhysics,
+        air: BlockPhysics,
+        ladder: Option<BlockPhysics>,
+    }
+
+    impl TestWorld {
+        fn flat() -> Self {
+            Self {
+                ground: BlockPhysics::solid(),
+                air: BlockPhysics::empty(),
+                ladder: None,
+            }
+        }
+
+        fn water() -> Self {
+            Self {
+                ground: BlockPhysics {
+                    water: true,
+                    ..BlockPhysics::empty()
+                },
+                ..Self::flat()
+            }
+        }
+
+        fn ladder() -> Self {
+            Self {
+                ladder: Some(BlockPhysics {
+                    shape: vec![Aabb::new(DVec3::new(0.0, 0.0, 0.8125), DVec3::ONE)],
+                    climbable: true,
+                    ..BlockPhysics::empty()
+                }),
+                ..Self::flat()
+            }
+        }
+    }
+
+    impl BlockSource for TestWorld {
+        fn block_physics(&self, pos: IVec3) -> &BlockPhysics {
+            match &self.ladder {
+                Some(ladder) if pos.z == 1 && pos.y >= 0 => ladder,
+                _ if pos.y < 0 || (self.ladder.is_some() && pos.z >= 2) => &self.ground,
+                _ => &self.air,
+            }
+        }
+    }
+
+    // The standing jump, free fall and ladder climb are vanilla's own numbers. The walking,
+    // sprinting, sprint jump and water trajectories are regression pins only: they record what
+    // this simulation did when it was last checked, apart from the first tick of the sprint
+    // jump. The steady speeds further down are what hold those against vanilla.
+
+    /// Hold `input` from rest for as many ticks as `expected` has values, and compare what
+    /// `measure` reads after each tick.
+    fn assert_trajectory(
+        world: &TestWorld,
+        start: DVec3,
+        yaw: f32,
+        input: MoveInput,
+        measure: impl Fn(&PlayerBody) -> f64,
+        expected: &[f64],
+    ) {
+        let mut body = PlayerBody::new(start, yaw, 0.0);
+        if start.y == 0.0 {
+            // a standing player falls into the ground every tick; start from that
+            body.tick(&NO_INPUT, world);
+            body.tick(&NO_INPUT, world);
+        }
+        for (tick, expected) in expected.iter().enumerate() {
+            body.tick(&input, world);
+            let actual = measure(&body);
+            assert!(
+                (actual - expected).abs() < 1.0e-9,
+                "tick {}: {} instead of {}",
+                tick + 1,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn standing_jump() {
+        let input = MoveInput {
+            jump: true,
+            ..NO_INPUT
+        };
+        let expected = [
+            0.41999998688697815,
+            0.7531999805212017,
+            1.0013359791121474,
+            1.166109260938214,
+            1.2491870787446813,
+            1.2522033402537238,
+            1.1767592750642373,
+            1.0244240882136801,
+            0.7967356006686922,
+            0.49520087700591187,
+            0.12129684053918977,
+            0.0,
+        ];
+        assert_trajectory(
+            &TestWorld::flat(),
+            DVec3::ZERO,
+            0.0,
+            input,
+            |b| b.position.y,
+            &expected,
+        );
+    }
+
+    #[test]
+    fn free_fall() {
+        let expected = [
+            -0.0784000015258789,
+            -0.1552320045166016,
+            -0.230527368912964,
+            -0.30431682745754424,
+            -0.37663049823865513,
+        ];
+        let start = DVec3::Y * 20.0;
+        assert_trajectory(
+            &TestWorld::flat(),
+            start,
+            0.0,
+            NO_INPUT,
+            |b| b.velocity.y,
+            &expected,
+        );
+    }
+
+    #[test]
+    fn walking() {
+        let input = MoveInput {
+            forward: 1.0,
+            ..NO_INPUT
+        };
+        let expected = [
+            0.053508008053839436,
+            0.08272338384467842,
+            0.09867498087929642,
+            0.10738455387183765,
+            0.11213998127812054,
+        ];
+        assert_trajectory(
+            &TestWorld::flat(),
+            DVec3::ZERO,
+            90.0,
+            input,
+            |b| -b.velocity.x,
+            &expected,
+        );
+    }
+
+    #[test]
+    fn sprinting() {
+        let input = MoveInput {
+            forward: 1.0,
+            sprint: true,
+            ..NO_INPUT
+        };
+        let expected = [
+            0.06956040688199978,
+            0.10754039345104689,
+            0.12827746852641236,
+            0.13959991283269357,
+            0.1457819681419851,
+        ];
+        assert_trajectory(
+            &TestWorld::flat(),
+            DVec3::ZERO,
+            90.0,
+            input,
+            |b| -b.velocity.x,
+            &expected,
+        );
+    }
+
+    #[test]
+    fn sprint_jump() {
+        let input = MoveInput {
+            forward: 1.0,
+            sprint: true,
+            jump: true,
+            ..NO_INPUT
+        };
+        let expected = [
+            0.327399997806549,
+            0.5316404165686486,
+            0.7429792021948086,
+            0.9607775018534258,
+            1.1844539594509866,
+            1.4134795409271468,
+            1.6473728252731188,
+            1.8856957193582802,
+            2.128049558422275,
+            2.374071557522724,
+            2.6234315823525476,
+            2.875829210683645,
+        ];
+        assert_trajectory(
+            &TestWorld::flat(),
+            DVec3::ZERO,
+            90.0,
+            input,
+            |b| -b.position.x,
+            &expected,
+        );
+    }
+
+    #[test]
+    fn sinking_in_water() {
+        let expected = [
+            -0.005,
+            -0.009000000000000001,
+            -0.012200000000000003,
+            -0.014760000000000002,
+            -0.016808000000000003,
+        ];
+        assert_trajectory(
+            &TestWorld::water(),
+            DVec3::NEG_Y,
+            0.0,
+            NO_INPUT,
+            |b| b.velocity.y,
+            &expected,
+        );
+    }
+
+    #[test]
+    fn climbing_a_ladder() {
+        let input = MoveInput {
+            forward: 1.0,
+            ..NO_INPUT
+        };
+        let expected = [
+            0.0,
+            0.11760000228881837,
+            0.23520000457763673,
+            0.3528000068664551,
+            0.47040000915527347,
+        ];
+        let start = DVec3::new(0.5, 0.0, 1.5);
+        assert_trajectory(
+            &TestWorld::ladder(),
+            start,
+            0.0,
+            input,
+            |b| b.position.y,
+            &expected,
+        );
+    }
+
+    fn described(name: &str, properties: &[(&str, &str)]) -> BlockPhysics {
+        BlockPhysics::of(&BlockDescription {
+            name: format!("minecraft:{}", name),
+            properties: properties
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn shapes_from_block_data() {
+        let water = described("water", &[("level", "0")]);
+        assert!(water.water && water.shape.is_empty());
+        assert!(described("lava", &[]).lava);
+        assert!(described("short_grass", &[]).shape.is_empty());
+        assert!(described("oak_sapling", &[]).shape.is_empty());
+        assert_eq!(described("stone", &[]), BlockPhysics::solid());
+
+        let ladder = described("ladder", &[("facing", "north")]);
+        assert!(ladder.climbable);
+        assert_eq!(
+            ladder.shape,
+            vec![Aabb::new(DVec3::new(0.0, 0.0, 0.8125), DVec3::ONE)]
+        );
+        let bedrock_ladder = described("ladder", &[("facing_direction", "2")]);
+        assert_eq!(bedrock_ladder.shape, ladder.shape);
+
+        let top = described("oak_slab", &[("type", "top"), ("waterlogged", "true")]);
+        assert_eq!(top.shape, vec![Aabb::new(DVec3::Y * 0.5, DVec3::ONE)]);
+        assert!(top.water);
+        let bedrock_bottom = described("oak_slab", &[("minecraft:vertical_half", "bottom")]);
+        assert_eq!(bedrock_bottom.shape[0].max.y, 0.5);
+        assert_eq!(
+            described("oak_double_slab", &[]).shape,
+            BlockPhysics::solid().shape
+        );
+
+        let stairs = described("oak_stairs", &[("facing", "north"), ("half", "bottom")]);
+        assert_eq!(
+            stairs.shape[1],
+            Aabb::new(DVec3::Y * 0.5, DVec3::new(1.0, 1.0, 0.5))
+        );
+
+        let soul_sand = described("soul_sand", &[]);
+        assert_eq!(soul_sand.speed_factor, 0.4);
+        assert_eq!(soul_sand.shape[0].max.y, 0.875);
+        assert_eq!(described("stone", &[]).speed_factor, 1.0);
+    }
+
+    #[test]
+    fn hardness_from_block_data() {
+        let hardness = |name| described(name, &[]).hardness;
+        assert_eq!(hardness("dirt"), 0.5);
+        assert_eq!(hardness("stone"), 1.5);
+        assert_eq!(hardness("obsidian"), 50.0);
+        assert_eq!(hardness("deepslate_iron_ore"), 4.5);
+        assert_eq!(hardness("oak_leaves"), 0.2);
+        assert!(hardness("bedrock") < 0.0);
+        assert_eq!(hardness("short_grass"), 0.0);
+        assert_eq!(hardness("torch"), 0.0);
+    }
+
+    #[test]
+    fn lava_is_slower_than_air() {
+        let world = TestWorld {
+            ground: BlockPhysics {
+                lava: true,
+                ..BlockPhysics::empty()
+            },
+            ..TestWorld::flat()
+        };
+        let mut body = PlayerBody::new(DVec3::NEG_Y * 4.0, 0.0, 0.0);
+        for _ in 0..5 {
+            body.tick(&NO_INPUT, &world);
+        }
+        assert!(body.in_lava);
+        assert!(
+            (body.velocity.y - -0.0387500).abs() < 1.0e-4,
+            "{}",
+            body.velocity.y
+        );
+    }
+
+    /// Horizontal blocks per second holding `input`, measured over `ticks` once `warmup`
+    /// ticks have brought the speed up.
+    fn steady_speed(
+        world: &TestWorld,
+        start: DVec3,
+        input: MoveInput,
+        warmup: usize,
+        ticks: usize,
+    ) -> f64 {
+        let mut body = PlayerBody::new(start, 90.0, 0.0);
+        for _ in 0..warmup {
+            body.tick(&input, world);
+        }
+        let from = body.position;
+        for _ in 0..ticks {
+            body.tick(&input, world);
+        }
+        let moved = body.position - from;
+        (moved.x * moved.x + moved.z * moved.z).sqrt() / ticks as f64 * 20.0
+    }
+
+    fn assert_speed(name: &str, actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1.0e-3,
+            "{}: {} m/s instead of {}",
+            name,
+            actual,
+            expected
+        );
+    }
+
+    // Vanilla's top speeds follow from its constants: each tick adds `a` and keeps `drag` of
+    // the last tick's speed, so it settles at a / (1 - drag) blocks per tick. On the ground
+    // `a` is 0.1 * 0.216 / slipperiness^3 (times 1.3 sprinting, 0.3 sneaking) and drag is
+    // 0.91 * slipperiness.
+
+    #[test]
+    fn steady_ground_speeds() {
+        let walk = MoveInput {
+            forward: 1.0,
+            ..NO_INPUT
+        };
+        let ground = |a: f64, slipperiness: f64| {
+            let a = a * 0.98 * 0.216 / (slipperiness * slipperiness * slipperiness);
+            a / (1.0 - 0.91 * slipperiness) * 20.0
+        };
+        let flat = TestWorld::flat();
+        let speed = |world: &TestWorld, input| steady_speed(world, DVec3::ZERO, input, 100, 200);
+        assert_speed("walking", speed(&flat, walk), ground(0.1, 0.6));
+        assert_speed("walking", speed(&flat, walk), 4.317);
+        let sprint = MoveInput {
+            sprint: true,
+            ..walk
+        };
+        assert_speed("sprinting", speed(&flat, sprint), ground(0.13, 0.6));
+        assert_speed("sprinting", speed(&flat, sprint), 5.612);
+        let sneak = MoveInput {
+            sneak: true,
+            ..walk
+        };
+        assert_speed("sneaking", speed(&flat, sneak), ground(0.03, 0.6));
+        assert_speed("sneaking", speed(&flat, sneak), 1.295);
+        let ice = TestWorld {
+            ground: described("ice", &[]),
+            ..TestWorld::flat()
+        };
+        let on_ice = steady_speed(&ice, DVec3::ZERO, walk, 300, 200);
+        assert_speed("walking on ice", on_ice, ground(0.1, 0.98));
+    }
+
+    #[test]
+    fn soul_sand_and_water_slow_walking() {
+        let walk = MoveInput {
+            forward: 1.0,
+            ..NO_INPUT
+        };
+        let soul_sand = TestWorld {
+            ground: described("soul_sand", &[]),
+            ..TestWorld::flat()
+        };
+        // soul sand scales what is left of the speed after each tick by 0.4
+        let slowed = 0.098 / (1.0 - 0.4 * 0.546) * 20.0;
+        let speed = steady_speed(&soul_sand, DVec3::ZERO, walk, 100, 200);
+        assert_speed("walking on soul sand", speed, slowed);
+        // in water `a` is 0.02 and drag 0.8
+        let speed = steady_speed(&TestWorld::water(), DVec3::NEG_Y * 50.0, walk, 100, 200);
+        assert_speed("walking in water", speed, 0.0196 / 0.2 * 20.0);
+    }
+
+    #[test]
+    fn jumps_peak_and_land_on_time() {
+        let input = MoveInput {
+            jump: true,
+            ..NO_INPUT
+        };
+        let world = TestWorld::flat();
+        let mut body = PlayerBody::new(DVec3::ZERO, 0.0, 0.0);
+        body.tick(&NO_INPUT, &world);
+        body.tick(&NO_INPUT, &world);
+        body.tick(&input, &world);
+        let mut apex = (1, body.position.y);
+        let mut airtime = 1;
+        while !body.on_ground {
+            body.tick(&NO_INPUT, &world);
+            airtime += 1;
+            if body.position.y > apex.1 {
+                apex = (airtime, body.position.y);
+            }
+        }
+        // vanilla tops out at 1.2522 blocks six ticks in and is back down after twelve
+        assert_eq!(apex.0, 6);
+        assert!((apex.1 - 1.2522).abs() < 1.0e-4, "apex at {}", apex.1);
+        assert_eq!(airtime, 12);
+    }
+
+    #[test]
+    fn sprint_jumping_outpaces_sprinting() {
+        let input = MoveInput {
+            forward: 1.0,
+            sprint: true,
+            jump: true,
+            ..NO_INPUT
+        };
+        // 7.127 m/s is the vanilla sprint-jumping speed, 4.276 blocks per twelve-tick jump
+        let speed = steady_speed(&TestWorld::flat(), DVec3::ZERO, input, 120, 240);
+        assert_speed("sprint jumping", speed, 7.127);
+        let distance = speed / 20.0 * 12.0;
+        assert!(
+            (distance - 4.276).abs() < 1.0e-3,
+            "{} blocks a jump",
+            distance
+        );
+    }
+}
@@ -0,0 +1,461 @@
+//! Client-side prediction of the local player. Every tick the input is simulated at once and
+//! sent to the server with the result; when the server corrects a tick, its state is put in
+//! place of ours for that tick and the inputs since are replayed on top, while the drawn
+//! position glides over to the new one instead of snapping.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy_egui::egui;
+use std::collections::VecDeque;
+
+use crate::diagnostics::DiagnosticsAppExt;
+use crate::net::session::{PlayerAction, PlayerTick, SessionEvent};
+use crate::net::{SendAction, ServerEvent};
+use crate::physics::{
+    BlockPhysicsTable, LocalPlayer, MoveInput, PlayerBody, WorldBlocks, player_physics_system,
+    player_transform_system,
+};
+use crate::world::ChunkMap;
+
+/// Ticks of input kept for replaying; corrections for older ticks reset the player instead.
+pub const HISTORY_TICKS: usize = 100;
+/// Corrections closer than this to the prediction are taken as agreement.
+const CORRECTION_TOLERANCE: f64 = 1.0e-4;
+/// How fast the drawn position catches up with a correction, per second.
+const SMOOTHING_RATE: f32 = 12.0;
+/// Corrections further than this are shown at once; gliding over them would look worse.
+const MAX_SMOOTHED_DISTANCE: f64 = 2.0;
+
+/// Records, sends and reconciles the local player's ticks.
+pub struct PredictionPlugin;
+
+impl Plugin for PredictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_required_components::<LocalPlayer, Prediction>()
+            .add_diagnostics_panel("Prediction", prediction_panel)
+            .add_systems(Update, prediction_event_system)
+            .add_systems(
+                FixedUpdate,
+                prediction_record_system.after(player_physics_system),
+            )
+            .add_systems(
+                PostUpdate,
+                prediction_smoothing_system
+                    .after(player_transform_system)
+                    .before(TransformSystems::Propagate),
+            );
+    }
+}
+
+/// One predicted tick: the input it ran and the state it ended in.
+#[derive(Clone, Debug)]
+struct PredictedTick {
+    tick: u64,
+    input: MoveInput,
+    body: PlayerBody,
+}
+
+/// Prediction state of the local player.
+#[derive(Component, Debug, Default)]
+pub struct Prediction {
+    /// Number of the next client tick.
+    next_tick: u64,
+    /// `PlayerBody::ticks` of the last recorded tick.
+    recorded: u64,
+    history: VecDeque<PredictedTick>,
+    /// Offset still added to the drawn position after a correction; it shrinks to nothing.
+    pub smoothing: DVec3,
+    pub corrections: u32,
+    pub replayed_ticks: u64,
+    /// How far the last correction moved the player.
+    pub last_error: f64,
+}
+
+impl Prediction {
+    /// Forget the history, after the server placed the player itself.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.recorded = 0;
+        self.smoothing = DVec3::ZERO;
+    }
+
+    /// Remember the tick `body` just simulated and describe it for the server.
+    fn record(&mut self, input: MoveInput, body: &PlayerBody) -> PlayerTick {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        self.recorded = body.ticks;
+        if self.history.len() == HISTORY_TICKS {
+            self.history.pop_front();
+        }
+        self.history.push_back(PredictedTick {
+            tick,
+            input,
+            body: body.clone(),
+        });
+        PlayerTick {
+            tick,
+            position: body.position,
+            delta: body.position - body.previous,
+            yaw: body.yaw,
+            pitch: body.pitch,
+            forward: input.forward,
+            strafe: input.strafe,
+            jumping: input.jump,
+            sneaking: input.sneak,
+            sprinting: body.sprinting,
+            on_ground: body.on_ground,
+            horizontal_collision: body.horizontal_collision,
+        }
+    }
+
+    /// Apply the server's state after `tick` and replay the inputs since onto `body`.
+    fn reconcile(
+        &mut self,
+        body: &mut PlayerBody,
+        tick: u64,
+        position: DVec3,
+        velocity: DVec3,
+        on_ground: bool,
+        blocks: &WorldBlocks,
+    ) {
+        let Some(index) = self.history.iter().position(|t| t.tick == tick) else {
+            // too old to replay from: take the server's word for now
+            self.history.clear();
+            self.apply(body, |corrected| {
+                corrected.position = position;
+                corrected.velocity = velocity;
+                corrected.on_ground = on_ground;
+            });
+            return;
+        };
+        self.history.drain(..index);
+        let predicted = &self.history[0].body;
+        if predicted.position.distance(position) < CORRECTION_TOLERANCE
+            && predicted.velocity.distance(velocity) < CORRECTION_TOLERANCE
+        {
+            return;
+        }
+
+        let mut replayed = predicted.clone();
+        replayed.position = position;
+        replayed.velocity = velocity;
+        replayed.on_ground = on_ground;
+        self.history[0].body = replayed.clone();
+        for entry in self.history.iter_mut().skip(1) {
+            replayed.yaw = entry.body.yaw;
+            replayed.pitch = entry.body.pitch;
+            replayed.tick(&entry.input, blocks);
+            entry.body = replayed.clone();
+        }
+        self.replayed_ticks += self.history.len() as u64 - 1;
+        self.apply(body, |corrected| *corrected = replayed);
+    }
+
+    /// Change `body` with `correct`, keeping it drawn where it was.
+    fn apply(&mut self, body: &mut PlayerBody, correct: impl FnOnce(&mut PlayerBody)) {
+        let before = body.position;
+        let previous = body.previous;
+        correct(body);
+        let moved = body.position - before;
+        // shift the previous position with it so interpolation carries on smoothly
+        body.previous = previous + moved;
+        self.corrections += 1;
+        self.last_error = moved.length();
+        self.smoothing = if (self.smoothing - moved).length() > MAX_SMOOTHED_DISTANCE {
+            DVec3::ZERO
+        } else {
+            self.smoothing - moved
+        };
+    }
+}
+
+/// Record and send each tick the local player was simulated.
+pub fn prediction_record_system(
+    input: Res<MoveInput>,
+    mut actions: MessageWriter<SendAction>,
+    mut players: Query<(&PlayerBody, &mut Prediction), With<LocalPlayer>>,
+) {
+    for (body, mut prediction) in &mut players {
+        if body.ticks == prediction.recorded {
+            continue;
+        }
+        let tick = prediction.record(*input, body);
+        actions.write(SendAction(PlayerAction::Tick(tick)));
+    }
+}
+
+/// Start over on teleports and reconcile corrections.
+pub fn prediction_event_system(
+    mut events: MessageReader<ServerEvent>,
+    chunks: Res<ChunkMap>,
+    table: Res<BlockPhysicsTable>,
+    mut players: Query<(&mut PlayerBody, &mut Prediction), With<LocalPlayer>>,
+) {
+    let blocks = WorldBlocks {
+        chunks: &chunks,
+        table: &table,
+    };
+    for ServerEvent(event) in events.read() {
+        let Ok((mut body, mut prediction)) = players.single_mut() else {
+            continue;
+        };
+        match event {
+            SessionEvent::Spawned { .. } | SessionEvent::Teleported { .. } => prediction.reset(),
+            SessionEvent::MovementCorrected {
+                tick,
+                position,
+                velocity,
+                on_ground,
+            } => prediction.reconcile(&mut body, *tick, *position, *velocity, *on_ground, &blocks),
+            _ => {}
+        }
+    }
+}
+
+/// Draw the local player offset by what is left of the last correction.
+pub fn prediction_smoothing_system(
+    time: Res<Time>,
+    mut players: Query<(&mut Prediction, &mut Transform), With<LocalPlayer>>,
+) {
+    let decay = (-SMOOTHING_RATE * time.delta_secs()).exp() as f64;
+    for (mut prediction, mut transform) in &mut players {
+        if prediction.smoothing == DVec3::ZERO {
+            continue;
+        }
+        prediction.smoothing *= decay;
+        if prediction.smoothing.length() < CORRECTION_TOLERANCE {
+            prediction.smoothing = DVec3::ZERO;
+        }
+        transform.translation += prediction.smoothing.as_vec3();
+    }
+}
+
+pub fn prediction_panel(
+    InMut(ui): InMut<egui::Ui>,
+    players: Query<(&PlayerBody, &Prediction), With<LocalPlayer>>,
+) {
+    let Ok((body, prediction)) = players.single() else {
+        ui.label("No local player");
+        return;
+    };
+    ui.label(format!(
+        "Position: {:.3}, {:.3}, {:.3}",
+        body.position.x, body.position.y, body.position.z
+    ));
+    ui.label(format!("Tick: {}", prediction.next_tick));
+    ui.label(format!("History: {} ticks", prediction.history.len()));
+    ui.label(format!(
+        "Corrections: {} (last {:.3} blocks)",
+        prediction.corrections, prediction.last_error
+    ));
+    ui.label(format!("Replayed ticks: {}", prediction.replayed_ticks));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{BlockState, ChunkPos};
+
+    const STONE: BlockState = BlockState(1);
+
+    fn walk(forward: f32, strafe: f32, jump: bool) -> MoveInput {
+        MoveInput {
+            forward,
+            strafe,
+            jump,
+            ..default()
+        }
+    }
+
+    /// A floor at y = 0 under one chunk column.
+    struct TestWorld {
+        chunks: ChunkMap,
+        table: BlockPhysicsTable,
+    }
+
+    impl TestWorld {
+        fn new() -> Self {
+            let mut chunks = ChunkMap {
+                min_y: 0,
+                height: 16,
+                ..default()
+            };
+            let chunk = chunks.chunk_or_empty(ChunkPos::new(0, 0));
+            for z in 0..16 {
+                for x in 0..16 {
+                    chunk.set_block(IVec3::new(x, 0, z), STONE);
+                }
+            }
+            Self {
+                chunks,
+                table: BlockPhysicsTable::default(),
+            }
+        }
+
+        fn blocks(&self) -> WorldBlocks<'_> {
+            WorldBlocks {
+                chunks: &self.chunks,
+                table: &self.table,
+            }
+        }
+
+        /// Simulate and record each of `inputs`, as the fixed update does.
+        fn run(&self, prediction: &mut Prediction, body: &mut PlayerBody, inputs: &[MoveInput]) {
+            for input in inputs {
+                body.tick(input, &self.blocks());
+                prediction.record(*input, body);
+            }
+        }
+    }
+
+    fn inputs() -> Vec<MoveInput> {
+        let mut inputs = vec![walk(1.0, 0.0, false); 4];
+        inputs.push(walk(1.0, 0.0, true));
+        inputs.extend([walk(0.0, 1.0, false); 5]);
+        inputs
+    }
+
+    fn start() -> PlayerBody {
+        PlayerBody::new(DVec3::new(4.5, 1.0, 4.5), 0.0, 0.0)
+    }
+
+    #[test]
+    fn matching_corrections_change_nothing() {
+        let world = TestWorld::new();
+        let mut prediction = Prediction::default();
+        let mut body = start();
+        world.run(&mut prediction, &mut body, &inputs());
+        let before = body.clone();
+
+        let server = prediction.history[3].body.clone();
+        let nudge = DVec3::splat(CORRECTION_TOLERANCE / 4.0);
+        prediction.reconcile(
+            &mut body,
+            3,
+            server.position + nudge,
+            server.velocity,
+            server.on_ground,
+            &world.blocks(),
+        );
+
+        assert_eq!(body, before);
+        assert_eq!(prediction.corrections, 0);
+        assert_eq!(prediction.replayed_ticks, 0);
+        assert_eq!(prediction.smoothing, DVec3::ZERO);
+        // history before the agreed tick is done with
+        assert_eq!(prediction.history.len(), 7);
+        assert_eq!(prediction.history[0].tick, 3);
+        assert_eq!(prediction.history[0].body.position, server.position);
+    }
+
+    #[test]
+    fn corrections_replay_the_later_inputs() {
+        let world = TestWorld::new();
+        let mut prediction = Prediction::default();
+        let mut body = start();
+        let inputs = inputs();
+        world.run(&mut prediction, &mut body, &inputs);
+        let predicted = body.position;
+        let drawn_offset = body.previous - body.position;
+
+        // the server had the player half a block further east and moving after tick 3
+        let mut server = prediction.history[3].body.clone();
+        server.position.x += 0.5;
+        server.velocity = DVec3::new(0.1, 0.0, 0.0);
+        prediction.reconcile(
+            &mut body,
+            3,
+            server.position,
+            server.velocity,
+            server.on_ground,
+            &world.blocks(),
+        );
+
+        // the same inputs run from the server's state
+        let mut expected = server.clone();
+        for input in &inputs[4..] {
+            expected.tick(input, &world.blocks());
+        }
+        assert_eq!(body.position, expected.position);
+        assert_eq!(body.velocity, expected.velocity);
+        assert_eq!(body.on_ground, expected.on_ground);
+        assert_eq!(body.ticks, 10);
+        assert_eq!(body.previous - body.position, drawn_offset);
+
+        assert_eq!(prediction.corrections, 1);
+        assert_eq!(prediction.replayed_ticks, 6);
+        assert_eq!(prediction.history.len(), 7);
+        assert_eq!(prediction.history[0].body.position, server.position);
+        assert_eq!(
+            prediction.history.back().unwrap().body.position,
+            body.position
+        );
+        // still drawn at the predicted position, gliding over
+        assert_eq!(prediction.smoothing, predicted - body.position);
+        assert_eq!(prediction.last_error, (body.position - predicted).length());
+    }
+
+    #[test]
+    fn history_keeps_the_latest_ticks() {
+        let world = TestWorld::new();
+        let mut prediction = Prediction::default();
+        let mut body = start();
+        world.run(
+            &mut prediction,
+            &mut body,
+            &vec![MoveInput::default(); HISTORY_TICKS + 20],
+        );
+        assert_eq!(prediction.history.len(), HISTORY_TICKS);
+        assert_eq!(prediction.history[0].tick, 20);
+        assert_eq!(
+            prediction.history.back().unwrap().tick,
+            HISTORY_TICKS as u64 + 19
+        );
+        assert_eq!(prediction.next_tick, HISTORY_TICKS as u64 + 20);
+    }
+
+    #[test]
+    fn corrections_too_old_to_replay_reset_the_player() {
+        let world = TestWorld::new();
+        let mut prediction = Prediction::default();
+        let mut body = start();
+        world.run(
+            &mut prediction,
+            &mut body,
+            &vec![walk(1.0, 0.0, false); HISTORY_TICKS + 20],
+        );
+
+        let position = DVec3::new(2.5, 1.0, 2.5);
+        prediction.reconcile(&mut body, 10, position, DVec3::ZERO, true, &world.blocks());
+        assert!(prediction.history.is_empty());
+        assert_eq!(body.position, position);
+        assert_eq!(body.velocity, DVec3::ZERO);
+        assert!(body.on_ground);
+        assert_eq!(prediction.corrections, 1);
+        assert_eq!(prediction.replayed_ticks, 0);
+    }
+
+    #[test]
+    fn only_small_corrections_are_smoothed() {
+        let mut prediction = Prediction::default();
+        let mut body = start();
+
+        prediction.apply(&mut body, |b| b.position.x += 1.5);
+        assert_eq!(prediction.smoothing, DVec3::new(-1.5, 0.0, 0.0));
+        assert_eq!(prediction.last_error, 1.5);
+
+        // what is left of the last one counts too
+        prediction.apply(&mut body, |b| b.position.x += 1.0);
+        assert_eq!(prediction.smoothing, DVec3::ZERO);
+
+        prediction.apply(&mut body, |b| b.position.z -= MAX_SMOOTHED_DISTANCE);
+        assert_eq!(
+            prediction.smoothing,
+            DVec3::new(0.0, 0.0, MAX_SMOOTHED_DISTANCE)
+        );
+        prediction.apply(&mut body, |b| b.position.y += 10.0);
+        assert_eq!(prediction.smoothing, DVec3::ZERO);
+        assert_eq!(body.previous, body.position);
+        assert_eq!(prediction.corrections, 4);
+    }
+}
@@ -3,9 +3,11 @@ use sysinfo::System;
 
 use crate::data::{GlobalFlags, GlobalSettings, SysInfo, SystemThemeState, ThemeMode};
 
-pub fn setup(mut commands: Commands) {
-    // spawn a simple 2D camera
-    commands.spawn(Camera2d);
+pub fn setup(mut commands: Commands, windows: Query<(), With<Window>>) {
+    // spawn a simple 2D camera (nothing to render to when headless)
+    if !windows.is_empty() {
+        commands.spawn(Camera2d);
+    }
 
     let system_theme = dark_light::detect();
     let theme_mode = match system_theme {
@@ -4,13 +4,18 @@ use bevy::window::PrimaryWindow;
 use bevy_egui::{EguiContexts, EguiPrimaryContextPass};
 use crossbeam_channel::{Receiver, Sender};
 use egui::{CornerRadius, Id, Memory};
-use main_menu::main_menu_ui;
+use log_console::LogConsoleState;
+use main_menu::{MultiplayerMenu, main_menu_ui};
 use menu_bar::menu_bar_ui;
 
+pub mod dev_console;
+pub mod log_console;
 pub mod main_menu;
 pub mod menu_bar;
 
 use crate::data::{FpsCap, GlobalSettings};
+use crate::net::lan::LanServers;
+use crate::net::{ConnectRequest, ConnectionState};
 
 pub struct GameUIPlugin;
 
@@ -29,11 +34,17 @@ impl Plugin for GameUIPlugin {
     fn build(&self, app: &mut App) {
         let (sender, receiver) = crossbeam_channel::unbounded();
         app.insert_resource(FileDialogChannel { sender, receiver })
-            .insert_resource(MenuBarVisibility::default());
+            .insert_resource(MenuBarVisibility::default())
+            .insert_resource(LogConsoleState::default())
+            .init_resource::<MultiplayerMenu>();
         app.add_systems(Update, menu_bar::file_dialog_system);
         app.add_systems(
             EguiPrimaryContextPass,
-            (ui_system, crate::egui_dbg::egui_debug_system),
+            (
+                ui_system,
+                log_console::log_console_system,
+                dev_console::dev_console_system,
+            ),
         );
     }
 }
@@ -44,6 +55,10 @@ pub struct UiResBundle<'w> {
     pub file_dialog: Res<'w, FileDialogChannel>,
     pub fps_cap: ResMut<'w, FpsCap>,
     pub menu_bar_visibility: ResMut<'w, MenuBarVisibility>,
+    pub multiplayer: ResMut<'w, MultiplayerMenu>,
+    pub lan: Res<'w, LanServers>,
+    pub connection: Res<'w, ConnectionState>,
+    pub connects: MessageWriter<'w, ConnectRequest>,
 }
 
 pub fn ui_system(
@@ -58,6 +73,10 @@ pub fn ui_system(
         file_dialog,
         mut fps_cap,
         mut menu_bar_visibility,
+        mut multiplayer,
+        lan,
+        connection,
+        mut connects,
     } = ui;
 
     if let Ok(mut window) = windows.single_mut()
@@ -108,7 +127,7 @@ pub fn ui_system(
         }
 
         egui::CentralPanel::default().show(ctx, |ui_egui| {
-            main_menu_ui(ui_egui);
+            main_menu_ui(ui_egui, &mut multiplayer, &lan, &connection, &mut connects);
         });
     }
 }
@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use egui::Color32;
+
+use crate::console::{ConsoleLineKind, ConsoleRegistry, ConsoleState};
+
+fn line_color(kind: ConsoleLineKind) -> Color32 {
+    match kind {
+        ConsoleLineKind::Input => Color32::from_rgb(120, 170, 255),
+        ConsoleLineKind::Output => Color32::LIGHT_GRAY,
+        ConsoleLineKind::Error => Color32::from_rgb(255, 85, 85),
+    }
+}
+
+/// Drop-down developer console (backquote by default, see the `console_key` cvar).
+pub fn dev_console_system(
+    mut contexts: EguiContexts,
+    keys: Res<ButtonInput<KeyCode>>,
+    registry: Res<ConsoleRegistry>,
+    mut state: ResMut<ConsoleState>,
+) {
+    if !state.open {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+
+    // the toggle key also produces a character; keep it out of the input line
+    if keys.just_pressed(state.toggle_key) {
+        ctx.input_mut(|i| i.events.retain(|e| !matches!(e, egui::Event::Text(_))));
+    }
+    let (tab, up, down, enter) = ctx.input_mut(|i| {
+        (
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Tab),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+        )
+    });
+
+    let state = &mut *state;
+    if enter {
+        let line = std::mem::take(&mut state.input);
+        state.submit(line);
+    }
+    if tab {
+        let (completed, candidates) = registry.complete(&state.input);
+        if !candidates.is_empty() {
+            state.print(ConsoleLineKind::Output, candidates.join("  "));
+        }
+        state.input = completed;
+    }
+    if up && !state.history.is_empty() {
+        let cursor = state
+            .history_cursor
+            .map_or(state.history.len() - 1, |c| c.saturating_sub(1));
+        state.history_cursor = Some(cursor);
+        state.input = state.history[cursor].clone();
+    }
+    if down && let Some(cursor) = state.history_cursor {
+        if cursor + 1 < state.history.len() {
+            state.history_cursor = Some(cursor + 1);
+            state.input = state.history[cursor + 1].clone();
+        } else {
+            state.history_cursor = None;
+            state.input.clear();
+        }
+    }
+
+    egui::TopBottomPanel::top("dev_console")
+        .exact_height(ctx.content_rect().height() * 0.4)
+        .frame(egui::Frame::default().fill(Color32::from_black_alpha(220)))
+        .show(ctx, |ui| {
+            let input_height = ui.spacing().interact_size.y + 8.0;
+            egui::ScrollArea::vertical()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .max_height(ui.available_height() - input_height)
+                .show(ui, |ui| {
+                    for (kind, line) in &state.output {
+                        ui.label(
+                            egui::RichText::new(line)
+                                .monospace()
+                                .color(line_color(*kind)),
+                        );
+                    }
+                });
+
+            ui.separator();
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut state.input)
+                    .font(egui::TextStyle::Monospace)
+                    .hint_text("help")
+                    .desired_width(f32::INFINITY)
+                    .lock_focus(true),
+            );
+            if state.just_opened || tab || up || down || enter || !response.has_focus() {
+                response.request_focus();
+            }
+            if tab || up || down {
+                // move the cursor to the end of the replaced text
+                if let Some(mut text_state) = egui::TextEdit::load_state(ui.ctx(), response.id) {
+                    let end = egui::text::CCursor::new(state.input.chars().count());
+                    text_state
+                        .cursor
+                        .set_char_range(Some(egui::text::CCursorRange::one(end)));
+                    text_state.store(ui.ctx(), response.id);
+                }
+            }
+        });
+}
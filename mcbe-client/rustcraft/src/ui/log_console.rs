@@ -0,0 +1,166 @@
+use bevy::log::tracing::Level;
+use bevy::log::tracing::level_filters::LevelFilter;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use egui::Color32;
+
+use crate::logging::{LOG_LEVELS, LogLine, recent_logs, set_log_level};
+
+const LEVEL_FILTERS: [LevelFilter; 6] = [
+    LevelFilter::OFF,
+    LevelFilter::ERROR,
+    LevelFilter::WARN,
+    LevelFilter::INFO,
+    LevelFilter::DEBUG,
+    LevelFilter::TRACE,
+];
+
+/// State of the log console window (F6).
+#[derive(Resource)]
+pub struct LogConsoleState {
+    pub open: bool,
+    /// Most verbose level shown.
+    pub max_level: LevelFilter,
+    pub target_filter: String,
+    pub search: String,
+    pub new_module: String,
+}
+
+impl Default for LogConsoleState {
+    fn default() -> Self {
+        Self {
+            open: false,
+            max_level: LevelFilter::TRACE,
+            target_filter: String::new(),
+            search: String::new(),
+            new_module: String::new(),
+        }
+    }
+}
+
+impl LogConsoleState {
+    fn matches(&self, line: &LogLine) -> bool {
+        self.max_level >= line.level
+            && line.target.contains(self.target_filter.as_str())
+            && (self.search.is_empty()
+                || line
+                    .message
+                    .to_lowercase()
+                    .contains(&self.search.to_lowercase()))
+    }
+}
+
+fn level_color(level: Level) -> Color32 {
+    match level {
+        Level::ERROR => Color32::from_rgb(255, 85, 85),
+        Level::WARN => Color32::from_rgb(255, 200, 60),
+        Level::INFO => Color32::LIGHT_GRAY,
+        Level::DEBUG => Color32::from_rgb(120, 170, 255),
+        Level::TRACE => Color32::GRAY,
+    }
+}
+
+fn level_combo(ui: &mut egui::Ui, id: impl std::hash::Hash, level: &mut LevelFilter) -> bool {
+    let mut changed = false;
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(level.to_string())
+        .show_ui(ui, |ui| {
+            for option in LEVEL_FILTERS {
+                changed |= ui
+                    .selectable_value(level, option, option.to_string())
+                    .changed();
+            }
+        });
+    changed
+}
+
+/// Log console: filterable view of the recent log buffer plus runtime level controls.
+pub fn log_console_system(mut contexts: EguiContexts, mut state: ResMut<LogConsoleState>) {
+    if !state.open {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+
+    let mut open = state.open;
+    egui::Window::new("Log Console")
+        .open(&mut open)
+        .default_size(egui::vec2(720.0, 400.0))
+        .show(ctx, |ui| {
+            let state = &mut *state;
+            let lines: Vec<LogLine> = recent_logs()
+                .into_iter()
+                .filter(|line| state.matches(line))
+                .collect();
+
+            ui.horizontal(|ui| {
+                ui.label("Level");
+                level_combo(ui, "log_console_level", &mut state.max_level);
+                ui.label("Target");
+                ui.add(egui::TextEdit::singleline(&mut state.target_filter).desired_width(120.0));
+                ui.label("Search");
+                ui.add(egui::TextEdit::singleline(&mut state.search).desired_width(160.0));
+                if ui.button("Copy").clicked() {
+                    let text = lines
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    ui.ctx().copy_text(text);
+                }
+            });
+
+            egui::CollapsingHeader::new("Module Levels")
+                .id_salt("log_console_modules")
+                .show(ui, |ui| {
+                    let Ok(levels) = LOG_LEVELS.read().map(|l| l.clone()) else {
+                        return;
+                    };
+
+                    ui.horizontal(|ui| {
+                        ui.label("default");
+                        let mut level = levels.default;
+                        if level_combo(ui, "log_level_default", &mut level) {
+                            set_log_level(None, level);
+                        }
+                    });
+                    for (module, level) in &levels.modules {
+                        ui.horizontal(|ui| {
+                            ui.label(module.as_str());
+                            let mut level = *level;
+                            if level_combo(ui, module, &mut level) {
+                                set_log_level(Some(module), level);
+                            }
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut state.new_module)
+                                .hint_text("module::path")
+                                .desired_width(160.0),
+                        );
+                        if ui.button("Add").clicked() && !state.new_module.is_empty() {
+                            set_log_level(Some(state.new_module.trim()), LevelFilter::DEBUG);
+                            state.new_module.clear();
+                        }
+                    });
+                });
+
+            ui.separator();
+            let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+            egui::ScrollArea::vertical()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show_rows(ui, row_height, lines.len(), |ui, range| {
+                    for line in &lines[range] {
+                        ui.label(
+                            egui::RichText::new(line.to_string())
+                                .monospace()
+                                .color(level_color(line.level)),
+                        );
+                    }
+                });
+        });
+    state.open = open;
+}
@@ -1,5 +1,24 @@
-pub fn main_menu_ui(ui: &mut egui::Ui) {
-    ui.centered_and_justified(|ui| {
+use bevy::prelude::*;
+
+use crate::net::lan::LanServers;
+use crate::net::{ConnectRequest, ConnectionState, Edition, ServerAddress};
+
+/// What the multiplayer section's direct connect fields hold.
+#[derive(Resource, Default)]
+pub struct MultiplayerMenu {
+    pub address: String,
+    pub edition: Edition,
+    pub error: Option<String>,
+}
+
+pub fn main_menu_ui(
+    ui: &mut egui::Ui,
+    menu: &mut MultiplayerMenu,
+    lan: &LanServers,
+    state: &ConnectionState,
+    connects: &mut MessageWriter<ConnectRequest>,
+) {
+    ui.vertical_centered(|ui| {
         ui.add_space(100.0);
         if ui.button("Settings").clicked() {
             #[cfg(debug_assertions)]
@@ -8,5 +27,66 @@ pub fn main_menu_ui(ui: &mut egui::Ui) {
             #[cfg(not(debug_assertions))]
             info!("Do settings screen");
         }
+
+        if matches!(state, ConnectionState::Disconnected) {
+            ui.add_space(20.0);
+            multiplayer_ui(ui, menu, lan, connects);
+        }
     });
 }
+
+fn multiplayer_ui(
+    ui: &mut egui::Ui,
+    menu: &mut MultiplayerMenu,
+    lan: &LanServers,
+    connects: &mut MessageWriter<ConnectRequest>,
+) {
+    ui.heading("Multiplayer");
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut menu.address);
+        egui::ComboBox::from_id_salt("direct_connect_edition")
+            .selected_text(menu.edition.to_string())
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut menu.edition, Edition::Bedrock, "bedrock");
+                ui.selectable_value(&mut menu.edition, Edition::Java, "java");
+            });
+        if ui.button("Join").clicked() {
+            match ServerAddress::parse(&menu.address, menu.edition) {
+                Ok(address) => {
+                    menu.error = None;
+                    connects.write(ConnectRequest {
+                        address,
+                        edition: menu.edition,
+                    });
+                }
+                Err(err) => menu.error = Some(err),
+            }
+        }
+    });
+    if let Some(error) = &menu.error {
+        ui.colored_label(egui::Color32::LIGHT_RED, error);
+    }
+
+    ui.add_space(10.0);
+    ui.label("Local games");
+    if !lan.enabled {
+        ui.weak("LAN discovery is off (net.lan_discovery)");
+    } else if lan.servers.is_empty() {
+        ui.weak("Scanning for games on your local network...");
+    }
+    for server in &lan.servers {
+        ui.horizontal(|ui| {
+            ui.label(format!("[{}] {}", server.edition, server.motd));
+            ui.weak(server.address.to_string());
+            if let Some(details) = &server.details {
+                ui.weak(details);
+            }
+            if ui.button("Join").clicked() {
+                connects.write(ConnectRequest {
+                    address: server.address.clone(),
+                    edition: server.edition,
+                });
+            }
+        });
+    }
+}
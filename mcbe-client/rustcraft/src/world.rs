@@ -0,0 +1,379 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::net::ServerEvent;
+use crate::net::nbt::Nbt;
+use crate::net::session::SessionEvent;
+
+/// Width of a chunk column along X and Z, in blocks.
+pub const CHUNK_WIDTH: i32 = 16;
+/// Height of a single chunk section (sub-chunk), in blocks.
+pub const SECTION_HEIGHT: i32 = 16;
+/// Number of blocks stored in one section.
+pub const SECTION_VOLUME: usize = (CHUNK_WIDTH * CHUNK_WIDTH * SECTION_HEIGHT) as usize;
+/// Biomes are stored per 4x4x4 cell, as Java sends them.
+pub const BIOME_CELL: i32 = 4;
+/// Number of biome cells in one section.
+pub const BIOME_VOLUME: usize = (SECTION_VOLUME as i32 / BIOME_CELL.pow(3)) as usize;
+
+/// Edition-local block state id. `0` is always air.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockState(pub u32);
+
+impl BlockState {
+    pub const AIR: BlockState = BlockState(0);
+
+    pub fn is_air(self) -> bool {
+        self == Self::AIR
+    }
+}
+
+/// Column position of a chunk, in chunk coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+
+    /// Chunk column containing the given block position.
+    pub fn from_block(pos: IVec3) -> Self {
+        Self {
+            x: pos.x.div_euclid(CHUNK_WIDTH),
+            z: pos.z.div_euclid(CHUNK_WIDTH),
+        }
+    }
+
+    /// Chunk column containing the given world-space position.
+    pub fn from_world(pos: Vec3) -> Self {
+        Self::from_block(pos.floor().as_ivec3())
+    }
+
+    /// World-space X/Z of the chunk's north-west corner.
+    pub fn origin(self) -> IVec3 {
+        IVec3::new(self.x * CHUNK_WIDTH, 0, self.z * CHUNK_WIDTH)
+    }
+}
+
+/// 4-bit per block storage, as used for light values.
+#[derive(Clone, Debug)]
+pub struct NibbleArray(Box<[u8; SECTION_VOLUME / 2]>);
+
+impl NibbleArray {
+    pub fn filled(value: u8) -> Self {
+        let v = value & 0x0F;
+        Self(Box::new([v | (v << 4); SECTION_VOLUME / 2]))
+    }
+
+    pub fn get(&self, index: usize) -> u8 {
+        let byte = self.0[index >> 1];
+        if index & 1 == 0 {
+            byte & 0x0F
+        } else {
+            byte >> 4
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: u8) {
+        let byte = &mut self.0[index >> 1];
+        if index & 1 == 0 {
+            *byte = (*byte & 0xF0) | (value & 0x0F);
+        } else {
+            *byte = (*byte & 0x0F) | ((value & 0x0F) << 4);
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.0[..]
+    }
+}
+
+/// A 16x16x16 cube of blocks with its light data.
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub blocks: Box<[BlockState; SECTION_VOLUME]>,
+    /// Edition-local biome ids, see `Section::biome_index`.
+    pub biomes: [u32; BIOME_VOLUME],
+    pub sky_light: NibbleArray,
+    pub block_light: NibbleArray,
+}
+
+impl Default for Section {
+    fn default() -> Self {
+        Self {
+            blocks: Box::new([BlockState::AIR; SECTION_VOLUME]),
+            biomes: [0; BIOME_VOLUME],
+            sky_light: NibbleArray::filled(15),
+            block_light: NibbleArray::filled(0),
+        }
+    }
+}
+
+impl Section {
+    /// Index of a local position, laid out Y-major then Z then X.
+    pub fn index(x: i32, y: i32, z: i32) -> usize {
+        ((y * CHUNK_WIDTH + z) * CHUNK_WIDTH + x) as usize
+    }
+
+    /// Index of the biome cell holding a local block position, in the same order as blocks.
+    pub fn biome_index(x: i32, y: i32, z: i32) -> usize {
+        let cells = CHUNK_WIDTH / BIOME_CELL;
+        (((y / BIOME_CELL) * cells + z / BIOME_CELL) * cells + x / BIOME_CELL) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|b| b.is_air())
+    }
+}
+
+/// Extra data of a block such as a chest or sign.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockEntity {
+    /// Edition-local type: the `id` tag on Bedrock (e.g. `Chest`), the index of the block
+    /// entity type on Java.
+    pub id: String,
+    pub data: Nbt,
+}
+
+/// A full-height column of sections.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    /// Section index of `sections[0]` (e.g. `-4` for a world starting at y = -64).
+    pub min_section: i32,
+    pub sections: Vec<Section>,
+    /// By world position.
+    pub block_entities: HashMap<IVec3, BlockEntity>,
+    /// Y of the first free block above the motion blocking ones, `x + z * 16`, if the server
+    /// sent it.
+    pub heightmap: Option<Box<[i32; 256]>>,
+    /// Whether the server sent the column's light; if not the client computes it.
+    pub has_light: bool,
+}
+
+impl Chunk {
+    pub fn new(min_section: i32, section_count: usize) -> Self {
+        Self {
+            min_section,
+            sections: vec![Section::default(); section_count],
+            block_entities: HashMap::new(),
+            heightmap: None,
+            has_light: false,
+        }
+    }
+
+    /// The section with section index `y`, if the column reaches that far.
+    pub fn section_mut(&mut self, y: i32) -> Option<&mut Section> {
+        let index = y - self.min_section;
+        if index < 0 {
+            return None;
+        }
+        self.sections.get_mut(index as usize)
+    }
+
+    fn locate(&self, pos: IVec3) -> Option<(usize, usize)> {
+        let section = pos.y.div_euclid(SECTION_HEIGHT) - self.min_section;
+        if section < 0 || section as usize >= self.sections.len() {
+            return None;
+        }
+        let index = Section::index(
+            pos.x.rem_euclid(CHUNK_WIDTH),
+            pos.y.rem_euclid(SECTION_HEIGHT),
+            pos.z.rem_euclid(CHUNK_WIDTH),
+        );
+        Some((section as usize, index))
+    }
+
+    pub fn block(&self, pos: IVec3) -> BlockState {
+        self.locate(pos)
+            .map(|(s, i)| self.sections[s].blocks[i])
+            .unwrap_or(BlockState::AIR)
+    }
+
+    pub fn set_block(&mut self, pos: IVec3, state: BlockState) {
+        if let Some((s, i)) = self.locate(pos) {
+            self.sections[s].blocks[i] = state;
+        }
+    }
+
+    pub fn sky_light(&self, pos: IVec3) -> u8 {
+        self.locate(pos)
+            .map(|(s, i)| self.sections[s].sky_light.get(i))
+            .unwrap_or(15)
+    }
+
+    pub fn block_light(&self, pos: IVec3) -> u8 {
+        self.locate(pos)
+            .map(|(s, i)| self.sections[s].block_light.get(i))
+            .unwrap_or(0)
+    }
+
+    pub fn set_sky_light(&mut self, pos: IVec3, value: u8) {
+        if let Some((s, i)) = self.locate(pos) {
+            self.sections[s].sky_light.set(i, value);
+        }
+    }
+
+    pub fn set_block_light(&mut self, pos: IVec3, value: u8) {
+        if let Some((s, i)) = self.locate(pos) {
+            self.sections[s].block_light.set(i, value);
+        }
+    }
+}
+
+/// All chunk columns currently loaded by the client.
+#[derive(Resource)]
+pub struct ChunkMap {
+    pub chunks: HashMap<ChunkPos, Chunk>,
+    /// Lowest buildable Y coordinate of the current dimension.
+    pub min_y: i32,
+    /// Total buildable height of the current dimension.
+    pub height: i32,
+}
+
+impl Default for ChunkMap {
+    fn default() -> Self {
+        // overworld bounds for both editions
+        Self {
+            chunks: HashMap::new(),
+            min_y: -64,
+            height: 384,
+        }
+    }
+}
+
+impl ChunkMap {
+    pub fn min_section(&self) -> i32 {
+        self.min_y.div_euclid(SECTION_HEIGHT)
+    }
+
+    pub fn section_count(&self) -> usize {
+        (self.height / SECTION_HEIGHT) as usize
+    }
+
+    pub fn max_y(&self) -> i32 {
+        self.min_y + self.height
+    }
+
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    /// The column at `pos`, created empty if it isn't loaded.
+    pub fn chunk_or_empty(&mut self, pos: ChunkPos) -> &mut Chunk {
+        let (min_section, count) = (self.min_section(), self.section_count());
+        self.chunks
+            .entry(pos)
+            .or_insert_with(|| Chunk::new(min_section, count))
+    }
+
+    pub fn set_block(&mut self, pos: IVec3, state: BlockState) {
+        if let Some(chunk) = self.chunks.get_mut(&ChunkPos::from_block(pos)) {
+            chunk.set_block(pos, state);
+        }
+    }
+
+    pub fn block(&self, pos: IVec3) -> BlockState {
+        self.chunks
+            .get(&ChunkPos::from_block(pos))
+            .map(|c| c.block(pos))
+            .unwrap_or(BlockState::AIR)
+    }
+
+    pub fn sky_light(&self, pos: IVec3) -> u8 {
+        self.chunks
+            .get(&ChunkPos::from_block(pos))
+            .map(|c| c.sky_light(pos))
+            .unwrap_or(15)
+    }
+
+    pub fn block_light(&self, pos: IVec3) -> u8 {
+        self.chunks
+            .get(&ChunkPos::from_block(pos))
+            .map(|c| c.block_light(pos))
+            .unwrap_or(0)
+    }
+}
+
+/// Keeps `ChunkMap` in step with the world data the server sends.
+pub struct WorldPlugin;
+
+impl Plugin for WorldPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkMap>()
+            .add_systems(Update, chunk_event_system);
+    }
+}
+
+/// Apply chunk, light, block and dimension updates from the server to the chunk map.
+pub fn chunk_event_system(mut events: MessageReader<ServerEvent>, mut chunk_map: ResMut<ChunkMap>) {
+    for ServerEvent(event) in events.read() {
+        match event {
+            SessionEvent::ChunkLoaded { pos, chunk } => {
+                let pos = ChunkPos::new(pos.x, pos.y);
+                match chunk {
+                    Some(chunk) => {
+                        chunk_map.chunks.insert(pos, (**chunk).clone());
+                    }
+                    None => {
+                        chunk_map.chunk_or_empty(pos);
+                    }
+                }
+            }
+            SessionEvent::SectionLoaded {
+                pos,
+                section_y,
+                blocks,
+                block_entities,
+            } => {
+                let chunk = chunk_map.chunk_or_empty(ChunkPos::new(pos.x, pos.y));
+                let Some(section) = chunk.section_mut(*section_y) else {
+                    continue;
+                };
+                section.blocks.copy_from_slice(&blocks[..]);
+                let range = section_y * SECTION_HEIGHT..(section_y + 1) * SECTION_HEIGHT;
+                chunk.block_entities.retain(|at, _| !range.contains(&at.y));
+                chunk.block_entities.extend(block_entities.iter().cloned());
+            }
+            SessionEvent::ChunkUnloaded { pos } => {
+                chunk_map.chunks.remove(&ChunkPos::new(pos.x, pos.y));
+            }
+            SessionEvent::LightUpdated {
+                pos,
+                sky_light,
+                block_light,
+            } => {
+                let Some(chunk) = chunk_map.chunks.get_mut(&ChunkPos::new(pos.x, pos.y)) else {
+                    continue;
+                };
+                for (y, light) in sky_light {
+                    if let Some(section) = chunk.section_mut(*y) {
+                        section.sky_light = light.clone();
+                    }
+                }
+                for (y, light) in block_light {
+                    if let Some(section) = chunk.section_mut(*y) {
+                        section.block_light = light.clone();
+                    }
+                }
+            }
+            SessionEvent::BlockChanged { pos, state } => {
+                chunk_map.set_block(*pos, BlockState(*state));
+            }
+            SessionEvent::DimensionChanged { min_y, height } => {
+                chunk_map.chunks.clear();
+                chunk_map.min_y = *min_y;
+                chunk_map.height = *height;
+            }
+            SessionEvent::Disconnected { .. } => *chunk_map = ChunkMap::default(),
+            _ => {}
+        }
+    }
+}